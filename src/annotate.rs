@@ -0,0 +1,179 @@
+use crate::definition;
+use crate::sanitize;
+
+/// Most call sites of a function quoted in a prompt.
+const MAX_CALL_SITES: usize = 10;
+
+/// Whether ruff's rule `code` is one of flake8-annotations' (`ANN`).
+pub fn is_annotation_rule(code: &str) -> bool {
+    code.starts_with("ANN")
+}
+
+/// The function a missing-annotation issue is about, and what shows how it
+/// is used.
+pub struct Target {
+    /// The function, decorators and body included.
+    pub definition: String,
+    /// Its `return` and `yield` lines, as `line N: ...`.
+    pub exits: Vec<String>,
+    /// Lines of the file that call it, as `line N: ...`.
+    pub call_sites: Vec<String>,
+    name: String,
+    params: Vec<String>,
+    /// Zero-based lines of the header, `start..end`.
+    header: (usize, usize),
+    indent: String,
+}
+
+/// Finds the function whose header contains the one-based `row`, which is
+/// where `ANN` issues are reported.
+pub fn locate(content: &str, row: usize) -> Result<Target, String> {
+    let definition = definition::at_row(content, row)?;
+    if definition.is_one_line() {
+        return Err(format!(
+            "The function at line {} is written on one line; its signature can't be replaced on its own",
+            definition.header_start + 1
+        ));
+    }
+    let lines: Vec<&str> = content.lines().collect();
+    let quote_line = |line: usize| format!("line {}: {}", line + 1, lines[line].trim());
+    let call_sites = lines
+        .iter()
+        .enumerate()
+        .filter(|(line, _)| !(definition.first_line..definition.end).contains(line))
+        .filter(|(_, text)| calls(text, &definition.name))
+        .take(MAX_CALL_SITES)
+        .map(|(line, _)| quote_line(line))
+        .collect();
+    Ok(Target {
+        definition: definition::quote(content, definition.first_line, definition.end),
+        exits: definition
+            .exits
+            .iter()
+            .map(|&line| quote_line(line))
+            .collect(),
+        call_sites,
+        indent: lines[definition.header_start]
+            .chars()
+            .take_while(|c| *c == ' ' || *c == '\t')
+            .collect(),
+        name: definition.name,
+        params: definition.params,
+        header: (definition.header_start, definition.header_end),
+    })
+}
+
+impl Target {
+    /// Replaces the function's signature with the annotated one in the
+    /// model's `answer`, adding the imports the answer puts before it.
+    /// Anything but annotations changing in the signature is rejected; the
+    /// error is phrased for the model.
+    pub fn apply(&self, content: &str, answer: &str) -> Result<String, String> {
+        let answer = sanitize::clean(answer);
+        let answer_lines: Vec<&str> = answer.lines().collect();
+        let header_at = answer_lines
+            .iter()
+            .position(|line| {
+                let line = line.trim_start();
+                line.starts_with("def ") || line.starts_with("async def ")
+            })
+            .ok_or("The answer contains no function signature; return the annotated `def` line(s) only.")?;
+        let imports: Vec<&str> = answer_lines[..header_at]
+            .iter()
+            .map(|line| line.trim())
+            .filter(|line| !line.is_empty())
+            .collect();
+        if let Some(other) = imports
+            .iter()
+            .find(|line| !(line.starts_with("import ") || line.starts_with("from ")))
+        {
+            return Err(format!(
+                "Only import lines may come before the signature, found `{}`.",
+                other
+            ));
+        }
+
+        let signature = dedent(&answer_lines[header_at..]);
+        let signature = signature.trim_end();
+        // The signature alone has to parse as a function with the same name
+        // and parameters.
+        let stub = format!("{}\n    ...\n", signature);
+        let annotated = definition::at_row(&stub, 1)
+            .map_err(|_| "The signature is not valid Python; return the complete annotated signature, ending with a colon.".to_string())?;
+        if annotated.body_start != signature.lines().count() {
+            return Err("Return only the signature, without the body of the function.".to_string());
+        }
+        if annotated.name != self.name || annotated.params != self.params {
+            return Err(format!(
+                "The signature must keep the name `{}` and the parameters ({}) as they are; only add annotations.",
+                self.name,
+                self.params.join(", ")
+            ));
+        }
+
+        let lines: Vec<&str> = content.split_inclusive('\n').collect();
+        let (start, end) = self.header;
+        let mut header = String::new();
+        for line in signature.lines() {
+            if !line.trim().is_empty() {
+                header.push_str(&self.indent);
+                header.push_str(line.trim_end());
+            }
+            header.push('\n');
+        }
+        let replaced = format!(
+            "{}{}{}",
+            lines[..start].concat(),
+            header,
+            lines[end..].concat()
+        );
+
+        let missing: Vec<&str> = imports
+            .into_iter()
+            .filter(|import| !content.lines().any(|line| line.trim() == *import))
+            .collect();
+        if missing.is_empty() {
+            return Ok(replaced);
+        }
+        let at = definition::import_line(&replaced)?;
+        let lines: Vec<&str> = replaced.split_inclusive('\n').collect();
+        let mut with_imports = lines[..at].concat();
+        if !with_imports.is_empty() && !with_imports.ends_with('\n') {
+            with_imports.push('\n');
+        }
+        for import in missing {
+            with_imports.push_str(import);
+            with_imports.push('\n');
+        }
+        with_imports.push_str(&lines[at..].concat());
+        Ok(with_imports)
+    }
+}
+
+/// Whether `line` calls a function or method `name`, other than in a `def`.
+fn calls(line: &str, name: &str) -> bool {
+    let code = line.split('#').next().unwrap_or_default();
+    if code.trim_start().starts_with("def ") || code.trim_start().starts_with("async def ") {
+        return false;
+    }
+    code.match_indices(name).any(|(at, _)| {
+        let before = code[..at].chars().next_back();
+        let after = code[at + name.len()..].trim_start();
+        !before.is_some_and(|c| c.is_alphanumeric() || c == '_') && after.starts_with('(')
+    })
+}
+
+/// Removes the indentation all non-empty `lines` share.
+fn dedent(lines: &[&str]) -> String {
+    let common = lines
+        .iter()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| line.len() - line.trim_start().len())
+        .min()
+        .unwrap_or(0);
+    lines
+        .iter()
+        .map(|line| line.get(common..).unwrap_or_default())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
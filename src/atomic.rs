@@ -0,0 +1,150 @@
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Tells apart the temporary files of concurrent writes.
+static WRITES: AtomicUsize = AtomicUsize::new(0);
+
+/// Replaces the content of the file at `path` so that it is either all old
+/// or all new, even if the process dies halfway: the content goes to a
+/// temporary file next to it, which is synced and then renamed over it.
+/// The file's permissions, and where possible its owner and extended
+/// attributes, carry over. A symlink is followed, not replaced.
+pub fn write(path: &Path, content: &[u8]) -> io::Result<()> {
+    let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let temp = temp_path(&path);
+    let result = write_temp(&path, &temp, content).and_then(|()| fs::rename(&temp, &path));
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+        return result;
+    }
+    // Make the rename itself durable.
+    #[cfg(unix)]
+    if let Some(dir) = path.parent().and_then(|dir| fs::File::open(dir).ok()) {
+        let _ = dir.sync_all();
+    }
+    Ok(())
+}
+
+fn temp_path(path: &Path) -> PathBuf {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    path.with_file_name(format!(
+        ".{}.ruffer-{}-{}.tmp",
+        name,
+        std::process::id(),
+        WRITES.fetch_add(1, Ordering::Relaxed)
+    ))
+}
+
+fn write_temp(path: &Path, temp: &Path, content: &[u8]) -> io::Result<()> {
+    let mut file = OpenOptions::new().write(true).create_new(true).open(temp)?;
+    file.write_all(content)?;
+    if let Ok(metadata) = fs::metadata(path) {
+        fs::set_permissions(temp, metadata.permissions())?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            // Only root may give a file away; anyone else keeps the new
+            // file as their own.
+            let _ = std::os::unix::fs::chown(temp, Some(metadata.uid()), Some(metadata.gid()));
+        }
+        #[cfg(any(target_os = "linux", target_os = "macos"))]
+        xattr::copy(path, temp);
+    }
+    file.sync_all()
+}
+
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+mod xattr {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::Path;
+    use std::ptr;
+
+    /// Copies the extended attributes of `from` to `to`, leaving out any
+    /// that can't be read or set (such as security labels only root may
+    /// set).
+    pub fn copy(from: &Path, to: &Path) {
+        let (Ok(from), Ok(to)) = (c_path(from), c_path(to)) else {
+            return;
+        };
+        let Some(names) = read(|buf, len| list(&from, buf, len)) else {
+            return;
+        };
+        for name in names.split(|&b| b == 0).filter(|name| !name.is_empty()) {
+            let Ok(name) = CString::new(name) else {
+                continue;
+            };
+            if let Some(value) = read(|buf, len| get(&from, &name, buf, len)) {
+                set(&to, &name, &value);
+            }
+        }
+    }
+
+    fn c_path(path: &Path) -> Result<CString, std::ffi::NulError> {
+        CString::new(path.as_os_str().as_bytes())
+    }
+
+    /// Calls `call` once for the size of the data and once to read it.
+    fn read(call: impl Fn(*mut libc::c_void, usize) -> isize) -> Option<Vec<u8>> {
+        let len = usize::try_from(call(ptr::null_mut(), 0)).ok()?;
+        let mut buf = vec![0u8; len];
+        let len = usize::try_from(call(buf.as_mut_ptr().cast(), buf.len())).ok()?;
+        buf.truncate(len);
+        Some(buf)
+    }
+
+    // The calls below only read and write within the buffers passed with
+    // their lengths, and the strings are NUL-terminated.
+
+    #[cfg(target_os = "linux")]
+    fn list(path: &CString, buf: *mut libc::c_void, len: usize) -> isize {
+        unsafe { libc::listxattr(path.as_ptr(), buf.cast(), len) }
+    }
+
+    #[cfg(target_os = "macos")]
+    fn list(path: &CString, buf: *mut libc::c_void, len: usize) -> isize {
+        unsafe { libc::listxattr(path.as_ptr(), buf.cast(), len, 0) }
+    }
+
+    #[cfg(target_os = "linux")]
+    fn get(path: &CString, name: &CString, buf: *mut libc::c_void, len: usize) -> isize {
+        unsafe { libc::getxattr(path.as_ptr(), name.as_ptr(), buf, len) }
+    }
+
+    #[cfg(target_os = "macos")]
+    fn get(path: &CString, name: &CString, buf: *mut libc::c_void, len: usize) -> isize {
+        unsafe { libc::getxattr(path.as_ptr(), name.as_ptr(), buf, len, 0, 0) }
+    }
+
+    #[cfg(target_os = "linux")]
+    fn set(path: &CString, name: &CString, value: &[u8]) {
+        unsafe {
+            libc::setxattr(
+                path.as_ptr(),
+                name.as_ptr(),
+                value.as_ptr().cast(),
+                value.len(),
+                0,
+            );
+        }
+    }
+
+    #[cfg(target_os = "macos")]
+    fn set(path: &CString, name: &CString, value: &[u8]) {
+        unsafe {
+            libc::setxattr(
+                path.as_ptr(),
+                name.as_ptr(),
+                value.as_ptr().cast(),
+                value.len(),
+                0,
+                0,
+            );
+        }
+    }
+}
@@ -0,0 +1,113 @@
+//! The audit log of `--audit-dir`: everything sent to the model and
+//! everything that came back, for review after the fact.
+//!
+//! Every run that asks the model anything gets a JSON Lines file in the
+//! audit directory, named after the run's ID like its journal, with one
+//! line per request: when it was sent, how long the answer took, the
+//! model, the messages, the answer or the error, and token counts of both.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+
+use crate::provider::CompletionRequest;
+use crate::tokens::count_tokens;
+
+#[derive(Serialize)]
+struct Message<'a> {
+    role: &'static str,
+    content: &'a str,
+}
+
+#[derive(Serialize)]
+struct Exchange<'a> {
+    /// Milliseconds since the Unix epoch.
+    sent_at: u128,
+    seconds: f64,
+    model: &'a str,
+    messages: Vec<Message<'a>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    /// Estimated the way requests are budgeted, as providers don't report
+    /// what they counted.
+    prompt_tokens: usize,
+    response_tokens: usize,
+}
+
+/// The audit log of the current run; its file is created with the first
+/// request.
+pub struct AuditLog {
+    path: PathBuf,
+    file: Mutex<Option<File>>,
+}
+
+impl AuditLog {
+    pub fn new(dir: &Path, run_id: &str) -> AuditLog {
+        AuditLog {
+            path: dir.join(format!("{}.jsonl", run_id)),
+            file: Mutex::new(None),
+        }
+    }
+
+    /// Records that `request`, sent at `sent_at`, was answered with
+    /// `answer` after `took`.
+    pub fn record(
+        &self,
+        request: &CompletionRequest,
+        sent_at: SystemTime,
+        took: Duration,
+        answer: Result<&str, String>,
+    ) -> io::Result<()> {
+        let (response, error) = match answer {
+            Ok(response) => (Some(response), None),
+            Err(error) => (None, Some(error)),
+        };
+        let exchange = Exchange {
+            sent_at: sent_at
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |elapsed| elapsed.as_millis()),
+            seconds: took.as_secs_f64(),
+            model: &request.model,
+            messages: request
+                .messages
+                .iter()
+                .map(|message| Message {
+                    role: message.role.as_str(),
+                    content: &message.content,
+                })
+                .collect(),
+            response,
+            error,
+            prompt_tokens: request
+                .messages
+                .iter()
+                .map(|message| count_tokens(&message.content))
+                .sum(),
+            response_tokens: response.map_or(0, count_tokens),
+        };
+        let mut line = serde_json::to_string(&exchange)?;
+        line.push('\n');
+
+        let mut file = self.file.lock().unwrap();
+        if file.is_none() {
+            if let Some(dir) = self.path.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            *file = Some(
+                OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&self.path)?,
+            );
+        }
+        let file = file.as_mut().expect("opened above");
+        file.write_all(line.as_bytes())?;
+        file.sync_data()
+    }
+}
@@ -0,0 +1,63 @@
+use crate::issue::{Applicability, Issue};
+
+/// Byte range of the content and the text that replaces it.
+type Replacement<'a> = (usize, usize, &'a str);
+
+/// Applies ruff's own fixes for `issues` to `content`: the safe ones, and
+/// those ruff considers unsafe for issues `allow_unsafe` accepts. Returns
+/// the fixed content and the IDs of the issues that were fixed.
+///
+/// Like ruff within one pass, a fix that overlaps one applied before it is
+/// left out; its issue is then fixed like any other.
+pub fn apply<'a>(
+    content: &str,
+    issues: impl IntoIterator<Item = &'a Issue>,
+    allow_unsafe: impl Fn(&Issue) -> bool,
+) -> (String, Vec<String>) {
+    let mut candidates: Vec<(&Issue, Vec<Replacement>)> = issues
+        .into_iter()
+        .filter_map(|issue| {
+            let fix = issue.fix.as_ref()?;
+            let applies = match fix.applicability {
+                Applicability::Safe => true,
+                Applicability::Unsafe => allow_unsafe(issue),
+                Applicability::DisplayOnly => false,
+            };
+            if !applies || fix.edits.is_empty() {
+                return None;
+            }
+            let edits = fix
+                .edits
+                .iter()
+                .map(|edit| {
+                    let start = edit.location.offset_in(content);
+                    let end = edit.end_location.offset_in(content).max(start);
+                    (start, end, edit.content.as_deref().unwrap_or_default())
+                })
+                .collect();
+            Some((issue, edits))
+        })
+        .collect();
+    candidates.sort_by_key(|(_, edits)| edits.iter().map(|&(start, _, _)| start).min());
+
+    let mut accepted: Vec<Replacement> = Vec::new();
+    let mut fixed = Vec::new();
+    for (issue, edits) in candidates {
+        let overlaps = edits.iter().any(|&(start, end, _)| {
+            accepted
+                .iter()
+                .any(|&(other_start, other_end, _)| start < other_end && other_start < end)
+        });
+        if !overlaps {
+            accepted.extend(edits);
+            fixed.push(issue.id.clone());
+        }
+    }
+
+    accepted.sort_by_key(|&(start, end, _)| (start, end));
+    let mut result = content.to_string();
+    for &(start, end, replacement) in accepted.iter().rev() {
+        result.replace_range(start..end, replacement);
+    }
+    (result, fixed)
+}
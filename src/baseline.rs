@@ -0,0 +1,158 @@
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use structopt::StructOpt;
+
+use crate::check::CheckOptions;
+use crate::console::Console;
+use crate::encoding;
+use crate::fixer::DEFAULT_CACHE_DIR;
+use crate::issue::{self, display_path};
+
+/// Where the baseline is kept unless `--baseline` says otherwise.
+pub const DEFAULT_BASELINE: &str = "ruffer-baseline.json";
+
+/// Issues known when the baseline was taken, which fixing runs given the
+/// baseline leave alone.
+#[derive(Serialize, Deserialize)]
+pub struct Baseline {
+    issues: Vec<Entry>,
+}
+
+/// An issue in the baseline. Only the ID counts; the rest is there for
+/// whoever reads the file.
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    id: String,
+    filename: String,
+    code: String,
+    message: String,
+}
+
+impl Baseline {
+    pub fn load(path: &Path) -> io::Result<Baseline> {
+        let data = fs::read_to_string(path)?;
+        serde_json::from_str(&data).map_err(|err| {
+            io::Error::other(format!("Invalid baseline {}: {}", path.display(), err))
+        })
+    }
+
+    pub fn contains(&self, id: &str) -> bool {
+        self.issues.iter().any(|entry| entry.id == id)
+    }
+
+    fn ids(&self) -> HashSet<&str> {
+        self.issues.iter().map(|entry| entry.id.as_str()).collect()
+    }
+}
+
+/// Options of `ruffer baseline create` and `ruffer baseline update`.
+#[derive(StructOpt)]
+pub struct BaselineOptions {
+    #[structopt(flatten)]
+    check: CheckOptions,
+
+    #[structopt(
+        long,
+        default_value = DEFAULT_BASELINE,
+        parse(from_os_str),
+        help = "File the baseline is written to"
+    )]
+    baseline: PathBuf,
+
+    #[structopt(
+        long,
+        help = "Take the snapshot without running `ruff format` first; fixing runs format the code, so the issues they see may then not match"
+    )]
+    no_format: bool,
+
+    #[structopt(long, default_value = DEFAULT_CACHE_DIR, help = "Directory ruff is downloaded into with --ruff-version")]
+    cache_dir: PathBuf,
+
+    #[structopt(
+        long,
+        help = "Plain, screen-reader-friendly output: one line per event, no color or progress animations"
+    )]
+    plain: bool,
+}
+
+impl BaselineOptions {
+    /// Writes a new baseline of the issues ruff reports now. An existing
+    /// baseline is only replaced by [`update`](Self::update).
+    pub async fn create(&self) -> io::Result<()> {
+        if self.baseline.exists() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!(
+                    "{} already exists; use `ruffer baseline update` to replace it",
+                    self.baseline.display()
+                ),
+            ));
+        }
+        let console = Console::new(self.plain);
+        let baseline = self.snapshot(&console).await?;
+        self.write(&baseline)?;
+        console.status(format!(
+            "Wrote {} issues to {}",
+            baseline.issues.len(),
+            self.baseline.display()
+        ));
+        Ok(())
+    }
+
+    /// Replaces the baseline with the issues ruff reports now: issues fixed
+    /// since are dropped, and new ones are added.
+    pub async fn update(&self) -> io::Result<()> {
+        let previous = Baseline::load(&self.baseline)?;
+        let console = Console::new(self.plain);
+        let baseline = self.snapshot(&console).await?;
+        let (before, after) = (previous.ids(), baseline.ids());
+        self.write(&baseline)?;
+        console.status(format!(
+            "Updated {}: {} issues, {} no longer reported, {} new",
+            self.baseline.display(),
+            baseline.issues.len(),
+            before.difference(&after).count(),
+            after.difference(&before).count()
+        ));
+        Ok(())
+    }
+
+    async fn snapshot(&self, console: &Console) -> io::Result<Baseline> {
+        self.check.locate_ruff(console, &self.cache_dir).await?;
+        let mut issues = Vec::new();
+        for (filename, mut file_issues) in self.check.collect(console, !self.no_format)? {
+            // IDs are made from the file as fixing runs read it.
+            let (content, _) = encoding::read(Path::new(&filename))?;
+            issue::assign_ids(&mut file_issues, &content);
+            issues.extend(file_issues);
+        }
+        issues.sort_by(|a, b| {
+            (&a.filename, a.location.row, a.location.column).cmp(&(
+                &b.filename,
+                b.location.row,
+                b.location.column,
+            ))
+        });
+        Ok(Baseline {
+            issues: issues
+                .into_iter()
+                .map(|issue| Entry {
+                    filename: display_path(&issue.filename),
+                    id: issue.id,
+                    code: issue.code,
+                    message: issue.message,
+                })
+                .collect(),
+        })
+    }
+
+    fn write(&self, baseline: &Baseline) -> io::Result<()> {
+        let mut data = serde_json::to_string_pretty(baseline).map_err(io::Error::other)?;
+        data.push('\n');
+        fs::write(&self.baseline, data)
+    }
+}
@@ -0,0 +1,77 @@
+//! The budget of `--max-tokens` and `--max-cost`, and the order the work
+//! goes in when there is one, so that the fixes worth the most are made
+//! before it is spent.
+
+use std::cmp::Reverse;
+
+use crate::issue::{Issue, Severity};
+use crate::security::is_security_rule;
+use crate::tokens::Price;
+
+/// How much a run may spend on requests to the model.
+pub struct Budget {
+    pub max_tokens: Option<usize>,
+    /// In US dollars, with the price of the model of the run.
+    pub max_cost: Option<(f64, Price)>,
+}
+
+impl Budget {
+    /// Whether `prompt` tokens sent and `answer` tokens received use the
+    /// budget up. Requests in flight are let finish, so a run may go over
+    /// by the last of them.
+    pub fn spent(&self, prompt: usize, answer: usize) -> bool {
+        let tokens = self.max_tokens.is_some_and(|max| prompt + answer >= max);
+        let cost = self
+            .max_cost
+            .is_some_and(|(max, price)| price.cost(prompt, answer) >= max);
+        tokens || cost
+    }
+}
+
+/// What kind of problem a rule finds, the most worth fixing first.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Category {
+    /// Bugs: pyflakes, bugbear and pylint's errors and warnings.
+    Correctness,
+    Security,
+    /// Code that could be simpler or faster.
+    Modernization,
+    /// Formatting, naming, imports, docstrings and annotations.
+    Style,
+}
+
+/// The category of rule `code`, from the linter its prefix names.
+pub fn category(code: &str) -> Category {
+    const CORRECTNESS: &[&str] = &["F", "B", "E9", "PLE", "PLW", "RUF"];
+    const MODERNIZATION: &[&str] = &["UP", "SIM", "PERF", "C4", "PIE", "RET", "PLR"];
+    let prefixed = |prefixes: &[&str]| prefixes.iter().any(|prefix| code.starts_with(prefix));
+    if is_security_rule(code) {
+        Category::Security
+    } else if prefixed(CORRECTNESS) {
+        Category::Correctness
+    } else if prefixed(MODERNIZATION) {
+        Category::Modernization
+    } else {
+        Category::Style
+    }
+}
+
+/// Where an issue goes in the work of a budgeted run: the smallest first.
+/// Issues that break the program come before those that don't, bugs
+/// before style, and of those alike the ones cheapest to ask about.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Priority {
+    severity: Reverse<Severity>,
+    category: Category,
+    prompt_tokens: usize,
+}
+
+/// The priority of `issue` of `severity` in a file of `prompt_tokens`
+/// tokens, about as many as asking about it sends.
+pub fn priority(issue: &Issue, severity: Severity, prompt_tokens: usize) -> Priority {
+    Priority {
+        severity: Reverse(severity),
+        category: category(&issue.code),
+        prompt_tokens,
+    }
+}
@@ -0,0 +1,233 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use rusqlite::{params, Connection, OptionalExtension};
+use sha2::{Digest, Sha256};
+
+use crate::issue::Issue;
+
+const DATABASE_FILE: &str = "fixes.sqlite3";
+
+/// How much the cache may hold before old entries are dropped.
+#[derive(Clone, Copy)]
+pub struct Eviction {
+    /// Entries created longer ago than this are removed.
+    pub ttl: Duration,
+    /// Once the stored fixes exceed this many bytes, the least recently
+    /// used ones are removed.
+    pub max_bytes: u64,
+}
+
+/// Fixes from previous runs, so re-running on a mostly unchanged tree only
+/// pays for the issues whose file content actually changed.
+///
+/// The cache is only ever an optimization: when the database fails, the
+/// failure is logged and the fix is asked for as if it weren't cached.
+pub struct ResponseCache {
+    connection: Mutex<Connection>,
+    eviction: Eviction,
+}
+
+impl ResponseCache {
+    /// Opens (creating if needed) the cache in `dir` and evicts whatever
+    /// the limits no longer allow, as it does again with every fix stored,
+    /// for processes that keep it open, such as `serve` and `watch`.
+    pub fn open(dir: &Path, eviction: Eviction) -> io::Result<Self> {
+        fs::create_dir_all(dir)?;
+        let connection = Connection::open(dir.join(DATABASE_FILE)).map_err(io::Error::other)?;
+        connection
+            .execute_batch(
+                "CREATE TABLE IF NOT EXISTS fixes (
+                    key TEXT PRIMARY KEY,
+                    fixed_content TEXT NOT NULL,
+                    size INTEGER NOT NULL,
+                    created INTEGER NOT NULL,
+                    last_used INTEGER NOT NULL
+                );
+                CREATE INDEX IF NOT EXISTS fixes_last_used ON fixes (last_used);",
+            )
+            .map_err(io::Error::other)?;
+        let cache = ResponseCache {
+            connection: Mutex::new(connection),
+            eviction,
+        };
+        cache.evict().map_err(io::Error::other)?;
+        Ok(cache)
+    }
+
+    /// Removes every cached fix in `dir`.
+    pub fn clear(dir: &Path) -> io::Result<()> {
+        match fs::remove_file(dir.join(DATABASE_FILE)) {
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+            result => result,
+        }
+    }
+
+    /// Cache key for fixing `issue` in a file with `file_content` using
+    /// `model`, asked with `prompts`.
+    pub fn key(model: &str, prompts: &[&str], file_content: &str, issue: &Issue) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(model.as_bytes());
+        hasher.update([0]);
+        for prompt in prompts {
+            hasher.update(Sha256::digest(prompt.as_bytes()));
+        }
+        hasher.update(Sha256::digest(file_content.as_bytes()));
+        hasher.update(issue.code.as_bytes());
+        hasher.update([0]);
+        hasher.update(format!("{}:{}", issue.location.row, issue.location.column).as_bytes());
+        hex(&hasher.finalize())
+    }
+
+    pub fn get(&self, key: &str) -> Option<String> {
+        let connection = self.connection.lock().unwrap();
+        let fixed_content = connection
+            .query_row(
+                "SELECT fixed_content FROM fixes WHERE key = ?1",
+                params![key],
+                |row| row.get(0),
+            )
+            .optional()
+            .unwrap_or_else(|err| {
+                tracing::warn!("Error reading a cached fix: {}", err);
+                None
+            });
+        if fixed_content.is_some() {
+            if let Err(err) = connection.execute(
+                "UPDATE fixes SET last_used = ?1 WHERE key = ?2",
+                params![now(), key],
+            ) {
+                tracing::warn!("Error marking a cached fix as used: {}", err);
+            }
+        }
+        fixed_content
+    }
+
+    pub fn insert(&self, key: String, fixed_content: String) {
+        let now = now();
+        let inserted = self.connection.lock().unwrap().execute(
+            "INSERT OR REPLACE INTO fixes (key, fixed_content, size, created, last_used)
+             VALUES (?1, ?2, ?3, ?4, ?4)",
+            params![key, fixed_content, fixed_content.len() as i64, now],
+        );
+        if let Err(err) = inserted.and_then(|_| self.evict()) {
+            tracing::warn!("Error caching a fix: {}", err);
+        }
+    }
+
+    fn evict(&self) -> rusqlite::Result<()> {
+        let connection = self.connection.lock().unwrap();
+        let expired = now() - self.eviction.ttl.as_secs() as i64;
+        let removed =
+            connection.execute("DELETE FROM fixes WHERE created < ?1", params![expired])?;
+
+        let total: i64 =
+            connection.query_row("SELECT COALESCE(SUM(size), 0) FROM fixes", [], |row| {
+                row.get(0)
+            })?;
+        let excess = total - self.eviction.max_bytes as i64;
+        let mut dropped = 0;
+        if excess > 0 {
+            // Drop entries, least recently used first, until the freed
+            // bytes cover the excess.
+            dropped = connection.execute(
+                "DELETE FROM fixes WHERE key IN (
+                    SELECT key FROM (
+                        SELECT key, size, SUM(size) OVER (ORDER BY last_used, key) AS running
+                        FROM fixes
+                    ) WHERE running - size < ?1
+                )",
+                params![excess],
+            )?;
+        }
+        if removed + dropped > 0 {
+            tracing::debug!(
+                expired = removed,
+                over_size = dropped,
+                "Evicted cached fixes"
+            );
+        }
+        Ok(())
+    }
+}
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs() as i64)
+}
+
+pub fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn open(name: &str, eviction: Eviction) -> ResponseCache {
+        let dir =
+            std::env::temp_dir().join(format!("ruffer-cache-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        ResponseCache::open(&dir, eviction).unwrap()
+    }
+
+    /// Makes the fix of `key` as old as `seconds` ago in `column`.
+    fn age(cache: &ResponseCache, key: &str, column: &str, seconds: i64) {
+        cache
+            .connection
+            .lock()
+            .unwrap()
+            .execute(
+                &format!("UPDATE fixes SET {} = ?1 WHERE key = ?2", column),
+                params![now() - seconds, key],
+            )
+            .unwrap();
+    }
+
+    #[test]
+    fn drops_fixes_older_than_the_ttl() {
+        let cache = open(
+            "ttl",
+            Eviction {
+                ttl: Duration::from_secs(60 * 60),
+                max_bytes: 1024,
+            },
+        );
+        cache.insert("old".to_string(), "print()\n".to_string());
+        cache.insert("new".to_string(), "print()\n".to_string());
+        age(&cache, "old", "created", 2 * 60 * 60);
+        age(&cache, "new", "created", 30 * 60);
+
+        cache.evict().unwrap();
+
+        assert_eq!(cache.get("old"), None);
+        assert_eq!(cache.get("new").as_deref(), Some("print()\n"));
+    }
+
+    #[test]
+    fn drops_the_least_recently_used_fixes_beyond_the_size() {
+        let cache = open(
+            "size",
+            Eviction {
+                ttl: Duration::from_secs(60 * 60),
+                max_bytes: 10,
+            },
+        );
+        cache.insert("a".to_string(), "aaaaa".to_string());
+        cache.insert("b".to_string(), "bbbbb".to_string());
+        age(&cache, "a", "last_used", 60);
+        age(&cache, "b", "last_used", 120);
+        assert!(cache.get("a").is_some());
+
+        // Over the size by five bytes: `b`, used longest ago, goes.
+        cache.insert("c".to_string(), "ccccc".to_string());
+
+        assert_eq!(cache.get("b"), None);
+        assert_eq!(cache.get("a").as_deref(), Some("aaaaa"));
+        assert_eq!(cache.get("c").as_deref(), Some("ccccc"));
+    }
+}
@@ -0,0 +1,899 @@
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsStr;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::OnceLock;
+
+use structopt::StructOpt;
+
+use crate::clippy;
+use crate::console::Console;
+use crate::git::{self, Staged};
+use crate::glob;
+use crate::ignore::{self, IgnoreFile, IGNORE_FILE};
+use crate::issue::Issue;
+use crate::language::Language;
+use crate::linter::{self, LintSource, Linter};
+use crate::package::{self, Package};
+use crate::ruff;
+use crate::sarif;
+
+/// Which ruff checks which project, and with what rules; shared by the
+/// commands that fix issues and those that only look at them.
+#[derive(StructOpt)]
+pub struct CheckOptions {
+    #[structopt(
+        help = "Path to ruff tool; found in the project's virtualenv or on PATH when omitted"
+    )]
+    ruff_path: Option<String>,
+
+    #[structopt(
+        help = "Files, folders or glob patterns such as 'src/**/*.py' to run Ruff check on [default: .]"
+    )]
+    paths: Vec<String>,
+
+    #[structopt(
+        long,
+        help = "Download this release of ruff into the cache directory and use it, unless a ruff path is given"
+    )]
+    ruff_version: Option<String>,
+
+    /// The ruff that is run, once located.
+    #[structopt(skip)]
+    ruff: OnceLock<PathBuf>,
+
+    #[structopt(
+        long,
+        default_value = "python",
+        possible_values = &["python", "rust"],
+        help = "Language of the code to fix: python runs ruff, rust runs `cargo clippy` instead"
+    )]
+    language: Language,
+
+    /// Cargo and rustfmt, once located, with `--language rust`.
+    #[structopt(skip)]
+    rust_tools: OnceLock<(PathBuf, PathBuf)>,
+
+    /// The packages of the project, once found.
+    #[structopt(skip)]
+    packages: OnceLock<Vec<Package>>,
+
+    #[structopt(
+        long,
+        parse(from_os_str),
+        help = "Take the issues from this file of `ruff check --output-format json` output (`-` for stdin) instead of running Ruff"
+    )]
+    pub issues: Option<PathBuf>,
+
+    #[structopt(
+        long,
+        parse(from_os_str),
+        conflicts_with = "issues",
+        help = "Take the issues from this SARIF file (`-` for stdin), as semgrep or CodeQL write them, instead of running Ruff"
+    )]
+    pub from_sarif: Option<PathBuf>,
+
+    #[structopt(
+        long,
+        number_of_values = 1,
+        help = "Only work on issues of these rules (passed to `ruff check --select`); may be repeated"
+    )]
+    select: Vec<String>,
+
+    #[structopt(
+        long,
+        number_of_values = 1,
+        help = "Leave issues of these rules alone (passed to `ruff check --ignore`); may be repeated"
+    )]
+    ignore: Vec<String>,
+
+    #[structopt(
+        long,
+        number_of_values = 1,
+        help = "Ruff configuration file or inline setting, passed to ruff as `--config`; may be repeated"
+    )]
+    config: Vec<String>,
+
+    #[structopt(
+        long = "ruff-arg",
+        number_of_values = 1,
+        allow_hyphen_values = true,
+        help = "Extra argument passed to `ruff check`; may be repeated"
+    )]
+    ruff_args: Vec<String>,
+
+    #[structopt(
+        long = "linter",
+        number_of_values = 1,
+        help = "Also work on the issues this linter finds: pylint, flake8, mypy, bandit, eslint or one declared in [tool.ruffer.linter.<name>], found like ruff; may be repeated"
+    )]
+    linter_names: Vec<String>,
+
+    /// The linters of `--linter`, once located.
+    #[structopt(skip)]
+    linters: OnceLock<Vec<Linter>>,
+
+    #[structopt(
+        long,
+        help = "Also work on files git ignores, such as generated code, when ruff reports issues in them"
+    )]
+    no_respect_gitignore: bool,
+
+    #[structopt(
+        long,
+        help = "Also work on files in virtualenvs, site-packages, node_modules and .tox, which are skipped by default"
+    )]
+    include_vendored: bool,
+
+    #[structopt(
+        long,
+        default_value = "1000000",
+        help = "Skip files larger than this many bytes, such as generated code, instead of working on them"
+    )]
+    max_file_bytes: u64,
+
+    #[structopt(
+        long,
+        help = "Only check the files staged in git, among the paths given, and stage the fixes made to them"
+    )]
+    pub staged: bool,
+
+    #[structopt(
+        long,
+        conflicts_with = "staged",
+        help = "Only check the files changed since the branch left this git ref, such as origin/main, among the paths given"
+    )]
+    diff_base: Option<String>,
+
+    #[structopt(
+        long,
+        help = "Only work on issues in lines changed since --diff-base, leaving the code around them alone"
+    )]
+    changed_lines_only: bool,
+
+    /// The files changed since `--diff-base`, once asked for.
+    #[structopt(skip)]
+    changed_files: OnceLock<Vec<PathBuf>>,
+
+    /// The files staged, with `--staged`, once read.
+    #[structopt(skip)]
+    staged_files: OnceLock<Staged>,
+}
+
+impl CheckOptions {
+    /// The files and folders to check, with glob patterns expanded; `.`
+    /// by default. With `--staged` or `--diff-base`, the Python files
+    /// staged or changed among them.
+    pub fn paths(&self) -> io::Result<Vec<String>> {
+        let mut paths = Vec::new();
+        for target in self.targets().1 {
+            if glob::is_pattern(target) {
+                paths.extend(glob::expand(target)?);
+            } else {
+                paths.push(target.to_string());
+            }
+        }
+        let files = if self.staged {
+            &self.staged_files()?.files
+        } else if let Some(base) = &self.diff_base {
+            self.changed_files(base)?
+        } else {
+            return Ok(paths);
+        };
+        let targets: Vec<PathBuf> = paths.iter().filter_map(|path| canonical(path)).collect();
+        Ok(files
+            .iter()
+            .filter(|file| {
+                self.languages()
+                    .iter()
+                    .any(|language| language.is_source(file))
+                    && targets.iter().any(|target| file.starts_with(target))
+            })
+            .map(|file| file.display().to_string())
+            .collect())
+    }
+
+    /// The files changed since `base`, read the first time they are asked
+    /// for.
+    fn changed_files(&self, base: &str) -> io::Result<&Vec<PathBuf>> {
+        if let Some(changed) = self.changed_files.get() {
+            return Ok(changed);
+        }
+        let changed = git::changed_since(&self.project_dir(), base)?;
+        Ok(self.changed_files.get_or_init(|| changed))
+    }
+
+    /// The files staged in git, read the first time they are asked for.
+    pub fn staged_files(&self) -> io::Result<&Staged> {
+        if let Some(staged) = self.staged_files.get() {
+            return Ok(staged);
+        }
+        let staged = Staged::read(&self.project_dir())?;
+        Ok(self.staged_files.get_or_init(|| staged))
+    }
+
+    /// Checks only `paths` instead of the paths given, keeping the ruff
+    /// path given.
+    pub fn limit_to(&mut self, paths: Vec<String>) {
+        self.ruff_path = self.targets().0.map(str::to_string);
+        self.paths = paths;
+    }
+
+    /// The deepest directory holding everything to check: the root
+    /// folder, or the directory of a single file.
+    pub fn project_dir(&self) -> PathBuf {
+        let mut common: Option<PathBuf> = None;
+        for target in self.targets().1 {
+            let path = Path::new(target);
+            let dir = if glob::is_pattern(target) {
+                glob::base(target)
+            } else if path.is_dir() {
+                path.to_path_buf()
+            } else {
+                path.parent().map(Path::to_path_buf).unwrap_or_default()
+            };
+            // Relative and absolute targets can only be compared absolute.
+            let dir = if dir.as_os_str().is_empty() {
+                PathBuf::from(".")
+            } else {
+                dir
+            };
+            let dir = dir.canonicalize().unwrap_or(dir);
+            common = Some(match common {
+                None => dir,
+                Some(mut common) => {
+                    while !dir.starts_with(&common) && common.pop() {}
+                    common
+                }
+            });
+        }
+        common
+            .filter(|common| !common.as_os_str().is_empty())
+            .unwrap_or_else(|| PathBuf::from("."))
+    }
+
+    /// Finds the ruff to run, downloading a pinned version into
+    /// `cache_dir`; see [`ruff::locate`]. The linters of `--linter` are
+    /// found as well.
+    pub async fn locate_ruff(&self, console: &Console, cache_dir: &Path) -> io::Result<()> {
+        if self.language == Language::Rust {
+            // Clippy's findings are the only ones collected and verified.
+            if !self.linter_names.is_empty() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "--linter can't be used with --language rust, which only fixes what cargo clippy reports",
+                ));
+            }
+            let find = |name| {
+                ruff::find_in_environment(&self.project_dir(), name).ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::NotFound,
+                        format!("{} not found on PATH; --language rust needs it", name),
+                    )
+                })
+            };
+            let cargo = find("cargo")?;
+            let rustfmt = find("rustfmt")?;
+            console.status(format!("Using {}", cargo.display()));
+            let _ = self.rust_tools.set((cargo, rustfmt));
+            return Ok(());
+        }
+        let ruff = match self.targets().0 {
+            // Ruff runs in the directory of each package, where a relative
+            // path would lead elsewhere.
+            Some(ruff_path) if Path::new(ruff_path).components().count() > 1 => {
+                fs::canonicalize(ruff_path).unwrap_or_else(|_| PathBuf::from(ruff_path))
+            }
+            Some(ruff_path) => PathBuf::from(ruff_path),
+            None => {
+                let ruff =
+                    ruff::locate(&self.project_dir(), self.ruff_version.as_deref(), cache_dir)
+                        .await?;
+                console.status(format!("Using {}", ruff.display()));
+                ruff
+            }
+        };
+        let _ = self.ruff.set(ruff);
+
+        let mut configured = if self.linter_names.is_empty() {
+            Vec::new()
+        } else {
+            linter::configured(&self.project_dir())?
+        };
+        let mut linters = Vec::new();
+        for name in &self.linter_names {
+            let source: Box<dyn LintSource> = match configured
+                .iter()
+                .position(|linter| linter.name() == name)
+            {
+                Some(index) => Box::new(configured.swap_remove(index)),
+                None => linter::builtin(name).ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!(
+                            "Unknown linter {}; expected one of {}, or one declared in [tool.ruffer.linter.{}]",
+                            name,
+                            linter::BUILTIN.join(", "),
+                            name
+                        ),
+                    )
+                })?,
+            };
+            let linter = Linter::locate(&self.project_dir(), source)?;
+            console.status(format!("Using {}", linter.path().display()));
+            linters.push(linter);
+        }
+        let _ = self.linters.set(linters);
+        Ok(())
+    }
+
+    /// The linters of `--linter`, besides ruff.
+    pub fn linters(&self) -> &[Linter] {
+        self.linters.get().map_or(&[], Vec::as_slice)
+    }
+
+    /// The language of the code to fix.
+    pub fn language(&self) -> Language {
+        self.language
+    }
+
+    /// The languages of the code to fix: that of `--language`, and those
+    /// the linters of `--linter` check once located.
+    pub fn languages(&self) -> Vec<Language> {
+        let mut languages = vec![self.language];
+        for linter in self.linters() {
+            for &language in linter.languages() {
+                if !languages.contains(&language) {
+                    languages.push(language);
+                }
+            }
+        }
+        languages
+    }
+
+    pub fn ruff(&self) -> &Path {
+        self.ruff
+            .get()
+            .expect("ruff is located at the start of the run")
+    }
+
+    /// The issues to work on, by file: those of the report given with
+    /// `--issues`, or else those ruff finds in the paths to check after
+    /// formatting them, if `format` says so. Files the ruff configuration
+    /// excludes are left out. Empty if there is nothing to do.
+    pub fn collect(
+        &self,
+        console: &Console,
+        format: bool,
+    ) -> io::Result<HashMap<String, Vec<Issue>>> {
+        if self.changed_lines_only && self.diff_base.is_none() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "--changed-lines-only needs --diff-base",
+            ));
+        }
+        let paths = self.paths()?;
+        if let (Some(base), true) = (&self.diff_base, paths.is_empty()) {
+            console.status(format!("No Python files changed since {}", base));
+            return Ok(HashMap::new());
+        }
+        if self.staged {
+            if paths.is_empty() {
+                console.status("No Python files staged");
+                return Ok(HashMap::new());
+            }
+            for file in &self.staged_files()?.partly {
+                if paths.iter().any(|path| Path::new(path) == file) {
+                    console.status(format!(
+                        "{} has unstaged changes too; fixes to it won't be staged",
+                        file.display()
+                    ));
+                }
+            }
+        }
+        let packages = package::discover(&self.project_dir())?;
+        let _ = self.packages.set(packages);
+        let runs = self.runs(&paths);
+        if runs.iter().filter(|run| run.package.is_some()).count() > 1 {
+            console.status(format!("Checking {} packages separately", runs.len()));
+        }
+        let issues = if let Some(source) = &self.issues {
+            // Formatting now would move the code away from the locations
+            // in the report.
+            read_issues(source)?
+        } else if let Some(source) = &self.from_sarif {
+            sarif::read(source)?
+        } else if let Some((cargo, _)) = self.rust_tools.get() {
+            let dir = self.project_dir();
+            console.status(format!("Running cargo clippy on {}...", dir.display()));
+            clippy::check(cargo, &dir)?
+        } else {
+            let mut issues = Vec::new();
+            for run in &runs {
+                let python = Run {
+                    package: run.package,
+                    paths: sources_of(&run.paths, &[Language::Python]),
+                    nested: run.nested.clone(),
+                };
+                if !python.paths.is_empty() {
+                    let targets = python.paths.join(" ");
+                    if format {
+                        console.status(format!("Formatting code in {}...", targets));
+                        self.run_ruff_format(&python)?;
+                    }
+
+                    console.status(format!("Running Ruff check on {}...", targets));
+                    issues.extend(self.run_ruff_check(&python)?);
+                }
+                for linter in self.linters() {
+                    let paths = sources_of(&run.paths, linter.languages());
+                    if paths.is_empty() {
+                        continue;
+                    }
+                    console.status(format!(
+                        "Running {} on {}...",
+                        linter.name(),
+                        paths.join(" ")
+                    ));
+                    let dir = run.package.map(|package| package.root.as_path());
+                    issues.extend(linter.check(dir, &paths)?);
+                }
+            }
+            linter::drop_duplicates(&mut issues);
+            issues
+        };
+        if issues.is_empty() {
+            console.status("All good");
+            return Ok(HashMap::new());
+        }
+
+        // Group issues by file
+        let mut issues_by_file = group_issues_by_file(issues);
+
+        // Reports from elsewhere, or ruff itself when given a file
+        // explicitly, can list files the project excludes; those are never
+        // touched. Ruff only knows Python files, and clippy only reports
+        // those of the workspace.
+        if self.language == Language::Python {
+            match self.included_files(&runs) {
+                Ok(included) => issues_by_file.retain(|filename, _| {
+                    if Language::of(filename) != Language::Python {
+                        return true;
+                    }
+                    let known = canonical(filename).is_some_and(|path| included.contains(&path));
+                    if !known {
+                        console.status(format!(
+                            "Skipping {}: excluded by the ruff configuration",
+                            filename
+                        ));
+                    }
+                    known
+                }),
+                Err(err) => console.error(format!(
+                    "Error listing the files ruff checks, not filtering excluded files: {}",
+                    err
+                )),
+            }
+        }
+
+        // What the project's .rufferignore lists is never read or sent,
+        // whatever ruff checks; if it can't be read, nothing is.
+        let dir = self.project_dir();
+        let ignore = IgnoreFile::load(&dir).map_err(|err| {
+            io::Error::other(format!(
+                "Error reading {}: {}",
+                dir.join(IGNORE_FILE).display(),
+                err
+            ))
+        })?;
+        if let Some(ignore) = ignore {
+            issues_by_file.retain(|filename, _| {
+                let ignored = ignore.is_ignored(Path::new(filename));
+                if ignored {
+                    console.status(format!("Skipping {}: listed in {}", filename, IGNORE_FILE));
+                }
+                !ignored
+            });
+        }
+
+        if !self.include_vendored {
+            issues_by_file.retain(
+                |filename, _| match ignore::vendored_dir(Path::new(filename)) {
+                    Some(dir) => {
+                        console.status(format!("Skipping {}: inside {}", filename, dir));
+                        false
+                    }
+                    None => true,
+                },
+            );
+        }
+
+        issues_by_file.retain(|filename, _| match fs::metadata(filename) {
+            Ok(metadata) if metadata.len() > self.max_file_bytes => {
+                console.status(format!(
+                    "Skipping {}: {} bytes is more than --max-file-bytes ({})",
+                    filename,
+                    metadata.len(),
+                    self.max_file_bytes
+                ));
+                false
+            }
+            _ => true,
+        });
+
+        // Ruff leaves out git-ignored files when it looks for files itself,
+        // but not those it's given or that a report lists.
+        if !self.no_respect_gitignore {
+            match ignore::git_ignored(&dir, issues_by_file.keys().map(String::as_str)) {
+                Ok(ignored) => issues_by_file.retain(|filename, _| {
+                    let skipped = canonical(filename).is_some_and(|path| ignored.contains(&path));
+                    if skipped {
+                        console.status(format!("Skipping {}: ignored by git", filename));
+                    }
+                    !skipped
+                }),
+                Err(err) => console.error(format!(
+                    "Error asking git which files it ignores, not skipping any: {}",
+                    err
+                )),
+            }
+        }
+
+        if let (Some(base), true) = (&self.diff_base, self.changed_lines_only) {
+            let changed = git::changed_lines(&dir, base)?;
+            issues_by_file.retain(|filename, issues| {
+                let lines = canonical(filename)
+                    .and_then(|path| changed.get(&path))
+                    .map_or(&[][..], Vec::as_slice);
+                let before = issues.len();
+                issues.retain(|issue| {
+                    lines
+                        .iter()
+                        .any(|&(first, last)| (first..=last).contains(&issue.location.row))
+                });
+                if issues.len() < before {
+                    console.status(format!(
+                        "Leaving {} issues in {} alone: not in lines changed since {}",
+                        before - issues.len(),
+                        filename,
+                        base
+                    ));
+                }
+                !issues.is_empty()
+            });
+        }
+        Ok(issues_by_file)
+    }
+
+    pub fn config_args(&self) -> Vec<String> {
+        self.config
+            .iter()
+            .flat_map(|config| {
+                // Configuration files are found wherever ruff runs.
+                let config = match fs::canonicalize(config) {
+                    Ok(path) if Path::new(config).is_file() => path.display().to_string(),
+                    _ => config.clone(),
+                };
+                ["--config".to_string(), config]
+            })
+            .collect()
+    }
+
+    /// The innermost package holding `filename`, if it is in one.
+    pub fn package(&self, filename: &str) -> Option<&Package> {
+        package::containing(self.packages.get()?, Path::new(filename))
+    }
+
+    /// Arguments every `ruff check` of files in `package` gets, so that
+    /// verification sees the same rules as the check that found the issues.
+    pub fn check_args(&self, package: Option<&Package>) -> Vec<String> {
+        let overrides = package.map(|package| &package.overrides);
+        let select = overrides.and_then(|overrides| overrides.select.as_ref());
+        let ignore = overrides.and_then(|overrides| overrides.ignore.as_ref());
+        let mut args = self.config_args();
+        for (flag, rules) in [
+            ("--select", select.unwrap_or(&self.select)),
+            ("--ignore", ignore.unwrap_or(&self.ignore)),
+        ] {
+            if !rules.is_empty() {
+                args.push(flag.to_string());
+                args.push(rules.join(","));
+            }
+        }
+        args.extend(self.ruff_args.iter().cloned());
+        args
+    }
+
+    /// The paths to check split by the package they are in, so that ruff
+    /// checks each package from its own directory. Paths outside every
+    /// package are checked from the working directory, as given.
+    fn runs<'a>(&'a self, paths: &[String]) -> Vec<Run<'a>> {
+        let packages = self.packages.get().map_or(&[][..], Vec::as_slice);
+        let mut runs: Vec<Run> = Vec::new();
+        let mut add = |package: Option<&'a Package>, path: String| match runs
+            .iter_mut()
+            .find(|run| run.package.map(|p| &p.root) == package.map(|p| &p.root))
+        {
+            Some(run) if !run.paths.contains(&path) => run.paths.push(path),
+            Some(_) => {}
+            None => runs.push(Run {
+                package,
+                paths: vec![path],
+                nested: Vec::new(),
+            }),
+        };
+        for path in paths {
+            let Some(absolute) = canonical(path) else {
+                add(None, path.clone());
+                continue;
+            };
+            let package = package::containing(packages, &absolute);
+            match package {
+                Some(package) => add(Some(package), absolute.display().to_string()),
+                None => add(None, path.clone()),
+            }
+            // Packages inside a folder to check get their own run.
+            for inner in packages.iter().filter(|inner| {
+                package.is_none_or(|package| inner.root != package.root)
+                    && inner.root.starts_with(&absolute)
+            }) {
+                add(Some(inner), inner.root.display().to_string());
+            }
+        }
+        for run in &mut runs {
+            let root = run.package.map(|package| package.root.as_path());
+            run.nested = packages
+                .iter()
+                .filter(|inner| {
+                    root.is_none_or(|root| inner.root != root && inner.root.starts_with(root))
+                })
+                .map(|inner| inner.root.as_path())
+                .collect();
+        }
+        runs
+    }
+
+    /// Ruff with `args`, to run on the paths of `run`.
+    fn ruff_for<S: AsRef<OsStr>>(&self, run: &Run, args: impl IntoIterator<Item = S>) -> Command {
+        let mut command = Command::new(self.ruff());
+        command.args(args);
+        if let Some(package) = run.package {
+            command.current_dir(&package.root);
+        }
+        command.args(&run.paths);
+        for nested in &run.nested {
+            command.arg("--extend-exclude").arg(nested);
+        }
+        command
+    }
+
+    fn run_ruff_format(&self, run: &Run) -> io::Result<()> {
+        tracing::debug!(paths = ?run.paths, "Running {} format", self.ruff().display());
+        let mut args = vec!["format".to_string()];
+        args.extend(self.config_args());
+        let output = self.ruff_for(run, args).output()?;
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            let stdout = String::from_utf8_lossy(&output.stdout);
+            return Err(io::Error::other(format!(
+                "Ruff format failed: {}, {}",
+                stderr, stdout
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// `content` formatted by `ruff format` as if it were the file
+    /// `filename`, and with its imports sorted by ruff's isort rules if
+    /// `sort_imports` says so; Rust is formatted by rustfmt, which sorts
+    /// imports anyway, and other languages are left as they are.
+    pub fn format_content(
+        &self,
+        filename: &str,
+        content: &str,
+        sort_imports: bool,
+    ) -> io::Result<String> {
+        match (Language::of(filename), self.rust_tools.get()) {
+            (Language::Python, _) => {}
+            (Language::Rust, Some((_, rustfmt))) => return clippy::format(rustfmt, content),
+            _ => return Ok(content.to_string()),
+        }
+        let package = self.package(filename);
+        // Ruff runs in the package, where a relative name means another file.
+        let filename = match package.and_then(|_| fs::canonicalize(filename).ok()) {
+            Some(path) => path.display().to_string(),
+            None => filename.to_string(),
+        };
+        let ruff_on_stdin = |args: &[&str], content: &str| -> io::Result<String> {
+            let mut command = Command::new(self.ruff());
+            if let Some(package) = package {
+                command.current_dir(&package.root);
+            }
+            let mut child = command
+                .args(args)
+                .args(self.config_args())
+                .arg("--stdin-filename")
+                .arg(&filename)
+                .arg("-")
+                .stdin(Stdio::piped())
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped())
+                .spawn()?;
+            child
+                .stdin
+                .take()
+                .expect("stdin is piped")
+                .write_all(content.as_bytes())?;
+            let output = child.wait_with_output()?;
+            if !output.status.success() {
+                return Err(io::Error::other(format!(
+                    "Ruff {} failed: {}",
+                    args[0],
+                    String::from_utf8_lossy(&output.stderr)
+                )));
+            }
+            String::from_utf8(output.stdout).map_err(io::Error::other)
+        };
+        let content = if sort_imports {
+            ruff_on_stdin(&["check", "--fix", "--exit-zero", "--select", "I"], content)?
+        } else {
+            content.to_string()
+        };
+        ruff_on_stdin(&["format"], &content)
+    }
+
+    /// The files ruff checks in the paths of `runs`, i.e. those its
+    /// `exclude` and `extend-exclude` settings leave in.
+    fn included_files(&self, runs: &[Run]) -> io::Result<HashSet<PathBuf>> {
+        let mut included = HashSet::new();
+        for run in runs {
+            tracing::debug!(paths = ?run.paths, "Listing the files ruff checks");
+            let output = self
+                .ruff_for(run, ["check", "--show-files"])
+                .args(self.check_args(run.package))
+                .output()?;
+            if !output.status.success() {
+                return Err(io::Error::other(format!(
+                    "Ruff failed with {}: {}",
+                    output.status,
+                    String::from_utf8_lossy(&output.stderr)
+                )));
+            }
+            // Relative to where ruff ran.
+            let dir = run.package.map_or(Path::new(""), |package| &package.root);
+            included.extend(
+                String::from_utf8_lossy(&output.stdout)
+                    .lines()
+                    .filter_map(|line| canonical(&dir.join(line.trim()).to_string_lossy())),
+            );
+        }
+        Ok(included)
+    }
+
+    fn run_ruff_check(&self, run: &Run) -> io::Result<Vec<Issue>> {
+        let args = self.check_args(run.package);
+        tracing::debug!(paths = ?run.paths, ?args, "Running {} check", self.ruff().display());
+        let output = self
+            .ruff_for(run, ["check", "--no-fix"])
+            .args(["--output-format", "json"])
+            .args(args)
+            .output()
+            .map_err(|err| {
+                io::Error::new(
+                    err.kind(),
+                    format!("Failed to run {}: {}", self.ruff().display(), err),
+                )
+            })?;
+
+        match output.status.code() {
+            // No issues found
+            Some(0) => Ok(Vec::new()),
+            Some(1) => {
+                let data = String::from_utf8_lossy(&output.stdout);
+                serde_json::from_str(&data).map_err(|err| {
+                    tracing::debug!(output = %data, "Unexpected output of ruff check");
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!(
+                            "Ruff check didn't report its issues as JSON, is {} a supported version of ruff? {}",
+                            self.ruff().display(),
+                            err
+                        ),
+                    )
+                })
+            }
+            _ => {
+                let stderr = String::from_utf8_lossy(&output.stderr);
+                let stdout = String::from_utf8_lossy(&output.stdout);
+                tracing::error!(%stderr, %stdout, "Ruff check failed with {}", output.status);
+                Err(io::Error::other(format!(
+                    "Ruff check failed with {}: {}",
+                    output.status,
+                    stderr.trim()
+                )))
+            }
+        }
+    }
+
+    /// The ruff path and the paths to check as given. A first argument
+    /// that is a directory, a Python file or a glob pattern is the first
+    /// path to check, and ruff is looked for.
+    fn targets(&self) -> (Option<&str>, Vec<&str>) {
+        let mut targets: Vec<&str> = self.paths.iter().map(String::as_str).collect();
+        let ruff_path = match self.ruff_path.as_deref() {
+            Some(first) if is_target(first) => {
+                targets.insert(0, first);
+                None
+            }
+            ruff_path => ruff_path,
+        };
+        if targets.is_empty() {
+            targets.push(".");
+        }
+        (ruff_path, targets)
+    }
+}
+
+/// One invocation of ruff, on the paths to check in a package.
+struct Run<'a> {
+    /// The package the paths are in; ruff runs in its directory.
+    package: Option<&'a Package>,
+    paths: Vec<String>,
+    /// Packages inside the paths, checked by runs of their own.
+    nested: Vec<&'a Path>,
+}
+
+/// Those of `paths` a tool checking `languages` is given: directories, and
+/// files of those languages.
+fn sources_of(paths: &[String], languages: &[Language]) -> Vec<String> {
+    paths
+        .iter()
+        .filter(|path| {
+            let path = Path::new(path);
+            !path.is_file() || languages.iter().any(|language| language.is_source(path))
+        })
+        .cloned()
+        .collect()
+}
+
+fn group_issues_by_file(issues: Vec<Issue>) -> HashMap<String, Vec<Issue>> {
+    let mut issues_by_file = HashMap::new();
+    for issue in issues {
+        issues_by_file
+            .entry(issue.filename.clone())
+            .or_insert_with(Vec::new)
+            .push(issue);
+    }
+    issues_by_file
+}
+
+/// Whether the argument `arg` names something to check rather than ruff.
+fn is_target(arg: &str) -> bool {
+    let path = Path::new(arg);
+    glob::is_pattern(arg)
+        || path.is_dir()
+        || Language::ALL
+            .iter()
+            .any(|language| language.is_source(path))
+}
+
+fn canonical(filename: &str) -> Option<PathBuf> {
+    Path::new(filename).canonicalize().ok()
+}
+
+/// Reads ruff's JSON output from `source`, or from stdin for `-`.
+fn read_issues(source: &Path) -> io::Result<Vec<Issue>> {
+    let (name, data) = if source == Path::new("-") {
+        let mut data = String::new();
+        io::stdin().read_to_string(&mut data)?;
+        ("stdin".to_string(), data)
+    } else {
+        (source.display().to_string(), fs::read_to_string(source)?)
+    };
+    serde_json::from_str(&data)
+        .map_err(|err| io::Error::other(format!("Invalid ruff output in {}: {}", name, err)))
+}
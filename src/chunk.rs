@@ -0,0 +1,240 @@
+use crate::tokens::count_tokens;
+
+/// One of several overlapping pieces a file is cut into when not even the
+/// context around an issue fits the model's window.
+///
+/// Offsets are in bytes, so unlike an [`Excerpt`](crate::excerpt::Excerpt)
+/// a chunk may start or end in the middle of a line; very long lines are
+/// split at whitespace or commas.
+#[derive(Clone, Copy)]
+pub struct Chunk {
+    pub start: usize,
+    pub end: usize,
+    /// Bytes at the start shared with the previous chunk.
+    pub lead: usize,
+    /// Bytes at the end shared with the next chunk.
+    pub trail: usize,
+}
+
+impl Chunk {
+    pub fn text<'a>(&self, content: &'a str) -> &'a str {
+        &content[self.start..self.end]
+    }
+
+    pub fn lead_text<'a>(&self, content: &'a str) -> &'a str {
+        &content[self.start..self.start + self.lead]
+    }
+
+    pub fn trail_text<'a>(&self, content: &'a str) -> &'a str {
+        &content[self.end - self.trail..self.end]
+    }
+
+    /// A fixed chunk can only be put back if it still starts and ends with
+    /// the text it shares with its neighbours; anything else means the model
+    /// rewrote or lost the seams.
+    pub fn validate(&self, content: &str, fixed: &str) -> bool {
+        let lead = self.lead_text(content);
+        let trail = self.trail_text(content);
+        fixed.len() >= lead.len() + trail.len() && fixed.starts_with(lead) && fixed.ends_with(trail)
+    }
+
+    pub fn reassemble(&self, content: &str, fixed: &str) -> String {
+        let mut reassembled = String::with_capacity(content.len() + fixed.len());
+        reassembled.push_str(&content[..self.start]);
+        reassembled.push_str(fixed);
+        reassembled.push_str(&content[self.end..]);
+        reassembled
+    }
+}
+
+/// Splits `content` into chunks of at most `budget` tokens, each repeating
+/// up to `overlap` segments (lines, or pieces of overlong lines) of its
+/// predecessor.
+pub fn split(content: &str, budget: usize, overlap: usize) -> Vec<Chunk> {
+    let segments = segments(content, (budget / 4).max(1));
+    let costs: Vec<usize> = segments
+        .iter()
+        .map(|&(start, end)| count_tokens(&content[start..end]))
+        .collect();
+
+    let mut ranges: Vec<(usize, usize)> = Vec::new();
+    let mut first = 0;
+    while first < segments.len() {
+        let mut last = first;
+        let mut used = costs[first];
+        while last + 1 < segments.len() && used + costs[last + 1] <= budget {
+            last += 1;
+            used += costs[last];
+        }
+        ranges.push((first, last));
+        if last + 1 == segments.len() {
+            break;
+        }
+        first = (last + 1).saturating_sub(overlap).max(first + 1);
+    }
+
+    let mut chunks: Vec<Chunk> = ranges
+        .iter()
+        .map(|&(first, last)| Chunk {
+            start: segments[first].0,
+            end: segments[last].1,
+            lead: 0,
+            trail: 0,
+        })
+        .collect();
+    for i in 1..chunks.len() {
+        let shared = chunks[i - 1].end.saturating_sub(chunks[i].start);
+        chunks[i].lead = shared;
+        chunks[i - 1].trail = shared;
+    }
+    chunks
+}
+
+/// Chunks containing byte `offset`, best candidate first: the one where the
+/// offset is furthest from a seam, so the model sees the most context.
+pub fn containing(chunks: &[Chunk], offset: usize) -> Vec<&Chunk> {
+    let mut found: Vec<&Chunk> = chunks
+        .iter()
+        .filter(|chunk| chunk.start <= offset && offset < chunk.end)
+        .collect();
+    found.sort_by_key(|chunk| {
+        std::cmp::Reverse(std::cmp::min(offset - chunk.start, chunk.end - offset))
+    });
+    found
+}
+
+/// Byte offset of a one-based `row`/`column` position.
+pub fn offset_of(content: &str, row: usize, column: usize) -> usize {
+    let line_start: usize = content
+        .split_inclusive('\n')
+        .take(row.saturating_sub(1))
+        .map(str::len)
+        .sum();
+    let line = content[line_start..].lines().next().unwrap_or_default();
+    let in_line = line
+        .char_indices()
+        .nth(column.saturating_sub(1))
+        .map_or(line.len(), |(i, _)| i);
+    (line_start + in_line).min(content.len().saturating_sub(1))
+}
+
+/// Byte ranges of the lines of `content`, with lines longer than
+/// `max_tokens` cut into several pieces.
+fn segments(content: &str, max_tokens: usize) -> Vec<(usize, usize)> {
+    // Estimated tokens are never more than one per byte, so this many bytes
+    // always fit.
+    let max_bytes = max_tokens.max(1);
+    let mut segments = Vec::new();
+    let mut offset = 0;
+    for line in content.split_inclusive('\n') {
+        let mut start = 0;
+        while line.len() - start > max_bytes && count_tokens(&line[start..]) > max_tokens {
+            let mut cut = start + max_bytes;
+            while !line.is_char_boundary(cut) {
+                cut -= 1;
+            }
+            // Prefer to cut right after whitespace or a comma.
+            if let Some(pos) = line[start..cut].rfind(|c: char| c.is_whitespace() || c == ',') {
+                if pos > 0 {
+                    cut = start + pos + 1;
+                }
+            }
+            if cut <= start {
+                break;
+            }
+            segments.push((offset + start, offset + cut));
+            start = cut;
+        }
+        segments.push((offset + start, offset + line.len()));
+        offset += line.len();
+    }
+    segments
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Ten lines of three tokens each, four of which fit a budget of 12.
+    fn lines() -> String {
+        (0..10).map(|i| format!("line {}\n", i)).collect()
+    }
+
+    #[test]
+    fn chunks_cover_the_content_and_share_their_seams() {
+        let content = lines();
+        let chunks = split(&content, 12, 1);
+
+        let texts: Vec<&str> = chunks.iter().map(|chunk| chunk.text(&content)).collect();
+        assert_eq!(
+            texts,
+            [
+                "line 0\nline 1\nline 2\nline 3\n",
+                "line 3\nline 4\nline 5\nline 6\n",
+                "line 6\nline 7\nline 8\nline 9\n",
+            ]
+        );
+        assert_eq!(chunks[0].lead, 0);
+        assert_eq!(chunks.last().unwrap().trail, 0);
+        for pair in chunks.windows(2) {
+            assert_eq!(pair[0].trail_text(&content), pair[1].lead_text(&content));
+            assert_eq!(
+                pair[1].lead_text(&content),
+                &content[pair[1].start..pair[0].end]
+            );
+        }
+    }
+
+    #[test]
+    fn cuts_overlong_lines_after_whitespace_or_commas() {
+        let content = "f(a, b, c, d, e, g, h, i, j, k, l, m, n, o, p, q)\n";
+        let chunks = split(content, 16, 0);
+
+        assert!(chunks.len() > 1);
+        for chunk in &chunks[..chunks.len() - 1] {
+            assert!(chunk.text(content).ends_with([' ', ',']));
+        }
+        let texts: String = chunks.iter().map(|chunk| chunk.text(content)).collect();
+        assert_eq!(texts, content);
+    }
+
+    #[test]
+    fn an_issue_on_a_seam_prefers_the_chunk_with_more_context_around_it() {
+        let content = lines();
+        let chunks = split(&content, 12, 1);
+        // Line 3 closes the first chunk and opens the second.
+        let offset = offset_of(&content, 4, 1);
+
+        let found = containing(&chunks, offset);
+
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[0].start, chunks[0].start);
+        assert_eq!(found[1].start, chunks[1].start);
+        assert_eq!(containing(&chunks, offset_of(&content, 5, 1)).len(), 1);
+    }
+
+    #[test]
+    fn reassembles_a_fix_that_keeps_the_seams() {
+        let content = lines();
+        let chunks = split(&content, 12, 1);
+        let chunk = chunks[1];
+
+        let fixed = "line 3\nline four\nline 5\nline 6\n";
+        assert!(chunk.validate(&content, fixed));
+        assert_eq!(
+            chunk.reassemble(&content, fixed),
+            content.replace("line 4\n", "line four\n")
+        );
+    }
+
+    #[test]
+    fn rejects_a_fix_that_edits_a_seam() {
+        let content = lines();
+        let chunks = split(&content, 12, 1);
+        let chunk = chunks[1];
+
+        assert!(!chunk.validate(&content, "line three\nline 4\nline 5\nline 6\n"));
+        assert!(!chunk.validate(&content, "line 3\nline 4\nline 5\nline six\n"));
+        assert!(!chunk.validate(&content, "line 3\n"));
+    }
+}
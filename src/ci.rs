@@ -0,0 +1,75 @@
+//! The exit-code policy of `--ci` and `--check`: what about a run fails
+//! the job.
+
+use std::error;
+use std::fmt;
+use std::str::FromStr;
+
+/// What `--fail-on` fails a run for.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum FailOn {
+    /// Issues left in the code: failed, skipped, left for review or not
+    /// reached.
+    Unfixed,
+    /// Any file changed, or that would be with `--patch-out` or `--check`.
+    AnyChange,
+    /// Requests to the model that failed.
+    Error,
+}
+
+impl FromStr for FailOn {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "unfixed" => Ok(FailOn::Unfixed),
+            "any-change" => Ok(FailOn::AnyChange),
+            "error" => Ok(FailOn::Error),
+            other => Err(format!("unknown failure condition: {}", other)),
+        }
+    }
+}
+
+/// What a finished run left behind, as far as the policy cares.
+pub struct Outcome {
+    pub unfixed: usize,
+    pub changed: usize,
+    pub errors: usize,
+}
+
+/// The error a run ends with when the policy fails it; the binary exits
+/// with [`POLICY_EXIT_CODE`] on it.
+#[derive(Debug)]
+pub struct PolicyFailure(pub String);
+
+impl fmt::Display for PolicyFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl error::Error for PolicyFailure {}
+
+/// Exit code of a run that `--fail-on` fails, distinct from the 1 of a run
+/// that ends with an error.
+pub const POLICY_EXIT_CODE: i32 = 2;
+
+/// Why `fail_on` fails a run that ended with `outcome`, if it does.
+pub fn verdict(fail_on: &[FailOn], outcome: &Outcome) -> Option<PolicyFailure> {
+    let reasons: Vec<String> = fail_on
+        .iter()
+        .filter_map(|condition| match condition {
+            FailOn::Unfixed if outcome.unfixed > 0 => {
+                Some(format!("{} issues left unfixed", outcome.unfixed))
+            }
+            FailOn::AnyChange if outcome.changed > 0 => {
+                Some(format!("{} files with fixes", outcome.changed))
+            }
+            FailOn::Error if outcome.errors > 0 => {
+                Some(format!("{} requests to the model failed", outcome.errors))
+            }
+            _ => None,
+        })
+        .collect();
+    (!reasons.is_empty()).then(|| PolicyFailure(format!("Failing the run: {}", reasons.join(", "))))
+}
@@ -0,0 +1,224 @@
+//! Rust projects, with `--language rust`: the issues are those of
+//! `cargo clippy --message-format=json`, and fixed code is formatted by
+//! rustfmt. Clippy's own suggestions are fixes like ruff's: those it marks
+//! machine-applicable are safe, and the others unsafe.
+
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use serde::Deserialize;
+
+use crate::issue::{Applicability, Edit, Fix, Issue, Location};
+use crate::linter;
+
+#[derive(Deserialize)]
+struct CargoMessage {
+    reason: String,
+    #[serde(default)]
+    message: Option<Diagnostic>,
+}
+
+#[derive(Deserialize)]
+struct Diagnostic {
+    message: String,
+    level: String,
+    #[serde(default)]
+    code: Option<DiagnosticCode>,
+    #[serde(default)]
+    spans: Vec<Span>,
+    #[serde(default)]
+    children: Vec<Diagnostic>,
+}
+
+#[derive(Deserialize)]
+struct DiagnosticCode {
+    code: String,
+}
+
+#[derive(Deserialize)]
+struct Span {
+    file_name: String,
+    line_start: u32,
+    line_end: u32,
+    column_start: u32,
+    column_end: u32,
+    is_primary: bool,
+    #[serde(default)]
+    suggested_replacement: Option<String>,
+    #[serde(default)]
+    suggestion_applicability: Option<String>,
+}
+
+/// The issues clippy finds in the workspace holding `dir`, in files under
+/// it.
+pub fn check(cargo: &Path, dir: &Path) -> io::Result<Vec<Issue>> {
+    let root = workspace_root(cargo, dir)?;
+    tracing::debug!(dir = %dir.display(), "Running {} clippy", cargo.display());
+    let output = Command::new(cargo)
+        .current_dir(dir)
+        .args(["clippy", "--message-format=json", "--quiet"])
+        .stdin(Stdio::null())
+        .output()?;
+    let mut issues = Vec::new();
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        let Ok(message) = serde_json::from_str::<CargoMessage>(line) else {
+            continue;
+        };
+        if message.reason != "compiler-message" {
+            continue;
+        }
+        if let Some(issue) = message
+            .message
+            .and_then(|diagnostic| to_issue(&root, diagnostic))
+        {
+            issues.push(issue);
+        }
+    }
+    // Compile errors fail the run but are issues all the same.
+    if !output.status.success() && issues.is_empty() {
+        return Err(io::Error::other(format!(
+            "Cargo clippy failed with {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr)
+        )));
+    }
+    let dir = dir.canonicalize()?;
+    issues.retain(|issue| {
+        Path::new(&issue.filename)
+            .canonicalize()
+            .is_ok_and(|path| path.starts_with(&dir))
+    });
+    // Code built for several targets, such as the library and its tests,
+    // is reported once for each.
+    linter::drop_duplicates(&mut issues);
+    Ok(issues)
+}
+
+/// `content` formatted by `rustfmt`.
+pub fn format(rustfmt: &Path, content: &str) -> io::Result<String> {
+    let mut child = Command::new(rustfmt)
+        .args(["--edition", "2021", "--emit", "stdout"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    child
+        .stdin
+        .take()
+        .expect("stdin is piped")
+        .write_all(content.as_bytes())?;
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        return Err(io::Error::other(format!(
+            "Rustfmt failed: {}",
+            String::from_utf8_lossy(&output.stderr)
+        )));
+    }
+    String::from_utf8(output.stdout).map_err(io::Error::other)
+}
+
+/// The directory of the workspace holding `dir`, which the paths of
+/// clippy's diagnostics are relative to.
+fn workspace_root(cargo: &Path, dir: &Path) -> io::Result<PathBuf> {
+    let output = Command::new(cargo)
+        .current_dir(dir)
+        .args(["locate-project", "--workspace", "--message-format", "plain"])
+        .output()?;
+    if !output.status.success() {
+        return Err(io::Error::other(format!(
+            "No Cargo.toml found for {}: {}",
+            dir.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    let manifest = PathBuf::from(String::from_utf8_lossy(&output.stdout).trim());
+    Ok(manifest
+        .parent()
+        .map_or_else(|| dir.to_path_buf(), Path::to_path_buf))
+}
+
+fn to_issue(root: &Path, diagnostic: Diagnostic) -> Option<Issue> {
+    if !matches!(diagnostic.level.as_str(), "error" | "warning") {
+        return None;
+    }
+    let span = diagnostic.spans.iter().find(|span| span.is_primary)?;
+    // Errors without a code, such as those of syntax, are rustc's.
+    let code = diagnostic
+        .code
+        .map_or_else(|| "rustc".to_string(), |code| code.code);
+    let url = code.strip_prefix("clippy::").map(|lint| {
+        format!(
+            "https://rust-lang.github.io/rust-clippy/master/index.html#{}",
+            lint
+        )
+    });
+    let edits: Vec<(Edit, Option<&str>)> = diagnostic
+        .children
+        .iter()
+        .flat_map(|child| &child.spans)
+        .filter_map(|span| {
+            let content = span.suggested_replacement.clone()?;
+            Some((
+                Edit {
+                    content: Some(content),
+                    location: Location {
+                        row: span.line_start,
+                        column: span.column_start,
+                    },
+                    end_location: Location {
+                        row: span.line_end,
+                        column: span.column_end,
+                    },
+                },
+                span.suggestion_applicability.as_deref(),
+            ))
+        })
+        .collect();
+    let fix = (!edits.is_empty()).then(|| {
+        let applicability = if edits
+            .iter()
+            .all(|(_, applicability)| *applicability == Some("MachineApplicable"))
+        {
+            Applicability::Safe
+        } else if edits
+            .iter()
+            .any(|(_, applicability)| *applicability == Some("HasPlaceholders"))
+        {
+            Applicability::DisplayOnly
+        } else {
+            Applicability::Unsafe
+        };
+        Fix {
+            applicability,
+            message: diagnostic
+                .children
+                .iter()
+                .find(|child| {
+                    child
+                        .spans
+                        .iter()
+                        .any(|span| span.suggested_replacement.is_some())
+                })
+                .map(|child| child.message.clone()),
+            edits: edits.into_iter().map(|(edit, _)| edit).collect(),
+        }
+    });
+    Some(Issue {
+        filename: root.join(&span.file_name).display().to_string(),
+        code,
+        message: diagnostic.message,
+        location: Location {
+            row: span.line_start,
+            column: span.column_start,
+        },
+        end_location: Some(Location {
+            row: span.line_end,
+            column: span.column_end,
+        }),
+        noqa_row: None,
+        url,
+        fix,
+        id: String::new(),
+    })
+}
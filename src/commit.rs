@@ -0,0 +1,120 @@
+//! The commits of `--commit`: the files a run changed, grouped by file or
+//! by the rules fixed in them, with messages saying what was fixed.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::str::FromStr;
+
+use crate::report::Fixed;
+
+/// How `--commit` groups the files changed into commits.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum CommitBy {
+    /// One commit per file.
+    File,
+    /// One commit per set of rules fixed, holding every file where just
+    /// those rules were fixed.
+    Rule,
+}
+
+impl FromStr for CommitBy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "file" => Ok(CommitBy::File),
+            "rule" => Ok(CommitBy::Rule),
+            other => Err(format!("unknown commit grouping: {}", other)),
+        }
+    }
+}
+
+/// A commit to make: the files, by display path, and its message.
+pub struct Commit {
+    pub files: Vec<String>,
+    pub message: String,
+}
+
+/// The commits of the files changed, with the issues fixed in each, as
+/// `by` groups them.
+pub fn commits(changes: &BTreeMap<String, Vec<Fixed>>, by: CommitBy) -> Vec<Commit> {
+    match by {
+        CommitBy::File => changes
+            .iter()
+            .map(|(file, fixed)| Commit {
+                files: vec![file.clone()],
+                message: message(&codes(fixed), file, &issue_lines(fixed)),
+            })
+            .collect(),
+        CommitBy::Rule => {
+            let mut groups: BTreeMap<Vec<String>, Vec<&String>> = BTreeMap::new();
+            for (file, fixed) in changes {
+                groups.entry(codes(fixed)).or_default().push(file);
+            }
+            groups
+                .into_iter()
+                .map(|(codes, files)| {
+                    let subject = match files.as_slice() {
+                        [file] => file.to_string(),
+                        _ => format!("{} files", files.len()),
+                    };
+                    let lines = files
+                        .iter()
+                        .flat_map(|file| {
+                            changes[*file].iter().map(move |fixed| {
+                                format!(
+                                    "- {} in {} at line {}: {}",
+                                    fixed.code, file, fixed.row, fixed.message
+                                )
+                            })
+                        })
+                        .collect::<Vec<_>>();
+                    Commit {
+                        files: files.into_iter().cloned().collect(),
+                        message: message(&codes, &subject, &lines),
+                    }
+                })
+                .collect()
+        }
+    }
+}
+
+/// The rule codes fixed, each once, in order.
+fn codes(fixed: &[Fixed]) -> Vec<String> {
+    fixed
+        .iter()
+        .map(|fixed| fixed.code.clone())
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect()
+}
+
+fn issue_lines(fixed: &[Fixed]) -> Vec<String> {
+    fixed
+        .iter()
+        .map(|fixed| format!("- {} at line {}: {}", fixed.code, fixed.row, fixed.message))
+        .collect()
+}
+
+/// "Fix F401, E711 in src/app.py", with the issues fixed in the body. A
+/// file changed without an issue fixed in it was only formatted.
+fn message(codes: &[String], subject: &str, lines: &[String]) -> String {
+    let (summary, by) = if codes.is_empty() {
+        (format!("Format {}", subject), "Formatted by ruffer.")
+    } else {
+        (
+            format!("Fix {} in {}", codes.join(", "), subject),
+            "Fixed by ruffer.",
+        )
+    };
+    let mut message = summary + "\n\n";
+    for line in lines {
+        message.push_str(line);
+        message.push('\n');
+    }
+    if !lines.is_empty() {
+        message.push('\n');
+    }
+    message.push_str(by);
+    message.push('\n');
+    message
+}
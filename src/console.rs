@@ -0,0 +1,1038 @@
+use std::fmt::Display;
+use std::io::{self, IsTerminal, Write};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
+use serde::Serialize;
+use similar::{ChangeTag, TextDiff};
+use terminal_size::{terminal_size_of, Width};
+
+use crate::dashboard::{Counts, Dashboard};
+use crate::highlight::{self, Kind};
+use crate::issue;
+use crate::provider::{Completion, CompletionRequest, FixProvider};
+use crate::report::{Report, Resolution};
+use crate::state::Outcome;
+use crate::tokens::model_price;
+
+/// Console output shared by all fixing tasks.
+///
+/// Everything user-facing goes through here so the presentation can be
+/// switched in one place. In plain mode every message is a single
+/// self-describing line: no cursor movement, no color, and diffs spell out
+/// what happened to each line instead of relying on `-`/`+` markers.
+///
+/// When stderr is a terminal and output isn't plain, the progress of the
+/// run is shown in bars at the bottom, redrawn as messages scroll past
+/// above them: the files, the issues, each file being fixed, and the
+/// requests to the model.
+///
+/// With a [`Dashboard`], for `--tui`, all of this is shown there instead,
+/// until the run finishes.
+///
+/// With an event stream, every step of the run is also written to it as
+/// a line of JSON, and the messages for people go to stderr so that the
+/// stream can be stdout. They go to stderr too when stdout carries
+/// something else, such as the fixed code of `--stdin-filename`.
+#[derive(Clone)]
+pub struct Console {
+    plain: bool,
+    /// Whether diffs may be colored, when they go to a terminal.
+    color: bool,
+    /// Lines of context around the changes in diffs.
+    context: usize,
+    layout: DiffLayout,
+    progress: Arc<Progress>,
+    events: Option<Arc<Mutex<Box<dyn Write + Send>>>>,
+    /// Whether stdout is left to the caller.
+    stdout_taken: bool,
+    /// Whether messages for people are left out.
+    quiet: bool,
+    dashboard: Option<Arc<Dashboard>>,
+}
+
+/// What a run writes to stdout.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Output {
+    /// Messages for people.
+    Text,
+    /// One JSON [`Event`] per line.
+    Jsonl,
+    /// Messages for people, then GitHub Actions workflow commands
+    /// annotating every issue.
+    Github,
+}
+
+impl FromStr for Output {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Output::Text),
+            "jsonl" => Ok(Output::Jsonl),
+            "github" => Ok(Output::Github),
+            other => Err(format!("unknown output: {}", other)),
+        }
+    }
+}
+
+/// A step of the run, for programs following it with `--output jsonl`.
+#[derive(Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event<'a> {
+    IssueDiscovered {
+        file: &'a str,
+        id: &'a str,
+        code: &'a str,
+        message: &'a str,
+        row: u32,
+        column: u32,
+    },
+    PromptSent {
+        file: &'a str,
+        id: &'a str,
+        attempt: usize,
+    },
+    FixRejected {
+        file: &'a str,
+        id: &'a str,
+        attempt: usize,
+        reason: &'a str,
+    },
+    FixApplied {
+        file: &'a str,
+        id: &'a str,
+    },
+    FixFailed {
+        file: &'a str,
+        id: &'a str,
+    },
+    IssueLeftAlone {
+        file: &'a str,
+        id: &'a str,
+    },
+    FileWritten {
+        file: &'a str,
+    },
+    Error {
+        message: String,
+    },
+    RunFinished {
+        files: usize,
+        files_done: usize,
+        fixed: usize,
+        failed: usize,
+        left_alone: usize,
+        requests: usize,
+        tokens: usize,
+        /// In US dollars, if the price of the model is known.
+        cost: Option<f64>,
+        seconds: f64,
+    },
+}
+
+/// Counts of what a run has done so far.
+struct Progress {
+    started: Instant,
+    files: AtomicUsize,
+    files_done: AtomicUsize,
+    issues: AtomicUsize,
+    fixed: AtomicUsize,
+    failed: AtomicUsize,
+    left_alone: AtomicUsize,
+    requests: AtomicUsize,
+    in_flight: AtomicUsize,
+    /// Requests the model failed to answer.
+    request_errors: AtomicUsize,
+    bars: Bars,
+}
+
+/// The progress bars, drawn on stderr if it is a terminal and hidden
+/// otherwise. Messages are printed with the bars suspended, so they don't
+/// interleave.
+struct Bars {
+    multi: MultiProgress,
+    files: ProgressBar,
+    issues: ProgressBar,
+    /// Below the bars of the files being fixed.
+    requests: ProgressBar,
+}
+
+impl Bars {
+    fn new(live: bool) -> Self {
+        let multi = MultiProgress::with_draw_target(if live {
+            ProgressDrawTarget::stderr()
+        } else {
+            ProgressDrawTarget::hidden()
+        });
+        let bar = |template| {
+            let bar = multi.add(ProgressBar::hidden());
+            bar.set_style(ProgressStyle::with_template(template).expect("templates are valid"));
+            bar
+        };
+        Bars {
+            files: bar("[{elapsed:>4}] {bar:30} {pos}/{len} files"),
+            issues: bar("       {bar:30} {pos}/{len} issues: {msg}"),
+            requests: bar("       {msg}"),
+            multi,
+        }
+    }
+}
+
+/// The bar of a file being fixed, taken down when dropped.
+pub struct FileProgress {
+    bar: ProgressBar,
+    multi: MultiProgress,
+}
+
+impl FileProgress {
+    /// Shows that `left` of the file's issues are still to be worked on.
+    pub fn left(&self, left: usize) {
+        let issues = self.bar.length().unwrap_or(0);
+        self.bar.set_position(issues.saturating_sub(left as u64));
+    }
+}
+
+impl Drop for FileProgress {
+    fn drop(&mut self) {
+        self.bar.finish_and_clear();
+        self.multi.remove(&self.bar);
+    }
+}
+
+impl Console {
+    pub fn new(plain: bool) -> Self {
+        // Terminals that declare themselves dumb can't render anything else.
+        let dumb_terminal = std::env::var("TERM").is_ok_and(|term| term == "dumb");
+        let plain = plain || dumb_terminal;
+        let live = !plain && io::stderr().is_terminal();
+        Console {
+            plain,
+            color: !plain && std::env::var_os("NO_COLOR").is_none(),
+            context: 3,
+            layout: DiffLayout::Unified,
+            progress: Arc::new(Progress {
+                started: Instant::now(),
+                files: AtomicUsize::new(0),
+                files_done: AtomicUsize::new(0),
+                issues: AtomicUsize::new(0),
+                fixed: AtomicUsize::new(0),
+                failed: AtomicUsize::new(0),
+                left_alone: AtomicUsize::new(0),
+                requests: AtomicUsize::new(0),
+                in_flight: AtomicUsize::new(0),
+                request_errors: AtomicUsize::new(0),
+                bars: Bars::new(live),
+            }),
+            events: None,
+            stdout_taken: false,
+            quiet: false,
+            dashboard: None,
+        }
+    }
+
+    /// Shows `lines` lines of context around the changes in diffs.
+    pub fn with_context(mut self, lines: usize) -> Self {
+        self.context = lines;
+        self
+    }
+
+    /// Shows diffs as `layout` says, except in plain mode.
+    pub fn with_layout(mut self, layout: DiffLayout) -> Self {
+        self.layout = layout;
+        self
+    }
+
+    /// Never colors diffs.
+    pub fn without_color(mut self) -> Self {
+        self.color = false;
+        self
+    }
+
+    /// Writes the events of the run to `out`, and the messages for people
+    /// to stderr.
+    pub fn with_events(mut self, out: Box<dyn Write + Send>) -> Self {
+        self.events = Some(Arc::new(Mutex::new(out)));
+        self
+    }
+
+    /// Writes the messages for people to stderr, leaving stdout to the
+    /// caller.
+    pub fn leaving_stdout(mut self) -> Self {
+        self.stdout_taken = true;
+        self
+    }
+
+    /// Shows the run on `dashboard` instead of printing it, until
+    /// [`Self::finish`] takes it down.
+    pub fn with_dashboard(mut self, dashboard: Arc<Dashboard>) -> Self {
+        self.progress
+            .bars
+            .multi
+            .set_draw_target(ProgressDrawTarget::hidden());
+        self.dashboard = Some(dashboard);
+        self
+    }
+
+    /// Whether diffs may be colored, as the dashboard's are.
+    pub fn colors(&self) -> bool {
+        self.color
+    }
+
+    /// Resolves once quitting is asked for on the dashboard; never without
+    /// one.
+    pub async fn quit_requested(&self) {
+        match &self.dashboard {
+            Some(dashboard) => dashboard.quit_requested().await,
+            None => std::future::pending().await,
+        }
+    }
+
+    /// Takes the dashboard down, if it's up, and prints the messages it
+    /// showed.
+    pub fn close_dashboard(&self) {
+        let Some(dashboard) = &self.dashboard else {
+            return;
+        };
+        // Closing waits for the dashboard's last drawing on stdout.
+        let messages = dashboard.close();
+        let mut out = self.text_out();
+        for (error, message) in messages {
+            if error {
+                eprintln!("{}", message);
+            } else {
+                let _ = writeln!(out, "{}", message);
+            }
+        }
+    }
+
+    pub fn event(&self, event: Event) {
+        if let Some(dashboard) = &self.dashboard {
+            dashboard.event(&event);
+        }
+        if let Some(events) = &self.events {
+            let mut line = serde_json::to_string(&event).expect("events serialize");
+            line.push('\n');
+            let mut out = events.lock().unwrap();
+            let _ = out.write_all(line.as_bytes());
+            let _ = out.flush();
+        }
+    }
+
+    pub fn status(&self, message: impl Display) {
+        tracing::info!("{}", message);
+        if let Some(dashboard) = &self.dashboard {
+            return dashboard.message(false, message.to_string());
+        }
+        self.above_progress(|| {
+            if self.stdout_taken() {
+                eprintln!("{}", message);
+            } else {
+                println!("{}", message);
+            }
+        });
+    }
+
+    pub fn error(&self, message: impl Display) {
+        tracing::error!("{}", message);
+        self.event(Event::Error {
+            message: message.to_string(),
+        });
+        if let Some(dashboard) = &self.dashboard {
+            return dashboard.message(true, message.to_string());
+        }
+        self.above_progress(|| {
+            if self.plain {
+                eprintln!("error: {}", message);
+            } else {
+                eprintln!("{}", message);
+            }
+        });
+    }
+
+    /// Prints `question` and leaves the cursor after it for the answer,
+    /// taking down the progress bars until the next message.
+    pub fn ask(&self, question: impl Display) {
+        if let Some(dashboard) = &self.dashboard {
+            return dashboard.ask(question.to_string());
+        }
+        let _ = self.progress.bars.multi.clear();
+        let mut out = self.text_out();
+        let _ = write!(out, "{}", question);
+        let _ = out.flush();
+    }
+
+    /// Starts showing progress over `files` files with `issues` issues.
+    pub fn start(&self, files: usize, issues: usize) {
+        self.progress.files.store(files, Ordering::Relaxed);
+        self.progress.issues.store(issues, Ordering::Relaxed);
+        self.redraw();
+    }
+
+    /// Adds `files` with `issues` to the work of the run, as another
+    /// iteration finds more.
+    pub fn more(&self, files: usize, issues: usize) {
+        self.progress.files.fetch_add(files, Ordering::Relaxed);
+        self.progress.issues.fetch_add(issues, Ordering::Relaxed);
+        self.redraw();
+    }
+
+    /// Shows a bar of the `issues` issues of `filename` while it is being
+    /// fixed.
+    pub fn file(&self, filename: &str, issues: usize) -> FileProgress {
+        let bars = &self.progress.bars;
+        let bar = bars
+            .multi
+            .insert_before(&bars.requests, ProgressBar::hidden());
+        bar.set_style(
+            ProgressStyle::with_template("       {bar:30} {pos}/{len} {msg}")
+                .expect("templates are valid"),
+        );
+        bar.set_length(issues as u64);
+        bar.set_message(issue::display_path(filename));
+        FileProgress {
+            bar,
+            multi: bars.multi.clone(),
+        }
+    }
+
+    pub fn file_done(&self) {
+        self.progress.files_done.fetch_add(1, Ordering::Relaxed);
+        self.redraw();
+    }
+
+    pub fn issue_done(&self, outcome: Outcome) {
+        let counter = match outcome {
+            Outcome::Fixed => &self.progress.fixed,
+            Outcome::Failed => &self.progress.failed,
+            Outcome::LeftAlone => &self.progress.left_alone,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        self.redraw();
+    }
+
+    /// `provider`, counting the requests it answers for the progress line.
+    pub fn track(&self, provider: Arc<dyn FixProvider>) -> Arc<dyn FixProvider> {
+        Arc::new(Tracked {
+            provider,
+            console: self.clone(),
+        })
+    }
+
+    /// How many requests the model failed to answer so far.
+    pub fn request_errors(&self) -> usize {
+        self.progress.request_errors.load(Ordering::Relaxed)
+    }
+
+    /// Takes down the progress bars and prints a table of how the run
+    /// went, from `report`, if it started working on files: the files done,
+    /// how the issues ended, the time taken, and the tokens the requests to
+    /// `model` took and their cost.
+    pub fn finish(&self, report: &Report, model: &str) {
+        let progress = &self.progress;
+        self.close_dashboard();
+        let files = progress.files.load(Ordering::Relaxed);
+        if files == 0 || self.quiet {
+            return;
+        }
+        for bar in [
+            &progress.bars.files,
+            &progress.bars.issues,
+            &progress.bars.requests,
+        ] {
+            bar.finish_and_clear();
+        }
+        let seconds = progress.started.elapsed().as_secs_f64();
+        let _ = self
+            .text_out()
+            .write_all(self.summary(report, model, seconds).as_bytes());
+        let (prompt_tokens, answer_tokens) = report.tokens();
+        self.event(Event::RunFinished {
+            files,
+            files_done: progress.files_done.load(Ordering::Relaxed),
+            fixed: progress.fixed.load(Ordering::Relaxed),
+            failed: progress.failed.load(Ordering::Relaxed),
+            left_alone: progress.left_alone.load(Ordering::Relaxed),
+            requests: progress.requests.load(Ordering::Relaxed),
+            tokens: prompt_tokens + answer_tokens,
+            cost: model_price(model).map(|price| price.cost(prompt_tokens, answer_tokens)),
+            seconds,
+        });
+    }
+
+    /// The table [`Console::finish`] prints, for a run of `seconds`.
+    fn summary(&self, report: &Report, model: &str, seconds: f64) -> String {
+        let progress = &self.progress;
+        let (files, files_done, requests) = (
+            progress.files.load(Ordering::Relaxed),
+            progress.files_done.load(Ordering::Relaxed),
+            progress.requests.load(Ordering::Relaxed),
+        );
+        let (prompt_tokens, answer_tokens) = report.tokens();
+        let cost = model_price(model).map(|price| price.cost(prompt_tokens, answer_tokens));
+
+        let mut rows = vec![("files".to_string(), format!("{} of {}", files_done, files))];
+        for (resolution, count) in report.tally() {
+            // The usual outcomes always show; the others when they happened.
+            let usual = matches!(
+                resolution,
+                Resolution::AutoFixed
+                    | Resolution::LlmFixed
+                    | Resolution::Failed
+                    | Resolution::Skipped
+            );
+            if usual || count > 0 {
+                rows.push((resolution.name().to_string(), count.to_string()));
+            }
+        }
+        rows.push(("time".to_string(), format!("{:.1}s", seconds)));
+        rows.push(("requests".to_string(), requests.to_string()));
+        rows.push((
+            "tokens".to_string(),
+            format!(
+                "{} ({} prompt, {} answer)",
+                prompt_tokens + answer_tokens,
+                prompt_tokens,
+                answer_tokens
+            ),
+        ));
+        rows.push((
+            "cost".to_string(),
+            match cost {
+                Some(cost) => format!("${:.4} with {}", cost, model),
+                None => format!("unknown for {}", model),
+            },
+        ));
+        let width = rows.iter().map(|(name, _)| name.len()).max().unwrap_or(0);
+        let mut table = String::from("Summary:\n");
+        for (name, value) in rows {
+            table.push_str(&format!("  {:width$}  {}\n", name, value, width = width));
+        }
+        table
+    }
+
+    /// Prints the `n` rules and files with the most issues, for `--top`.
+    pub fn top(&self, report: &Report, n: usize) {
+        let (rules, files) = report.top(n);
+        if rules.is_empty() || self.quiet {
+            return;
+        }
+        let mut table = String::new();
+        for (title, tallies) in [("Noisiest rules:", rules), ("Noisiest files:", files)] {
+            let width = tallies
+                .iter()
+                .map(|tally| tally.name.len())
+                .max()
+                .unwrap_or(0);
+            table.push_str(title);
+            table.push('\n');
+            for tally in tallies {
+                table.push_str(&format!(
+                    "  {:width$}  {} issues, {} fixed\n",
+                    tally.name,
+                    tally.issues,
+                    tally.fixed,
+                    width = width
+                ));
+            }
+        }
+        let _ = self.text_out().write_all(table.as_bytes());
+    }
+
+    /// Prints nothing for people, as for `ruffer estimate`, which has
+    /// only its estimate to tell; logs and events still go out.
+    pub fn quiet(mut self) -> Self {
+        self.quiet = true;
+        self
+    }
+
+    /// Whether stdout is taken by events or by the caller.
+    fn stdout_taken(&self) -> bool {
+        self.stdout_taken || self.events.is_some()
+    }
+
+    /// Where messages for people go: stdout, unless that is taken.
+    fn text_out(&self) -> Box<dyn Write> {
+        if self.stdout_taken() {
+            Box::new(io::stderr().lock())
+        } else {
+            Box::new(io::stdout().lock())
+        }
+    }
+
+    /// Prints with `print`, keeping the progress bars below the output.
+    fn above_progress(&self, print: impl FnOnce()) {
+        if self.quiet {
+            return;
+        }
+        self.progress.bars.multi.suspend(print);
+    }
+
+    /// Brings the progress bars up to date with the counts of the run.
+    fn redraw(&self) {
+        let progress = &self.progress;
+        let files = progress.files.load(Ordering::Relaxed);
+        if self.quiet || files == 0 {
+            return;
+        }
+        let bars = &progress.bars;
+        let fixed = progress.fixed.load(Ordering::Relaxed);
+        let failed = progress.failed.load(Ordering::Relaxed);
+        if let Some(dashboard) = &self.dashboard {
+            dashboard.progress(Counts {
+                files,
+                files_done: progress.files_done.load(Ordering::Relaxed),
+                issues: progress.issues.load(Ordering::Relaxed),
+                fixed,
+                failed,
+                in_flight: progress.in_flight.load(Ordering::Relaxed),
+                requests: progress.requests.load(Ordering::Relaxed),
+            });
+        }
+        let done = fixed + failed + progress.left_alone.load(Ordering::Relaxed);
+        bars.files.set_length(files as u64);
+        bars.files
+            .set_position(progress.files_done.load(Ordering::Relaxed) as u64);
+        bars.issues
+            .set_length(progress.issues.load(Ordering::Relaxed) as u64);
+        bars.issues.set_position(done as u64);
+        bars.issues
+            .set_message(format!("{} fixed, {} failed", fixed, failed));
+        bars.requests.set_message(format!(
+            "{} requests in flight, {} sent",
+            progress.in_flight.load(Ordering::Relaxed),
+            progress.requests.load(Ordering::Relaxed)
+        ));
+    }
+
+    /// Prints the changes between two versions of a file as a unified
+    /// diff, which `patch` and `git apply` take, colored and highlighted
+    /// when printing to a terminal. The whole diff is written at once so
+    /// output from concurrent tasks doesn't interleave.
+    pub fn diff(&self, filename: &str, original: &str, fixed: &str) {
+        if let Some(dashboard) = &self.dashboard {
+            return dashboard.diff(filename, original, fixed, self.context);
+        }
+        let color = self.color
+            && if self.stdout_taken() {
+                io::stderr().is_terminal()
+            } else {
+                io::stdout().is_terminal()
+            };
+        let diff = TextDiff::from_lines(original, fixed);
+        let out = if self.layout == DiffLayout::SideBySide && !self.plain {
+            let width = (terminal_width().saturating_sub(3) / 2).max(20);
+            self.side_by_side(filename, &diff, color, width)
+        } else {
+            self.unified(filename, &diff, color)
+        };
+
+        self.above_progress(|| {
+            let mut text_out = self.text_out();
+            let _ = text_out.write_all(out.as_bytes());
+            let _ = text_out.flush();
+        });
+    }
+
+    fn unified<'a>(&self, filename: &str, diff: &TextDiff<'a, 'a, str>, color: bool) -> String {
+        let mut out = String::new();
+        if self.plain {
+            out.push_str(&format!("Changes in {}:\n", filename));
+        } else {
+            // Like `git diff` shows paths, and `patch::unified` writes them.
+            let path = issue::display_path(filename);
+            let path = path.trim_start_matches('/');
+            out.push_str(&paint(color, BOLD, &format!("--- a/{}", path)));
+            out.push_str(&paint(color, BOLD, &format!("+++ b/{}", path)));
+        }
+        for hunk in diff
+            .unified_diff()
+            .context_radius(self.context)
+            .iter_hunks()
+        {
+            if !self.plain {
+                out.push_str(&paint(color, CYAN, &hunk.header().to_string()));
+            }
+            for change in hunk.iter_changes() {
+                let line = change.value().trim_end_matches(['\n', '\r']);
+                if self.plain {
+                    match change.tag() {
+                        ChangeTag::Delete => out.push_str(&format!(
+                            "line {} removed: {}\n",
+                            change.old_index().map_or(0, |index| index + 1),
+                            line
+                        )),
+                        ChangeTag::Insert => out.push_str(&format!(
+                            "line {} added: {}\n",
+                            change.new_index().map_or(0, |index| index + 1),
+                            line
+                        )),
+                        ChangeTag::Equal => {}
+                    }
+                    continue;
+                }
+                let (marker, line_color) = match change.tag() {
+                    ChangeTag::Delete => ('-', RED),
+                    ChangeTag::Insert => ('+', GREEN),
+                    ChangeTag::Equal => (' ', RESET),
+                };
+                if color {
+                    out.push_str(line_color);
+                    out.push(marker);
+                    for (kind, text) in highlight::python(line) {
+                        let token_color = match kind {
+                            Kind::Keyword => MAGENTA,
+                            Kind::String => YELLOW,
+                            Kind::Comment => GREY,
+                            Kind::Number => BLUE,
+                            Kind::Other => {
+                                out.push_str(text);
+                                continue;
+                            }
+                        };
+                        out.push_str(token_color);
+                        out.push_str(text);
+                        out.push_str(RESET);
+                        out.push_str(line_color);
+                    }
+                    out.push_str(RESET);
+                    out.push('\n');
+                } else {
+                    out.push(marker);
+                    out.push_str(line);
+                    out.push('\n');
+                }
+                if change.missing_newline() {
+                    out.push_str("\\ No newline at end of file\n");
+                }
+            }
+        }
+
+        out
+    }
+
+    /// The changes in two columns `width` characters wide, the original on
+    /// the left and the fixed version on the right. Lines changed in place
+    /// are paired up, with the changed parts highlighted.
+    fn side_by_side<'a>(
+        &self,
+        filename: &str,
+        diff: &TextDiff<'a, 'a, str>,
+        color: bool,
+        width: usize,
+    ) -> String {
+        let mut out = paint(color, BOLD, &issue::display_path(filename));
+        for hunk in diff
+            .unified_diff()
+            .context_radius(self.context)
+            .iter_hunks()
+        {
+            out.push_str(&paint(color, CYAN, &hunk.header().to_string()));
+            let changes: Vec<_> = hunk.iter_changes().collect();
+            let mut i = 0;
+            while i < changes.len() {
+                if changes[i].tag() == ChangeTag::Equal {
+                    let line = expand_tabs(changes[i].value());
+                    let cell = column(&[(false, line)], width, color, RESET);
+                    out.push_str(format!("{}   {}", cell, cell).trim_end());
+                    out.push('\n');
+                    i += 1;
+                    continue;
+                }
+                let mut removed = Vec::new();
+                while i < changes.len() && changes[i].tag() == ChangeTag::Delete {
+                    removed.push(expand_tabs(changes[i].value()));
+                    i += 1;
+                }
+                let mut added = Vec::new();
+                while i < changes.len() && changes[i].tag() == ChangeTag::Insert {
+                    added.push(expand_tabs(changes[i].value()));
+                    i += 1;
+                }
+                for row in 0..removed.len().max(added.len()) {
+                    let (left, gutter, right) = match (removed.get(row), added.get(row)) {
+                        (Some(old), Some(new)) => {
+                            let (old, new) = changed_parts(old, new);
+                            (
+                                column(&old, width, color, RED),
+                                '|',
+                                column(&new, width, color, GREEN),
+                            )
+                        }
+                        (Some(old), None) => (
+                            column(&[(false, old.clone())], width, color, RED),
+                            '<',
+                            String::new(),
+                        ),
+                        (None, Some(new)) => (
+                            " ".repeat(width),
+                            '>',
+                            column(&[(false, new.clone())], width, color, GREEN),
+                        ),
+                        (None, None) => unreachable!("row is within one of them"),
+                    };
+                    out.push_str(format!("{} {} {}", left, gutter, right).trim_end());
+                    out.push('\n');
+                }
+            }
+        }
+        out
+    }
+}
+
+const RESET: &str = "\x1b[0m";
+const BOLD: &str = "\x1b[1m";
+const RED: &str = "\x1b[31m";
+const GREEN: &str = "\x1b[32m";
+const YELLOW: &str = "\x1b[33m";
+const BLUE: &str = "\x1b[34m";
+const MAGENTA: &str = "\x1b[35m";
+const REVERSE: &str = "\x1b[7m";
+const CYAN: &str = "\x1b[36m";
+const GREY: &str = "\x1b[90m";
+
+/// `line` and a newline, in `style` if `color`.
+fn paint(color: bool, style: &str, line: &str) -> String {
+    if color {
+        format!("{}{}{}\n", style, line, RESET)
+    } else {
+        format!("{}\n", line)
+    }
+}
+
+/// A provider whose requests show on the progress line.
+struct Tracked {
+    provider: Arc<dyn FixProvider>,
+    console: Console,
+}
+
+impl FixProvider for Tracked {
+    fn complete<'a>(&'a self, request: &'a CompletionRequest) -> Completion<'a> {
+        Box::pin(async move {
+            let progress = &self.console.progress;
+            progress.requests.fetch_add(1, Ordering::Relaxed);
+            progress.in_flight.fetch_add(1, Ordering::Relaxed);
+            self.console.redraw();
+            let result = self.provider.complete(request).await;
+            progress.in_flight.fetch_sub(1, Ordering::Relaxed);
+            if result.is_err() {
+                progress.request_errors.fetch_add(1, Ordering::Relaxed);
+            }
+            self.console.redraw();
+            result
+        })
+    }
+}
+
+/// What `--diff` shows diffs as.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum DiffLayout {
+    Unified,
+    SideBySide,
+}
+
+impl FromStr for DiffLayout {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "unified" => Ok(DiffLayout::Unified),
+            "side-by-side" => Ok(DiffLayout::SideBySide),
+            other => Err(format!("unknown diff layout: {}", other)),
+        }
+    }
+}
+
+/// Columns of the terminal, or of one wide enough for two columns of code
+/// when that can't be told.
+fn terminal_width() -> usize {
+    if let Some(columns) = std::env::var("COLUMNS")
+        .ok()
+        .and_then(|columns| columns.parse().ok())
+    {
+        return columns;
+    }
+    terminal_size_of(io::stdout())
+        .or_else(|| terminal_size_of(io::stderr()))
+        .map_or(160, |(Width(columns), _)| columns as usize)
+}
+
+fn expand_tabs(line: &str) -> String {
+    line.trim_end_matches(['\n', '\r']).replace('\t', "    ")
+}
+
+/// Pieces of a line, marked if changed.
+type Parts = Vec<(bool, String)>;
+
+/// `old` and `new` cut into the parts they share and those they don't.
+fn changed_parts(old: &str, new: &str) -> (Parts, Parts) {
+    fn add(parts: &mut Parts, changed: bool, text: &str) {
+        match parts.last_mut() {
+            Some((last, part)) if *last == changed => part.push_str(text),
+            _ => parts.push((changed, text.to_string())),
+        }
+    }
+    let (mut old_parts, mut new_parts) = (Vec::new(), Vec::new());
+    for change in TextDiff::from_chars(old, new).iter_all_changes() {
+        match change.tag() {
+            ChangeTag::Equal => {
+                add(&mut old_parts, false, change.value());
+                add(&mut new_parts, false, change.value());
+            }
+            ChangeTag::Delete => add(&mut old_parts, true, change.value()),
+            ChangeTag::Insert => add(&mut new_parts, true, change.value()),
+        }
+    }
+    (old_parts, new_parts)
+}
+
+/// `parts` of a line in a column `width` characters wide, cut short if
+/// longer, in `style` with the marked parts reversed if `color`.
+fn column(parts: &[(bool, String)], width: usize, color: bool, style: &str) -> String {
+    let mut cell = String::new();
+    if color {
+        cell.push_str(style);
+    }
+    let cut = parts
+        .iter()
+        .map(|(_, text)| text.chars().count())
+        .sum::<usize>()
+        > width;
+    let mut used = 0;
+    'parts: for (changed, text) in parts {
+        if color && *changed {
+            cell.push_str(REVERSE);
+        }
+        for c in text.chars() {
+            if cut && used + 1 == width {
+                cell.push('…');
+                used += 1;
+                break 'parts;
+            }
+            cell.push(c);
+            used += 1;
+        }
+        if color && *changed {
+            cell.push_str(RESET);
+            cell.push_str(style);
+        }
+    }
+    if color {
+        cell.push_str(RESET);
+    }
+    cell.push_str(&" ".repeat(width.saturating_sub(used)));
+    cell
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ORIGINAL: &str = "import os\n\ndef f():\n\treturn 1\n";
+    const FIXED: &str = "\ndef f():\n\treturn 2\n";
+
+    fn unified(console: Console, color: bool) -> String {
+        console.unified("src/a.py", &TextDiff::from_lines(ORIGINAL, FIXED), color)
+    }
+
+    #[test]
+    fn writes_a_unified_diff() {
+        let expected = [
+            "--- a/src/a.py\n",
+            "+++ b/src/a.py\n",
+            "@@ -1,4 +1,3 @@\n",
+            "-import os\n",
+            " \n",
+            " def f():\n",
+            "-\treturn 1\n",
+            "+\treturn 2\n",
+        ];
+        assert_eq!(unified(Console::new(false), false), expected.concat());
+    }
+
+    #[test]
+    fn colors_and_highlights_a_unified_diff() {
+        let expected = [
+            "\x1b[1m--- a/src/a.py\x1b[0m\n",
+            "\x1b[1m+++ b/src/a.py\x1b[0m\n",
+            "\x1b[36m@@ -1,4 +1,3 @@\x1b[0m\n",
+            "\x1b[31m-\x1b[35mimport\x1b[0m\x1b[31m os\x1b[0m\n",
+            "\x1b[0m \x1b[0m\n",
+            "\x1b[0m \x1b[35mdef\x1b[0m\x1b[0m f():\x1b[0m\n",
+            "\x1b[31m-\t\x1b[35mreturn\x1b[0m\x1b[31m \x1b[34m1\x1b[0m\x1b[31m\x1b[0m\n",
+            "\x1b[32m+\t\x1b[35mreturn\x1b[0m\x1b[32m \x1b[34m2\x1b[0m\x1b[32m\x1b[0m\n",
+        ];
+        assert_eq!(unified(Console::new(false), true), expected.concat());
+    }
+
+    #[test]
+    fn spells_out_each_change_in_plain_mode() {
+        // Never colored, whatever the terminal.
+        assert_eq!(
+            unified(Console::new(true), true),
+            "Changes in src/a.py:\n\
+             line 1 removed: import os\n\
+             line 4 removed: \treturn 1\n\
+             line 3 added: \treturn 2\n"
+        );
+    }
+
+    #[test]
+    fn writes_the_changes_side_by_side() {
+        let diff = TextDiff::from_lines(ORIGINAL, FIXED);
+        let expected = [
+            "src/a.py\n",
+            "@@ -1,4 +1,3 @@\n",
+            "import os            <\n",
+            "\n",
+            "def f():               def f():\n",
+            "    return 1         |     return 2\n",
+        ];
+        assert_eq!(
+            Console::new(false).side_by_side("src/a.py", &diff, false, 20),
+            expected.concat()
+        );
+    }
+
+    #[test]
+    fn sums_up_the_run() {
+        let console = Console::new(false);
+        console.start(2, 3);
+        console.file_done();
+        console.progress.requests.fetch_add(2, Ordering::Relaxed);
+        let report = Report::default();
+        report.spent(1200, 300);
+
+        let expected = [
+            "Summary:\n",
+            "  files       1 of 2\n",
+            "  auto-fixed  0\n",
+            "  llm-fixed   0\n",
+            "  failed      0\n",
+            "  skipped     0\n",
+            "  time        12.3s\n",
+            "  requests    2\n",
+            "  tokens      1500 (1200 prompt, 300 answer)\n",
+            "  cost        $0.0004 with gpt-4o-mini\n",
+        ];
+        assert_eq!(
+            console.summary(&report, "gpt-4o-mini", 12.345),
+            expected.concat()
+        );
+        let unpriced = console.summary(&report, "unknown-model", 0.04);
+        assert!(unpriced.contains("  time        0.0s\n"), "{}", unpriced);
+        assert!(unpriced.ends_with("  cost        unknown for unknown-model\n"));
+    }
+
+    #[test]
+    fn cuts_lines_short_in_columns() {
+        let parts = [(false, "abc".to_string()), (true, "defghij".to_string())];
+
+        assert_eq!(column(&parts, 6, false, RESET), "abcde…");
+        assert_eq!(column(&parts, 12, false, RESET), "abcdefghij  ");
+        assert_eq!(column(&parts, 6, true, RED), "\x1b[31mabc\x1b[7mde…\x1b[0m");
+    }
+}
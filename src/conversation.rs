@@ -0,0 +1,43 @@
+use crate::provider::Message;
+use crate::tokens::count_tokens;
+
+/// The exchange with the model about one file, continued from issue to
+/// issue for as long as the file only changes through the model's answers.
+#[derive(Default)]
+pub struct Conversation {
+    messages: Vec<Message>,
+    /// The file content as of the last answer in `messages`.
+    content: String,
+}
+
+impl Conversation {
+    /// The earlier messages, if they still describe `file_content`; `None`
+    /// when there are none yet, or when the last answer was rejected or the
+    /// file changed some other way.
+    pub fn history(&self, file_content: &str) -> Option<&[Message]> {
+        if self.messages.is_empty() || self.content != file_content {
+            None
+        } else {
+            Some(&self.messages)
+        }
+    }
+
+    /// Continues the conversation with `prompt` and the model's `answer`,
+    /// which turned the file into `content`. Without `continued`, the
+    /// conversation starts over with this exchange.
+    pub fn record(&mut self, continued: bool, prompt: &str, answer: &str, content: String) {
+        if !continued {
+            self.messages.clear();
+        }
+        self.messages.push(Message::user(prompt));
+        self.messages.push(Message::assistant(answer));
+        self.content = content;
+    }
+
+    pub fn tokens(&self) -> usize {
+        self.messages
+            .iter()
+            .map(|message| count_tokens(&message.content))
+            .sum()
+    }
+}
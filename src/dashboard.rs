@@ -0,0 +1,677 @@
+//! `--tui`: a dashboard of the run drawn in the terminal with ratatui, in
+//! place of messages scrolling past.
+//!
+//! It shows what the [`Console`](crate::console::Console) is told: the
+//! events of the run fill the queue of issues and the status of each file,
+//! the diff of every fix from the model shows as it comes, and messages go
+//! to a pane of their own. Its keys answer the questions `--interactive`
+//! asks, which `--tui` asks too, through [`Answers`].
+//!
+//! The terminal is drawn on, and keys read, on a thread of its own, so
+//! that the run goes on while nobody presses anything.
+
+use std::collections::VecDeque;
+use std::io::{self, IsTerminal, Read};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use ratatui::crossterm::event::{self, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, List, ListItem, Paragraph};
+use ratatui::{DefaultTerminal, Frame};
+use similar::{ChangeTag, TextDiff};
+use tokio::sync::Notify;
+
+use crate::console::Event;
+use crate::fixer::INTERRUPTED_EXIT_CODE;
+use crate::issue;
+
+/// Messages kept for the pane, the oldest dropped first.
+const MESSAGES_KEPT: usize = 500;
+
+/// The dashboard on the terminal, while it's up.
+pub struct Dashboard {
+    state: Arc<Mutex<State>>,
+    /// Notified when quitting is asked for with a key, as Ctrl-C is
+    /// caught as a key rather than a signal.
+    quit: Arc<Notify>,
+    /// Tells the drawing thread to stop, and the thread.
+    drawing: Mutex<Option<(Arc<AtomicBool>, JoinHandle<()>)>>,
+}
+
+/// How far the run has got, for the line at the top.
+pub struct Counts {
+    pub files: usize,
+    pub files_done: usize,
+    pub issues: usize,
+    pub fixed: usize,
+    pub failed: usize,
+    pub in_flight: usize,
+    pub requests: usize,
+}
+
+/// The answers given with keys, one per line, as the questions of
+/// `--interactive` read them; the end of the input once the dashboard is
+/// taken down.
+pub struct Answers {
+    keys: mpsc::Receiver<&'static str>,
+    pending: Vec<u8>,
+}
+
+impl Read for Answers {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pending.is_empty() {
+            match self.keys.recv() {
+                Ok(answer) => self.pending = format!("{}\n", answer).into_bytes(),
+                Err(_) => return Ok(0),
+            }
+        }
+        let n = buf.len().min(self.pending.len());
+        buf[..n].copy_from_slice(&self.pending[..n]);
+        self.pending.drain(..n);
+        Ok(n)
+    }
+}
+
+impl Dashboard {
+    /// Takes over the terminal, which stdout needs to be, and draws the
+    /// dashboard until [`Self::close`], in color if `color`.
+    pub fn start(color: bool) -> io::Result<(Arc<Dashboard>, Answers)> {
+        if !io::stdout().is_terminal() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "--tui needs a terminal",
+            ));
+        }
+        let (sender, keys) = mpsc::channel();
+        let state = Arc::new(Mutex::new(State::new(color, sender)));
+        let quit = Arc::new(Notify::new());
+        let stop = Arc::new(AtomicBool::new(false));
+        let terminal = ratatui::try_init()?;
+        let thread = thread::spawn({
+            let (state, quit, stop) = (Arc::clone(&state), Arc::clone(&quit), Arc::clone(&stop));
+            move || draw_until_stopped(terminal, &state, &quit, &stop)
+        });
+        let dashboard = Dashboard {
+            state,
+            quit,
+            drawing: Mutex::new(Some((stop, thread))),
+        };
+        let answers = Answers {
+            keys,
+            pending: Vec::new(),
+        };
+        Ok((Arc::new(dashboard), answers))
+    }
+
+    /// Resolves once quitting is asked for with a key.
+    pub async fn quit_requested(&self) {
+        self.quit.notified().await;
+    }
+
+    /// Gives the terminal back, returning the messages shown, errors
+    /// marked, so that they can be printed; nothing once closed.
+    pub fn close(&self) -> Vec<(bool, String)> {
+        let Some((stop, thread)) = self.drawing.lock().unwrap().take() else {
+            return Vec::new();
+        };
+        stop.store(true, Ordering::SeqCst);
+        let _ = thread.join();
+        let mut state = self.state.lock().unwrap();
+        // Nobody is left to answer.
+        state.question = None;
+        state.messages.drain(..).collect()
+    }
+
+    pub fn event(&self, event: &Event) {
+        self.state.lock().unwrap().event(event);
+    }
+
+    pub fn message(&self, error: bool, message: String) {
+        self.state.lock().unwrap().message(error, message);
+    }
+
+    pub fn ask(&self, question: String) {
+        self.state.lock().unwrap().question = Some(question);
+    }
+
+    /// Shows the changes between `original` and `fixed` of `filename`,
+    /// with `context` lines around them.
+    pub fn diff(&self, filename: &str, original: &str, fixed: &str, context: usize) {
+        self.state
+            .lock()
+            .unwrap()
+            .diff(filename, original, fixed, context);
+    }
+
+    pub fn progress(&self, counts: Counts) {
+        self.state.lock().unwrap().counts = counts;
+    }
+}
+
+/// Draws the dashboard every tenth of a second, or as soon as a key is
+/// pressed, and handles the keys, until `stop` is set.
+fn draw_until_stopped(
+    mut terminal: DefaultTerminal,
+    state: &Mutex<State>,
+    quit: &Notify,
+    stop: &AtomicBool,
+) {
+    while !stop.load(Ordering::SeqCst) {
+        let _ = terminal.draw(|frame| state.lock().unwrap().draw(frame));
+        if !event::poll(Duration::from_millis(100)).unwrap_or(false) {
+            continue;
+        }
+        let Ok(event::Event::Key(key)) = event::read() else {
+            continue;
+        };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+        let pressed = state.lock().unwrap().press(key);
+        match pressed {
+            Key::Handled => {}
+            Key::Quit => quit.notify_one(),
+            Key::QuitAtOnce => {
+                let _ = ratatui::try_restore();
+                std::process::exit(INTERRUPTED_EXIT_CODE);
+            }
+        }
+    }
+    let _ = ratatui::try_restore();
+}
+
+/// What a key press calls for beyond the dashboard.
+#[derive(Debug, PartialEq, Eq)]
+enum Key {
+    Handled,
+    /// Stop the run as Ctrl-C does.
+    Quit,
+    /// Quit at once, as a second Ctrl-C does.
+    QuitAtOnce,
+}
+
+/// What the dashboard shows.
+struct State {
+    color: bool,
+    started: Instant,
+    counts: Counts,
+    /// Every issue found, in the order found.
+    issues: Vec<QueuedIssue>,
+    /// Every file with issues, in the order found, and whether it was
+    /// written.
+    files: Vec<(String, bool)>,
+    /// The latest diff, of the file named.
+    diff: Option<(String, Vec<(ChangeTag, String)>)>,
+    /// Lines the diff is scrolled down by.
+    scroll: usize,
+    messages: VecDeque<(bool, String)>,
+    /// The question waiting for an answer.
+    question: Option<String>,
+    answers: mpsc::Sender<&'static str>,
+    quitting: bool,
+}
+
+struct QueuedIssue {
+    file: String,
+    id: String,
+    code: String,
+    message: String,
+    row: u32,
+    status: Status,
+}
+
+enum Status {
+    Queued,
+    /// The model was asked for a fix, the attempt-th time.
+    Asking(usize),
+    /// The fix of an attempt was rejected, for a reason.
+    Rejected(usize, String),
+    Fixed,
+    Failed,
+    LeftAlone,
+}
+
+impl Status {
+    fn done(&self) -> bool {
+        matches!(self, Status::Fixed | Status::Failed | Status::LeftAlone)
+    }
+
+    fn working(&self) -> bool {
+        matches!(self, Status::Asking(_) | Status::Rejected(..))
+    }
+}
+
+impl State {
+    fn new(color: bool, answers: mpsc::Sender<&'static str>) -> Self {
+        State {
+            color,
+            started: Instant::now(),
+            counts: Counts {
+                files: 0,
+                files_done: 0,
+                issues: 0,
+                fixed: 0,
+                failed: 0,
+                in_flight: 0,
+                requests: 0,
+            },
+            issues: Vec::new(),
+            files: Vec::new(),
+            diff: None,
+            scroll: 0,
+            messages: VecDeque::new(),
+            question: None,
+            answers,
+            quitting: false,
+        }
+    }
+
+    fn event(&mut self, event: &Event) {
+        let (file, id, status) = match event {
+            Event::IssueDiscovered {
+                file,
+                id,
+                code,
+                message,
+                row,
+                ..
+            } => {
+                if !self.files.iter().any(|(name, _)| name == file) {
+                    self.files.push((file.to_string(), false));
+                }
+                self.issues.push(QueuedIssue {
+                    file: file.to_string(),
+                    id: id.to_string(),
+                    code: code.to_string(),
+                    message: message.to_string(),
+                    row: *row,
+                    status: Status::Queued,
+                });
+                return;
+            }
+            Event::FileWritten { file } => {
+                if let Some((_, written)) = self.files.iter_mut().find(|(name, _)| name == file) {
+                    *written = true;
+                }
+                return;
+            }
+            Event::PromptSent { file, id, attempt } => (file, id, Status::Asking(*attempt)),
+            Event::FixRejected {
+                file,
+                id,
+                attempt,
+                reason,
+            } => (file, id, Status::Rejected(*attempt, reason.to_string())),
+            Event::FixApplied { file, id } => (file, id, Status::Fixed),
+            Event::FixFailed { file, id } => (file, id, Status::Failed),
+            Event::IssueLeftAlone { file, id } => (file, id, Status::LeftAlone),
+            // Errors come as messages, and the end closes the dashboard.
+            Event::Error { .. } | Event::RunFinished { .. } => return,
+        };
+        // An issue found again by a later iteration is the latest one.
+        if let Some(issue) = self
+            .issues
+            .iter_mut()
+            .rev()
+            .find(|issue| issue.file == *file && issue.id == *id)
+        {
+            issue.status = status;
+        }
+    }
+
+    fn message(&mut self, error: bool, message: String) {
+        if self.messages.len() == MESSAGES_KEPT {
+            self.messages.pop_front();
+        }
+        self.messages.push_back((error, message));
+    }
+
+    fn diff(&mut self, filename: &str, original: &str, fixed: &str, context: usize) {
+        let mut lines = Vec::new();
+        for hunk in TextDiff::from_lines(original, fixed)
+            .unified_diff()
+            .context_radius(context)
+            .iter_hunks()
+        {
+            lines.push((ChangeTag::Equal, hunk.header().to_string()));
+            for change in hunk.iter_changes() {
+                let marker = match change.tag() {
+                    ChangeTag::Delete => '-',
+                    ChangeTag::Insert => '+',
+                    ChangeTag::Equal => ' ',
+                };
+                let line = change.value().trim_end_matches(['\n', '\r']);
+                lines.push((change.tag(), format!("{}{}", marker, line)));
+            }
+        }
+        self.diff = Some((filename.to_string(), lines));
+        self.scroll = 0;
+    }
+
+    fn press(&mut self, key: KeyEvent) -> Key {
+        let ctrl_c =
+            key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL);
+        if ctrl_c || key.code == KeyCode::Char('q') {
+            if self.quitting {
+                return Key::QuitAtOnce;
+            }
+            self.quitting = true;
+            self.answer("q");
+            return Key::Quit;
+        }
+        match key.code {
+            KeyCode::Char('y') | KeyCode::Enter => self.answer("y"),
+            KeyCode::Char('n') | KeyCode::Char('s') => self.answer("n"),
+            KeyCode::Char('r') => self.answer("r"),
+            KeyCode::Char('a') => self.answer("a"),
+            KeyCode::Down | KeyCode::Char('j') => self.scroll += 1,
+            KeyCode::Up | KeyCode::Char('k') => self.scroll = self.scroll.saturating_sub(1),
+            KeyCode::PageDown => self.scroll += 10,
+            KeyCode::PageUp => self.scroll = self.scroll.saturating_sub(10),
+            _ => {}
+        }
+        let lines = self.diff.as_ref().map_or(0, |(_, lines)| lines.len());
+        self.scroll = self.scroll.min(lines.saturating_sub(1));
+        Key::Handled
+    }
+
+    /// Answers the question waiting, if any; keys pressed while there is
+    /// none are dropped rather than kept for the next one.
+    fn answer(&mut self, answer: &'static str) {
+        if self.question.take().is_some() {
+            let _ = self.answers.send(answer);
+        }
+    }
+
+    fn style(&self, color: Color) -> Style {
+        if self.color {
+            Style::new().fg(color)
+        } else {
+            Style::new()
+        }
+    }
+
+    fn draw(&self, frame: &mut Frame) {
+        let [header, lists, diff, messages, footer] = Layout::vertical([
+            Constraint::Length(1),
+            Constraint::Percentage(35),
+            Constraint::Min(5),
+            Constraint::Length(7),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+        let [issues, files] =
+            Layout::horizontal([Constraint::Percentage(60), Constraint::Percentage(40)])
+                .areas(lists);
+
+        let counts = &self.counts;
+        frame.render_widget(
+            Paragraph::new(format!(
+                "ruffer  {}/{} files  {}/{} issues  {} fixed  {} failed  {} requests in flight, {} sent  {:.0}s",
+                counts.files_done,
+                counts.files,
+                self.issues.iter().filter(|issue| issue.status.done()).count(),
+                counts.issues,
+                counts.fixed,
+                counts.failed,
+                counts.in_flight,
+                counts.requests,
+                self.started.elapsed().as_secs_f64()
+            ))
+            .style(Style::new().add_modifier(Modifier::BOLD)),
+            header,
+        );
+        self.draw_issues(frame, issues);
+        self.draw_files(frame, files);
+        self.draw_diff(frame, diff);
+
+        let shown = messages.height.saturating_sub(2) as usize;
+        let lines: Vec<Line> = self
+            .messages
+            .iter()
+            .skip(self.messages.len().saturating_sub(shown))
+            .map(|(error, message)| {
+                let style = if *error {
+                    self.style(Color::Red)
+                } else {
+                    Style::new()
+                };
+                Line::styled(message.as_str(), style)
+            })
+            .collect();
+        frame.render_widget(
+            Paragraph::new(lines).block(Block::bordered().title("Messages")),
+            messages,
+        );
+
+        let footer_line = match &self.question {
+            Some(question) => Line::styled(
+                question.trim_end(),
+                self.style(Color::Yellow).add_modifier(Modifier::BOLD),
+            ),
+            None if self.quitting => Line::raw("Quitting: q again to quit at once"),
+            None => Line::raw("q quit  ↑↓ scroll the diff"),
+        };
+        frame.render_widget(Paragraph::new(footer_line), footer);
+    }
+
+    /// The queue of issues: those being worked on, then those waiting,
+    /// then those done, the latest first.
+    fn draw_issues(&self, frame: &mut Frame, area: Rect) {
+        let working = self.issues.iter().filter(|issue| issue.status.working());
+        let queued = self
+            .issues
+            .iter()
+            .filter(|issue| matches!(issue.status, Status::Queued));
+        let done = self.issues.iter().rev().filter(|issue| issue.status.done());
+        let items: Vec<ListItem> = working
+            .chain(queued)
+            .chain(done)
+            .map(|issue| {
+                let (mark, status, color) = match &issue.status {
+                    Status::Queued => ("·", "queued".to_string(), Color::Reset),
+                    Status::Asking(attempt) => {
+                        ("▶", format!("asking, attempt {}", attempt), Color::Cyan)
+                    }
+                    Status::Rejected(attempt, reason) => (
+                        "▶",
+                        format!("attempt {} rejected: {}", attempt, reason),
+                        Color::Yellow,
+                    ),
+                    Status::Fixed => ("✓", "fixed".to_string(), Color::Green),
+                    Status::Failed => ("✗", "failed".to_string(), Color::Red),
+                    Status::LeftAlone => ("-", "left alone".to_string(), Color::DarkGray),
+                };
+                ListItem::new(Line::from(vec![
+                    Span::styled(format!("{} ", mark), self.style(color)),
+                    Span::styled(
+                        format!("{} ", issue.code),
+                        Style::new().add_modifier(Modifier::BOLD),
+                    ),
+                    Span::raw(format!(
+                        "{}:{} {} — ",
+                        issue::display_path(&issue.file),
+                        issue.row,
+                        issue.message
+                    )),
+                    Span::styled(status, self.style(color)),
+                ]))
+            })
+            .collect();
+        frame.render_widget(
+            List::new(items).block(Block::bordered().title("Issues")),
+            area,
+        );
+    }
+
+    fn draw_files(&self, frame: &mut Frame, area: Rect) {
+        let items: Vec<ListItem> = self
+            .files
+            .iter()
+            .map(|(file, written)| {
+                let issues = self.issues.iter().filter(|issue| issue.file == *file);
+                let (mut total, mut done, mut working) = (0, 0, false);
+                for issue in issues {
+                    total += 1;
+                    done += usize::from(issue.status.done());
+                    working |= issue.status.working();
+                }
+                let (status, color) = if *written {
+                    ("written", Color::Green)
+                } else if working {
+                    ("fixing", Color::Cyan)
+                } else if done == total {
+                    ("done", Color::Reset)
+                } else {
+                    ("queued", Color::Reset)
+                };
+                ListItem::new(Line::from(vec![
+                    Span::raw(format!("{} {}/{} ", issue::display_path(file), done, total)),
+                    Span::styled(status, self.style(color)),
+                ]))
+            })
+            .collect();
+        frame.render_widget(
+            List::new(items).block(Block::bordered().title("Files")),
+            area,
+        );
+    }
+
+    fn draw_diff(&self, frame: &mut Frame, area: Rect) {
+        let (title, lines) = match &self.diff {
+            Some((file, lines)) => (
+                format!("Diff of {}", issue::display_path(file)),
+                lines
+                    .iter()
+                    .skip(self.scroll)
+                    .map(|(tag, line)| {
+                        let style = match tag {
+                            ChangeTag::Delete => self.style(Color::Red),
+                            ChangeTag::Insert => self.style(Color::Green),
+                            ChangeTag::Equal if line.starts_with("@@") => self.style(Color::Cyan),
+                            ChangeTag::Equal => Style::new(),
+                        };
+                        Line::styled(line.as_str(), style)
+                    })
+                    .collect(),
+            ),
+            None => ("Diff".to_string(), Vec::new()),
+        };
+        frame.render_widget(
+            Paragraph::new(lines).block(Block::bordered().title(title)),
+            area,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ratatui::backend::TestBackend;
+    use ratatui::Terminal;
+
+    fn screen(state: &State) -> String {
+        let mut terminal = Terminal::new(TestBackend::new(100, 30)).unwrap();
+        terminal.draw(|frame| state.draw(frame)).unwrap();
+        let buffer = terminal.backend().buffer();
+        let mut screen = String::new();
+        for y in 0..buffer.area.height {
+            for x in 0..buffer.area.width {
+                screen.push_str(buffer[(x, y)].symbol());
+            }
+            screen.push('\n');
+        }
+        screen
+    }
+
+    fn key(code: KeyCode) -> KeyEvent {
+        KeyEvent::new(code, KeyModifiers::NONE)
+    }
+
+    #[test]
+    fn shows_the_issues_files_and_diffs_of_the_run() {
+        let (sender, _keys) = mpsc::channel();
+        let mut state = State::new(false, sender);
+        for (id, code, row) in [("a1", "F401", 1), ("a2", "E501", 3)] {
+            state.event(&Event::IssueDiscovered {
+                file: "src/a.py",
+                id,
+                code,
+                message: "found",
+                row,
+                column: 1,
+            });
+        }
+        state.event(&Event::PromptSent {
+            file: "src/a.py",
+            id: "a2",
+            attempt: 2,
+        });
+        state.event(&Event::FixApplied {
+            file: "src/a.py",
+            id: "a1",
+        });
+        state.diff("src/a.py", "import os\nx = 1\n", "x = 1\n", 3);
+        state.message(false, "Processing file: src/a.py".to_string());
+        state.question = Some("Apply the fix of [a2] E501 in src/a.py? ".to_string());
+
+        let screen = screen(&state);
+        assert!(
+            screen.contains("▶ E501 src/a.py:3 found — asking, attempt 2"),
+            "{}",
+            screen
+        );
+        assert!(
+            screen.contains("✓ F401 src/a.py:1 found — fixed"),
+            "{}",
+            screen
+        );
+        assert!(screen.contains("src/a.py 1/2 fixing"), "{}", screen);
+        assert!(screen.contains("Diff of src/a.py"), "{}", screen);
+        assert!(screen.contains("-import os"), "{}", screen);
+        assert!(screen.contains("Processing file: src/a.py"), "{}", screen);
+        assert!(screen.contains("Apply the fix of [a2] E501"), "{}", screen);
+    }
+
+    #[test]
+    fn answers_the_question_waiting_with_keys() {
+        let (sender, keys) = mpsc::channel();
+        let mut state = State::new(false, sender);
+        let mut answers = Answers {
+            keys,
+            pending: Vec::new(),
+        };
+
+        // Nothing is asked yet.
+        assert_eq!(state.press(key(KeyCode::Char('y'))), Key::Handled);
+        for (pressed, answer) in [
+            (KeyCode::Char('r'), "r\n"),
+            (KeyCode::Enter, "y\n"),
+            (KeyCode::Char('s'), "n\n"),
+        ] {
+            state.question = Some("Apply?".to_string());
+            assert_eq!(state.press(key(pressed)), Key::Handled);
+            assert!(state.question.is_none());
+            let mut line = [0; 2];
+            answers.read_exact(&mut line).unwrap();
+            assert_eq!(std::str::from_utf8(&line).unwrap(), answer);
+        }
+
+        state.question = Some("Apply?".to_string());
+        let ctrl_c = KeyEvent::new(KeyCode::Char('c'), KeyModifiers::CONTROL);
+        assert_eq!(state.press(ctrl_c), Key::Quit);
+        let mut line = [0; 2];
+        answers.read_exact(&mut line).unwrap();
+        assert_eq!(&line, b"q\n");
+        assert_eq!(state.press(key(KeyCode::Char('q'))), Key::QuitAtOnce);
+
+        // Once the dashboard is gone, nobody is there to answer.
+        drop(state);
+        assert_eq!(answers.read(&mut line).unwrap(), 0);
+    }
+}
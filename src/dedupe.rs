@@ -0,0 +1,91 @@
+//! Fixes repeated for identical issues: the same rule broken on the same
+//! lines, such as the same deprecated import in many files, is asked about
+//! once, and the change the model made is made again for the others.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use similar::TextDiff;
+
+use crate::issue::Issue;
+
+/// Lines a repeatable fix may replace at most.
+const MAX_EDIT_LINES: usize = 20;
+
+/// How far from the issue a repeatable fix may start, in lines.
+const MAX_EDIT_DISTANCE: usize = 5;
+
+/// The fixes of the run so far that can be repeated, by rule code and the
+/// lines of the issue.
+#[derive(Default)]
+pub struct Duplicates {
+    edits: Mutex<HashMap<(String, String), Edit>>,
+}
+
+/// Lines replaced by a fix, starting `offset` lines from the issue's.
+struct Edit {
+    offset: isize,
+    before: Vec<String>,
+    after: String,
+}
+
+impl Duplicates {
+    /// Remembers how `before` was fixed into `after` for `issue`, if the
+    /// fix changed a few lines at a single place near it.
+    pub fn learn(&self, issue: &Issue, before: &str, after: &str) {
+        let diff = TextDiff::from_lines(before, after);
+        let groups = diff.grouped_ops(0);
+        let [group] = groups.as_slice() else {
+            return;
+        };
+        let (Some(first), Some(last)) = (group.first(), group.last()) else {
+            return;
+        };
+        let old = first.old_range().start..last.old_range().end;
+        let new = first.new_range().start..last.new_range().end;
+        let row = issue.location.row as usize - 1;
+        // Pure insertions have no lines to find the place again by.
+        if old.is_empty()
+            || old.len() > MAX_EDIT_LINES
+            || old.start.abs_diff(row) > MAX_EDIT_DISTANCE
+        {
+            return;
+        }
+        let old_lines: Vec<&str> = before.split_inclusive('\n').collect();
+        let new_lines: Vec<&str> = after.split_inclusive('\n').collect();
+        let edit = Edit {
+            offset: old.start as isize - row as isize,
+            before: old_lines[old].iter().map(|line| line.to_string()).collect(),
+            after: new_lines[new].concat(),
+        };
+        self.edits.lock().unwrap().insert(key(issue, before), edit);
+    }
+
+    /// `content` with the fix of an identical issue made for `issue`, if
+    /// one is known and the lines it replaced are there.
+    pub fn repeat(&self, issue: &Issue, content: &str) -> Option<String> {
+        let edits = self.edits.lock().unwrap();
+        let edit = edits.get(&key(issue, content))?;
+        let lines: Vec<&str> = content.split_inclusive('\n').collect();
+        let start = (issue.location.row as isize - 1 + edit.offset)
+            .try_into()
+            .ok()?;
+        let end = start + edit.before.len();
+        if end > lines.len() || lines[start..end] != edit.before[..] {
+            return None;
+        }
+        Some(
+            [
+                lines[..start].concat(),
+                edit.after.clone(),
+                lines[end..].concat(),
+            ]
+            .concat(),
+        )
+    }
+}
+
+/// What makes two issues identical: their rule and their lines.
+fn key(issue: &Issue, content: &str) -> (String, String) {
+    (issue.code.clone(), issue.lines(content).to_string())
+}
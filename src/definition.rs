@@ -0,0 +1,246 @@
+use rustpython_parser::ast::{Arguments, Constant, ExceptHandler, Expr, Mod, Ranged, Stmt};
+use rustpython_parser::{parse, Mode};
+
+/// Most lines of a definition quoted in a prompt.
+const MAX_QUOTED_LINES: usize = 80;
+
+/// A function or class definition in Python source. Lines are zero-based.
+pub struct Definition {
+    pub name: String,
+    /// Parameter names of a function, in order; empty for a class.
+    pub params: Vec<String>,
+    /// Line of the first decorator, or of the header without decorators.
+    pub first_line: usize,
+    /// Line of the `def` or `class` keyword.
+    pub header_start: usize,
+    /// One past the last line of the header, not counting comments that
+    /// open the body.
+    pub header_end: usize,
+    /// Line the first statement of the body starts on.
+    pub body_start: usize,
+    /// One past the last line.
+    pub end: usize,
+    /// Lines of the `return` and `yield` statements of the definition's
+    /// own body, leaving out nested functions and classes.
+    pub exits: Vec<usize>,
+}
+
+impl Definition {
+    /// Whether the body starts on the header's line, as in `def f(): pass`.
+    pub fn is_one_line(&self) -> bool {
+        self.body_start == self.header_start
+    }
+}
+
+/// The definition whose header contains the one-based `row`, which is
+/// where ruff reports issues with a definition.
+pub fn at_row(content: &str, row: usize) -> Result<Definition, String> {
+    let suite = parse_module(content)?;
+    let row = row.saturating_sub(1);
+    let line_of = |offset: usize| content[..offset].matches('\n').count();
+    let (stmt, body) = find(&suite, &|stmt, body| {
+        line_of(usize::from(stmt.start())) <= row && row < line_of(first_offset(&body[0]))
+    })
+    .ok_or_else(|| format!("No definition found at line {}", row + 1))?;
+
+    let (name, params) = match stmt {
+        Stmt::FunctionDef(def) => (def.name.to_string(), param_names(&def.args)),
+        Stmt::AsyncFunctionDef(def) => (def.name.to_string(), param_names(&def.args)),
+        Stmt::ClassDef(def) => (def.name.to_string(), Vec::new()),
+        _ => unreachable!("only definitions are found"),
+    };
+    let lines: Vec<&str> = content.split_inclusive('\n').collect();
+    let header_start = line_of(usize::from(stmt.start()));
+    let body_start = line_of(first_offset(&body[0]));
+    let mut header_end = body_start.max(header_start + 1);
+    while header_end > header_start + 1 && is_blank_or_comment(lines[header_end - 1]) {
+        header_end -= 1;
+    }
+    let mut exits = Vec::new();
+    collect_exits(body, &line_of, &mut exits);
+
+    Ok(Definition {
+        name,
+        params,
+        first_line: line_of(first_offset(stmt)),
+        header_start,
+        header_end,
+        body_start,
+        end: line_of(usize::from(stmt.end())) + 1,
+        exits,
+    })
+}
+
+/// Line the first statement of the module starts on, or the number of
+/// lines if there is none.
+pub fn first_statement_line(content: &str) -> Result<usize, String> {
+    let suite = parse_module(content)?;
+    Ok(suite.first().map_or_else(
+        || content.split_inclusive('\n').count(),
+        |stmt| content[..first_offset(stmt)].matches('\n').count(),
+    ))
+}
+
+/// Line new imports go on: the first after the module docstring and any
+/// `from __future__` imports.
+pub fn import_line(content: &str) -> Result<usize, String> {
+    let suite = parse_module(content)?;
+    let line_of = |offset: usize| content[..offset].matches('\n').count();
+    let is_docstring = |stmt: &Stmt| {
+        matches!(stmt, Stmt::Expr(expr) if matches!(
+            &*expr.value,
+            Expr::Constant(constant) if matches!(constant.value, Constant::Str(_))
+        ))
+    };
+    let is_future = |stmt: &Stmt| matches!(stmt, Stmt::ImportFrom(import) if import.module.as_deref() == Some("__future__"));
+    let mut preamble = suite.len();
+    for (index, stmt) in suite.iter().enumerate() {
+        if !((index == 0 && is_docstring(stmt)) || is_future(stmt)) {
+            preamble = index;
+            break;
+        }
+    }
+    Ok(match (suite.get(preamble), preamble.checked_sub(1)) {
+        (Some(stmt), _) => line_of(first_offset(stmt)),
+        (None, Some(last)) => line_of(usize::from(suite[last].end())) + 1,
+        (None, None) => 0,
+    })
+}
+
+/// Lines `start..end` of `content`, cut short after `MAX_QUOTED_LINES`.
+pub fn quote(content: &str, start: usize, end: usize) -> String {
+    let lines: Vec<&str> = content.split_inclusive('\n').collect();
+    let end = end.min(lines.len());
+    let start = start.min(end);
+    let mut quoted = lines[start..end.min(start + MAX_QUOTED_LINES)].concat();
+    if end - start > MAX_QUOTED_LINES {
+        quoted.push_str("# ... (the rest is left out)\n");
+    }
+    quoted
+}
+
+fn parse_module(content: &str) -> Result<Vec<Stmt>, String> {
+    match parse(content, Mode::Module, "<definition>") {
+        Ok(Mod::Module(module)) => Ok(module.body),
+        Ok(_) => unreachable!("parsed as a module"),
+        Err(err) => Err(format!(
+            "Can't find the definition, the file doesn't parse: {}",
+            err.error
+        )),
+    }
+}
+
+/// The definition (the statement and its body) that `covers` the issue,
+/// searching nested blocks as well.
+fn find<'a>(
+    suite: &'a [Stmt],
+    covers: &dyn Fn(&Stmt, &[Stmt]) -> bool,
+) -> Option<(&'a Stmt, &'a [Stmt])> {
+    for stmt in suite {
+        let body = match stmt {
+            Stmt::FunctionDef(def) => &def.body[..],
+            Stmt::AsyncFunctionDef(def) => &def.body[..],
+            Stmt::ClassDef(def) => &def.body[..],
+            _ => &[],
+        };
+        if !body.is_empty() {
+            if covers(stmt, body) {
+                return Some((stmt, body));
+            }
+            if let Some(found) = find(body, covers) {
+                return Some(found);
+            }
+        }
+        if let Some(found) = blocks(stmt)
+            .into_iter()
+            .find_map(|block| find(block, covers))
+        {
+            return Some(found);
+        }
+    }
+    None
+}
+
+fn collect_exits(suite: &[Stmt], line_of: &dyn Fn(usize) -> usize, exits: &mut Vec<usize>) {
+    for stmt in suite {
+        let exit = match stmt {
+            Stmt::Return(_) => true,
+            Stmt::Expr(expr) => matches!(*expr.value, Expr::Yield(_) | Expr::YieldFrom(_)),
+            _ => false,
+        };
+        if exit {
+            exits.push(line_of(usize::from(stmt.start())));
+        }
+        for block in blocks(stmt) {
+            collect_exits(block, line_of, exits);
+        }
+    }
+}
+
+/// The blocks of statements nested in a compound statement other than a
+/// function or class definition.
+fn blocks(stmt: &Stmt) -> Vec<&[Stmt]> {
+    match stmt {
+        Stmt::If(stmt) => vec![&stmt.body, &stmt.orelse],
+        Stmt::For(stmt) => vec![&stmt.body, &stmt.orelse],
+        Stmt::AsyncFor(stmt) => vec![&stmt.body, &stmt.orelse],
+        Stmt::While(stmt) => vec![&stmt.body, &stmt.orelse],
+        Stmt::With(stmt) => vec![&stmt.body],
+        Stmt::AsyncWith(stmt) => vec![&stmt.body],
+        Stmt::Try(stmt) => try_blocks(&stmt.body, &stmt.handlers, &stmt.orelse, &stmt.finalbody),
+        Stmt::TryStar(stmt) => {
+            try_blocks(&stmt.body, &stmt.handlers, &stmt.orelse, &stmt.finalbody)
+        }
+        Stmt::Match(stmt) => stmt.cases.iter().map(|case| &case.body[..]).collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn try_blocks<'a>(
+    body: &'a [Stmt],
+    handlers: &'a [ExceptHandler],
+    orelse: &'a [Stmt],
+    finalbody: &'a [Stmt],
+) -> Vec<&'a [Stmt]> {
+    let mut blocks = vec![body, orelse, finalbody];
+    blocks.extend(
+        handlers
+            .iter()
+            .map(|ExceptHandler::ExceptHandler(handler)| &handler.body[..]),
+    );
+    blocks
+}
+
+/// Where `stmt` starts, including its decorators.
+fn first_offset(stmt: &Stmt) -> usize {
+    let decorators = match stmt {
+        Stmt::FunctionDef(def) => &def.decorator_list[..],
+        Stmt::AsyncFunctionDef(def) => &def.decorator_list[..],
+        Stmt::ClassDef(def) => &def.decorator_list[..],
+        _ => &[],
+    };
+    decorators
+        .iter()
+        .map(|decorator| usize::from(decorator.start()))
+        .chain([usize::from(stmt.start())])
+        .min()
+        .unwrap_or_default()
+}
+
+fn param_names(args: &Arguments) -> Vec<String> {
+    let mut names: Vec<String> = args
+        .posonlyargs
+        .iter()
+        .chain(&args.args)
+        .map(|arg| arg.def.arg.to_string())
+        .collect();
+    names.extend(args.vararg.iter().map(|arg| format!("*{}", arg.arg)));
+    names.extend(args.kwonlyargs.iter().map(|arg| arg.def.arg.to_string()));
+    names.extend(args.kwarg.iter().map(|arg| format!("**{}", arg.arg)));
+    names
+}
+
+fn is_blank_or_comment(line: &str) -> bool {
+    let line = line.trim();
+    line.is_empty() || line.starts_with('#')
+}
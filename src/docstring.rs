@@ -0,0 +1,157 @@
+use std::str::FromStr;
+
+use crate::definition;
+use crate::sanitize;
+
+/// Whether ruff's rule `code` reports a missing docstring (pydocstyle's
+/// `D1xx` rules).
+pub fn is_missing_docstring(code: &str) -> bool {
+    code.starts_with("D1")
+}
+
+/// The docstring convention generated docstrings follow.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Style {
+    Google,
+    Numpy,
+}
+
+impl FromStr for Style {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "google" => Ok(Style::Google),
+            "numpy" => Ok(Style::Numpy),
+            other => Err(format!("unknown docstring style: {}", other)),
+        }
+    }
+}
+
+/// Where a missing docstring goes, and the code it documents.
+pub struct Target {
+    /// What is documented: a module, class, method or function.
+    pub kind: &'static str,
+    /// The definition, or the start of the module, as the model sees it.
+    pub context: String,
+    /// Zero-based index of the line the docstring is inserted before.
+    line: usize,
+    indent: String,
+}
+
+/// Finds what the missing-docstring issue of rule `code` at the one-based
+/// `row` is about.
+pub fn locate(content: &str, code: &str, row: usize) -> Result<Target, String> {
+    let kind = match code {
+        "D100" | "D104" => {
+            return Ok(Target {
+                kind: "module",
+                context: definition::quote(content, 0, usize::MAX),
+                line: definition::first_statement_line(content)?,
+                indent: String::new(),
+            });
+        }
+        "D101" | "D106" => "class",
+        "D102" | "D105" | "D107" => "method",
+        _ => "function",
+    };
+
+    let definition = definition::at_row(content, row)?;
+    if definition.is_one_line() {
+        return Err(format!(
+            "The {} at line {} is written on one line and can't take a docstring",
+            kind,
+            definition.header_start + 1
+        ));
+    }
+    let indent = content
+        .lines()
+        .nth(definition.body_start)
+        .unwrap_or_default()
+        .chars()
+        .take_while(|c| *c == ' ' || *c == '\t')
+        .collect();
+    Ok(Target {
+        kind,
+        context: definition::quote(content, definition.first_line, definition.end),
+        // Right after the header, above any comments opening the body.
+        line: definition.header_end,
+        indent,
+    })
+}
+
+impl Target {
+    /// Puts the docstring from the model's `answer` in place, indented like
+    /// the code it documents. The error is phrased for the model.
+    pub fn insert(&self, content: &str, answer: &str) -> Result<String, String> {
+        let docstring = self.format(answer)?;
+        let lines: Vec<&str> = content.split_inclusive('\n').collect();
+        let mut inserted = lines[..self.line].concat();
+        if !inserted.is_empty() && !inserted.ends_with('\n') {
+            inserted.push('\n');
+        }
+        inserted.push_str(&docstring);
+        inserted.push_str(&lines[self.line..].concat());
+        Ok(inserted)
+    }
+
+    /// The docstring in the answer as lines of code, triple-quoted.
+    fn format(&self, answer: &str) -> Result<String, String> {
+        let text = sanitize::clean(answer);
+        let text = dedent(text.trim_matches(['\n', '\r']));
+        let text = text.trim_end();
+        if text.trim().is_empty() {
+            return Err("The answer was empty; return the docstring.".to_string());
+        }
+        let quoted = if ["\"\"\"", "r\"\"\"", "'''", "r'''"]
+            .iter()
+            .any(|quote| text.starts_with(quote))
+        {
+            text.to_string()
+        } else {
+            if text.contains("\"\"\"") {
+                return Err(
+                    "The answer is not a single docstring; return only the docstring, including its triple quotes."
+                        .to_string(),
+                );
+            }
+            format!("\"\"\"{}\"\"\"", text)
+        };
+        if !(quoted.ends_with("\"\"\"") || quoted.ends_with("'''")) || quoted.len() < 6 {
+            return Err(
+                "The docstring is not closed; return the complete docstring, including its closing triple quotes."
+                    .to_string(),
+            );
+        }
+
+        let mut docstring = String::new();
+        for line in quoted.lines() {
+            if !line.trim().is_empty() {
+                docstring.push_str(&self.indent);
+                docstring.push_str(line.trim_end());
+            }
+            docstring.push('\n');
+        }
+        Ok(docstring)
+    }
+}
+
+/// Removes the indentation the lines of `text` after the first share, and
+/// any from the first line, like Python's `inspect.cleandoc`.
+fn dedent(text: &str) -> String {
+    let common = text
+        .lines()
+        .skip(1)
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| line.len() - line.trim_start().len())
+        .min()
+        .unwrap_or(0);
+    text.lines()
+        .enumerate()
+        .map(|(i, line)| match i {
+            0 => line.trim_start(),
+            _ => line.get(common..).unwrap_or_default(),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
@@ -0,0 +1,148 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use encoding_rs::{Encoding, UTF_8, WINDOWS_1251, WINDOWS_1252};
+
+use crate::atomic;
+
+/// How a Python file is encoded, so that fixes are written back the same
+/// way.
+#[derive(Clone, Copy)]
+pub struct SourceEncoding {
+    encoding: &'static Encoding,
+    /// Whether the file starts with a byte order mark.
+    bom: bool,
+}
+
+impl Default for SourceEncoding {
+    fn default() -> Self {
+        SourceEncoding {
+            encoding: UTF_8,
+            bom: false,
+        }
+    }
+}
+
+impl SourceEncoding {
+    pub fn name(&self) -> &'static str {
+        self.encoding.name()
+    }
+
+    pub fn is_utf8(&self) -> bool {
+        self.encoding == UTF_8
+    }
+
+    /// Rejects text with characters the encoding can't represent, phrased
+    /// for the model.
+    pub fn check(&self, text: &str) -> Result<(), String> {
+        if self.is_utf8() {
+            return Ok(());
+        }
+        match text
+            .chars()
+            .find(|c| self.encoding.encode(c.encode_utf8(&mut [0; 4])).2)
+        {
+            Some(c) => Err(format!(
+                "The file is encoded as {}, which can't represent the character `{}`; use only characters that encoding has.",
+                self.name(),
+                c
+            )),
+            None => Ok(()),
+        }
+    }
+
+    fn encode(&self, text: &str) -> io::Result<Vec<u8>> {
+        self.check(text)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        let mut bytes = if self.bom {
+            // The only byte order mark ASCII-compatible encodings have.
+            vec![0xEF, 0xBB, 0xBF]
+        } else {
+            Vec::new()
+        };
+        bytes.extend_from_slice(&self.encoding.encode(text).0);
+        Ok(bytes)
+    }
+}
+
+/// Reads a Python file in the encoding it declares with a PEP 263 `coding`
+/// comment or a byte order mark, UTF-8 otherwise. A file that isn't valid
+/// UTF-8 and declares nothing is read as windows-1251 if it looks like
+/// Cyrillic text, and as windows-1252 (a superset of latin-1) otherwise.
+pub fn read(path: &Path) -> io::Result<(String, SourceEncoding)> {
+    let bytes = fs::read(path)?;
+    let (encoding, bom) = match Encoding::for_bom(&bytes) {
+        Some((encoding, len)) => (encoding, len),
+        None => (declared(&bytes).unwrap_or_else(|| guess(&bytes)), 0),
+    };
+    if !encoding.is_ascii_compatible() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("unsupported encoding {}", encoding.name()),
+        ));
+    }
+    let (text, had_errors) = encoding.decode_without_bom_handling(&bytes[bom..]);
+    if had_errors {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("not valid {}", encoding.name()),
+        ));
+    }
+    Ok((
+        text.into_owned(),
+        SourceEncoding {
+            encoding,
+            bom: bom > 0,
+        },
+    ))
+}
+
+/// Writes `text` to `path` in `encoding`, see [`atomic::write`].
+pub fn write(path: &Path, text: &str, encoding: SourceEncoding) -> io::Result<()> {
+    atomic::write(path, &encoding.encode(text)?)
+}
+
+/// The encoding named by a `coding[:=]` comment on one of the first two
+/// lines, as Python looks for it.
+fn declared(bytes: &[u8]) -> Option<&'static Encoding> {
+    bytes
+        .split(|&b| b == b'\n')
+        .take(2)
+        .map(String::from_utf8_lossy)
+        .take_while(|line| {
+            let line = line.trim_start();
+            line.is_empty() || line.starts_with('#')
+        })
+        .find_map(|line| {
+            let at = line.find("coding")?;
+            let rest = line[at + "coding".len()..].strip_prefix([':', '='])?;
+            let label: String = rest
+                .trim_start()
+                .chars()
+                .take_while(|c| c.is_alphanumeric() || matches!(c, '-' | '_' | '.'))
+                .collect();
+            // Python takes `latin_1` and `latin-1` for `latin1`.
+            [label.replace('_', "-"), label.replace(['_', '-'], "")]
+                .iter()
+                .find_map(|label| Encoding::for_label(label.as_bytes()))
+        })
+}
+
+fn guess(bytes: &[u8]) -> &'static Encoding {
+    if std::str::from_utf8(bytes).is_ok() {
+        return UTF_8;
+    }
+    // Cyrillic words are runs of high bytes; accented Latin letters mostly
+    // stand alone between ASCII ones.
+    let high = bytes.iter().filter(|&&b| b >= 0x80).count();
+    let in_runs = bytes
+        .windows(2)
+        .filter(|pair| pair[0] >= 0xC0 && pair[1] >= 0xC0)
+        .count();
+    if in_runs * 2 > high {
+        WINDOWS_1251
+    } else {
+        WINDOWS_1252
+    }
+}
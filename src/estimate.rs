@@ -0,0 +1,150 @@
+//! `ruffer estimate`: the requests a run would send to the model, counted
+//! in tokens and priced for several models, before any money is spent.
+//!
+//! The run goes as usual, ruff and all, but writes nothing, and every
+//! request is counted instead of sent. Each issue is counted as fixed by
+//! its first answer; answers that fail verification and are asked again
+//! cost more. Fixes already in the cache cost nothing.
+
+use std::fmt;
+use std::io;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use structopt::StructOpt;
+
+use crate::fixer::RuffFixer;
+use crate::provider::{Completion, CompletionRequest, FixProvider};
+use crate::tokens::{count_tokens, model_price};
+
+/// Options of `ruffer estimate`: those of the run to estimate, and the
+/// models to price it for.
+#[derive(StructOpt)]
+pub struct EstimateOptions {
+    #[structopt(flatten)]
+    fixer: RuffFixer,
+
+    #[structopt(
+        long,
+        use_delimiter = true,
+        default_value = "gpt-4o-mini,gpt-4.1-mini,gpt-4o,gpt-4.1,o3-mini",
+        help = "Models to price the run for, besides --model; comma-separated"
+    )]
+    compare: Vec<String>,
+}
+
+/// What a run would ask of the model, and what that would cost.
+pub struct Estimate {
+    pub requests: usize,
+    pub prompt_tokens: usize,
+    /// Guessed from the code sent, since the answers aren't known.
+    pub answer_tokens: usize,
+    /// The cost with each model in US dollars, the model of the run first;
+    /// none where the price isn't known.
+    pub costs: Vec<(String, Option<f64>)>,
+}
+
+impl EstimateOptions {
+    pub fn init_logging(&self) -> io::Result<()> {
+        self.fixer.init_logging()
+    }
+
+    /// Prints the estimate.
+    pub async fn run(self) -> io::Result<()> {
+        print!("{}", self.estimate().await?);
+        Ok(())
+    }
+
+    /// Runs the fixer, counting the requests it would send.
+    pub async fn estimate(self) -> io::Result<Estimate> {
+        let counter = Arc::new(Counter {
+            answers_in_full: self.fixer.answers_in_full(),
+            requests: AtomicUsize::new(0),
+            prompt_tokens: AtomicUsize::new(0),
+            answer_tokens: AtomicUsize::new(0),
+        });
+        let model = self.fixer.model().to_string();
+        Arc::new(self.fixer.estimating())
+            .run_with(Arc::clone(&counter) as Arc<dyn FixProvider>)
+            .await?;
+
+        let prompt_tokens = counter.prompt_tokens.load(Ordering::Relaxed);
+        let answer_tokens = counter.answer_tokens.load(Ordering::Relaxed);
+        let mut models = vec![model];
+        for model in self.compare {
+            if !models.contains(&model) {
+                models.push(model);
+            }
+        }
+        let costs = models
+            .into_iter()
+            .map(|model| {
+                let cost =
+                    model_price(&model).map(|price| price.cost(prompt_tokens, answer_tokens));
+                (model, cost)
+            })
+            .collect();
+        Ok(Estimate {
+            requests: counter.requests.load(Ordering::Relaxed),
+            prompt_tokens,
+            answer_tokens,
+            costs,
+        })
+    }
+}
+
+impl fmt::Display for Estimate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} requests: {} prompt tokens, about {} answer tokens",
+            self.requests, self.prompt_tokens, self.answer_tokens
+        )?;
+        let width = self
+            .costs
+            .iter()
+            .map(|(model, _)| model.len())
+            .max()
+            .unwrap_or(0);
+        for (i, (model, cost)) in self.costs.iter().enumerate() {
+            let cost = cost.map_or("price unknown".to_string(), |cost| format!("${:.4}", cost));
+            let chosen = if i == 0 { "  (--model)" } else { "" };
+            writeln!(f, "  {:width$}  {}{}", model, cost, chosen, width = width)?;
+        }
+        Ok(())
+    }
+}
+
+/// A provider counting the requests instead of sending them. Every
+/// request fails, so each issue is asked about once.
+struct Counter {
+    answers_in_full: bool,
+    requests: AtomicUsize,
+    prompt_tokens: AtomicUsize,
+    answer_tokens: AtomicUsize,
+}
+
+impl FixProvider for Counter {
+    fn complete<'a>(&'a self, request: &'a CompletionRequest) -> Completion<'a> {
+        Box::pin(async move {
+            let prompt_tokens: usize = request
+                .messages
+                .iter()
+                .map(|message| count_tokens(&message.content))
+                .sum();
+            // The code sent comes back whole, or as a diff a fraction of it.
+            let code = count_tokens(request.prompt());
+            let answer_tokens = if self.answers_in_full {
+                code
+            } else {
+                code / 10
+            };
+            self.requests.fetch_add(1, Ordering::Relaxed);
+            self.prompt_tokens
+                .fetch_add(prompt_tokens, Ordering::Relaxed);
+            self.answer_tokens
+                .fetch_add(answer_tokens, Ordering::Relaxed);
+            Err("Not sent: estimating".into())
+        })
+    }
+}
@@ -0,0 +1,90 @@
+//! Fixes shown to the model as examples, with `--examples`: a directory
+//! holding a directory per rule code or prefix, such as `E501` or `SIM`,
+//! of pairs of files `<name>.before.<ext>` and `<name>.after.<ext>`, the
+//! code with an issue of the rule and the code fixed. The examples of the
+//! most specific directory matching an issue's rule go into the prompt
+//! fixing it, the first few by name.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::rules;
+
+/// Most examples put into one prompt.
+pub const MAX_EXAMPLES: usize = 3;
+
+pub struct Example {
+    pub before: String,
+    pub after: String,
+}
+
+/// The examples of `--examples`, by rule selector.
+pub struct Library {
+    rules: Vec<(String, Vec<Example>)>,
+}
+
+impl Library {
+    /// Reads the examples in `dir`. A file of a pair without the other is
+    /// an error; other files are left out.
+    pub fn load(dir: &Path) -> io::Result<Library> {
+        let mut rules = Vec::new();
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            if !entry.file_type()?.is_dir() {
+                continue;
+            }
+            let selector = entry.file_name().to_string_lossy().into_owned();
+            let mut pairs: BTreeMap<String, (Option<String>, Option<String>)> = BTreeMap::new();
+            for file in fs::read_dir(entry.path())? {
+                let path = file?.path();
+                let name = path
+                    .file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+                    .unwrap_or_default();
+                let (stem, side) = if let Some((stem, _)) = name.split_once(".before.") {
+                    (stem, 0)
+                } else if let Some((stem, _)) = name.split_once(".after.") {
+                    (stem, 1)
+                } else {
+                    continue;
+                };
+                let content = fs::read_to_string(&path)?;
+                let pair = pairs.entry(stem.to_string()).or_default();
+                if side == 0 {
+                    pair.0 = Some(content);
+                } else {
+                    pair.1 = Some(content);
+                }
+            }
+            let mut examples = Vec::new();
+            for (name, pair) in pairs {
+                match pair {
+                    (Some(before), Some(after)) => examples.push(Example { before, after }),
+                    _ => {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            format!(
+                                "Example {} in {} needs both {}.before.* and {}.after.*",
+                                name,
+                                entry.path().display(),
+                                name,
+                                name
+                            ),
+                        ))
+                    }
+                }
+            }
+            examples.truncate(MAX_EXAMPLES);
+            rules.push((selector, examples));
+        }
+        Ok(Library { rules })
+    }
+
+    /// The examples of rule `code`: those of the most specific directory
+    /// matching it.
+    pub fn for_rule(&self, code: &str) -> &[Example] {
+        rules::entry_for(code, &self.rules).map_or(&[], Vec::as_slice)
+    }
+}
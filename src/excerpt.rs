@@ -0,0 +1,234 @@
+use crate::tokens::count_tokens;
+
+/// A contiguous range of lines cut out of a file, sent to the model in
+/// place of the whole file when the file doesn't fit the context window.
+pub struct Excerpt {
+    /// Zero-based index of the first line.
+    pub start: usize,
+    /// Zero-based index one past the last line.
+    pub end: usize,
+    pub text: String,
+    /// Number of lines in the file the excerpt was taken from.
+    total_lines: usize,
+}
+
+impl Excerpt {
+    /// Grows a window outwards from `row` (one-based), alternating below and
+    /// above, for as long as it stays within `budget` tokens. Returns `None`
+    /// if not even the issue's own line fits.
+    pub fn around(content: &str, row: usize, budget: usize) -> Option<Excerpt> {
+        let lines: Vec<&str> = content.split_inclusive('\n').collect();
+        if lines.is_empty() {
+            return None;
+        }
+        let center = row.saturating_sub(1).min(lines.len() - 1);
+
+        let mut used = count_tokens(lines[center]);
+        if used > budget {
+            return None;
+        }
+        let (mut start, mut end) = (center, center + 1);
+        loop {
+            let mut grew = false;
+            if end < lines.len() {
+                let cost = count_tokens(lines[end]);
+                if used + cost <= budget {
+                    used += cost;
+                    end += 1;
+                    grew = true;
+                }
+            }
+            if start > 0 {
+                let cost = count_tokens(lines[start - 1]);
+                if used + cost <= budget {
+                    used += cost;
+                    start -= 1;
+                    grew = true;
+                }
+            }
+            if !grew {
+                break;
+            }
+        }
+
+        Some(Excerpt {
+            start,
+            end,
+            text: lines[start..end].concat(),
+            total_lines: lines.len(),
+        })
+    }
+
+    /// The issue's line (one-based `row`) plus up to `radius` lines on
+    /// either side.
+    pub fn lines_around(content: &str, row: usize, radius: usize) -> Option<Excerpt> {
+        let lines: Vec<&str> = content.split_inclusive('\n').collect();
+        if lines.is_empty() {
+            return None;
+        }
+        let center = row.saturating_sub(1).min(lines.len() - 1);
+        let start = center.saturating_sub(radius);
+        let end = (center + radius + 1).min(lines.len());
+        Some(Excerpt {
+            start,
+            end,
+            text: lines[start..end].concat(),
+            total_lines: lines.len(),
+        })
+    }
+
+    /// Whether the excerpt has at least `lines` lines on both sides of the
+    /// one-based `row`, or reaches the start/end of the file on that side.
+    pub fn has_context(&self, row: usize, lines: usize) -> bool {
+        let center = row.saturating_sub(1);
+        let above = self.start == 0 || center - self.start >= lines;
+        let below = self.end == self.total_lines || self.end - center > lines;
+        above && below
+    }
+
+    /// Human-readable one-based line range, e.g. `120-180`.
+    pub fn line_range(&self) -> String {
+        format!("{}-{}", self.start + 1, self.end)
+    }
+
+    /// Replaces the excerpt's lines in `content` with `replacement`.
+    pub fn splice(&self, content: &str, replacement: &str) -> String {
+        let lines: Vec<&str> = content.split_inclusive('\n').collect();
+        let mut spliced = lines[..self.start].concat();
+        spliced.push_str(replacement);
+        // Models tend to drop the final newline; the lines after the
+        // excerpt must still start on a line of their own, ended as the
+        // excerpt's last line was.
+        if self.text.ends_with('\n') && !replacement.ends_with('\n') {
+            spliced.push_str(if self.text.ends_with("\r\n") {
+                "\r\n"
+            } else {
+                "\n"
+            });
+        }
+        spliced.push_str(&lines[self.end..].concat());
+        spliced
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Ten lines of three tokens each.
+    fn lines(newline: &str) -> String {
+        (1..=10).map(|i| format!("line {}{}", i, newline)).collect()
+    }
+
+    #[test]
+    fn grows_around_the_issue_within_the_budget() {
+        let content = lines("\n");
+        let excerpt = Excerpt::around(&content, 5, 9).unwrap();
+
+        assert_eq!(excerpt.text, "line 4\nline 5\nline 6\n");
+        assert_eq!(excerpt.line_range(), "4-6");
+        assert!(excerpt.has_context(5, 1));
+        assert!(!excerpt.has_context(5, 2));
+    }
+
+    #[test]
+    fn grows_the_other_way_at_the_first_and_last_lines() {
+        let content = lines("\n");
+
+        let first = Excerpt::around(&content, 1, 9).unwrap();
+        assert_eq!(first.line_range(), "1-3");
+        // Nothing is missing above, but below are only two lines.
+        assert!(first.has_context(1, 2));
+        assert!(!first.has_context(1, 3));
+
+        let last = Excerpt::around(&content, 10, 9).unwrap();
+        assert_eq!(last.line_range(), "8-10");
+        assert!(last.has_context(10, 2));
+        assert!(!last.has_context(10, 3));
+
+        // Rows past the end are taken as the last line.
+        assert_eq!(Excerpt::around(&content, 12, 3).unwrap().text, "line 10\n");
+    }
+
+    #[test]
+    fn finds_nothing_when_the_issue_line_alone_is_over_budget() {
+        assert!(Excerpt::around(&lines("\n"), 5, 2).is_none());
+        assert!(Excerpt::around("", 1, 100).is_none());
+        assert!(Excerpt::lines_around("", 1, 3).is_none());
+    }
+
+    #[test]
+    fn takes_the_lines_around_the_issue() {
+        let content = lines("\n");
+
+        assert_eq!(
+            Excerpt::lines_around(&content, 5, 1).unwrap().line_range(),
+            "4-6"
+        );
+        assert_eq!(
+            Excerpt::lines_around(&content, 1, 2).unwrap().line_range(),
+            "1-3"
+        );
+        assert_eq!(
+            Excerpt::lines_around(&content, 10, 2).unwrap().line_range(),
+            "8-10"
+        );
+    }
+
+    #[test]
+    fn splices_the_fixed_lines_back_in() {
+        let content = lines("\n");
+        let excerpt = Excerpt::lines_around(&content, 5, 1).unwrap();
+
+        let spliced = excerpt.splice(&content, "line four\nline 5\n");
+        assert_eq!(
+            spliced,
+            content.replace("line 4\nline 5\nline 6\n", "line four\nline 5\n")
+        );
+        // A dropped final newline is put back.
+        assert_eq!(
+            excerpt.splice(&content, "line 4\nline five\nline 6"),
+            content.replace("line 5\n", "line five\n")
+        );
+    }
+
+    #[test]
+    fn splices_at_the_first_and_last_lines() {
+        let content = lines("\n");
+
+        let first = Excerpt::lines_around(&content, 1, 0).unwrap();
+        assert_eq!(
+            first.splice(&content, "line one\n"),
+            content.replacen("line 1\n", "line one\n", 1)
+        );
+
+        let last = Excerpt::lines_around(&content, 10, 0).unwrap();
+        assert_eq!(
+            last.splice(&content, "line ten"),
+            content.replace("line 10\n", "line ten\n")
+        );
+        let unterminated = content.trim_end();
+        let last = Excerpt::lines_around(unterminated, 10, 0).unwrap();
+        assert_eq!(
+            last.splice(unterminated, "line ten"),
+            unterminated.replace("line 10", "line ten")
+        );
+    }
+
+    #[test]
+    fn keeps_windows_line_endings() {
+        let content = lines("\r\n");
+        let excerpt = Excerpt::around(&content, 5, 9).unwrap();
+        assert_eq!(excerpt.text, "line 4\r\nline 5\r\nline 6\r\n");
+
+        assert_eq!(
+            excerpt.splice(&content, "line 4\r\nline five\r\nline 6\r\n"),
+            content.replace("line 5\r\n", "line five\r\n")
+        );
+        // A dropped final line ending is put back as the file has them.
+        assert_eq!(
+            excerpt.splice(&content, "line 4\r\nline five\r\nline 6"),
+            content.replace("line 5\r\n", "line five\r\n")
+        );
+    }
+}
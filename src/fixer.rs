@@ -0,0 +1,3683 @@
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::io::{self, BufRead, Read, Write};
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant, SystemTime};
+
+use structopt::StructOpt;
+use tokio::sync::mpsc;
+use tokio::task;
+use tracing::level_filters::LevelFilter;
+use tracing::Instrument;
+
+use crate::annotate;
+use crate::audit::AuditLog;
+use crate::autofix;
+use crate::baseline::Baseline;
+use crate::budget::{self, Budget};
+use crate::cache::{Eviction, ResponseCache};
+use crate::check::CheckOptions;
+use crate::chunk;
+use crate::ci::{self, FailOn};
+use crate::commit::{self, CommitBy};
+use crate::console::{Console, DiffLayout, Event, Output};
+use crate::conversation::Conversation;
+use crate::dashboard::Dashboard;
+use crate::dedupe::Duplicates;
+use crate::docstring;
+use crate::encoding::{self, SourceEncoding};
+use crate::examples::Library;
+use crate::excerpt::Excerpt;
+use crate::git;
+use crate::github::{self, GitHub};
+use crate::gitlab::{self, GitLab};
+use crate::guard;
+use crate::hook;
+use crate::impact;
+use crate::issue::{self, Issue, Severity};
+use crate::journal::{Journal, DEFAULT_JOURNAL_DIR};
+use crate::language::Language;
+use crate::linter;
+use crate::lock::ProjectLock;
+use crate::log;
+use crate::merge;
+use crate::newline;
+use crate::noqa;
+use crate::patch;
+use crate::prompt::{
+    self, AnnotationPrompt, BatchPrompt, DocstringPrompt, ExplanationPrompt, IssuePrompt,
+    JustificationPrompt, ResponseFormat, ReviewPrompt, RiskPrompt, Scope,
+};
+use crate::provider::{CompletionRequest, FixProvider, Message, OpenAiProvider};
+use crate::report::{self, Effort, Report, Resolution};
+use crate::review::{self, Comment};
+use crate::rules::{self, RulePrompt, RuleSeverity, RuleStrategy, Strategy};
+use crate::sanitize;
+use crate::security::{self, Proposal};
+use crate::state::{Outcome, RunState, DEFAULT_STATE_FILE};
+use crate::suggest;
+use crate::syntax;
+use crate::template::{Template, Templates};
+use crate::tokens::{count_tokens, model_limits, model_price};
+use crate::typecheck::{self, TypeChecker};
+use crate::verify;
+use crate::Error;
+
+/// Where fixes from previous runs are kept unless `--cache-dir` says otherwise.
+pub const DEFAULT_CACHE_DIR: &str = ".ruffer_cache";
+
+/// Exit code of a run stopped by Ctrl-C or SIGTERM, as shells report a
+/// process killed by SIGINT.
+pub const INTERRUPTED_EXIT_CODE: i32 = 130;
+
+/// Fewest lines of context on each side of an issue worth sending as an
+/// excerpt; below that the file is fixed in chunks instead.
+const MIN_CONTEXT_LINES: usize = 3;
+
+/// Lines (or pieces of overlong lines) repeated between neighbouring chunks.
+const CHUNK_OVERLAP_SEGMENTS: usize = 3;
+
+/// Where code comes from and where the fixed code goes.
+type Pipe = (Box<dyn Read + Send>, Box<dyn io::Write + Send>);
+
+/// Options of a fixing run, as given on the command line.
+#[derive(StructOpt)]
+pub struct RuffFixer {
+    #[structopt(help = "OpenAI API Key")]
+    api_key: String,
+
+    #[structopt(flatten)]
+    check: CheckOptions,
+
+    #[structopt(
+        long,
+        help = "Plain, screen-reader-friendly output: one line per event, no color or progress animations"
+    )]
+    plain: bool,
+
+    #[structopt(
+        long,
+        conflicts_with = "interactive",
+        help = "Run in a CI pipeline: plain output, and a failing exit code as --fail-on says"
+    )]
+    ci: bool,
+
+    #[structopt(
+        long,
+        use_delimiter = true,
+        possible_values = &["unfixed", "any-change", "error"],
+        help = "Fail the run if issues are left unfixed, if any file changed, or if requests to the model failed; comma-separated [default with --ci: unfixed,error]"
+    )]
+    fail_on: Vec<FailOn>,
+
+    #[structopt(long, help = "Never color diffs, even in a terminal")]
+    no_color: bool,
+
+    #[structopt(
+        long,
+        default_value = "3",
+        help = "Lines of context around the changes in diffs"
+    )]
+    diff_context: usize,
+
+    #[structopt(
+        long,
+        default_value = "unified",
+        possible_values = &["unified", "side-by-side"],
+        help = "Show diffs as unified diffs, or in two columns as wide as the terminal"
+    )]
+    diff: DiffLayout,
+
+    #[structopt(
+        long,
+        default_value = "gpt-4o-mini",
+        help = "OpenAI model used to generate fixes"
+    )]
+    model: String,
+
+    #[structopt(
+        long = "skip",
+        number_of_values = 1,
+        help = "ID of an issue to leave alone; may be repeated"
+    )]
+    skip: Vec<String>,
+
+    #[structopt(
+        long,
+        default_value = "50000",
+        help = "Files larger than this many bytes are fixed through a snippet around each issue instead of as a whole"
+    )]
+    snippet_threshold: usize,
+
+    #[structopt(
+        long,
+        default_value = "30",
+        help = "Number of lines on each side of the issue included in a snippet"
+    )]
+    snippet_lines: usize,
+
+    #[structopt(long, help = "Don't reuse or store fixes from previous runs")]
+    no_cache: bool,
+
+    #[structopt(long, default_value = DEFAULT_CACHE_DIR, help = "Directory holding the cache of fixes")]
+    cache_dir: PathBuf,
+
+    #[structopt(
+        long,
+        default_value = "30",
+        help = "Cached fixes older than this many days are discarded"
+    )]
+    cache_ttl_days: u64,
+
+    #[structopt(
+        long,
+        default_value = "256",
+        help = "Least recently used fixes are discarded once the cache grows beyond this many megabytes"
+    )]
+    cache_max_mb: u64,
+
+    /// The cache the caller keeps open, as `ruffer serve` does for all its
+    /// runs; opened by the run otherwise.
+    #[structopt(skip)]
+    shared_cache: Option<Arc<ResponseCache>>,
+
+    #[structopt(
+        long,
+        parse(from_os_str),
+        help = "Save every API request and response into this directory"
+    )]
+    record: Option<PathBuf>,
+
+    #[structopt(
+        long,
+        parse(from_os_str),
+        conflicts_with = "record",
+        help = "Answer API requests from recordings in this directory instead of calling the API; the API key is ignored"
+    )]
+    replay: Option<PathBuf>,
+
+    #[structopt(
+        long,
+        parse(from_os_str),
+        help = "Log every prompt sent to the model and every answer, with timestamps, model and token counts, into this directory"
+    )]
+    audit_dir: Option<PathBuf>,
+
+    /// The audit log of this run, with `--audit-dir`.
+    #[structopt(skip)]
+    audit: OnceLock<AuditLog>,
+
+    #[structopt(
+        long = "report",
+        number_of_values = 1,
+        help = "Write a report of every issue and what became of it as <format>=<path>, or to stdout as <format>; the format is json, sarif, junit, html or markdown. Can be given more than once"
+    )]
+    reports: Vec<report::Target>,
+
+    /// What became of every issue, for `--report`.
+    #[structopt(skip)]
+    report: Report,
+
+    /// The fixes made so far, to repeat for identical issues.
+    #[structopt(skip)]
+    duplicates: Duplicates,
+
+    #[structopt(
+        long,
+        default_value = "3",
+        help = "How many times to ask for a fix that passes verification before giving up on an issue"
+    )]
+    verify_attempts: usize,
+
+    #[structopt(long, help = "Write fixes without re-linting them first")]
+    no_verify: bool,
+
+    #[structopt(
+        long,
+        default_value = "30",
+        help = "Reject fixes that change more than this percentage of the file's lines"
+    )]
+    max_changed_lines: f64,
+
+    #[structopt(
+        long,
+        default_value = "25",
+        help = "Reject fixes that grow or shrink the file by more than this percentage"
+    )]
+    max_size_change: f64,
+
+    #[structopt(
+        long,
+        default_value = "file",
+        possible_values = &["file", "diff"],
+        help = "Ask the model for the whole fixed file, or for a unified diff that is applied as a patch"
+    )]
+    response_format: ResponseFormat,
+
+    #[structopt(
+        long,
+        parse(from_os_str),
+        help = "Ask for each fix with the prompt of this template file instead of the built-in one, with variables such as {{rule_code}}, {{message}}, {{snippet}} and {{instructions}}"
+    )]
+    prompt_template: Option<PathBuf>,
+
+    #[structopt(
+        long,
+        parse(from_os_str),
+        help = "Take the system message of every request from this template file, with {{language}} for the language of the code"
+    )]
+    system_prompt_template: Option<PathBuf>,
+
+    #[structopt(
+        long = "rule-prompt",
+        number_of_values = 1,
+        help = "Ask for fixes of a rule or rule prefix with the prompt of a template file, as RULE=PATH, instead of that of --prompt-template or the built-in one; may be repeated"
+    )]
+    rule_prompts: Vec<RulePrompt>,
+
+    #[structopt(
+        long,
+        parse(from_os_str),
+        help = "Show the model examples of fixes from this directory, with a directory per rule code or prefix of <name>.before.<ext> and <name>.after.<ext> files"
+    )]
+    examples: Option<PathBuf>,
+
+    #[structopt(
+        long,
+        parse(from_os_str),
+        help = "Append this file, such as the team's style guide in Markdown, to the system message of every request, so that fixes follow its conventions"
+    )]
+    style_guide: Option<PathBuf>,
+
+    #[structopt(
+        long,
+        help = "Fix all issues of a file with a single request, then fix whatever it left one by one"
+    )]
+    batch: bool,
+
+    #[structopt(
+        long,
+        help = "Fix the issues of a file in one conversation with the model, asking about each further issue as a follow-up instead of sending the file again"
+    )]
+    conversation: bool,
+
+    #[structopt(
+        long,
+        help = "Command checking the fixes, such as the project's tests: run after every file written with {file} replaced by its path, or else once at the end of the run, with {files} replaced by the paths of the files written; with {tests}, after every file some test imports, with {tests} replaced by the paths of those tests"
+    )]
+    post_fix_cmd: Option<String>,
+
+    /// The content every file written had before the run and has now, to
+    /// roll them back if the `--post-fix-cmd` run at the end fails.
+    #[structopt(skip)]
+    written: Mutex<BTreeMap<String, (String, String)>>,
+
+    #[structopt(
+        long,
+        help = "Sort the imports of the files fixed with ruff's isort rules, as they are formatted"
+    )]
+    sort_imports: bool,
+
+    #[structopt(
+        long,
+        help = "Also apply ruff's own fixes that ruff considers unsafe, instead of asking the model about those issues"
+    )]
+    unsafe_fixes: bool,
+
+    #[structopt(
+        long,
+        number_of_values = 1,
+        help = "Only send issues of these rules or rule prefixes (e.g. D1, ANN) to the model; may be repeated"
+    )]
+    llm_rules: Vec<String>,
+
+    #[structopt(
+        long,
+        number_of_values = 1,
+        help = "Never send issues of these rules or rule prefixes to the model; may be repeated"
+    )]
+    no_llm_rules: Vec<String>,
+
+    #[structopt(
+        long = "strategy",
+        number_of_values = 1,
+        use_delimiter = true,
+        help = "How to deal with a rule or rule prefix, as RULE=STRATEGY with STRATEGY one of autofix, llm, noqa or skip; may be repeated"
+    )]
+    strategies: Vec<RuleStrategy>,
+
+    #[structopt(
+        long = "severity",
+        number_of_values = 1,
+        use_delimiter = true,
+        help = "Severity of a rule or rule prefix, as RULE=LEVEL with LEVEL one of error, warning or info; may be repeated"
+    )]
+    severities: Vec<RuleSeverity>,
+
+    #[structopt(
+        long,
+        possible_values = &["error", "warning", "info"],
+        help = "Only send issues of at least this severity to the model"
+    )]
+    min_severity: Option<Severity>,
+
+    #[structopt(
+        long,
+        help = "Suppress issues with `# noqa` comments instead of fixing them, unless --strategy says otherwise for their rule"
+    )]
+    noqa: bool,
+
+    #[structopt(
+        long,
+        help = "Ask the model for a short justification to add to each new noqa comment"
+    )]
+    noqa_justify: bool,
+
+    #[structopt(
+        long,
+        default_value = "google",
+        possible_values = &["google", "numpy"],
+        help = "Docstring convention for the docstrings written for missing-docstring (D1) issues"
+    )]
+    docstring_style: docstring::Style,
+
+    #[structopt(
+        long,
+        default_value = "auto",
+        possible_values = &["auto", "mypy", "pyright", "none"],
+        help = "Type checker that validates annotations written for ANN issues; auto uses mypy or pyright if either is installed"
+    )]
+    type_checker: typecheck::Choice,
+
+    #[structopt(
+        long,
+        help = "Type check every fix, not only annotations, and ask again about fixes that bring new type errors, with the errors in the prompt; needs mypy or pyright"
+    )]
+    type_check_fixes: bool,
+
+    #[structopt(
+        long,
+        default_value = "ruffer-security.patch",
+        parse(from_os_str),
+        help = "File the proposed fixes of security (S) issues are written to for review; they are never applied"
+    )]
+    security_report: PathBuf,
+
+    #[structopt(
+        long,
+        help = "Ask the model to explain each fix it made, and print the explanation with the fix"
+    )]
+    explain: bool,
+
+    #[structopt(
+        long,
+        help = "Only ask the model how to fix each issue, and write its comments to a review document instead of changing any file"
+    )]
+    review: bool,
+
+    #[structopt(
+        long,
+        default_value = "ruffer-review.md",
+        parse(from_os_str),
+        help = "File the review document of --review is written to"
+    )]
+    review_file: PathBuf,
+
+    #[structopt(
+        long,
+        parse(from_os_str),
+        help = "Leave alone the issues recorded in this baseline (see `ruffer baseline create`), only working on new ones"
+    )]
+    baseline: Option<PathBuf>,
+
+    #[structopt(
+        long,
+        parse(from_os_str),
+        help = "Copy every file into this directory before changing it, so the run can be undone without git"
+    )]
+    backup_dir: Option<PathBuf>,
+
+    /// The files backed up into `--backup-dir` so far: only their content
+    /// before the run is kept, not that of a later iteration.
+    #[structopt(skip)]
+    backed_up: Mutex<HashSet<String>>,
+
+    /// Whether the run said that pytest isn't there to collect the tests
+    /// of `{tests}`, which it says once.
+    #[structopt(skip)]
+    said_no_pytest: AtomicBool,
+
+    #[structopt(
+        long,
+        parse(from_os_str),
+        help = "Leave the files as they are and write the fixes as patches instead: one per file into this directory, or all into one file if it ends in .patch or .diff"
+    )]
+    patch_out: Option<PathBuf>,
+
+    /// The patches of a combined `--patch-out`, by file.
+    #[structopt(skip)]
+    patches: Mutex<BTreeMap<String, String>>,
+
+    #[structopt(
+        long,
+        help = "Commit the fixes with git, with messages listing the issues fixed, keeping them apart from other changes"
+    )]
+    commit: bool,
+
+    #[structopt(
+        long,
+        default_value = "file",
+        possible_values = &["file", "rule"],
+        help = "Make one commit per file fixed, or one per set of rules fixed"
+    )]
+    commit_by: CommitBy,
+
+    /// The files with changes not committed before the run, which
+    /// `--commit` leaves alone.
+    #[structopt(skip)]
+    uncommitted: OnceLock<HashSet<PathBuf>>,
+
+    #[structopt(
+        long,
+        help = "Commit the fixes to a new branch, push it and open a GitHub pull request of it, with the token in GITHUB_TOKEN"
+    )]
+    open_pr: bool,
+
+    #[structopt(
+        long,
+        help = "Branch the fixes of --open-pr or --open-mr are committed to [default: ruffer/fixes-<run id>]"
+    )]
+    pr_branch: Option<String>,
+
+    #[structopt(
+        long,
+        help = "Branch the pull request of --open-pr, or the merge request of --open-mr, is into [default: the branch checked out]"
+    )]
+    pr_base: Option<String>,
+
+    #[structopt(
+        long,
+        default_value = "origin",
+        help = "Git remote to push branches to"
+    )]
+    git_remote: String,
+
+    #[structopt(
+        long,
+        help = "GitHub repository of --open-pr or --suggest-on-pr, as owner/name [default: that of the git remote]"
+    )]
+    github_repo: Option<String>,
+
+    #[structopt(
+        long,
+        default_value = "https://api.github.com",
+        help = "Address of the GitHub API, for GitHub Enterprise"
+    )]
+    github_api_url: String,
+
+    /// The API of the repository of `--open-pr` or `--suggest-on-pr`, once
+    /// connected.
+    #[structopt(skip)]
+    github: OnceLock<GitHub>,
+
+    #[structopt(
+        long,
+        conflicts_with = "open_pr",
+        help = "Commit the fixes to a new branch, push it and open a GitLab merge request of it, with the token in GITLAB_TOKEN"
+    )]
+    open_mr: bool,
+
+    #[structopt(
+        long = "mr-label",
+        number_of_values = 1,
+        help = "Label of the merge request of --open-mr; may be repeated"
+    )]
+    mr_labels: Vec<String>,
+
+    #[structopt(
+        long = "mr-reviewer",
+        number_of_values = 1,
+        help = "Username of a reviewer of the merge request of --open-mr; may be repeated"
+    )]
+    mr_reviewers: Vec<String>,
+
+    #[structopt(
+        long,
+        help = "GitLab project of --open-mr, as group/name [default: that of the git remote]"
+    )]
+    gitlab_project: Option<String>,
+
+    #[structopt(
+        long,
+        default_value = "https://gitlab.com",
+        help = "Address of the GitLab instance of --open-mr"
+    )]
+    gitlab_url: String,
+
+    /// The API of the project of `--open-mr`, once connected.
+    #[structopt(skip)]
+    gitlab: OnceLock<GitLab>,
+
+    #[structopt(
+        long,
+        conflicts_with_all = &["commit", "open_pr", "open_mr", "patch_out", "review", "stdin_filename"],
+        help = "Leave the files as they are and review this GitHub pull request instead, suggesting every fix as a change to apply with one click, with the token in GITHUB_TOKEN"
+    )]
+    suggest_on_pr: Option<u64>,
+
+    #[structopt(
+        long = "check",
+        conflicts_with_all = &["commit", "open_pr", "open_mr", "patch_out", "review", "suggest_on_pr", "stdin_filename"],
+        help = "Only tell whether there is anything to fix, writing nothing, and fail the run if there is, as a merge gate"
+    )]
+    check_only: bool,
+
+    #[structopt(
+        long,
+        value_name = "n",
+        help = "Stop asking the model once its requests took this many tokens, fixing the most serious issues first"
+    )]
+    max_tokens: Option<usize>,
+
+    #[structopt(
+        long,
+        value_name = "dollars",
+        help = "Stop asking the model once its requests cost this much at the list price of --model, fixing the most serious issues first"
+    )]
+    max_cost: Option<f64>,
+
+    #[structopt(
+        long,
+        default_value = "100",
+        help = "Skip files with more issues than this, rather than asking the model about each in turn, unless --force is given"
+    )]
+    max_issues_per_file: usize,
+
+    #[structopt(
+        long,
+        help = "Fix files with more issues than --max-issues-per-file as well"
+    )]
+    force: bool,
+
+    #[structopt(
+        long,
+        default_value = "1",
+        help = "Check and fix again, as long as the fixes bring new issues, until ruff finds nothing or this many iterations are done"
+    )]
+    max_iterations: usize,
+
+    /// The budget of `--max-tokens` and `--max-cost`, if there is one.
+    #[structopt(skip)]
+    budget: OnceLock<Budget>,
+
+    #[structopt(
+        long,
+        value_name = "n",
+        help = "After the summary, list the n rules and the n files with the most issues"
+    )]
+    top: Option<usize>,
+
+    /// Whether the run is `ruffer estimate`'s, writing and printing nothing.
+    #[structopt(skip)]
+    estimating: bool,
+
+    /// The files of `--suggest-on-pr` as they are and as fixed, by file.
+    #[structopt(skip)]
+    suggested: Mutex<BTreeMap<String, (String, String)>>,
+
+    #[structopt(
+        long,
+        help = "Fix the code read from stdin as if it were this file, and write the fixed code to stdout instead of touching any file"
+    )]
+    stdin_filename: Option<String>,
+
+    /// The code of `--stdin-filename`, as fixed so far.
+    #[structopt(skip)]
+    buffer: Mutex<Option<String>>,
+
+    /// Where the code of `--stdin-filename` is read from and written to;
+    /// stdin and stdout unless given.
+    #[structopt(skip)]
+    pipe: Mutex<Option<Pipe>>,
+
+    /// Whether stdin and stdout are the caller's, as they are for
+    /// `ruffer mcp`.
+    #[structopt(skip)]
+    stdio_taken: bool,
+
+    /// The issues of the code of `--stdin-filename`, when they are given
+    /// rather than found by ruff.
+    #[structopt(skip)]
+    given_issues: Mutex<Option<Vec<Issue>>>,
+
+    #[structopt(
+        long,
+        help = "Leave files that change on disk while they are being fixed as they are, instead of merging the fixes into the changes"
+    )]
+    no_merge: bool,
+
+    #[structopt(
+        long,
+        default_value = DEFAULT_JOURNAL_DIR,
+        parse(from_os_str),
+        help = "Directory recording the changes of every run, for `ruffer undo`"
+    )]
+    journal_dir: PathBuf,
+
+    /// The journal of this run, once started.
+    #[structopt(skip)]
+    journal: OnceLock<Journal>,
+
+    #[structopt(
+        long,
+        default_value = DEFAULT_STATE_FILE,
+        parse(from_os_str),
+        help = "File recording the outcome of every issue as the run goes, for `--resume`"
+    )]
+    state_file: PathBuf,
+
+    #[structopt(
+        long,
+        help = "Continue the run recorded in the state file, leaving alone the files it finished"
+    )]
+    resume: bool,
+
+    /// The progress of this run, once started.
+    #[structopt(skip)]
+    state: OnceLock<RunState>,
+
+    /// Set on Ctrl-C: no more requests are sent, and files are written with
+    /// the fixes made so far.
+    #[structopt(skip)]
+    interrupted: AtomicBool,
+
+    #[structopt(
+        long,
+        help = "Show every fix from the model and ask whether to apply it, skip it or ask for another"
+    )]
+    interactive: bool,
+
+    #[structopt(
+        long,
+        conflicts_with_all = &["interactive", "plain", "ci"],
+        help = "Show the run in a dashboard in the terminal, with the queue of issues, the status of each file and the diff of each fix, and keys to apply, skip or retry fixes"
+    )]
+    tui: bool,
+
+    /// Where the answers of `--interactive` come from, stdin unless given;
+    /// locked while a question is asked, so that questions about fixes of
+    /// different files come one at a time.
+    #[structopt(skip)]
+    answers: tokio::sync::Mutex<Option<Box<dyn BufRead + Send>>>,
+
+    /// Set when the answer was to apply all remaining fixes.
+    #[structopt(skip)]
+    approve_all: AtomicBool,
+
+    #[structopt(
+        long,
+        help = "Log at this level and above: off, error, warn, info, debug or trace [default: off, or info with --log-file]"
+    )]
+    log_level: Option<LevelFilter>,
+
+    #[structopt(
+        long,
+        parse(from_os_str),
+        help = "Write the log to this file instead of stderr"
+    )]
+    log_file: Option<PathBuf>,
+
+    #[structopt(
+        long,
+        default_value = "text",
+        possible_values = &["text", "jsonl", "github"],
+        help = "Write messages for people to stdout, one JSON event per line for programs (the messages then go to stderr), or messages followed by GitHub Actions annotations of every issue"
+    )]
+    output: Output,
+
+    /// Where `--output jsonl` writes events, and `--output github` its
+    /// annotations; stdout unless given.
+    #[structopt(skip)]
+    events_out: Mutex<Option<Box<dyn io::Write + Send>>>,
+
+    /// The issues of the baseline, once read.
+    #[structopt(skip)]
+    known: OnceLock<Baseline>,
+
+    /// How each file being fixed is encoded, as found when reading it.
+    #[structopt(skip)]
+    encodings: Mutex<HashMap<String, SourceEncoding>>,
+
+    /// The type checker that is run, once located.
+    #[structopt(skip)]
+    checker: OnceLock<Option<TypeChecker>>,
+
+    /// The templates of `--prompt-template`, `--rule-prompt` and
+    /// `--system-prompt-template`, once read.
+    #[structopt(skip)]
+    templates: OnceLock<Templates>,
+
+    /// The examples of `--examples`, once read.
+    #[structopt(skip)]
+    library: OnceLock<Library>,
+
+    /// The content of `--style-guide`, once read.
+    #[structopt(skip)]
+    style: OnceLock<String>,
+}
+
+impl RuffFixer {
+    /// Makes the run fix the issues read from stdin, unless `--issues`
+    /// or `--from-sarif` names a file, as `ruffer apply` does.
+    pub fn applying_issues(mut self) -> Self {
+        if self.check.from_sarif.is_none() {
+            self.check.issues.get_or_insert_with(|| PathBuf::from("-"));
+        }
+        self
+    }
+
+    /// Makes the run check only `files`, as `ruffer watch` does with the
+    /// files saved.
+    pub fn checking(mut self, files: Vec<String>) -> Self {
+        self.check.limit_to(files);
+        self
+    }
+
+    /// Fixes the issues using the OpenAI API.
+    pub async fn run(self: Arc<Self>) -> io::Result<()> {
+        let provider = self.provider();
+        self.run_with(provider).await
+    }
+
+    /// The OpenAI API, as the options say to use it, at `OPENAI_BASE_URL`
+    /// if that is set.
+    pub fn provider(&self) -> Arc<dyn FixProvider> {
+        let provider = OpenAiProvider::new(
+            self.api_key.clone(),
+            self.record.clone(),
+            self.replay.clone(),
+        );
+        Arc::new(match std::env::var("OPENAI_BASE_URL") {
+            Ok(base) if !base.is_empty() => provider.with_base_url(&base),
+            _ => provider,
+        })
+    }
+
+    /// The paths to check and the root folder holding them, for
+    /// `ruffer watch`.
+    pub fn targets(&self) -> io::Result<(Vec<String>, PathBuf)> {
+        Ok((self.check.paths()?, self.check.project_dir()))
+    }
+
+    /// The console of a run, set up as the options say.
+    pub fn console(&self) -> Console {
+        let mut console = Console::new(self.plain || self.ci)
+            .with_context(self.diff_context)
+            .with_layout(self.diff);
+        if self.no_color {
+            console = console.without_color();
+        }
+        if self.estimating {
+            console = console.quiet();
+        }
+        console
+    }
+
+    /// Sets up logging as `--log-level` and `--log-file` say, for the whole
+    /// process; the binary does this before the run.
+    pub fn init_logging(&self) -> io::Result<()> {
+        let level = self.log_level.unwrap_or(if self.log_file.is_some() {
+            LevelFilter::INFO
+        } else {
+            LevelFilter::OFF
+        });
+        if level == LevelFilter::OFF {
+            return Ok(());
+        }
+        let out: Box<dyn io::Write + Send> = match &self.log_file {
+            Some(path) => Box::new(fs::File::create(path)?),
+            None => Box::new(io::stderr()),
+        };
+        log::init(level, out)
+    }
+
+    /// Writes the events of `--output jsonl`, or the annotations of
+    /// `--output github`, to `out` instead of stdout.
+    pub fn writing_events_to(self, out: impl io::Write + Send + 'static) -> Self {
+        *self.events_out.lock().unwrap() = Some(Box::new(out));
+        self
+    }
+
+    /// Reads the code of `--stdin-filename` from `input` and writes the
+    /// fixed code to `out`, instead of stdin and stdout.
+    pub fn piping(
+        self,
+        input: impl Read + Send + 'static,
+        out: impl io::Write + Send + 'static,
+    ) -> Self {
+        *self.pipe.lock().unwrap() = Some((Box::new(input), Box::new(out)));
+        self
+    }
+
+    /// Fixes the code read from [`Self::piping`] as if it were `filename`,
+    /// as `--stdin-filename` does, working on `issues` if given instead of
+    /// those ruff finds in it.
+    pub fn fixing_content(mut self, filename: String, issues: Option<Vec<Issue>>) -> Self {
+        self.stdin_filename = Some(filename);
+        *self.given_issues.get_mut().unwrap() = issues;
+        self
+    }
+
+    /// Leaves stdin and stdout to the caller, as `ruffer mcp` does to talk
+    /// over them: messages and diffs go to stderr instead.
+    pub fn leaving_stdio(mut self) -> Self {
+        self.stdio_taken = true;
+        self
+    }
+
+    /// Makes the run go as usual but write nothing and print nothing, as
+    /// `ruffer estimate` does to see the requests it would send.
+    pub fn estimating(mut self) -> Self {
+        self.estimating = true;
+        self
+    }
+
+    /// The model fixes are asked of.
+    pub fn model(&self) -> &str {
+        &self.model
+    }
+
+    /// Whether the model answers with the whole fixed code rather than a
+    /// diff.
+    pub fn answers_in_full(&self) -> bool {
+        self.response_format == ResponseFormat::File
+    }
+
+    /// The issues ruff finds in the paths to check, by file and with their
+    /// IDs, leaving the files as they are.
+    pub async fn issues(&self, console: &Console) -> io::Result<HashMap<String, Vec<Issue>>> {
+        if self.stdio_taken {
+            self.check_piping()?;
+        }
+        self.check.locate_ruff(console, &self.cache_dir).await?;
+        let mut issues_by_file = self.check.collect(console, false)?;
+        for (filename, issues) in &mut issues_by_file {
+            let (content, _) = encoding::read(Path::new(filename))?;
+            issue::assign_ids(issues, &content);
+        }
+        Ok(issues_by_file)
+    }
+
+    /// The issues ruff finds in `content` as if it were `filename`, with
+    /// their IDs.
+    pub async fn issues_in(
+        &self,
+        console: &Console,
+        filename: &str,
+        content: &str,
+    ) -> io::Result<Vec<Issue>> {
+        self.check.locate_ruff(console, &self.cache_dir).await?;
+        let mut issues = self.lint(filename, content)?;
+        issue::assign_ids(&mut issues, content);
+        Ok(issues)
+    }
+
+    /// Reuses fixes from, and stores them into, `cache` instead of the one
+    /// in `--cache-dir`, unless `--no-cache` is given.
+    pub fn sharing_cache(mut self, cache: Arc<ResponseCache>) -> Self {
+        self.shared_cache = Some(cache);
+        self
+    }
+
+    /// The cache of fixes in `--cache-dir`, unless `--no-cache` is given or
+    /// it can't be opened.
+    pub fn open_cache(&self, console: &Console) -> Option<Arc<ResponseCache>> {
+        if self.no_cache {
+            return None;
+        }
+        let eviction = Eviction {
+            ttl: Duration::from_secs(self.cache_ttl_days * 24 * 60 * 60),
+            max_bytes: self.cache_max_mb * 1024 * 1024,
+        };
+        match ResponseCache::open(&self.cache_dir, eviction) {
+            Ok(cache) => Some(Arc::new(cache)),
+            Err(err) => {
+                console.error(format!(
+                    "Error opening cache in {}, continuing without it: {}",
+                    self.cache_dir.display(),
+                    err
+                ));
+                None
+            }
+        }
+    }
+
+    /// The `json` report of the run so far, for `ruffer serve`.
+    pub fn json_report(&self, duration: Duration) -> serde_json::Value {
+        let run_id = self.journal.get().map_or("", Journal::id);
+        self.report.json(run_id, duration)
+    }
+
+    /// Takes the answers of `--interactive` from `input` instead of stdin,
+    /// one per line.
+    pub fn answering_from(mut self, input: impl BufRead + Send + 'static) -> Self {
+        *self.answers.get_mut() = Some(Box::new(input));
+        self
+    }
+
+    /// Stops the run as Ctrl-C does: no more requests are sent, the files
+    /// being fixed are written with the fixes made so far, and the run
+    /// ends with an [`io::ErrorKind::Interrupted`] error.
+    pub fn interrupt(&self) {
+        self.interrupted.store(true, Ordering::SeqCst);
+    }
+
+    /// Fixes the issues using answers from `provider`.
+    pub async fn run_with(self: Arc<Self>, provider: Arc<dyn FixProvider>) -> io::Result<()> {
+        let mut console = self.console();
+        if self.stdin_filename.is_some() || self.stdio_taken {
+            self.check_piping()?;
+            console = console.leaving_stdout();
+        }
+        if self.output == Output::Jsonl {
+            let out = self.events_out.lock().unwrap().take();
+            console = console.with_events(out.unwrap_or_else(|| Box::new(io::stdout())));
+        }
+        if self.tui {
+            console = self.show_dashboard(console).await?;
+        }
+        let started = Instant::now();
+        let fixer = Arc::clone(&self);
+        let signal_console = console.clone();
+        let signals = task::spawn(async move {
+            let console = signal_console;
+            tokio::select! {
+                _ = shutdown_signal() => {}
+                _ = console.quit_requested() => {}
+            }
+            fixer.interrupt();
+            console.error(
+                "Interrupted: finishing the files being fixed; interrupt again to quit at once",
+            );
+            shutdown_signal().await;
+            console.close_dashboard();
+            std::process::exit(INTERRUPTED_EXIT_CODE);
+        });
+        let result = Arc::clone(&self)
+            .run_until_done(provider, console.clone())
+            .await;
+        signals.abort();
+        console.finish(&self.report, &self.model);
+        if let Some(n) = self.top {
+            console.top(&self.report, n);
+        }
+        result?;
+        if !self.review {
+            self.write_combined_patch(&console)?;
+            self.write_reports(&console, started.elapsed())?;
+            if self.output == Output::Github {
+                self.annotate(started.elapsed())?;
+            }
+        }
+        self.write_buffer()?;
+        if self.check.staged && self.stdin_filename.is_none() {
+            self.restage(&console)?;
+        }
+        if self.estimating {
+            // Nothing was written to commit or suggest.
+        } else if let Some(number) = self.suggest_on_pr {
+            self.suggest_fixes(&console, number).await?;
+        } else if self.open_pr || self.open_mr {
+            self.open_pull_request(&console, started.elapsed()).await?;
+        } else if self.commit {
+            let changes = self.committable(&console);
+            self.commit_fixes(&console, &changes)?;
+        }
+        if self.interrupted.load(Ordering::SeqCst) {
+            return Err(io::Error::new(
+                io::ErrorKind::Interrupted,
+                "Interrupted; run again with --resume to continue",
+            ));
+        }
+        self.apply_policy(&console)
+    }
+
+    /// Fails the run as `--fail-on` says, or as `--check` or `--ci` do by
+    /// default: `--check` if there is anything to fix.
+    fn apply_policy(&self, console: &Console) -> io::Result<()> {
+        if self.estimating {
+            return Ok(());
+        }
+        let fail_on = match (&self.fail_on[..], self.check_only, self.ci) {
+            ([], true, _) => &[FailOn::AnyChange, FailOn::Unfixed][..],
+            ([], false, true) => &[FailOn::Unfixed, FailOn::Error][..],
+            (fail_on, _, _) => fail_on,
+        };
+        let outcome = ci::Outcome {
+            unfixed: self.report.unfixed(),
+            changed: self.report.changes().len(),
+            errors: console.request_errors(),
+        };
+        match ci::verdict(fail_on, &outcome) {
+            Some(failure) => Err(io::Error::other(failure)),
+            None => Ok(()),
+        }
+    }
+
+    /// `console` showing the run on the dashboard of `--tui`, whose keys
+    /// answer the questions about fixes unless answers were given.
+    async fn show_dashboard(&self, console: Console) -> io::Result<Console> {
+        if self.output != Output::Text {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "--tui can't be used with --output, as the dashboard takes the terminal",
+            ));
+        }
+        let (dashboard, keys) = Dashboard::start(console.colors())?;
+        let mut answers = self.answers.lock().await;
+        if answers.is_none() {
+            *answers = Some(Box::new(io::BufReader::new(keys)));
+        }
+        Ok(console.with_dashboard(dashboard))
+    }
+
+    /// Fails for options that can't go with `--stdin-filename`, which
+    /// takes stdin and stdout for the code, or with a caller taking them.
+    fn check_piping(&self) -> io::Result<()> {
+        let piping = self.stdin_filename.is_some();
+        let conflict = if self
+            .check
+            .issues
+            .as_ref()
+            .is_some_and(|issues| piping || issues == Path::new("-"))
+        {
+            Some("--issues")
+        } else if self
+            .check
+            .from_sarif
+            .as_ref()
+            .is_some_and(|sarif| piping || sarif == Path::new("-"))
+        {
+            Some("--from-sarif")
+        } else if self.interactive {
+            Some("--interactive")
+        } else if self.tui {
+            Some("--tui")
+        } else if piping && self.review {
+            Some("--review")
+        } else if self.output != Output::Text && self.events_out.lock().unwrap().is_none() {
+            Some("--output")
+        } else if self.reports.iter().any(|target| target.path.is_none()) {
+            Some("--report without a file")
+        } else {
+            None
+        };
+        match conflict {
+            Some(option) => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                if piping {
+                    format!(
+                        "{} can't be used with --stdin-filename, which reads the code from stdin and writes it to stdout",
+                        option
+                    )
+                } else {
+                    format!(
+                        "{} can't be used when stdin and stdout are taken, as by ruffer mcp",
+                        option
+                    )
+                },
+            )),
+            None => Ok(()),
+        }
+    }
+
+    /// Reads the code of `--stdin-filename` and finds its issues.
+    fn collect_stdin(
+        &self,
+        console: &Console,
+        filename: &str,
+    ) -> io::Result<HashMap<String, Vec<Issue>>> {
+        if self.check.language() == Language::Rust {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "--stdin-filename needs ruff; clippy only checks the files on disk",
+            ));
+        }
+        let mut buffer = String::new();
+        match self.pipe.lock().unwrap().as_mut() {
+            Some((input, _)) => input.read_to_string(&mut buffer)?,
+            None => io::stdin().read_to_string(&mut buffer)?,
+        };
+        let issues = match self.given_issues.lock().unwrap().take() {
+            Some(issues) => issues,
+            None => {
+                console.status(format!("Running Ruff check on {}...", filename));
+                verify::lint_content(
+                    self.check.ruff(),
+                    None,
+                    &self.check.check_args(None),
+                    filename,
+                    &buffer,
+                )?
+            }
+        };
+        *self.buffer.lock().unwrap() = Some(buffer);
+        if issues.is_empty() {
+            console.status("All good");
+            return Ok(HashMap::new());
+        }
+        Ok(HashMap::from([(filename.to_string(), issues)]))
+    }
+
+    /// Writes the code of `--stdin-filename`, with the fixes made, to
+    /// stdout.
+    fn write_buffer(&self) -> io::Result<()> {
+        let Some(buffer) = self.buffer.lock().unwrap().take() else {
+            return Ok(());
+        };
+        let mut pipe = self.pipe.lock().unwrap();
+        let out: &mut dyn io::Write = match pipe.as_mut() {
+            Some((_, out)) => out,
+            None => &mut io::stdout(),
+        };
+        out.write_all(buffer.as_bytes())?;
+        out.flush()
+    }
+
+    /// Stages the fixes made to the files of `--staged`.
+    fn restage(&self, console: &Console) -> io::Result<()> {
+        let staged = self.check.staged_files()?.restage()?;
+        if !staged.is_empty() {
+            console.status(format!("Staged the fixes to {} files", staged.len()));
+        }
+        Ok(())
+    }
+
+    /// Whether the fixes are written to the files, rather than left as
+    /// patches, suggestions or a review, written to stdout or only counted.
+    fn writes(&self) -> bool {
+        !self.review
+            && self.patch_out.is_none()
+            && self.suggest_on_pr.is_none()
+            && !self.check_only
+            && !self.estimating
+            && self.stdin_filename.is_none()
+    }
+
+    /// The budget of `--max-tokens` and `--max-cost`; an error if the
+    /// price of the model isn't known for `--max-cost`.
+    fn budget(&self) -> io::Result<Budget> {
+        let max_cost = match self.max_cost {
+            Some(max) => match model_price(&self.model) {
+                Some(price) => Some((max, price)),
+                None => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!(
+                            "The price of {} isn't known, so --max-cost can't be kept; use --max-tokens instead",
+                            self.model
+                        ),
+                    ))
+                }
+            },
+            None => None,
+        };
+        Ok(Budget {
+            max_tokens: self.max_tokens,
+            max_cost,
+        })
+    }
+
+    /// Whether the requests to the model have spent the budget, if there
+    /// is one.
+    fn over_budget(&self) -> bool {
+        let (prompt, answer) = self.report.tokens();
+        self.budget
+            .get()
+            .is_some_and(|budget| budget.spent(prompt, answer))
+    }
+
+    /// Fails for options that can't go with `--commit` or `--open-pr`,
+    /// which commit the files fixed.
+    fn check_commit(&self) -> io::Result<()> {
+        let conflict = if self.stdin_filename.is_some() {
+            Some("--stdin-filename")
+        } else if self.review {
+            Some("--review")
+        } else if self.patch_out.is_some() {
+            Some("--patch-out")
+        } else if self.check.staged {
+            Some("--staged")
+        } else {
+            None
+        };
+        match conflict {
+            Some(option) => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "{} can't be used with {}, which leaves the files uncommitted",
+                    if self.open_pr {
+                        "--open-pr"
+                    } else if self.open_mr {
+                        "--open-mr"
+                    } else {
+                        "--commit"
+                    },
+                    option
+                ),
+            )),
+            None => Ok(()),
+        }
+    }
+
+    /// The API of the repository of `--open-pr` or `--suggest-on-pr`, with
+    /// the token in `GITHUB_TOKEN`.
+    fn connect_github(&self) -> io::Result<GitHub> {
+        let token = std::env::var("GITHUB_TOKEN").map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "The GitHub token is taken from GITHUB_TOKEN, which isn't set",
+            )
+        })?;
+        let repo = match &self.github_repo {
+            Some(repo) => repo.clone(),
+            None => {
+                let url = git::remote_url(&self.check.project_dir(), &self.git_remote)?;
+                github::repo_of(&url).ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!(
+                            "Can't tell the GitHub repository of {}; give it with --github-repo",
+                            url
+                        ),
+                    )
+                })?
+            }
+        };
+        Ok(GitHub::new(&self.github_api_url, token, repo))
+    }
+
+    /// The API of the project of `--open-mr`, with the token in
+    /// `GITLAB_TOKEN`.
+    fn connect_gitlab(&self) -> io::Result<GitLab> {
+        let token = std::env::var("GITLAB_TOKEN").map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "--open-mr takes the GitLab token from GITLAB_TOKEN, which isn't set",
+            )
+        })?;
+        let project = match &self.gitlab_project {
+            Some(project) => project.clone(),
+            None => {
+                let url = git::remote_url(&self.check.project_dir(), &self.git_remote)?;
+                gitlab::project_of(&url).ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!(
+                            "Can't tell the GitLab project of {}; give it with --gitlab-project",
+                            url
+                        ),
+                    )
+                })?
+            }
+        };
+        Ok(GitLab::new(&self.gitlab_url, token, project))
+    }
+
+    /// The files fixed, with the issues fixed in each, but for those that
+    /// had changes of their own before the run, which are left alone.
+    fn committable(&self, console: &Console) -> BTreeMap<String, Vec<report::Fixed>> {
+        let uncommitted = self.uncommitted.get();
+        let mut changes = self.report.changes();
+        changes.retain(|file, _| {
+            let dirty = fs::canonicalize(file)
+                .is_ok_and(|path| uncommitted.is_some_and(|files| files.contains(&path)));
+            if dirty {
+                console.status(format!(
+                    "{} had uncommitted changes before the run; fixes to it won't be committed",
+                    file
+                ));
+            }
+            !dirty
+        });
+        changes
+    }
+
+    /// Commits `changes` as `--commit-by` groups them.
+    fn commit_fixes(
+        &self,
+        console: &Console,
+        changes: &BTreeMap<String, Vec<report::Fixed>>,
+    ) -> io::Result<()> {
+        let dir = self.check.project_dir();
+        let commits = commit::commits(changes, self.commit_by);
+        for commit in &commits {
+            let files: Vec<PathBuf> = commit
+                .files
+                .iter()
+                .map(fs::canonicalize)
+                .collect::<io::Result<_>>()?;
+            git::commit(&dir, &files, &commit.message)?;
+        }
+        if !commits.is_empty() {
+            console.status(format!(
+                "Committed the fixes to {} files in {} commits",
+                changes.len(),
+                commits.len()
+            ));
+        }
+        Ok(())
+    }
+
+    /// Commits the fixes to a new branch, pushes it and opens a GitHub
+    /// pull request or GitLab merge request of it with the markdown report
+    /// as its description, then checks out the branch the run started on
+    /// again.
+    async fn open_pull_request(&self, console: &Console, duration: Duration) -> io::Result<()> {
+        let changes = self.committable(console);
+        if changes.is_empty() {
+            console.status("Nothing to open a pull request with");
+            return Ok(());
+        }
+        let dir = self.check.project_dir();
+        let started_on = git::current_branch(&dir)?;
+        let base = self.pr_base.clone().unwrap_or_else(|| started_on.clone());
+        let branch = self.pr_branch.clone().unwrap_or_else(|| {
+            format!(
+                "ruffer/fixes-{}",
+                self.journal.get().map_or("", Journal::id)
+            )
+        });
+        git::switch(&dir, &branch, true)?;
+        let committed = self.commit_fixes(console, &changes);
+        // Back where the run started, whether the commits were made or not.
+        git::switch(&dir, &started_on, false)?;
+        committed?;
+        git::push(&dir, &self.git_remote, &branch)?;
+        let fixed: usize = changes.values().map(Vec::len).sum();
+        let title = format!("Fix {} ruff issues in {} files", fixed, changes.len());
+        let description = self.report.markdown(duration);
+        if let Some(github) = self.github.get() {
+            let url = github
+                .open_pull_request(&title, &branch, &base, &description)
+                .await?;
+            console.status(format!("Opened pull request {}", url));
+        }
+        if let Some(gitlab) = self.gitlab.get() {
+            let url = gitlab
+                .open_merge_request(
+                    &title,
+                    &branch,
+                    &base,
+                    &description,
+                    &self.mr_labels,
+                    &self.mr_reviewers,
+                )
+                .await?;
+            console.status(format!("Opened merge request {}", url));
+        }
+        Ok(())
+    }
+
+    /// Reviews pull request `number` with a suggestion for every run of
+    /// lines the fixes change.
+    async fn suggest_fixes(&self, console: &Console, number: u64) -> io::Result<()> {
+        let Some(github) = self.github.get() else {
+            return Ok(());
+        };
+        let root = git::top_level(&self.check.project_dir())?;
+        let fixed = self.report.changes();
+        let mut comments = Vec::new();
+        for (filename, (before, after)) in self.suggested.lock().unwrap().iter() {
+            let path = fs::canonicalize(filename)?;
+            let path = path.strip_prefix(&root).unwrap_or(&path);
+            let fixed = fixed
+                .get(&issue::display_path(filename))
+                .map_or(&[][..], Vec::as_slice);
+            for suggestion in suggest::suggestions(before, after) {
+                comments.push(github::Comment {
+                    path: path.to_string_lossy().replace('\\', "/"),
+                    start: suggestion.start,
+                    end: suggestion.end,
+                    body: suggestion.body(fixed),
+                });
+            }
+        }
+        if comments.is_empty() {
+            console.status(format!("No fixes to suggest on pull request #{}", number));
+            return Ok(());
+        }
+        let issues: usize = fixed.values().map(Vec::len).sum();
+        let body = format!(
+            "ruffer suggests fixes of {} ruff issues; each suggestion can be applied as is.",
+            issues
+        );
+        let url = github.review(number, &body, &comments).await?;
+        console.status(format!(
+            "Suggested {} changes on pull request #{}: {}",
+            comments.len(),
+            number,
+            url
+        ));
+        Ok(())
+    }
+
+    /// Writes the reports `--report` asks for.
+    fn write_reports(&self, console: &Console, duration: Duration) -> io::Result<()> {
+        let run_id = self.journal.get().map_or("", Journal::id);
+        for target in &self.reports {
+            let destination = target
+                .path
+                .as_ref()
+                .map_or("stdout".into(), |path| path.display().to_string());
+            self.report.write(target, run_id, duration).map_err(|err| {
+                io::Error::new(
+                    err.kind(),
+                    format!("Error writing report {}: {}", destination, err),
+                )
+            })?;
+            if target.path.is_some() {
+                console.status(format!(
+                    "Wrote the {} report to {}",
+                    target.format, destination
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Prints a GitHub Actions annotation for every issue, and adds a
+    /// summary of the run to the job summary when there is one.
+    fn annotate(&self, duration: Duration) -> io::Result<()> {
+        let mut out = self
+            .events_out
+            .lock()
+            .unwrap()
+            .take()
+            .unwrap_or_else(|| Box::new(io::stdout()));
+        out.write_all(self.report.annotations().as_bytes())?;
+        out.flush()?;
+        if let Some(path) = std::env::var_os("GITHUB_STEP_SUMMARY") {
+            let mut summary = fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&path)?;
+            summary.write_all(self.report.markdown(duration).as_bytes())?;
+        }
+        Ok(())
+    }
+
+    async fn run_until_done(
+        self: Arc<Self>,
+        provider: Arc<dyn FixProvider>,
+        console: Console,
+    ) -> io::Result<()> {
+        self.check.locate_ruff(&console, &self.cache_dir).await?;
+        let checker = TypeChecker::locate(&self.check.project_dir(), self.type_checker)?;
+        match &checker {
+            Some(checker) => console.status(format!(
+                "Type checking {} with {}",
+                if self.type_check_fixes {
+                    "fixes"
+                } else {
+                    "annotations"
+                },
+                checker.path().display()
+            )),
+            None if self.type_check_fixes => {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    "--type-check-fixes needs a type checker, and neither mypy nor pyright was found in the project's virtualenv or on PATH",
+                ))
+            }
+            None => {}
+        }
+        let _ = self.checker.set(checker);
+        self.load_templates()?;
+        if let Some(dir) = &self.examples {
+            let library = Library::load(dir).map_err(|err| {
+                io::Error::new(
+                    err.kind(),
+                    format!("Error reading the examples in {}: {}", dir.display(), err),
+                )
+            })?;
+            let _ = self.library.set(library);
+        }
+        if let Some(path) = &self.style_guide {
+            let guide = fs::read_to_string(path).map_err(|err| {
+                io::Error::new(
+                    err.kind(),
+                    format!("Error reading the style guide {}: {}", path.display(), err),
+                )
+            })?;
+            let _ = self.style.set(guide);
+        }
+        let journal = Journal::new(&self.journal_dir);
+        if let Some(dir) = &self.audit_dir {
+            let _ = self.audit.set(AuditLog::new(dir, journal.id()));
+        }
+        let _ = self.journal.set(journal);
+        // Code from stdin has no progress worth resuming.
+        if self.stdin_filename.is_none() {
+            let state = if self.resume {
+                RunState::resume(&self.state_file)?
+            } else {
+                RunState::start(&self.state_file)
+            };
+            let _ = self.state.set(state);
+        }
+        if let Some(path) = &self.baseline {
+            let _ = self.known.set(Baseline::load(path)?);
+        }
+
+        // A review leaves the files alone, formatting included, so another
+        // run may well be fixing them; so does fixing code from stdin.
+        let _lock = if self.review || self.stdin_filename.is_some() {
+            None
+        } else {
+            Some(ProjectLock::acquire(&self.check.project_dir())?)
+        };
+        if self.commit || self.open_pr || self.open_mr {
+            self.check_commit()?;
+            let _ = self
+                .uncommitted
+                .set(git::uncommitted(&self.check.project_dir())?);
+        }
+        if self.open_pr || self.suggest_on_pr.is_some() {
+            let _ = self.github.set(self.connect_github()?);
+        }
+        if self.open_mr {
+            let _ = self.gitlab.set(self.connect_gitlab()?);
+        }
+        if self.max_tokens.is_some() || self.max_cost.is_some() {
+            let _ = self.budget.set(self.budget()?);
+        }
+        // Only the files fixed are formatted, once they are.
+        let issues_by_file = match &self.stdin_filename {
+            Some(filename) => self.collect_stdin(&console, filename)?,
+            None => self.check.collect(&console, false)?,
+        };
+        if issues_by_file.is_empty() {
+            return Ok(());
+        }
+
+        let provider = console.track(provider);
+        if self.review {
+            return self.write_review(provider, console, issues_by_file).await;
+        }
+        console.start(
+            issues_by_file.len(),
+            issues_by_file.values().map(Vec::len).sum(),
+        );
+
+        let cache = match &self.shared_cache {
+            Some(cache) if !self.no_cache => Some(Arc::clone(cache)),
+            _ => self.open_cache(&console),
+        };
+
+        let mut proposals = self
+            .fix_files(&provider, &console, cache.clone(), issues_by_file)
+            .await;
+        // Only fixes written to the files can be checked again.
+        if self.max_iterations > 1 && self.writes() && !self.check.staged {
+            proposals.extend(self.iterate(&provider, &console, cache).await?);
+        }
+        if !proposals.is_empty() {
+            proposals.sort_by(|a, b| (&a.filename, a.row).cmp(&(&b.filename, b.row)));
+            // `--check` and `--estimate` write nothing, the review included.
+            if self.check_only || self.estimating {
+                console.status(format!(
+                    "Would write {} proposed fixes of security issues to {} for review",
+                    proposals.len(),
+                    self.security_report.display()
+                ));
+            } else {
+                fs::write(&self.security_report, security::render(&proposals))?;
+                console.status(format!(
+                    "Wrote {} proposed fixes of security issues to {} for review",
+                    proposals.len(),
+                    self.security_report.display()
+                ));
+            }
+        }
+        if let Some(command) = self.post_fix_cmd.as_deref() {
+            let written = std::mem::take(&mut *self.written.lock().unwrap());
+            let files: Vec<String> = written.keys().cloned().collect();
+            if !files.is_empty() && !self.post_fix(&console, command, &files) {
+                for (filename, (original, now)) in written {
+                    self.roll_back(&console, &filename, &now, &original);
+                }
+            }
+        }
+        let failed = self.state.get().map_or(0, RunState::failed);
+        if failed > 0 {
+            console.status(format!(
+                "{} issues couldn't be fixed; run again with --resume to retry only their files",
+                failed
+            ));
+        }
+        if let Some(journal) = self.journal.get().filter(|journal| !journal.is_empty()) {
+            console.status(format!(
+                "Recorded the changes as run {}; `ruffer undo` reverts them",
+                journal.id()
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Fixes the issues of `issues_by_file`, each file in a task of its
+    /// own; the fixes of security issues proposed for review.
+    async fn fix_files(
+        self: &Arc<Self>,
+        provider: &Arc<dyn FixProvider>,
+        console: &Console,
+        cache: Option<Arc<ResponseCache>>,
+        issues_by_file: HashMap<String, Vec<Issue>>,
+    ) -> Vec<Proposal> {
+        let mut issues_by_file: Vec<(String, Vec<Issue>)> = issues_by_file.into_iter().collect();
+        let budgeted = self.budget.get().is_some();
+        if budgeted {
+            // The file with the issue most worth fixing goes first.
+            issues_by_file.sort_by_cached_key(|(filename, issues)| {
+                let tokens = encoding::read(Path::new(filename))
+                    .map_or(0, |(content, _)| count_tokens(&content));
+                issues
+                    .iter()
+                    .map(|issue| budget::priority(issue, self.severity(issue), tokens))
+                    .min()
+            });
+        }
+
+        let (tx, mut rx) = mpsc::channel(10);
+        let mut previous: Option<task::JoinHandle<()>> = None;
+        for (filename, issues) in issues_by_file {
+            // With a budget, files are fixed one after the other, in order,
+            // rather than all at once.
+            let after = if budgeted { previous.take() } else { None };
+            let tx = tx.clone();
+            let provider = Arc::clone(provider);
+            let cache = cache.clone();
+            let fixer = Arc::clone(self);
+            let console = console.clone();
+
+            let span = tracing::info_span!("file", path = %filename);
+            previous = Some(task::spawn(
+                async move {
+                    if let Some(after) = after {
+                        let _ = after.await;
+                    }
+                    let proposals = match fixer.start_file(provider, console, cache, filename) {
+                        Some(mut file) => file.fix(issues, budgeted).await,
+                        None => Vec::new(),
+                    };
+                    tx.send(proposals).await.unwrap();
+                }
+                .instrument(span),
+            ));
+        }
+
+        drop(tx);
+
+        let mut proposals = Vec::new();
+        while let Some(file_proposals) = rx.recv().await {
+            console.file_done();
+            proposals.extend(file_proposals);
+        }
+        proposals
+    }
+
+    /// Reads `filename` to fix it, unless the run was interrupted, the file
+    /// can't be read or the run being resumed finished it.
+    fn start_file(
+        self: Arc<Self>,
+        provider: Arc<dyn FixProvider>,
+        console: Console,
+        cache: Option<Arc<ResponseCache>>,
+        filename: String,
+    ) -> Option<FileRun> {
+        if self.interrupted.load(Ordering::SeqCst) {
+            return None;
+        }
+        console.status(format!("Processing file: {}", filename));
+        let content = match self.read(&console, &filename) {
+            Ok(content) => content,
+            Err(err) => {
+                console.error(format!("Error reading {}: {}", filename, err));
+                return None;
+            }
+        };
+        if self.finished(&filename, &content) {
+            console.status(format!(
+                "Skipping {}: finished by the run being resumed",
+                filename
+            ));
+            return None;
+        }
+        Some(FileRun {
+            conversation: self.conversation.then(Conversation::default),
+            fixer: self,
+            provider,
+            console,
+            cache,
+            filename,
+            original: content.clone(),
+            content,
+            proposals: Vec::new(),
+        })
+    }
+
+    /// Checks the files again after the fixes, for `--max-iterations`, and
+    /// fixes the issues the fixes brought, until ruff finds nothing, no
+    /// new issues are left or the iterations run out; the fixes of
+    /// security issues proposed for review.
+    async fn iterate(
+        self: &Arc<Self>,
+        provider: &Arc<dyn FixProvider>,
+        console: &Console,
+        cache: Option<Arc<ResponseCache>>,
+    ) -> io::Result<Vec<Proposal>> {
+        let mut proposals = Vec::new();
+        let mut iteration = 1;
+        while !self.interrupted.load(Ordering::SeqCst) {
+            let mut issues_by_file = self.check.collect(console, false)?;
+            let left: usize = issues_by_file.values().map(Vec::len).sum();
+            if left == 0 {
+                console.status(format!("Clean after {} iterations", iteration));
+                break;
+            }
+            if iteration == self.max_iterations {
+                console.status(format!(
+                    "Stopped after {} iterations, the most --max-iterations allows, with {} issues left",
+                    iteration, left
+                ));
+                break;
+            }
+            // Issues worked on before, and left, are left for good.
+            for (filename, issues) in issues_by_file.iter_mut() {
+                match self.read(console, filename) {
+                    Ok(content) => {
+                        issue::assign_ids(issues, &content);
+                        issues.retain(|issue| !self.report.knows(filename, &issue.id));
+                    }
+                    Err(err) => {
+                        console.error(format!("Error reading {}: {}", filename, err));
+                        issues.clear();
+                    }
+                }
+            }
+            issues_by_file.retain(|_, issues| !issues.is_empty());
+            if issues_by_file.is_empty() {
+                console.status(format!(
+                    "Stopped after {} iterations with {} issues left, none of them new",
+                    iteration, left
+                ));
+                break;
+            }
+            iteration += 1;
+            let new: usize = issues_by_file.values().map(Vec::len).sum();
+            console.status(format!(
+                "Iteration {}: fixing {} new issues in {} files",
+                iteration,
+                new,
+                issues_by_file.len()
+            ));
+            console.more(issues_by_file.len(), new);
+            proposals.extend(
+                self.fix_files(provider, console, cache.clone(), issues_by_file)
+                    .await,
+            );
+        }
+        Ok(proposals)
+    }
+
+    /// Asks the model how to fix each issue, and writes its comments to the
+    /// review file instead of fixing anything.
+    async fn write_review(
+        self: Arc<Self>,
+        provider: Arc<dyn FixProvider>,
+        console: Console,
+        issues_by_file: HashMap<String, Vec<Issue>>,
+    ) -> io::Result<()> {
+        let (tx, mut rx) = mpsc::channel(10);
+        for (filename, mut file_issues) in issues_by_file {
+            let tx = tx.clone();
+            let provider = Arc::clone(&provider);
+            let fixer = Arc::clone(&self);
+            let console = console.clone();
+
+            let span = tracing::info_span!("file", path = %filename);
+            task::spawn(
+                async move {
+                    let mut comments = Vec::new();
+                    match fixer.read(&console, &filename) {
+                        Ok(file_content) => {
+                            issue::assign_ids(&mut file_issues, &file_content);
+                            fixer.drop_known(&console, &filename, &mut file_issues);
+                            for issue in &file_issues {
+                                if fixer.skip.contains(&issue.id)
+                                    || fixer.strategy(issue) == Some(Strategy::Skip)
+                                {
+                                    continue;
+                                }
+                                console.status(format!(
+                                    "Reviewing [{}] {} at {}:{}:{}: {}",
+                                    issue.id,
+                                    issue.code,
+                                    filename,
+                                    issue.location.row,
+                                    issue.location.column,
+                                    issue.message
+                                ));
+                                match fixer
+                                    .comment(provider.as_ref(), &filename, issue, &file_content)
+                                    .await
+                                {
+                                    Ok(comment) => comments.push(comment),
+                                    Err(err) => console.error(format!(
+                                        "Error reviewing [{}] in {}: {}",
+                                        issue.id, filename, err
+                                    )),
+                                }
+                            }
+                        }
+                        Err(err) => console.error(format!("Error reading {}: {}", filename, err)),
+                    }
+                    tx.send(comments).await.unwrap();
+                }
+                .instrument(span),
+            );
+        }
+
+        drop(tx);
+
+        let mut comments = Vec::new();
+        while let Some(file_comments) = rx.recv().await {
+            comments.extend(file_comments);
+        }
+        comments.sort_by(|a, b| (&a.filename, a.row).cmp(&(&b.filename, b.row)));
+        fs::write(&self.review_file, review::render(&comments))?;
+        console.status(format!(
+            "Wrote {} review comments to {}",
+            comments.len(),
+            self.review_file.display()
+        ));
+        Ok(())
+    }
+
+    /// The model's comment on how to fix `issue`, made from the lines
+    /// around it.
+    async fn comment(
+        &self,
+        provider: &dyn FixProvider,
+        filename: &str,
+        issue: &Issue,
+        file_content: &str,
+    ) -> Result<Comment, Error> {
+        let row = issue.location.row as usize;
+        let excerpt = Excerpt::lines_around(file_content, row, self.snippet_lines)
+            .ok_or("The file is empty")?;
+        let issue_lines = issue.lines(file_content);
+        let prompt = ReviewPrompt {
+            filename,
+            issue_code: &issue.code,
+            issue_message: &issue.message,
+            issue_lines,
+            excerpt: &excerpt,
+        };
+        let answer = self
+            .request_completion(provider, &[], &prompt.render())
+            .await?;
+        Ok(Comment::new(
+            filename,
+            issue,
+            issue_lines,
+            &sanitize::clean(&answer),
+        ))
+    }
+
+    /// Reads a file to fix in its encoding, and remembers the encoding for
+    /// writing it back. The code of `--stdin-filename` was read already.
+    fn read(&self, console: &Console, filename: &str) -> io::Result<String> {
+        if self.stdin_filename.as_deref() == Some(filename) {
+            let buffer = self.buffer.lock().unwrap();
+            return Ok(buffer.clone().expect("stdin is read before fixing"));
+        }
+        let (content, source_encoding) = encoding::read(Path::new(filename))?;
+        if !source_encoding.is_utf8() {
+            console.status(format!(
+                "Reading {} as {}",
+                filename,
+                source_encoding.name()
+            ));
+        }
+        self.encodings
+            .lock()
+            .unwrap()
+            .insert(filename.to_string(), source_encoding);
+        Ok(content)
+    }
+
+    fn encoding(&self, filename: &str) -> SourceEncoding {
+        self.encodings
+            .lock()
+            .unwrap()
+            .get(filename)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Whether the run being resumed finished `filename`, leaving it with
+    /// `content`.
+    fn finished(&self, filename: &str, content: &str) -> bool {
+        self.resume
+            && self
+                .state
+                .get()
+                .is_some_and(|state| state.finished(filename, content))
+    }
+
+    /// Records what became of the issue `id`, for `--resume`, `--report`
+    /// and the progress line.
+    fn record(
+        &self,
+        console: &Console,
+        filename: &str,
+        id: &str,
+        resolution: Resolution,
+        effort: &Effort,
+    ) {
+        self.report.resolve(filename, id, resolution, effort);
+        let outcome = resolution.outcome();
+        console.issue_done(outcome);
+        console.event(match outcome {
+            Outcome::Fixed => Event::FixApplied { file: filename, id },
+            Outcome::Failed => Event::FixFailed { file: filename, id },
+            Outcome::LeftAlone => Event::IssueLeftAlone { file: filename, id },
+        });
+        if let Some(Err(err)) = self
+            .state
+            .get()
+            .map(|state| state.record(filename, id, outcome))
+        {
+            console.error(format!(
+                "Error saving progress to {}: {}",
+                self.state_file.display(),
+                err
+            ));
+        }
+    }
+
+    /// Records the issue `id` of `filename` as skipped for `reason` in the
+    /// report, but not in the state file, so that `--resume` or another
+    /// run picks it up.
+    fn leave_for_later(&self, console: &Console, filename: &str, id: &str, reason: &str) {
+        self.report.skip(filename, id, reason);
+        console.issue_done(Outcome::LeftAlone);
+        console.event(Event::IssueLeftAlone { file: filename, id });
+    }
+
+    /// Leaves out the issues of the baseline, if one was given.
+    fn drop_known(&self, console: &Console, filename: &str, issues: &mut Vec<Issue>) {
+        let Some(known) = self.known.get() else {
+            return;
+        };
+        let count = issues.len();
+        issues.retain(|issue| !known.contains(&issue.id));
+        if issues.len() < count {
+            console.status(format!(
+                "Leaving {} issues of the baseline in {} alone",
+                count - issues.len(),
+                filename
+            ));
+        }
+    }
+
+    /// Whether the model may be asked to fix `issue`. A rule explicitly
+    /// given the `llm` strategy doesn't need to be in `--llm-rules`, but
+    /// `--no-llm-rules` always keeps the model away.
+    fn for_model(&self, issue: &Issue) -> bool {
+        // A package's own `llm-rules` and `no-llm-rules` replace those given.
+        let overrides = self
+            .check
+            .package(&issue.filename)
+            .map(|package| &package.overrides);
+        let llm_rules = overrides
+            .and_then(|overrides| overrides.llm_rules.as_ref())
+            .unwrap_or(&self.llm_rules);
+        let no_llm_rules = overrides
+            .and_then(|overrides| overrides.no_llm_rules.as_ref())
+            .unwrap_or(&self.no_llm_rules);
+        let selected = match self.strategy(issue) {
+            None => llm_rules.is_empty() || rules::any_matches(&issue.code, llm_rules),
+            Some(strategy) => strategy == Strategy::Llm,
+        };
+        selected
+            && !self.skip.contains(&issue.id)
+            && !rules::any_matches(&issue.code, no_llm_rules)
+            && self.severe_enough(issue)
+    }
+
+    /// The severity of `issue`, as `--severity` or its package's own
+    /// `severity` map its rule.
+    fn severity(&self, issue: &Issue) -> Severity {
+        let severities = self
+            .check
+            .package(&issue.filename)
+            .and_then(|package| package.overrides.severities.as_ref())
+            .unwrap_or(&self.severities);
+        rules::severity_for(&issue.code, severities)
+    }
+
+    /// Whether `issue` is at least as severe as `--min-severity` asks.
+    fn severe_enough(&self, issue: &Issue) -> bool {
+        self.min_severity
+            .is_none_or(|min| self.severity(issue) >= min)
+    }
+
+    /// Whether `issue` goes into a batch with `--batch`. Missing docstrings
+    /// and annotations are always asked for on their own.
+    fn batched(&self, issue: &Issue) -> bool {
+        self.for_model(issue)
+            && !docstring::is_missing_docstring(&issue.code)
+            && !annotate::is_annotation_rule(&issue.code)
+            && !security::is_security_rule(&issue.code)
+    }
+
+    fn strategy(&self, issue: &Issue) -> Option<Strategy> {
+        rules::strategy_for(&issue.code, &self.strategies).or(self.noqa.then_some(Strategy::Noqa))
+    }
+
+    /// Fixes a security issue like any other, but only to put the fix up
+    /// for review, along with the model's explanation of the risk.
+    async fn propose(
+        &self,
+        provider: &dyn FixProvider,
+        cache: Option<&ResponseCache>,
+        console: &Console,
+        filename: &str,
+        issue: &Issue,
+        file_content: &str,
+    ) -> Result<Proposal, Error> {
+        let fixed_content = self
+            .fix_issue(
+                provider,
+                cache,
+                Some(&self.duplicates),
+                console,
+                filename,
+                issue,
+                file_content,
+                None,
+            )
+            .await?;
+        let mut proposal = Proposal::new(filename, issue, file_content, fixed_content);
+        let prompt = RiskPrompt {
+            issue_code: &issue.code,
+            issue_message: &issue.message,
+            issue_lines: issue.lines(file_content),
+            patch: &proposal.patch(),
+        };
+        let answer = self
+            .request_completion(provider, &[], &prompt.render())
+            .await?;
+        proposal.risk = sanitize::clean(&answer).trim().to_string();
+        Ok(proposal)
+    }
+
+    /// Asks the model why the fix of `issues` from `before` to `after` was
+    /// made that way, and prints the answer.
+    async fn print_explanation(
+        &self,
+        provider: &dyn FixProvider,
+        console: &Console,
+        filename: &str,
+        issues: &[&Issue],
+        before: &str,
+        after: &str,
+    ) {
+        let described: Vec<String> = issues
+            .iter()
+            .map(|issue| format!("{}: {}", issue.code, issue.message))
+            .collect();
+        let prompt = ExplanationPrompt {
+            issues: &described.join("\n"),
+            patch: &patch::unified(&issue::display_path(filename), before, after),
+        };
+        let ids: Vec<&str> = issues.iter().map(|issue| issue.id.as_str()).collect();
+        match self
+            .request_completion(provider, &[], &prompt.render())
+            .await
+        {
+            Ok(answer) => console.status(format!(
+                "Why [{}] was fixed this way: {}",
+                ids.join(", "),
+                sanitize::clean(&answer).trim()
+            )),
+            Err(err) => console.error(format!(
+                "Error explaining the fix of [{}] in {}: {}",
+                ids.join(", "),
+                filename,
+                err
+            )),
+        }
+    }
+
+    /// A justification for suppressing `issue`, to go into its noqa comment.
+    async fn justify(
+        &self,
+        provider: &dyn FixProvider,
+        issue: &Issue,
+        file_content: &str,
+    ) -> Result<Option<String>, Error> {
+        let prompt = JustificationPrompt {
+            issue_message: &issue.message,
+            issue_lines: issue.lines(file_content),
+        };
+        let answer = self
+            .request_completion(provider, &[], &prompt.render())
+            .await?;
+        Ok(noqa::reason_from_answer(&answer))
+    }
+
+    /// Whether fixes are linted again before they are accepted: not with
+    /// `--no-verify`, and not in Rust, since clippy only checks the files
+    /// on disk.
+    fn verifies(&self) -> bool {
+        !self.no_verify && self.check.language() == Language::Python
+    }
+
+    /// Lints `content` as the file `filename`, see [`verify::lint_content`].
+    fn lint(&self, filename: &str, content: &str) -> io::Result<Vec<Issue>> {
+        if self.check.language() == Language::Rust {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "Clippy only checks the files on disk",
+            ));
+        }
+        tracing::debug!("Linting {} lines with ruff", content.lines().count());
+        let package = self.check.package(filename);
+        // Ruff runs in the package, where a relative name means another file.
+        let filename = match package.and_then(|_| fs::canonicalize(filename).ok()) {
+            Some(path) => path.display().to_string(),
+            None => filename.to_string(),
+        };
+        let dir = package.map(|package| package.root.as_path());
+        let language = Language::of(&filename);
+        let mut issues = if language == Language::Python {
+            verify::lint_content(
+                self.check.ruff(),
+                dir,
+                &self.check.check_args(package),
+                &filename,
+                content,
+            )?
+        } else {
+            Vec::new()
+        };
+        if !self.check.linters().is_empty() {
+            for linter in self.check.linters() {
+                if linter.languages().contains(&language) {
+                    issues.extend(linter.lint_content(dir, &filename, content)?);
+                }
+            }
+            linter::drop_duplicates(&mut issues);
+            issue::assign_ids(&mut issues, content);
+        }
+        Ok(issues)
+    }
+
+    /// Fixes `issue` with [`Self::fix_issue`] and prints the diff. With
+    /// `--interactive`, asks whether to apply the fix; `None` if it was
+    /// declined.
+    #[allow(clippy::too_many_arguments)]
+    async fn fix_approved(
+        &self,
+        provider: &dyn FixProvider,
+        mut cache: Option<&ResponseCache>,
+        console: &Console,
+        filename: &str,
+        issue: &Issue,
+        file_content: &str,
+        mut conversation: Option<&mut Conversation>,
+    ) -> Result<Option<String>, Error> {
+        let mut duplicates = Some(&self.duplicates);
+        loop {
+            let fixed_content = self
+                .fix_issue(
+                    provider,
+                    cache,
+                    duplicates,
+                    console,
+                    filename,
+                    issue,
+                    file_content,
+                    conversation.as_deref_mut(),
+                )
+                .await?;
+            console.diff(filename, file_content, &fixed_content);
+            match self.approve(console, filename, issue).await? {
+                Approval::Apply => return Ok(Some(fixed_content)),
+                Approval::Skip => return Ok(None),
+                // The cache would only give the same fix again, and so
+                // would an identical issue's.
+                Approval::Retry => {
+                    cache = None;
+                    duplicates = None;
+                }
+            }
+        }
+    }
+
+    /// Whether each fix is asked about before it's applied, as with
+    /// `--interactive`, or on the dashboard of `--tui`.
+    fn asks(&self) -> bool {
+        self.interactive || self.tui
+    }
+
+    /// Asks whether to apply the fix of `issue` just shown, with
+    /// `--interactive`. Quitting stops the run as Ctrl-C does.
+    async fn approve(
+        &self,
+        console: &Console,
+        filename: &str,
+        issue: &Issue,
+    ) -> io::Result<Approval> {
+        if !self.asks() {
+            return Ok(Approval::Apply);
+        }
+        let mut answers = self.answers.lock().await;
+        loop {
+            if self.approve_all.load(Ordering::SeqCst) {
+                return Ok(Approval::Apply);
+            }
+            if self.interrupted.load(Ordering::SeqCst) {
+                return Ok(Approval::Skip);
+            }
+            console.ask(format!(
+                "Apply the fix of [{}] {} in {}? [y]es, [n]o, [r]etry, [a]ll remaining, [q]uit: ",
+                issue.id, issue.code, filename
+            ));
+            let mut answer = String::new();
+            let read = match answers.as_mut() {
+                Some(input) => input.read_line(&mut answer)?,
+                None => io::stdin().read_line(&mut answer)?,
+            };
+            match answer.trim() {
+                "y" | "yes" | "" if read > 0 => return Ok(Approval::Apply),
+                "n" | "no" => return Ok(Approval::Skip),
+                "r" | "retry" => return Ok(Approval::Retry),
+                "a" | "all" => self.approve_all.store(true, Ordering::SeqCst),
+                // Nothing more to read means nobody is there to answer.
+                "q" | "quit" | "" => self.interrupt(),
+                _ => {}
+            }
+        }
+    }
+
+    /// Returns the fixed file content, reusing the fix from a previous run
+    /// when the same issue was fixed in the same content before, or
+    /// repeating the fix of an identical issue of this run.
+    ///
+    /// A candidate that rewrites too much of the file or isn't valid Python
+    /// is never accepted (unless the file didn't parse to begin with), and
+    /// unless verification is off, every candidate is linted as well. A
+    /// rejected candidate is retried with the complaint added to the prompt.
+    #[allow(clippy::too_many_arguments)]
+    async fn fix_issue(
+        &self,
+        provider: &dyn FixProvider,
+        cache: Option<&ResponseCache>,
+        duplicates: Option<&Duplicates>,
+        console: &Console,
+        filename: &str,
+        issue: &Issue,
+        file_content: &str,
+        mut conversation: Option<&mut Conversation>,
+    ) -> Result<String, Error> {
+        let originally_parses = syntax::check(filename, file_content).is_ok();
+        let before = if !self.verifies() {
+            None
+        } else {
+            Some(self.lint(filename, file_content)?)
+        };
+
+        // The prompts are part of the key, so that a fix asked for with
+        // other templates, examples or style guide isn't reused.
+        let prompt = self
+            .issue_prompt(filename, issue, file_content, None)
+            .render(&Scope::File, file_content);
+        let key = ResponseCache::key(
+            &self.model,
+            &[&self.system_prompt(), &prompt],
+            file_content,
+            issue,
+        );
+        let mut cached = cache.and_then(|cache| cache.get(&key));
+        let repeated = duplicates
+            .filter(|_| cached.is_none())
+            .and_then(|duplicates| duplicates.repeat(issue, file_content));
+        if let Some(fixed_content) = repeated {
+            // A repeat that doesn't pass is asked about instead, as usual.
+            let verdict = self.check_candidate(
+                filename,
+                issue,
+                file_content,
+                &fixed_content,
+                before.as_deref(),
+                originally_parses,
+            )?;
+            if verdict.is_ok() {
+                console.status(format!(
+                    "Repeating the fix of an identical issue for [{}]",
+                    issue.id
+                ));
+                return Ok(fixed_content);
+            }
+        }
+        let attempts = self.verify_attempts.max(1);
+        let mut feedback: Option<String> = None;
+        for attempt in 1..=attempts {
+            let candidate = match cached.take() {
+                Some(fixed_content) => {
+                    console.status(format!("Reusing cached fix for [{}]", issue.id));
+                    Ok(fixed_content)
+                }
+                // Ask ChatGPT for a fix for the current issue
+                None => {
+                    console.event(Event::PromptSent {
+                        file: filename,
+                        id: &issue.id,
+                        attempt,
+                    });
+                    match self
+                        .ask_chatgpt_for_fix(
+                            provider,
+                            filename,
+                            issue,
+                            file_content,
+                            feedback.as_deref(),
+                            conversation.as_deref_mut(),
+                        )
+                        .await
+                    {
+                        Ok(fixed_content) => Ok(fixed_content),
+                        Err(err) => Err(err.downcast::<verify::Rejected>()?.0),
+                    }
+                }
+            };
+
+            let verdict = match candidate.map(|fixed| newline::match_original(file_content, &fixed))
+            {
+                Ok(fixed_content) => self
+                    .check_candidate(
+                        filename,
+                        issue,
+                        file_content,
+                        &fixed_content,
+                        before.as_deref(),
+                        originally_parses,
+                    )?
+                    .map(|()| fixed_content),
+                Err(reason) => Err(reason),
+            };
+            match verdict {
+                Ok(fixed_content) => {
+                    if let Some(cache) = cache {
+                        cache.insert(key, fixed_content.clone());
+                    }
+                    if let Some(duplicates) = duplicates {
+                        duplicates.learn(issue, file_content, &fixed_content);
+                    }
+                    return Ok(fixed_content);
+                }
+                Err(reason) => {
+                    console.event(Event::FixRejected {
+                        file: filename,
+                        id: &issue.id,
+                        attempt,
+                        reason: &reason,
+                    });
+                    console.status(format!(
+                        "Rejected fix for [{}] (attempt {}/{}): {}",
+                        issue.id, attempt, attempts, reason
+                    ));
+                    feedback = Some(reason);
+                }
+            }
+        }
+
+        Err(format!("No acceptable fix after {} attempts", attempts).into())
+    }
+
+    /// Runs the checks a candidate has to pass before it can be written.
+    /// The inner error is the reason for rejecting it; the outer one means
+    /// a check itself couldn't run.
+    fn check_candidate(
+        &self,
+        filename: &str,
+        issue: &Issue,
+        file_content: &str,
+        fixed_content: &str,
+        before: Option<&[Issue]>,
+        originally_parses: bool,
+    ) -> Result<Result<(), String>, Error> {
+        let limits = guard::Limits {
+            max_changed_lines_percent: self.max_changed_lines,
+            max_size_change_percent: self.max_size_change,
+        };
+        // A docstring is only ever inserted, so it can't rewrite the file.
+        let mut verdict = if docstring::is_missing_docstring(&issue.code) {
+            Ok(())
+        } else {
+            guard::check(file_content, fixed_content, limits)
+        };
+        if verdict.is_ok() {
+            verdict = self.encoding(filename).check(fixed_content);
+        }
+        if verdict.is_ok() && originally_parses {
+            verdict = syntax::check(filename, fixed_content);
+        }
+        if let (Ok(()), Some(before)) = (&verdict, before) {
+            let after = self.lint(filename, fixed_content)?;
+            let row_after = issue::moved_row(issue.location.row, file_content, fixed_content);
+            verdict = verify::check_fix(issue, row_after, before, &after);
+        }
+        let checker = self.checker.get().and_then(Option::as_ref);
+        if let (Ok(()), Some(checker)) = (&verdict, checker) {
+            if self.type_check_fixes || annotate::is_annotation_rule(&issue.code) {
+                let before = checker.errors(filename, file_content)?;
+                let after = checker.errors(filename, fixed_content)?;
+                verdict = typecheck::check_introduced(&before, &after);
+            }
+        }
+        Ok(verdict)
+    }
+
+    async fn ask_chatgpt_for_fix(
+        &self,
+        provider: &dyn FixProvider,
+        filename: &str,
+        issue: &Issue,
+        file_content: &str,
+        feedback: Option<&str>,
+        conversation: Option<&mut Conversation>,
+    ) -> Result<String, Error> {
+        if docstring::is_missing_docstring(&issue.code) {
+            return self
+                .ask_for_docstring(provider, filename, issue, file_content, feedback)
+                .await;
+        }
+        if annotate::is_annotation_rule(&issue.code) {
+            return self
+                .ask_for_annotation(provider, filename, issue, file_content, feedback)
+                .await;
+        }
+
+        let row = issue.location.row as usize;
+        let prompt = self.issue_prompt(filename, issue, file_content, feedback);
+
+        let budget = self.content_budget(&prompt.render(&Scope::File, ""));
+
+        // Huge files are only ever sent as a snippet; anything that still
+        // doesn't fit is trimmed further to whatever the window allows, and
+        // when that leaves too little context the file is fixed in chunks.
+        let mut scope = Scope::File;
+        if file_content.len() > self.snippet_threshold {
+            if let Some(excerpt) = Excerpt::lines_around(file_content, row, self.snippet_lines) {
+                scope = Scope::Excerpt(excerpt);
+            }
+        }
+        if count_tokens(scope.text(file_content)) > budget {
+            match Excerpt::around(file_content, row, budget) {
+                Some(excerpt) if excerpt.has_context(row, MIN_CONTEXT_LINES) => {
+                    scope = Scope::Excerpt(excerpt)
+                }
+                _ => {
+                    return self
+                        .fix_in_chunks(provider, &prompt, issue, file_content, budget)
+                        .await
+                }
+            }
+        }
+
+        // In a conversation the whole file is sent once, and later issues
+        // are asked about as follow-ups for as long as the exchange fits.
+        let conversation = conversation.filter(|_| matches!(scope, Scope::File));
+        let follow_up = prompt.render_follow_up();
+        let window = model_limits(&self.model).context_window * 9 / 10;
+        let history = conversation
+            .as_deref()
+            .and_then(|conversation| {
+                let needed = count_tokens(&self.system_prompt())
+                    + conversation.tokens()
+                    + count_tokens(&follow_up)
+                    + count_tokens(file_content);
+                conversation
+                    .history(file_content)
+                    .filter(|_| needed <= window)
+            })
+            .map(<[Message]>::to_vec);
+        let continued = history.is_some();
+        let user_prompt = if continued {
+            follow_up
+        } else {
+            prompt.render(&scope, file_content)
+        };
+
+        let response = self
+            .request_completion(
+                provider,
+                history.as_deref().unwrap_or_default(),
+                &user_prompt,
+            )
+            .await?;
+        let content = self.answer_content(scope.text(file_content), &response)?;
+        sanitize::check_complete(scope.text(file_content), &content).map_err(verify::Rejected)?;
+        if let Some(conversation) = conversation {
+            conversation.record(continued, &user_prompt, &response, content.clone());
+        }
+
+        match scope {
+            Scope::Excerpt(excerpt) => Ok(excerpt.splice(file_content, &content)),
+            _ => Ok(content),
+        }
+    }
+
+    /// The prompt asking for a fix of `issue`, with the examples and the
+    /// template of its rule.
+    fn issue_prompt<'a>(
+        &'a self,
+        filename: &'a str,
+        issue: &'a Issue,
+        file_content: &'a str,
+        feedback: Option<&'a str>,
+    ) -> IssuePrompt<'a> {
+        IssuePrompt {
+            filename,
+            issue_code: &issue.code,
+            issue_row: issue.location.row,
+            issue_message: &issue.message,
+            issue_lines: issue.lines(file_content),
+            issue_span: issue.span(file_content),
+            feedback,
+            examples: self
+                .library
+                .get()
+                .map_or(&[], |library| library.for_rule(&issue.code)),
+            format: self.response_format,
+            template: self
+                .templates
+                .get()
+                .and_then(|templates| templates.for_rule(&issue.code)),
+        }
+    }
+
+    /// Asks for just the docstring a missing-docstring issue is about, with
+    /// the definition as context, and puts it in place.
+    async fn ask_for_docstring(
+        &self,
+        provider: &dyn FixProvider,
+        filename: &str,
+        issue: &Issue,
+        file_content: &str,
+        feedback: Option<&str>,
+    ) -> Result<String, Error> {
+        let target = docstring::locate(file_content, &issue.code, issue.location.row as usize)?;
+        let prompt = DocstringPrompt {
+            filename,
+            issue_message: &issue.message,
+            kind: target.kind,
+            code: &target.context,
+            style: self.docstring_style,
+            feedback,
+        };
+        let response = self
+            .request_completion(provider, &[], &prompt.render())
+            .await?;
+        Ok(target
+            .insert(file_content, &response)
+            .map_err(verify::Rejected)?)
+    }
+
+    /// Asks for just the annotated signature of the function an `ANN` issue
+    /// is about, showing how the function returns and is called, and puts
+    /// it in place.
+    async fn ask_for_annotation(
+        &self,
+        provider: &dyn FixProvider,
+        filename: &str,
+        issue: &Issue,
+        file_content: &str,
+        feedback: Option<&str>,
+    ) -> Result<String, Error> {
+        let target = annotate::locate(file_content, issue.location.row as usize)?;
+        let prompt = AnnotationPrompt {
+            filename,
+            issue_message: &issue.message,
+            definition: &target.definition,
+            exits: &target.exits,
+            call_sites: &target.call_sites,
+            feedback,
+        };
+        let response = self
+            .request_completion(provider, &[], &prompt.render())
+            .await?;
+        Ok(target
+            .apply(file_content, &response)
+            .map_err(verify::Rejected)?)
+    }
+
+    /// How many tokens of file content fit into a request whose prompt is
+    /// `prompt` without the content.
+    ///
+    /// The model answers with the (fixed) content it was given, so the
+    /// content has to fit twice into the context window: once in the
+    /// prompt and once in the response. A diff is much shorter than the
+    /// content, so in diff mode only some room is left for the answer.
+    fn content_budget(&self, prompt: &str) -> usize {
+        let limits = model_limits(&self.model);
+        let overhead = count_tokens(&self.system_prompt()) + count_tokens(prompt) + 16;
+        let available = limits.context_window.saturating_sub(overhead);
+        let budget = match self.response_format {
+            ResponseFormat::File => (available / 2).min(limits.max_output),
+            ResponseFormat::Diff => {
+                available.saturating_sub(limits.max_output.min(limits.context_window / 4))
+            }
+        };
+        budget * 9 / 10
+    }
+
+    /// Asks for one fix of all the issues of a file at once, and checks each
+    /// of them against the result. Returns the fixed content and the issues
+    /// it fixed, and leaves in `issues` only those that still have to be
+    /// fixed one by one.
+    async fn fix_batch(
+        &self,
+        provider: &dyn FixProvider,
+        filename: &str,
+        issues: &mut Vec<Issue>,
+        file_content: &str,
+    ) -> Result<(String, Vec<Issue>), Error> {
+        let targets: Vec<&Issue> = issues.iter().filter(|issue| self.batched(issue)).collect();
+        let prompt = BatchPrompt {
+            filename,
+            issues: &targets,
+            format: self.response_format,
+        };
+        if count_tokens(file_content) > self.content_budget(&prompt.render("")) {
+            return Err("The file is too large to fix all issues in one request".into());
+        }
+
+        let response = self
+            .request_completion(provider, &[], &prompt.render(file_content))
+            .await?;
+        let fixed_content =
+            newline::match_original(file_content, &self.answer_content(file_content, &response)?);
+        sanitize::check_complete(file_content, &fixed_content)?;
+        let limits = guard::Limits {
+            max_changed_lines_percent: self.max_changed_lines,
+            max_size_change_percent: self.max_size_change,
+        };
+        guard::check(file_content, &fixed_content, limits)?;
+        if syntax::check(filename, file_content).is_ok() {
+            syntax::check(filename, &fixed_content)?;
+        }
+
+        let remaining = if !self.verifies() {
+            Vec::new()
+        } else {
+            let before = self.lint(filename, file_content)?;
+            let after = self.lint(filename, &fixed_content)?;
+            verify::check_batch(&targets, &before, &after)?
+        };
+        let (fixed, left): (Vec<Issue>, Vec<Issue>) = issues
+            .drain(..)
+            .partition(|issue| self.batched(issue) && !remaining.contains(&issue.id));
+        *issues = left;
+        Ok((fixed_content, fixed))
+    }
+
+    /// Fixes the chunk that contains the issue and puts it back into the
+    /// file. Because chunks overlap, an issue near a seam is covered by two
+    /// of them; if the answer for one damages its boundaries the other is
+    /// tried before giving up.
+    async fn fix_in_chunks(
+        &self,
+        provider: &dyn FixProvider,
+        prompt: &IssuePrompt<'_>,
+        issue: &Issue,
+        file_content: &str,
+        budget: usize,
+    ) -> Result<String, Error> {
+        let chunks = chunk::split(file_content, budget, CHUNK_OVERLAP_SEGMENTS);
+        let offset = chunk::offset_of(
+            file_content,
+            issue.location.row as usize,
+            issue.location.column as usize,
+        );
+
+        for candidate in chunk::containing(&chunks, offset) {
+            let scope = Scope::Chunk(*candidate);
+            let response = self
+                .request_completion(provider, &[], &prompt.render(&scope, file_content))
+                .await?;
+            let fixed = self.answer_content(candidate.text(file_content), &response)?;
+            if candidate.validate(file_content, &fixed) {
+                // The seams hold even when the answer stops short of the
+                // end of the chunk but repeats its last lines.
+                let reassembled = candidate.reassemble(file_content, &fixed);
+                sanitize::check_complete(file_content, &reassembled).map_err(verify::Rejected)?;
+                return Ok(reassembled);
+            }
+        }
+
+        Err("Fixed chunk did not preserve the text shared with its neighbours".into())
+    }
+
+    /// Turns the model's answer into the fixed version of `original`,
+    /// applying it as a patch in diff mode. A diff that doesn't apply is
+    /// rejected so that it is asked for again.
+    fn answer_content(&self, original: &str, response: &str) -> Result<String, Error> {
+        let content = sanitize::clean(response);
+        match self.response_format {
+            ResponseFormat::File => Ok(content),
+            ResponseFormat::Diff => Ok(patch::apply(original, &content).map_err(verify::Rejected)?),
+        }
+    }
+
+    /// Writes the fixed content of a file. If the file was changed on disk while
+    /// it was being fixed, the fixes are merged into those changes; when they
+    /// conflict, or with `--no-merge`, the file is left as it is on disk.
+    /// The code of `--stdin-filename` is kept for stdout instead.
+    fn write_fixed(&self, console: &Console, filename: &str, original: &str, fixed: &str) {
+        if self.stdin_filename.as_deref() == Some(filename) {
+            self.report.written(filename, original, fixed);
+            *self.buffer.lock().unwrap() = Some(fixed.to_string());
+            return;
+        }
+        let on_disk = match encoding::read(Path::new(filename)) {
+            Ok((on_disk, _)) => on_disk,
+            Err(err) => {
+                console.error(format!("Error reading {}: {}", filename, err));
+                return;
+            }
+        };
+        let content = if on_disk == original {
+            fixed.to_string()
+        } else if self.no_merge {
+            console.error(format!(
+                "{} changed on disk while it was being fixed; leaving it unchanged",
+                filename
+            ));
+            return;
+        } else {
+            match merge::merge(original, &on_disk, fixed) {
+                Ok(merged) => {
+                    console.status(format!(
+                        "{} changed on disk while it was being fixed; merged the fixes into the changes",
+                        filename
+                    ));
+                    merged
+                }
+                Err(conflicts) => {
+                    let regions: Vec<String> = conflicts
+                        .iter()
+                        .map(|conflict| {
+                            if conflict.start > conflict.end {
+                                format!("before line {}", conflict.start)
+                            } else if conflict.start == conflict.end {
+                                format!("line {}", conflict.start)
+                            } else {
+                                format!("lines {}-{}", conflict.start, conflict.end)
+                            }
+                        })
+                        .collect();
+                    console.error(format!(
+                        "{} changed on disk while it was being fixed, and the changes conflict with the fixes at {}; leaving it unchanged",
+                        filename,
+                        regions.join(", ")
+                    ));
+                    return;
+                }
+            }
+        };
+
+        if let Some(out) = &self.patch_out {
+            if content != on_disk {
+                self.write_patch(console, out, filename, &on_disk, &content);
+            }
+            return;
+        }
+        if self.check_only || self.estimating {
+            if content != on_disk {
+                self.report.written(filename, &on_disk, &content);
+                console.status(format!("Would fix issues in {}", filename));
+            }
+            return;
+        }
+        if self.suggest_on_pr.is_some() {
+            if content != on_disk {
+                self.report.written(filename, &on_disk, &content);
+                self.suggested
+                    .lock()
+                    .unwrap()
+                    .insert(filename.to_string(), (on_disk, content));
+            }
+            return;
+        }
+        if content == on_disk {
+            // Nothing to write: the file keeps its inode and mtime, and no
+            // post-fix command runs for it.
+            self.mark_finished(console, filename, &content);
+            return;
+        }
+        if let Some(dir) = &self.backup_dir {
+            if !self.backed_up.lock().unwrap().contains(filename) {
+                if let Err(err) = back_up(dir, filename) {
+                    console.error(format!(
+                        "Error backing up {} into {}, leaving it unchanged: {}",
+                        filename,
+                        dir.display(),
+                        err
+                    ));
+                    return;
+                }
+                self.backed_up.lock().unwrap().insert(filename.to_string());
+            }
+        }
+        // Each fix was verified as it was made, but not merged with what
+        // changed meanwhile.
+        if on_disk != original && self.verifies() {
+            if let Err(reason) = self.check_file(filename, &on_disk, &content) {
+                console.error(format!(
+                    "{} fails validation with all its fixes, leaving it unchanged: {}",
+                    filename, reason
+                ));
+                self.report.roll_back(filename);
+                return;
+            }
+        }
+        // A last look, since merging and backing up take time: whatever was
+        // written to the file meanwhile isn't overwritten.
+        if !matches!(encoding::read(Path::new(filename)), Ok((now, _)) if now == on_disk) {
+            console.error(format!(
+                "{} changed on disk while the fixes were being written; leaving it unchanged",
+                filename
+            ));
+            return;
+        }
+        if let Err(err) = encoding::write(Path::new(filename), &content, self.encoding(filename)) {
+            console.error(format!("Error writing to {}: {}", filename, err));
+            return;
+        }
+        console.status(format!("Fixed issues in {}", filename));
+        console.event(Event::FileWritten { file: filename });
+        self.report.written(filename, &on_disk, &content);
+        self.mark_finished(console, filename, &content);
+        if let Some(journal) = self.journal.get() {
+            if let Err(err) = journal.record(filename, &on_disk, &content) {
+                console.error(format!(
+                    "Error recording the change of {} in the journal: {}",
+                    filename, err
+                ));
+            }
+        }
+        match self.post_fix_cmd.as_deref() {
+            Some(command) if hook::per_file(command) => {
+                let files = [filename.to_string()];
+                if !self.post_fix(console, command, &files) {
+                    self.roll_back(console, filename, &content, &on_disk);
+                }
+            }
+            Some(_) => {
+                self.written
+                    .lock()
+                    .unwrap()
+                    .entry(filename.to_string())
+                    .and_modify(|(_, now)| now.clone_from(&content))
+                    .or_insert((on_disk, content));
+            }
+            None => {}
+        }
+    }
+
+    /// Records in the state file that `filename` is done with, as
+    /// `content`, for a resumed run to skip. An interrupted file, or one the
+    /// budget ran out on, may have issues nobody looked at yet.
+    fn mark_finished(&self, console: &Console, filename: &str, content: &str) {
+        let interrupted = self.interrupted.load(Ordering::SeqCst) || self.over_budget();
+        if let Some(Err(err)) = self
+            .state
+            .get()
+            .filter(|_| !interrupted)
+            .map(|state| state.finish(filename, content))
+        {
+            console.error(format!(
+                "Error saving progress to {}: {}",
+                self.state_file.display(),
+                err
+            ));
+        }
+    }
+
+    /// Lints `content`, all the fixes of `filename` together, against
+    /// `original`; the reason it mustn't be written, if any.
+    fn check_file(&self, filename: &str, original: &str, content: &str) -> Result<(), String> {
+        if syntax::check(filename, original).is_ok() {
+            syntax::check(filename, content)?;
+        }
+        let lint = |content| {
+            self.lint(filename, content)
+                .map_err(|err| format!("Error linting it: {}", err))
+        };
+        verify::check_file(&lint(original)?, &lint(content)?)
+    }
+
+    /// Puts `filename` back as it was before the run wrote `written` to
+    /// it, since the post-fix command failed; the issues fixed in it are
+    /// left for people.
+    fn roll_back(&self, console: &Console, filename: &str, written: &str, original: &str) {
+        if !matches!(encoding::read(Path::new(filename)), Ok((now, _)) if now == written) {
+            console.error(format!(
+                "{} changed since it was fixed; leaving it as it is",
+                filename
+            ));
+            return;
+        }
+        if let Err(err) = encoding::write(Path::new(filename), original, self.encoding(filename)) {
+            console.error(format!("Error rolling back {}: {}", filename, err));
+            return;
+        }
+        if let Some(journal) = self.journal.get() {
+            if let Err(err) = journal.record(filename, written, original) {
+                console.error(format!(
+                    "Error recording the change of {} in the journal: {}",
+                    filename, err
+                ));
+            }
+        }
+        self.report.roll_back(filename);
+        console.status(format!(
+            "Rolled back the fixes of {}, since the post-fix command failed",
+            filename
+        ));
+    }
+
+    /// The test files of the package at `root`, as pytest collects them, or
+    /// by their names if pytest isn't installed, which is said once.
+    fn test_files(&self, console: &Console, root: &Path) -> io::Result<Vec<PathBuf>> {
+        if let Some(tests) = impact::collected_tests(root)? {
+            return Ok(tests);
+        }
+        if !self.said_no_pytest.swap(true, Ordering::SeqCst) {
+            console.status(format!(
+                "pytest isn't installed for {}; taking the files named test_*.py and *_test.py as its tests",
+                root.display()
+            ));
+        }
+        impact::named_tests(root)
+    }
+
+    /// Runs `--post-fix-cmd` for `files`, recording its output in the
+    /// report; whether it passed.
+    fn post_fix(&self, console: &Console, command: &str, files: &[String]) -> bool {
+        let mut tests = Vec::new();
+        if let Some(filename) = files.first().filter(|_| hook::wants_tests(command)) {
+            let root = self
+                .check
+                .package(filename)
+                .map_or_else(|| self.check.project_dir(), |package| package.root.clone());
+            let impacted = self
+                .test_files(console, &root)
+                .and_then(|tests| impact::tests_importing(&root, Path::new(filename), &tests));
+            match impacted {
+                Ok(impacted) if impacted.is_empty() => {
+                    console.status(format!(
+                        "No tests import {}; not running the post-fix command",
+                        filename
+                    ));
+                    return true;
+                }
+                Ok(impacted) => {
+                    tests = impacted
+                        .iter()
+                        .map(|test| test.to_string_lossy().into_owned())
+                        .collect()
+                }
+                Err(err) => {
+                    console.error(format!(
+                        "Error finding the tests importing {}: {}",
+                        filename, err
+                    ));
+                    return false;
+                }
+            }
+        }
+        let run = match hook::run(command, files, &tests) {
+            Ok(run) => run,
+            Err(err) => {
+                console.error(format!("Error running the post-fix command: {}", err));
+                return false;
+            }
+        };
+        let passed = run.passed;
+        if passed {
+            console.status(format!("Post-fix command passed: {}", run.command));
+        } else {
+            console.error(format!(
+                "Post-fix command failed with {}: {}\n{}",
+                run.exit_code
+                    .map_or("a signal".to_string(), |code| format!("exit code {}", code)),
+                run.command,
+                run.output.trim_end()
+            ));
+        }
+        self.report.post_fix(run);
+        passed
+    }
+
+    /// Writes the changes to `filename` as a patch to `out` instead of
+    /// changing it, or keeps the patch for the combined one written at the
+    /// end of the run.
+    fn write_patch(
+        &self,
+        console: &Console,
+        out: &Path,
+        filename: &str,
+        before: &str,
+        after: &str,
+    ) {
+        let path = issue::display_path(filename);
+        let diff = patch::unified(&path, before, after);
+        self.report.written(filename, before, after);
+        if combined_patch(out) {
+            self.patches.lock().unwrap().insert(path, diff);
+            return;
+        }
+        let relative: PathBuf = Path::new(&path)
+            .components()
+            .filter(|component| matches!(component, Component::Normal(_)))
+            .collect();
+        let mut patch_file = out.join(relative).into_os_string();
+        patch_file.push(".patch");
+        let patch_file = PathBuf::from(patch_file);
+        let written = patch_file
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|()| fs::write(&patch_file, diff));
+        match written {
+            Ok(()) => console.status(format!(
+                "Wrote the fixes of {} to {}",
+                filename,
+                patch_file.display()
+            )),
+            Err(err) => console.error(format!(
+                "Error writing the fixes of {} to {}: {}",
+                filename,
+                patch_file.display(),
+                err
+            )),
+        }
+    }
+
+    /// Writes the combined patch of `--patch-out`, if it asks for one.
+    fn write_combined_patch(&self, console: &Console) -> io::Result<()> {
+        let Some(out) = self.patch_out.as_deref().filter(|out| combined_patch(out)) else {
+            return Ok(());
+        };
+        let patches = self.patches.lock().unwrap();
+        fs::write(out, patches.values().cloned().collect::<String>())?;
+        console.status(format!(
+            "Wrote the fixes of {} files to {}",
+            patches.len(),
+            out.display()
+        ));
+        Ok(())
+    }
+
+    fn system_prompt(&self) -> String {
+        let template = self
+            .templates
+            .get()
+            .and_then(|templates| templates.system.as_ref());
+        prompt::system_prompt(
+            &self.check.languages(),
+            template,
+            self.style.get().map(String::as_str),
+        )
+    }
+
+    /// Reads the templates of `--prompt-template`, `--rule-prompt` and
+    /// `--system-prompt-template`, failing the run if they are invalid.
+    fn load_templates(&self) -> io::Result<()> {
+        let load = |path: &Option<PathBuf>, variables| {
+            path.as_deref()
+                .map(|path| Template::load(path, variables))
+                .transpose()
+        };
+        let rules = self
+            .rule_prompts
+            .iter()
+            .map(|entry| {
+                let template = Template::load(&entry.path, prompt::ISSUE_VARIABLES)?;
+                Ok((entry.selector.clone(), template))
+            })
+            .collect::<io::Result<_>>()?;
+        let _ = self.templates.set(Templates {
+            issue: load(&self.prompt_template, prompt::ISSUE_VARIABLES)?,
+            rules,
+            system: load(&self.system_prompt_template, prompt::SYSTEM_VARIABLES)?,
+        });
+        Ok(())
+    }
+
+    /// Sends `prompt`, following the earlier messages of a conversation if
+    /// there are any.
+    async fn request_completion(
+        &self,
+        provider: &dyn FixProvider,
+        history: &[Message],
+        prompt: &str,
+    ) -> Result<String, Error> {
+        if self.interrupted.load(Ordering::SeqCst) {
+            return Err("interrupted".into());
+        }
+        let mut messages = vec![Message::system(self.system_prompt())];
+        messages.extend_from_slice(history);
+        messages.push(Message::user(prompt));
+        let request = CompletionRequest {
+            model: self.model.clone(),
+            messages,
+        };
+        tracing::debug!(model = %request.model, "Sending {} messages", request.messages.len());
+        tracing::trace!("Prompt:\n{}", prompt);
+        let sent_at = SystemTime::now();
+        let started = Instant::now();
+        let answer = provider.complete(&request).await;
+        match &answer {
+            Ok(answer) => tracing::trace!("Answer:\n{}", answer),
+            Err(err) => tracing::debug!("Request failed: {}", err),
+        }
+        let prompt_tokens: usize = request
+            .messages
+            .iter()
+            .map(|message| count_tokens(&message.content))
+            .sum();
+        report::count(prompt_tokens + answer.as_deref().map_or(0, count_tokens));
+        if let Ok(answer) = &answer {
+            self.report.spent(prompt_tokens, count_tokens(answer));
+        }
+        if let Some(audit) = self.audit.get() {
+            let logged = answer.as_deref().map_err(|err| err.to_string());
+            // An answer that can't be accounted for isn't used.
+            audit
+                .record(&request, sent_at, started.elapsed(), logged)
+                .map_err(|err| format!("Error writing the audit log: {}", err))?;
+        }
+        answer
+    }
+}
+
+/// A file being fixed by [`RuffFixer::fix_files`], with its content as the
+/// fixes so far left it.
+struct FileRun {
+    fixer: Arc<RuffFixer>,
+    provider: Arc<dyn FixProvider>,
+    console: Console,
+    cache: Option<Arc<ResponseCache>>,
+    filename: String,
+    /// The content before the run.
+    original: String,
+    content: String,
+    conversation: Option<Conversation>,
+    /// Fixes of security issues, for review.
+    proposals: Vec<Proposal>,
+}
+
+impl FileRun {
+    /// Fixes `issues`, writes the file and returns the fixes proposed for
+    /// review. With a budget, the issues most worth fixing go first.
+    async fn fix(&mut self, mut issues: Vec<Issue>, budgeted: bool) -> Vec<Proposal> {
+        issue::assign_ids(&mut issues, &self.content);
+        self.fixer
+            .drop_known(&self.console, &self.filename, &mut issues);
+        self.discover(&issues);
+        if self.too_many(&issues) {
+            return Vec::new();
+        }
+        let progress = self.console.file(&self.filename, issues.len());
+
+        // Fix from the bottom up, so that fixes mostly leave the lines of
+        // the issues still to come where they were; whatever does move is
+        // followed after each fix.
+        issues.sort_by_key(|issue| (issue.location.row, issue.location.column));
+        self.autofix(&mut issues);
+        self.suppress(&mut issues).await;
+        self.fix_batch(&mut issues).await;
+        progress.left(issues.len());
+        if budgeted {
+            // The issues most worth fixing go first instead, popped off the
+            // end.
+            let tokens = count_tokens(&self.content);
+            let fixer = &self.fixer;
+            issues.sort_by_cached_key(|issue| {
+                Reverse(budget::priority(issue, fixer.severity(issue), tokens))
+            });
+        }
+        while let Some(issue) = issues.pop() {
+            if self.fixer.interrupted.load(Ordering::SeqCst) {
+                break;
+            }
+            if let Some(fixed_content) = self.fix_one(&issue).await {
+                issue::follow_edit(&mut issues, &self.content, &fixed_content);
+                self.content = fixed_content;
+            }
+            progress.left(issues.len());
+        }
+
+        self.write()
+    }
+
+    /// Reports `issues` as found.
+    fn discover(&self, issues: &[Issue]) {
+        for issue in issues {
+            self.fixer.report.discover(&self.filename, issue);
+            self.console.event(Event::IssueDiscovered {
+                file: &self.filename,
+                id: &issue.id,
+                code: &issue.code,
+                message: &issue.message,
+                row: issue.location.row,
+                column: issue.location.column,
+            });
+        }
+    }
+
+    /// Skips all of `issues` if there are more than `--max-issues-per-file`
+    /// allows without `--force`, saying so.
+    fn too_many(&self, issues: &[Issue]) -> bool {
+        let fixer = &self.fixer;
+        if issues.len() <= fixer.max_issues_per_file || fixer.force {
+            return false;
+        }
+        self.console.status(format!(
+            "Skipping {}: {} issues is more than --max-issues-per-file ({}); pass --force to fix it anyway",
+            self.filename,
+            issues.len(),
+            fixer.max_issues_per_file
+        ));
+        for issue in issues {
+            self.record(&issue.id, Resolution::Skipped, &Effort::default());
+        }
+        true
+    }
+
+    /// Applies ruff's own fixes, as issues ruff can fix itself don't need
+    /// the model.
+    fn autofix(&mut self, issues: &mut Vec<Issue>) {
+        let fixer = &self.fixer;
+        let (autofixed, fixed_ids) = autofix::apply(
+            &self.content,
+            issues.iter().filter(|issue| {
+                // Security fixes are only proposed, unless a strategy
+                // explicitly asks for ruff's.
+                let applies = match fixer.strategy(issue) {
+                    None => !security::is_security_rule(&issue.code),
+                    Some(strategy) => strategy == Strategy::Autofix,
+                };
+                !fixer.skip.contains(&issue.id) && applies
+            }),
+            |issue| fixer.unsafe_fixes || fixer.strategy(issue) == Some(Strategy::Autofix),
+        );
+        if fixed_ids.is_empty() {
+            return;
+        }
+        self.console.status(format!(
+            "Applied ruff's own fixes for {} issues in {}",
+            fixed_ids.len(),
+            self.filename
+        ));
+        self.console.diff(&self.filename, &self.content, &autofixed);
+        let effort = Effort::default().with_diff(&self.filename, &self.content, &autofixed);
+        for id in &fixed_ids {
+            self.record(id, Resolution::AutoFixed, &effort);
+        }
+        issues.retain(|issue| !fixed_ids.contains(&issue.id));
+        issue::follow_edit(issues, &self.content, &autofixed);
+        self.content = autofixed;
+    }
+
+    /// Suppresses the issues of the `noqa` strategy with comments, with
+    /// the model's reasons for them with `--noqa-justify`.
+    async fn suppress(&mut self, issues: &mut Vec<Issue>) {
+        let fixer = &self.fixer;
+        let to_suppress: Vec<&Issue> = issues
+            .iter()
+            .filter(|issue| {
+                !fixer.skip.contains(&issue.id)
+                    && fixer.strategy(issue) == Some(Strategy::Noqa)
+                    && Language::of(&issue.filename) == Language::Python
+            })
+            .collect();
+        let mut reasons = HashMap::new();
+        if fixer.noqa_justify {
+            for issue in &to_suppress {
+                match fixer
+                    .justify(self.provider.as_ref(), issue, &self.content)
+                    .await
+                {
+                    Ok(Some(reason)) => {
+                        reasons.insert(issue.id.clone(), reason);
+                    }
+                    Ok(None) => {}
+                    Err(err) => self.console.error(format!(
+                        "Error justifying [{}] in {}: {}",
+                        issue.id, self.filename, err
+                    )),
+                }
+            }
+        }
+        let (suppressed, suppressed_ids) = noqa::insert(&self.content, to_suppress, &reasons);
+        if suppressed_ids.is_empty() {
+            return;
+        }
+        self.console.status(format!(
+            "Suppressed {} issues in {} with noqa comments",
+            suppressed_ids.len(),
+            self.filename
+        ));
+        self.console
+            .diff(&self.filename, &self.content, &suppressed);
+        let effort = Effort::default().with_diff(&self.filename, &self.content, &suppressed);
+        for id in &suppressed_ids {
+            self.record(id, Resolution::Suppressed, &effort);
+        }
+        issues.retain(|issue| !suppressed_ids.contains(&issue.id));
+        self.content = suppressed;
+    }
+
+    /// Fixes the issues that can be with one request, with `--batch`.
+    /// Those it leaves are fixed one by one.
+    async fn fix_batch(&mut self, issues: &mut Vec<Issue>) {
+        let fixer = &self.fixer;
+        let batched = issues.iter().filter(|issue| fixer.batched(issue)).count();
+        // Fixes to approve one by one come one by one.
+        if !fixer.batch || fixer.asks() || batched <= 1 || fixer.over_budget() {
+            return;
+        }
+        self.console.status(format!(
+            "Fixing {} issues in {} with one request",
+            batched, self.filename
+        ));
+        let (result, effort) = report::measure(fixer.fix_batch(
+            self.provider.as_ref(),
+            &self.filename,
+            issues,
+            &self.content,
+        ))
+        .await;
+        let (fixed_content, fixed) = match result {
+            Ok(fixed) => fixed,
+            Err(err) => {
+                self.console.status(format!(
+                    "Batch fix of {} rejected, fixing its issues one by one: {}",
+                    self.filename, err
+                ));
+                return;
+            }
+        };
+        self.console
+            .diff(&self.filename, &self.content, &fixed_content);
+        let effort =
+            effort
+                .split(fixed.len())
+                .with_diff(&self.filename, &self.content, &fixed_content);
+        for issue in &fixed {
+            self.record(&issue.id, Resolution::LlmFixed, &effort);
+        }
+        if fixer.explain {
+            fixer
+                .print_explanation(
+                    self.provider.as_ref(),
+                    &self.console,
+                    &self.filename,
+                    &fixed.iter().collect::<Vec<_>>(),
+                    &self.content,
+                    &fixed_content,
+                )
+                .await;
+        }
+        issue::follow_edit(issues, &self.content, &fixed_content);
+        self.content = fixed_content;
+    }
+
+    /// Fixes `issue` with the model, or proposes a fix of a security issue
+    /// for review, unless it is to be left alone; the fixed content.
+    async fn fix_one(&mut self, issue: &Issue) -> Option<String> {
+        let fixer = Arc::clone(&self.fixer);
+        let filename = &self.filename;
+        if fixer.skip.contains(&issue.id) {
+            self.console.status(format!(
+                "Skipping [{}] {} in {}",
+                issue.id, issue.code, filename
+            ));
+            self.record(&issue.id, Resolution::Skipped, &Effort::default());
+            return None;
+        }
+        if !fixer.for_model(issue) {
+            let reason = match fixer.strategy(issue) {
+                Some(Strategy::Skip) => "its rule is skipped",
+                Some(Strategy::Autofix) => "ruff has no fix for it",
+                Some(Strategy::Noqa) => "no noqa comment can go on its line",
+                _ if !fixer.severe_enough(issue) => "it is less severe than --min-severity",
+                _ => "not a rule for the model",
+            };
+            self.console.status(format!(
+                "Leaving [{}] {} in {} alone: {}",
+                issue.id, issue.code, filename, reason
+            ));
+            let resolution = match fixer.strategy(issue) {
+                Some(Strategy::Skip) => Resolution::Skipped,
+                _ if !fixer.severe_enough(issue) => Resolution::Skipped,
+                _ => Resolution::NeedsHuman,
+            };
+            self.record(&issue.id, resolution, &Effort::default());
+            return None;
+        }
+        if fixer.over_budget() {
+            self.console.status(format!(
+                "Leaving [{}] {} in {} alone: the budget is spent",
+                issue.id, issue.code, filename
+            ));
+            fixer.leave_for_later(&self.console, filename, &issue.id, "budget exhausted");
+            return None;
+        }
+
+        if security::is_security_rule(&issue.code) {
+            self.propose(issue).await;
+            return None;
+        }
+
+        self.console.status(format!(
+            "Fixing [{}] {} at {}:{}:{}: {}",
+            issue.id,
+            issue.code,
+            filename,
+            issue.location.row,
+            issue.location.column,
+            issue.message
+        ));
+        let (result, effort) = report::measure(
+            fixer
+                .fix_approved(
+                    self.provider.as_ref(),
+                    self.cache.as_deref(),
+                    &self.console,
+                    filename,
+                    issue,
+                    &self.content,
+                    self.conversation.as_mut(),
+                )
+                .instrument(tracing::info_span!(
+                    "issue",
+                    id = %issue.id,
+                    code = %issue.code
+                )),
+        )
+        .await;
+        match result {
+            Ok(None) => {
+                self.record(&issue.id, Resolution::Skipped, &effort);
+                self.console.status(format!(
+                    "Leaving [{}] {} in {} alone: the fix was declined",
+                    issue.id, issue.code, self.filename
+                ));
+                None
+            }
+            Ok(Some(fixed_content)) => {
+                self.record(
+                    &issue.id,
+                    Resolution::LlmFixed,
+                    &effort.with_diff(&self.filename, &self.content, &fixed_content),
+                );
+                if fixer.explain {
+                    fixer
+                        .print_explanation(
+                            self.provider.as_ref(),
+                            &self.console,
+                            &self.filename,
+                            &[issue],
+                            &self.content,
+                            &fixed_content,
+                        )
+                        .await;
+                }
+                Some(fixed_content)
+            }
+            Err(err) => {
+                self.record(&issue.id, Resolution::Failed, &effort);
+                self.console.error(format!(
+                    "Error processing [{}] in {}: {}",
+                    issue.id, self.filename, err
+                ));
+                None
+            }
+        }
+    }
+
+    /// Asks the model for a fix of security issue `issue`, to propose for
+    /// review instead of applying.
+    async fn propose(&mut self, issue: &Issue) {
+        self.console.status(format!(
+            "Proposing a fix for [{}] {} at {}:{}:{} for review: {}",
+            issue.id,
+            issue.code,
+            self.filename,
+            issue.location.row,
+            issue.location.column,
+            issue.message
+        ));
+        let (result, effort) = report::measure(self.fixer.propose(
+            self.provider.as_ref(),
+            self.cache.as_deref(),
+            &self.console,
+            &self.filename,
+            issue,
+            &self.content,
+        ))
+        .await;
+        match result {
+            Ok(proposal) => {
+                self.record(&issue.id, Resolution::NeedsHuman, &effort);
+                self.proposals.push(proposal);
+            }
+            Err(err) => {
+                self.record(&issue.id, Resolution::Failed, &effort);
+                self.console.error(format!(
+                    "Error processing [{}] in {}: {}",
+                    issue.id, self.filename, err
+                ));
+            }
+        }
+    }
+
+    /// Formats the fixed content and writes it back to the file; the fixes
+    /// proposed for review, against the content written.
+    fn write(&mut self) -> Vec<Proposal> {
+        let fixer = &self.fixer;
+        if self.content != self.original && fixer.writes() {
+            match fixer
+                .check
+                .format_content(&self.filename, &self.content, fixer.sort_imports)
+            {
+                Ok(formatted) => self.content = formatted,
+                Err(err) => self.console.error(format!(
+                    "Error formatting {}, writing it as fixed: {}",
+                    self.filename, err
+                )),
+            }
+        }
+
+        fixer.write_fixed(&self.console, &self.filename, &self.original, &self.content);
+        let mut proposals = std::mem::take(&mut self.proposals);
+        for proposal in &mut proposals {
+            proposal.rebase(&self.content);
+        }
+        proposals
+    }
+
+    fn record(&self, id: &str, resolution: Resolution, effort: &Effort) {
+        self.fixer
+            .record(&self.console, &self.filename, id, resolution, effort);
+    }
+}
+
+/// An answer to `--interactive`.
+enum Approval {
+    Apply,
+    Skip,
+    /// Ask the model for another fix.
+    Retry,
+}
+
+/// Resolves on Ctrl-C, or on SIGTERM where there is such a thing.
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        if let Ok(mut terminate) = signal(SignalKind::terminate()) {
+            tokio::select! {
+                _ = tokio::signal::ctrl_c() => {}
+                _ = terminate.recv() => {}
+            }
+            return;
+        }
+    }
+    let _ = tokio::signal::ctrl_c().await;
+}
+
+/// Whether `--patch-out` names a single patch rather than a directory.
+fn combined_patch(out: &Path) -> bool {
+    matches!(
+        out.extension().and_then(|extension| extension.to_str()),
+        Some("patch" | "diff")
+    )
+}
+
+/// Copies `filename` as it is on disk into `dir`, at its path relative to
+/// the working directory (or its absolute path, for files outside it).
+fn back_up(dir: &Path, filename: &str) -> io::Result<()> {
+    let relative: PathBuf = Path::new(&issue::display_path(filename))
+        .components()
+        .filter(|component| matches!(component, Component::Normal(_)))
+        .collect();
+    let backup = dir.join(relative);
+    if let Some(parent) = backup.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::copy(filename, backup).map(|_| ())
+}
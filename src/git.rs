@@ -0,0 +1,303 @@
+//! What ruffer asks of git: the files staged for a commit or changed on a
+//! branch, and staging or committing the fixes made to them.
+
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsStr;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// The files of a commit in the making, when `--staged` is given.
+pub struct Staged {
+    /// The top level of the work tree, where git runs.
+    pub root: PathBuf,
+    /// The files staged, added or modified, canonical.
+    pub files: Vec<PathBuf>,
+    /// Those of the files with changes that aren't staged as well.
+    pub partly: HashSet<PathBuf>,
+}
+
+impl Staged {
+    /// The files staged in the work tree holding `dir`.
+    pub fn read(dir: &Path) -> io::Result<Self> {
+        let root = top_level(dir)?;
+        let files = names(
+            &root,
+            [
+                "diff",
+                "--cached",
+                "--name-only",
+                "-z",
+                "--diff-filter=ACMR",
+            ],
+        )?;
+        let unstaged: HashSet<PathBuf> = names(&root, ["diff", "--name-only", "-z"])?
+            .into_iter()
+            .collect();
+        let partly = files
+            .iter()
+            .filter(|file| unstaged.contains(*file))
+            .cloned()
+            .collect();
+        Ok(Staged {
+            root,
+            files,
+            partly,
+        })
+    }
+
+    /// Stages the changes made to the staged files since they were read,
+    /// leaving alone those with unstaged changes of their own, which would
+    /// be staged along. The files staged.
+    pub fn restage(&self) -> io::Result<Vec<PathBuf>> {
+        let changed: HashSet<PathBuf> = names(&self.root, ["diff", "--name-only", "-z"])?
+            .into_iter()
+            .collect();
+        let files: Vec<PathBuf> = self
+            .files
+            .iter()
+            .filter(|file| changed.contains(*file) && !self.partly.contains(*file))
+            .cloned()
+            .collect();
+        if !files.is_empty() {
+            git(
+                &self.root,
+                ["add", "--"]
+                    .iter()
+                    .map(OsStr::new)
+                    .chain(files.iter().map(|file| file.as_os_str())),
+            )?;
+        }
+        Ok(files)
+    }
+}
+
+/// The files added or modified since the work tree holding `dir` branched
+/// off `base`, committed or not, canonical: what a pull request into
+/// `base` changes.
+pub fn changed_since(dir: &Path, base: &str) -> io::Result<Vec<PathBuf>> {
+    let root = top_level(dir)?;
+    let merge_base = merge_base(&root, base)?;
+    names(
+        &root,
+        [
+            "diff",
+            "--name-only",
+            "-z",
+            "--diff-filter=ACMR",
+            merge_base.as_str(),
+        ],
+    )
+}
+
+/// The lines of each file added or modified since the work tree holding
+/// `dir` branched off `base`, as ranges of one-based line numbers, by
+/// canonical path.
+pub fn changed_lines(dir: &Path, base: &str) -> io::Result<HashMap<PathBuf, Vec<(u32, u32)>>> {
+    let root = top_level(dir)?;
+    let merge_base = merge_base(&root, base)?;
+    let output = git(
+        &root,
+        [
+            "-c",
+            "core.quotePath=false",
+            "diff",
+            "--unified=0",
+            "--no-color",
+            "--no-ext-diff",
+            "--diff-filter=ACMR",
+            merge_base.as_str(),
+        ],
+    )?;
+    let mut lines: HashMap<PathBuf, Vec<(u32, u32)>> = HashMap::new();
+    let mut file = None;
+    for line in String::from_utf8_lossy(&output).lines() {
+        if let Some(name) = line.strip_prefix("+++ b/") {
+            // Git ends names with spaces in them with a tab.
+            file = Some(root.join(name.trim_end_matches('\t')));
+        } else if let (Some(file), Some(hunk)) = (&file, line.strip_prefix("@@ ")) {
+            // `@@ -12,3 +14,5 @@`: five lines from line 14 in the new file.
+            let Some(new) = hunk.split(' ').find_map(|range| range.strip_prefix('+')) else {
+                continue;
+            };
+            let (start, count) = match new.split_once(',') {
+                Some((start, count)) => (start.parse(), count.parse()),
+                None => (new.parse(), Ok(1)),
+            };
+            if let (Ok(start), Ok(count)) = (start, count) {
+                if count > 0 {
+                    lines
+                        .entry(file.clone())
+                        .or_default()
+                        .push((start, start + count - 1));
+                }
+            }
+        }
+    }
+    Ok(lines)
+}
+
+/// The files of the work tree holding `dir` with changes not committed,
+/// untracked ones included, canonical.
+pub fn uncommitted(dir: &Path) -> io::Result<HashSet<PathBuf>> {
+    let root = top_level(dir)?;
+    let mut files: HashSet<PathBuf> = names(&root, ["diff", "HEAD", "--name-only", "-z"])?
+        .into_iter()
+        .collect();
+    files.extend(names(
+        &root,
+        ["ls-files", "--others", "--exclude-standard", "-z"],
+    )?);
+    Ok(files)
+}
+
+/// Commits `files`, and nothing else staged, with `message`, in the work
+/// tree holding `dir`.
+pub fn commit(dir: &Path, files: &[PathBuf], message: &str) -> io::Result<()> {
+    let root = top_level(dir)?;
+    let files = || files.iter().map(|file| file.as_os_str());
+    git(&root, ["add", "--"].iter().map(OsStr::new).chain(files()))?;
+    git(
+        &root,
+        ["commit", "-q", "-m", message, "--"]
+            .iter()
+            .map(OsStr::new)
+            .chain(files()),
+    )?;
+    Ok(())
+}
+
+/// The branch checked out in the work tree holding `dir`.
+pub fn current_branch(dir: &Path) -> io::Result<String> {
+    let output = git(dir, ["symbolic-ref", "--quiet", "--short", "HEAD"])?;
+    Ok(String::from_utf8_lossy(&output).trim_end().to_string())
+}
+
+/// Checks out `branch` in the work tree holding `dir`, creating it at the
+/// commit checked out if `create` says so. Changes not committed are
+/// carried over.
+pub fn switch(dir: &Path, branch: &str, create: bool) -> io::Result<()> {
+    let args = if create {
+        vec!["checkout", "-q", "-b", branch]
+    } else {
+        vec!["checkout", "-q", branch]
+    };
+    git(dir, args)?;
+    Ok(())
+}
+
+/// Pushes `branch` to `remote`.
+pub fn push(dir: &Path, remote: &str, branch: &str) -> io::Result<()> {
+    git(dir, ["push", "-q", remote, branch])?;
+    Ok(())
+}
+
+/// The address of `remote`.
+pub fn remote_url(dir: &Path, remote: &str) -> io::Result<String> {
+    let output = git(dir, ["remote", "get-url", remote])?;
+    Ok(String::from_utf8_lossy(&output).trim_end().to_string())
+}
+
+/// The commit the branch checked out in `root` left `base` at.
+fn merge_base(root: &Path, base: &str) -> io::Result<String> {
+    let output = git(root, ["merge-base", base, "HEAD"])?;
+    Ok(String::from_utf8_lossy(&output).trim_end().to_string())
+}
+
+/// The top level of the work tree holding `dir`, canonical.
+pub fn top_level(dir: &Path) -> io::Result<PathBuf> {
+    let output = git(dir, ["rev-parse", "--show-toplevel"])?;
+    PathBuf::from(String::from_utf8_lossy(&output).trim_end()).canonicalize()
+}
+
+/// Installs a pre-commit hook in the repository holding `dir` that runs
+/// `exe pre-commit --staged` with `args`, fixing the Python files being
+/// committed. An existing hook is only replaced if `force` says so. The
+/// path of the hook.
+pub fn install_hook(dir: &Path, exe: &Path, args: &[String], force: bool) -> io::Result<PathBuf> {
+    let output = git(dir, ["rev-parse", "--git-path", "hooks"])?;
+    let hooks = dir.join(String::from_utf8_lossy(&output).trim_end());
+    let hook = hooks.join("pre-commit");
+    if hook.exists() && !force {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{} exists; pass --force to replace it", hook.display()),
+        ));
+    }
+    let command: Vec<String> = [
+        exe.display().to_string(),
+        "pre-commit".into(),
+        "--staged".into(),
+    ]
+    .into_iter()
+    .chain(args.iter().cloned())
+    .map(|arg| shell_quote(&arg))
+    .collect();
+    let script = format!(
+        "#!/bin/sh\n\
+         # Installed by `ruffer install-hook`: fixes the issues ruff finds in the\n\
+         # Python files being committed, and stages the fixes. The API key is\n\
+         # taken from OPENAI_API_KEY.\n\
+         exec {}\n",
+        command.join(" ")
+    );
+    fs::create_dir_all(&hooks)?;
+    fs::write(&hook, script)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&hook, fs::Permissions::from_mode(0o755))?;
+    }
+    Ok(hook)
+}
+
+/// `arg` quoted for sh, if it needs to be.
+fn shell_quote(arg: &str) -> String {
+    let plain = !arg.is_empty()
+        && arg
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./=:,+@%".contains(c));
+    if plain {
+        arg.to_string()
+    } else {
+        format!("'{}'", arg.replace('\'', "'\\''"))
+    }
+}
+
+/// The files a git command listing names relative to `root` with `-z`
+/// lists, joined to it.
+fn names<I, S>(root: &Path, args: I) -> io::Result<Vec<PathBuf>>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<OsStr>,
+{
+    let output = git(root, args)?;
+    Ok(String::from_utf8_lossy(&output)
+        .split('\0')
+        .filter(|name| !name.is_empty())
+        .map(|name| root.join(name))
+        .collect())
+}
+
+/// The output of git run in `dir`; an error with what git said if it
+/// fails.
+fn git<I, S>(dir: &Path, args: I) -> io::Result<Vec<u8>>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<OsStr>,
+{
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .output()
+        .map_err(|err| io::Error::new(err.kind(), format!("Error running git: {}", err)))?;
+    if !output.status.success() {
+        return Err(io::Error::other(format!(
+            "git failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(output.stdout)
+}
@@ -0,0 +1,144 @@
+//! What ruffer asks of the GitHub API: opening a pull request with the
+//! fixes of a run, for `--open-pr`, or reviewing one with them, for
+//! `--suggest-on-pr`.
+
+use std::io;
+
+use reqwest::Client;
+use serde_json::{json, Value};
+
+use crate::redact::redact;
+
+/// A review comment on lines `start` to `end` of the file at `path`, in
+/// the repository, as it is in the pull request.
+pub struct Comment {
+    pub path: String,
+    pub start: u32,
+    pub end: u32,
+    pub body: String,
+}
+
+/// The API of the GitHub instance at `api_url`, for one repository.
+pub struct GitHub {
+    client: Client,
+    api_url: String,
+    token: String,
+    /// `owner/name`.
+    repo: String,
+}
+
+impl GitHub {
+    pub fn new(api_url: &str, token: String, repo: String) -> Self {
+        GitHub {
+            client: Client::new(),
+            api_url: api_url.trim_end_matches('/').to_string(),
+            token,
+            repo,
+        }
+    }
+
+    /// Opens a pull request of `head` into `base`; its web address.
+    pub async fn open_pull_request(
+        &self,
+        title: &str,
+        head: &str,
+        base: &str,
+        body: &str,
+    ) -> io::Result<String> {
+        let answer = self
+            .post(
+                &format!("repos/{}/pulls", self.repo),
+                json!({ "title": title, "head": head, "base": base, "body": body }),
+            )
+            .await?;
+        answer["html_url"]
+            .as_str()
+            .map(str::to_string)
+            .ok_or_else(|| io::Error::other("GitHub answered without the pull request's address"))
+    }
+
+    /// Reviews pull request `number` with `body` and `comments`, without
+    /// approving it or asking for changes; the review's web address.
+    pub async fn review(
+        &self,
+        number: u64,
+        body: &str,
+        comments: &[Comment],
+    ) -> io::Result<String> {
+        let comments: Vec<Value> = comments
+            .iter()
+            .map(|comment| {
+                let mut value = json!({
+                    "path": comment.path,
+                    "line": comment.end,
+                    "side": "RIGHT",
+                    "body": comment.body,
+                });
+                if comment.start < comment.end {
+                    value["start_line"] = json!(comment.start);
+                    value["start_side"] = json!("RIGHT");
+                }
+                value
+            })
+            .collect();
+        let answer = self
+            .post(
+                &format!("repos/{}/pulls/{}/reviews", self.repo, number),
+                json!({ "event": "COMMENT", "body": body, "comments": comments }),
+            )
+            .await?;
+        answer["html_url"]
+            .as_str()
+            .map(str::to_string)
+            .ok_or_else(|| io::Error::other("GitHub answered without the review's address"))
+    }
+
+    /// The answer of the API to `body` posted to `path`; an error with
+    /// what the API said if it refuses. The token never shows in errors.
+    async fn post(&self, path: &str, body: Value) -> io::Result<Value> {
+        let failed = |err: &dyn std::fmt::Display| {
+            io::Error::other(redact(
+                &format!("Error calling the GitHub API: {}", err),
+                &self.token,
+            ))
+        };
+        let response = self
+            .client
+            .post(format!("{}/{}", self.api_url, path))
+            .bearer_auth(&self.token)
+            .header("accept", "application/vnd.github+json")
+            .header("user-agent", "ruffer")
+            .json(&body)
+            .send()
+            .await
+            .map_err(|err| failed(&err))?;
+        let status = response.status();
+        let answer: Value = response.json().await.map_err(|err| failed(&err))?;
+        if !status.is_success() {
+            let message = answer["message"].as_str().unwrap_or_default();
+            return Err(failed(&format!("{}: {}", status, message)));
+        }
+        Ok(answer)
+    }
+}
+
+/// `owner/name` of a repository on GitHub, from the address of a remote
+/// such as `git@github.com:owner/name.git` or
+/// `https://github.com/owner/name`.
+pub fn repo_of(url: &str) -> Option<String> {
+    if !url.contains("github") {
+        return None;
+    }
+    let path = url
+        .trim_end_matches('/')
+        .trim_end_matches(".git")
+        .rsplit([':', '/'])
+        .take(2)
+        .collect::<Vec<_>>();
+    match path.as_slice() {
+        [name, owner] if !name.is_empty() && !owner.is_empty() && !owner.contains('@') => {
+            Some(format!("{}/{}", owner, name))
+        }
+        _ => None,
+    }
+}
@@ -0,0 +1,121 @@
+//! What ruffer asks of the GitLab API: opening a merge request with the
+//! fixes of a run, for `--open-mr`.
+
+use std::io;
+
+use reqwest::{Client, Method};
+use serde_json::{json, Value};
+
+use crate::redact::redact;
+
+/// The API of the GitLab instance at `url`, for one project.
+pub struct GitLab {
+    client: Client,
+    url: String,
+    token: String,
+    /// `group/name`, subgroups included.
+    project: String,
+}
+
+impl GitLab {
+    pub fn new(url: &str, token: String, project: String) -> Self {
+        GitLab {
+            client: Client::new(),
+            url: url.trim_end_matches('/').to_string(),
+            token,
+            project,
+        }
+    }
+
+    /// Opens a merge request of `source` into `target` with `labels`,
+    /// asking the users named `reviewers` to review it; its web address.
+    pub async fn open_merge_request(
+        &self,
+        title: &str,
+        source: &str,
+        target: &str,
+        description: &str,
+        labels: &[String],
+        reviewers: &[String],
+    ) -> io::Result<String> {
+        let mut reviewer_ids = Vec::new();
+        for username in reviewers {
+            reviewer_ids.push(self.user_id(username).await?);
+        }
+        let answer = self
+            .call(
+                Method::POST,
+                &format!(
+                    "projects/{}/merge_requests",
+                    self.project.replace('/', "%2F")
+                ),
+                Some(json!({
+                    "title": title,
+                    "source_branch": source,
+                    "target_branch": target,
+                    "description": description,
+                    "labels": labels.join(","),
+                    "reviewer_ids": reviewer_ids,
+                    "remove_source_branch": true,
+                })),
+            )
+            .await?;
+        answer["web_url"]
+            .as_str()
+            .map(str::to_string)
+            .ok_or_else(|| io::Error::other("GitLab answered without the merge request's address"))
+    }
+
+    /// The ID of the user named `username`.
+    async fn user_id(&self, username: &str) -> io::Result<u64> {
+        let answer = self
+            .call(Method::GET, &format!("users?username={}", username), None)
+            .await?;
+        answer[0]["id"].as_u64().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("No GitLab user named {}", username),
+            )
+        })
+    }
+
+    /// The answer of the API to a request for `path`; an error with what
+    /// the API said if it refuses. The token never shows in errors.
+    async fn call(&self, method: Method, path: &str, body: Option<Value>) -> io::Result<Value> {
+        let failed = |err: &dyn std::fmt::Display| {
+            io::Error::other(redact(
+                &format!("Error calling the GitLab API: {}", err),
+                &self.token,
+            ))
+        };
+        let mut request = self
+            .client
+            .request(method, format!("{}/api/v4/{}", self.url, path))
+            .header("private-token", &self.token);
+        if let Some(body) = body {
+            request = request.json(&body);
+        }
+        let response = request.send().await.map_err(|err| failed(&err))?;
+        let status = response.status();
+        let answer: Value = response.json().await.map_err(|err| failed(&err))?;
+        if !status.is_success() {
+            let message = match &answer["message"] {
+                Value::String(message) => message.clone(),
+                message => message.to_string(),
+            };
+            return Err(failed(&format!("{}: {}", status, message)));
+        }
+        Ok(answer)
+    }
+}
+
+/// `group/name` of a project, from the address of a remote such as
+/// `git@gitlab.com:group/name.git` or `https://gitlab.com/group/name`.
+pub fn project_of(url: &str) -> Option<String> {
+    let path = match url.split_once("://") {
+        Some((_, rest)) => rest.split_once('/')?.1,
+        None => url.split_once(':')?.1,
+    };
+    let path = path.trim_matches('/').trim_end_matches(".git");
+    path.contains('/').then(|| path.to_string())
+}
@@ -0,0 +1,93 @@
+//! Expands the glob patterns given as targets, such as `'src/**/*.py'`,
+//! for shells that don't or when they are quoted. The wildcards are those
+//! of `.rufferignore`: `*`, `?`, `[...]` classes and `**` for any number of
+//! directories. As in shells, wildcards don't match names starting with a
+//! dot.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::ignore::segments_match;
+
+/// Whether `target` holds wildcards, rather than naming a path.
+pub fn is_pattern(target: &str) -> bool {
+    target.contains(['*', '?', '['])
+}
+
+/// The directory a pattern is matched under: its components before the
+/// first one with a wildcard.
+pub fn base(pattern: &str) -> PathBuf {
+    Path::new(pattern)
+        .components()
+        .take_while(|component| !is_pattern(&component.as_os_str().to_string_lossy()))
+        .collect()
+}
+
+/// The files and directories matching `pattern`, in order; an error if
+/// there are none, as a target that names nothing is a mistake.
+pub fn expand(pattern: &str) -> io::Result<Vec<String>> {
+    let base = base(pattern);
+    let segments: Vec<Vec<char>> = Path::new(pattern)
+        .components()
+        .skip(base.components().count())
+        .map(|component| component.as_os_str().to_string_lossy().chars().collect())
+        .collect();
+    let recursive = segments.iter().any(|segment| *segment == ['*', '*']);
+    let depth = if recursive {
+        usize::MAX
+    } else {
+        segments.len()
+    };
+    let dir = if base.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        &base
+    };
+
+    let mut found = Vec::new();
+    walk(dir, &mut Vec::new(), depth, &mut |relative| {
+        let names: Vec<&str> = relative.iter().map(String::as_str).collect();
+        if segments_match(&segments, &names) {
+            found.push(base.join(relative.join("/")));
+        }
+    })?;
+    if found.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("{} matches no files", pattern),
+        ));
+    }
+    found.sort();
+    Ok(found
+        .into_iter()
+        .map(|path| path.display().to_string())
+        .collect())
+}
+
+/// Calls `visit` with the path relative to the start of the walk of
+/// everything in `dir`, down to `depth` levels, leaving out hidden names.
+fn walk(
+    dir: &Path,
+    relative: &mut Vec<String>,
+    depth: usize,
+    visit: &mut dyn FnMut(&[String]),
+) -> io::Result<()> {
+    if depth == 0 {
+        return Ok(());
+    }
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if name.starts_with('.') {
+            continue;
+        }
+        relative.push(name);
+        visit(relative);
+        if entry.file_type()?.is_dir() {
+            walk(&entry.path(), relative, depth - 1, visit)?;
+        }
+        relative.pop();
+    }
+    Ok(())
+}
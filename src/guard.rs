@@ -0,0 +1,54 @@
+use similar::{ChangeTag, TextDiff};
+
+/// Changes at least this small always pass, so fixes in tiny files aren't
+/// rejected just because every line is a large share of the file.
+const ALWAYS_ALLOWED_LINES: usize = 3;
+const ALWAYS_ALLOWED_BYTES: usize = 256;
+
+/// How far a single fix may move away from the original file.
+#[derive(Clone, Copy)]
+pub struct Limits {
+    /// Largest share of the original lines a fix may delete or rewrite.
+    pub max_changed_lines_percent: f64,
+    /// Largest growth or shrinkage of the file, relative to its size.
+    pub max_size_change_percent: f64,
+}
+
+/// Rejects fixes that look like the model rewrote or truncated the file
+/// instead of fixing one issue.
+pub fn check(original: &str, fixed: &str, limits: Limits) -> Result<(), String> {
+    let diff = TextDiff::from_lines(original, fixed);
+    let (mut deleted, mut inserted) = (0, 0);
+    for change in diff.iter_all_changes() {
+        match change.tag() {
+            ChangeTag::Delete => deleted += 1,
+            ChangeTag::Insert => inserted += 1,
+            ChangeTag::Equal => {}
+        }
+    }
+    let changed = usize::max(deleted, inserted);
+    let original_lines = original.lines().count().max(1);
+    let changed_percent = changed as f64 * 100.0 / original_lines as f64;
+    if changed > ALWAYS_ALLOWED_LINES && changed_percent > limits.max_changed_lines_percent {
+        return Err(format!(
+            "The fix changed {} of {} lines ({:.0}%); change only what is needed to fix the issue and keep the rest of the file as it is.",
+            changed, original_lines, changed_percent
+        ));
+    }
+
+    let size_change = original.len().abs_diff(fixed.len());
+    let size_change_percent = size_change as f64 * 100.0 / original.len().max(1) as f64;
+    if size_change > ALWAYS_ALLOWED_BYTES && size_change_percent > limits.max_size_change_percent {
+        let direction = if fixed.len() < original.len() {
+            "shrank"
+        } else {
+            "grew"
+        };
+        return Err(format!(
+            "The file {} by {:.0}% with the fix; return the complete content without omitting or adding unrelated code.",
+            direction, size_change_percent
+        ));
+    }
+
+    Ok(())
+}
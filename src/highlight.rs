@@ -0,0 +1,71 @@
+//! Just enough Python highlighting for diffs, in the terminal and in the
+//! HTML report.
+
+const KEYWORDS: &[&str] = &[
+    "False", "None", "True", "and", "as", "assert", "async", "await", "break", "class", "continue",
+    "def", "del", "elif", "else", "except", "finally", "for", "from", "global", "if", "import",
+    "in", "is", "lambda", "nonlocal", "not", "or", "pass", "raise", "return", "try", "while",
+    "with", "yield",
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Kind {
+    Keyword,
+    String,
+    Comment,
+    Number,
+    Other,
+}
+
+/// `line` of Python cut into pieces of each kind. Strings spanning lines
+/// are only recognized on their first line.
+pub fn python(line: &str) -> Vec<(Kind, &str)> {
+    let mut pieces: Vec<(Kind, &str)> = Vec::new();
+    let chars: Vec<(usize, char)> = line.char_indices().collect();
+    let offset = |i: usize| chars.get(i).map_or(line.len(), |&(offset, _)| offset);
+    let mut push = |kind: Kind, start: usize, end: usize| {
+        let text = &line[offset(start)..offset(end)];
+        match pieces.last_mut() {
+            Some((Kind::Other, last)) if kind == Kind::Other => {
+                *last = &line[offset(start) - last.len()..offset(end)];
+            }
+            _ => pieces.push((kind, text)),
+        }
+    };
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i].1;
+        let start = i;
+        if c == '#' {
+            push(Kind::Comment, start, chars.len());
+            break;
+        } else if c == '"' || c == '\'' {
+            i += 1;
+            while i < chars.len() && chars[i].1 != c {
+                i += if chars[i].1 == '\\' { 2 } else { 1 };
+            }
+            i = (i + 1).min(chars.len());
+            push(Kind::String, start, i);
+        } else if c.is_alphabetic() || c == '_' {
+            while i < chars.len() && (chars[i].1.is_alphanumeric() || chars[i].1 == '_') {
+                i += 1;
+            }
+            let word = &line[offset(start)..offset(i)];
+            let kind = if KEYWORDS.contains(&word) {
+                Kind::Keyword
+            } else {
+                Kind::Other
+            };
+            push(kind, start, i);
+        } else if c.is_ascii_digit() {
+            while i < chars.len() && (chars[i].1.is_ascii_alphanumeric() || chars[i].1 == '.') {
+                i += 1;
+            }
+            push(Kind::Number, start, i);
+        } else {
+            i += 1;
+            push(Kind::Other, start, i);
+        }
+    }
+    pieces
+}
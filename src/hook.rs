@@ -0,0 +1,103 @@
+//! The command of `--post-fix-cmd`, which checks the fixes with the
+//! project's own checks, such as its tests, once they are written.
+//!
+//! `{file}` in the command stands for the file just fixed, and makes it
+//! run after every file; `{files}` stands for all the files the run
+//! fixed, and a command without either runs once at the end of the run.
+//! `{tests}` stands for the test files importing the file just fixed, as
+//! [`crate::impact`] finds them, and runs after every file too, but only
+//! for files some test imports.
+
+use std::io;
+use std::process::Command;
+use std::time::Instant;
+
+use serde::Serialize;
+
+use crate::issue::display_path;
+
+/// Most bytes of a command's output kept, from its end, where test
+/// runners put their summaries.
+const MAX_OUTPUT: usize = 20_000;
+
+/// A run of the command, for the report.
+#[derive(Clone, Serialize)]
+pub struct HookRun {
+    /// As run, with the files filled in.
+    pub command: String,
+    pub files: Vec<String>,
+    /// Those of `{tests}`, if the command has it.
+    pub tests: Vec<String>,
+    /// None if the command was killed by a signal.
+    pub exit_code: Option<i32>,
+    pub passed: bool,
+    pub seconds: f64,
+    /// Stdout and stderr.
+    pub output: String,
+}
+
+/// Whether `template` runs after every file rather than once.
+pub fn per_file(template: &str) -> bool {
+    template.contains("{file}") || wants_tests(template)
+}
+
+/// Whether `template` runs the tests of the file fixed.
+pub fn wants_tests(template: &str) -> bool {
+    template.contains("{tests}")
+}
+
+/// Runs `template` in a shell for `files`, and `tests` for `{tests}`.
+pub fn run(template: &str, files: &[String], tests: &[String]) -> io::Result<HookRun> {
+    let quoted: Vec<String> = files.iter().map(|file| quote(file)).collect();
+    let quoted_tests: Vec<String> = tests.iter().map(|test| quote(test)).collect();
+    let command = template
+        .replace("{files}", &quoted.join(" "))
+        .replace("{file}", quoted.first().map_or("", String::as_str))
+        .replace("{tests}", &quoted_tests.join(" "));
+    tracing::debug!(%command, "Running the post-fix command");
+    let started = Instant::now();
+    let output = shell(&command).output()?;
+    let mut text = String::from_utf8_lossy(&output.stdout).into_owned();
+    text.push_str(&String::from_utf8_lossy(&output.stderr));
+    if text.len() > MAX_OUTPUT {
+        let mut start = text.len() - MAX_OUTPUT;
+        while !text.is_char_boundary(start) {
+            start += 1;
+        }
+        text = format!("[...]\n{}", &text[start..]);
+    }
+    Ok(HookRun {
+        command,
+        files: files.iter().map(|file| display_path(file)).collect(),
+        tests: tests.iter().map(|test| display_path(test)).collect(),
+        exit_code: output.status.code(),
+        passed: output.status.success(),
+        seconds: started.elapsed().as_secs_f64(),
+        output: text,
+    })
+}
+
+#[cfg(unix)]
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("sh");
+    shell.arg("-c").arg(command);
+    shell
+}
+
+#[cfg(not(unix))]
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("cmd");
+    shell.arg("/C").arg(command);
+    shell
+}
+
+/// `path` as one word of the shell's.
+#[cfg(unix)]
+fn quote(path: &str) -> String {
+    format!("'{}'", path.replace('\'', r"'\''"))
+}
+
+#[cfg(not(unix))]
+fn quote(path: &str) -> String {
+    format!("\"{}\"", path)
+}
@@ -0,0 +1,276 @@
+use std::collections::HashSet;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Component, Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// Name of the file listing what ruffer never reads or sends, whatever
+/// ruff's configuration says.
+pub const IGNORE_FILE: &str = ".rufferignore";
+
+/// Directories of virtualenvs, installed packages and other people's code,
+/// which are never sent to the API unless asked for.
+pub const VENDORED_DIRS: &[&str] = &[".venv", "venv", "site-packages", "node_modules", ".tox"];
+
+/// The patterns of a `.rufferignore`, in gitignore syntax, matched against
+/// paths relative to the directory holding it.
+pub struct IgnoreFile {
+    dir: PathBuf,
+    patterns: Vec<Pattern>,
+}
+
+struct Pattern {
+    /// `!pattern`: re-includes what an earlier pattern ignored.
+    negated: bool,
+    /// `pattern/`: only matches directories.
+    dir_only: bool,
+    /// A pattern with a slash before its end is matched against the whole
+    /// relative path, otherwise against the last component at any depth.
+    anchored: bool,
+    segments: Vec<Vec<char>>,
+}
+
+impl IgnoreFile {
+    /// Reads the `.rufferignore` in `dir`, if there is one.
+    pub fn load(dir: &Path) -> io::Result<Option<IgnoreFile>> {
+        let content = match fs::read_to_string(dir.join(IGNORE_FILE)) {
+            Ok(content) => content,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err),
+        };
+        Ok(Some(IgnoreFile {
+            dir: dir.canonicalize()?,
+            patterns: content.lines().filter_map(Pattern::parse).collect(),
+        }))
+    }
+
+    /// The patterns `patterns`, as if they were the lines of a
+    /// `.rufferignore` in `dir`.
+    pub fn from_patterns(dir: &Path, patterns: &[String]) -> io::Result<IgnoreFile> {
+        Ok(IgnoreFile {
+            dir: dir.canonicalize()?,
+            patterns: patterns
+                .iter()
+                .filter_map(|pattern| Pattern::parse(pattern))
+                .collect(),
+        })
+    }
+
+    /// Whether the file `filename` is ignored. Like git, a file in an
+    /// ignored directory can't be re-included. Files outside the directory
+    /// of the ignore file are never ignored.
+    pub fn is_ignored(&self, filename: &Path) -> bool {
+        let Some(relative) = filename
+            .canonicalize()
+            .ok()
+            .and_then(|path| path.strip_prefix(&self.dir).ok().map(Path::to_path_buf))
+        else {
+            return false;
+        };
+        let components: Vec<String> = relative
+            .components()
+            .filter_map(|component| match component {
+                Component::Normal(name) => Some(name.to_string_lossy().into_owned()),
+                _ => None,
+            })
+            .collect();
+        let components: Vec<&str> = components.iter().map(String::as_str).collect();
+        (1..=components.len())
+            .any(|depth| self.matches(&components[..depth], depth < components.len()))
+    }
+
+    /// Whether the last pattern matching `path` ignores it.
+    fn matches(&self, path: &[&str], is_dir: bool) -> bool {
+        self.patterns
+            .iter()
+            .rev()
+            .find(|pattern| pattern.matches(path, is_dir))
+            .is_some_and(|pattern| !pattern.negated)
+    }
+}
+
+/// The vendored directory `filename` is in, if any, at whatever depth.
+pub fn vendored_dir(filename: &Path) -> Option<&'static str> {
+    let dirs = filename.parent()?.components();
+    dirs.filter_map(|component| match component {
+        Component::Normal(name) => VENDORED_DIRS.iter().find(|dir| name == **dir).copied(),
+        _ => None,
+    })
+    .next()
+}
+
+/// The canonical paths of those of `files` git ignores, asking git itself
+/// so that nested `.gitignore` files, `.git/info/exclude` and the global
+/// excludes all count. Nothing is ignored outside a git work tree or
+/// without git.
+pub fn git_ignored<'a>(
+    dir: &Path,
+    files: impl IntoIterator<Item = &'a str>,
+) -> io::Result<HashSet<PathBuf>> {
+    let child = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(["check-ignore", "--stdin", "-z"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn();
+    let mut child = match child {
+        Ok(child) => child,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(HashSet::new()),
+        Err(err) => return Err(err),
+    };
+    let mut input = Vec::new();
+    // Relative paths would be taken as relative to `dir`.
+    for file in files
+        .into_iter()
+        .filter_map(|file| fs::canonicalize(file).ok())
+    {
+        input.extend_from_slice(file.to_string_lossy().as_bytes());
+        input.push(0);
+    }
+    child
+        .stdin
+        .take()
+        .expect("stdin is piped")
+        .write_all(&input)?;
+    let output = child.wait_with_output()?;
+    match output.status.code() {
+        // 1: none of the files is ignored.
+        Some(0) | Some(1) => Ok(String::from_utf8_lossy(&output.stdout)
+            .split('\0')
+            .filter(|file| !file.is_empty())
+            .map(PathBuf::from)
+            .collect()),
+        _ => {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            if stderr.contains("not a git repository") {
+                Ok(HashSet::new())
+            } else {
+                Err(io::Error::other(format!(
+                    "git check-ignore failed with {}: {}",
+                    output.status,
+                    stderr.trim()
+                )))
+            }
+        }
+    }
+}
+
+impl Pattern {
+    fn parse(line: &str) -> Option<Pattern> {
+        // Trailing spaces are dropped unless escaped.
+        let mut line = line.trim_end_matches(['\r', '\n']);
+        while line.ends_with(' ') && !line.ends_with("\\ ") {
+            line = &line[..line.len() - 1];
+        }
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+        let (negated, line) = match line.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        // `\#` and `\!` stand for a literal first character.
+        let line = line
+            .strip_prefix('\\')
+            .filter(|rest| rest.starts_with(['#', '!']))
+            .unwrap_or(line);
+        let (dir_only, line) = match line.strip_suffix('/') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        let anchored = line.contains('/');
+        let line = line.strip_prefix('/').unwrap_or(line);
+        if line.is_empty() {
+            return None;
+        }
+        Some(Pattern {
+            negated,
+            dir_only,
+            anchored,
+            segments: line
+                .split('/')
+                .map(|segment| segment.chars().collect())
+                .collect(),
+        })
+    }
+
+    fn matches(&self, path: &[&str], is_dir: bool) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        }
+        if self.anchored {
+            segments_match(&self.segments, path)
+        } else {
+            path.last().is_some_and(|name| {
+                wildcard_match(&self.segments[0], &name.chars().collect::<Vec<_>>())
+            })
+        }
+    }
+}
+
+/// Matches pattern segments against path components, where a `**`
+/// segment stands for any number of components (at least one at the end,
+/// so that `dir/**` matches what is inside `dir` but not `dir` itself).
+pub fn segments_match(segments: &[Vec<char>], path: &[&str]) -> bool {
+    match segments.split_first() {
+        None => path.is_empty(),
+        Some((first, rest)) if *first == ['*', '*'] => {
+            let least = usize::from(rest.is_empty());
+            (least..=path.len()).any(|skip| segments_match(rest, &path[skip..]))
+        }
+        Some((first, rest)) => path.split_first().is_some_and(|(name, path)| {
+            wildcard_match(first, &name.chars().collect::<Vec<_>>()) && segments_match(rest, path)
+        }),
+    }
+}
+
+/// Matches one path component against a segment with `*`, `?`, `[...]`
+/// classes and `\` escapes.
+fn wildcard_match(pattern: &[char], text: &[char]) -> bool {
+    match pattern.first() {
+        None => text.is_empty(),
+        Some('*') => (0..=text.len()).any(|skip| wildcard_match(&pattern[1..], &text[skip..])),
+        Some('?') => !text.is_empty() && wildcard_match(&pattern[1..], &text[1..]),
+        Some('[') => match (class_match(&pattern[1..], text.first()), text.split_first()) {
+            (Some((true, len)), Some((_, text))) => wildcard_match(&pattern[1 + len..], text),
+            (Some(_), _) => false,
+            // An unclosed bracket is a literal one.
+            (None, _) => text.first() == Some(&'[') && wildcard_match(&pattern[1..], &text[1..]),
+        },
+        Some('\\') if pattern.len() > 1 => {
+            text.first() == Some(&pattern[1]) && wildcard_match(&pattern[2..], &text[1..])
+        }
+        Some(c) => text.first() == Some(c) && wildcard_match(&pattern[1..], &text[1..]),
+    }
+}
+
+/// Whether `c` is in the class whose body (after `[`) starts `pattern`, and
+/// how many characters the body takes up to and including `]`; `None` if
+/// the class isn't closed.
+fn class_match(pattern: &[char], c: Option<&char>) -> Option<(bool, usize)> {
+    let negated = matches!(pattern.first(), Some('!' | '^'));
+    let mut at = usize::from(negated);
+    let mut found = false;
+    let mut first = true;
+    loop {
+        match pattern.get(at)? {
+            ']' if !first => break,
+            &start
+                if pattern.get(at + 1) == Some(&'-')
+                    && pattern.get(at + 2).is_some_and(|end| *end != ']') =>
+            {
+                let end = pattern[at + 2];
+                found |= c.is_some_and(|c| (start..=end).contains(c));
+                at += 3;
+            }
+            other => {
+                found |= c == Some(other);
+                at += 1;
+            }
+        }
+        first = false;
+    }
+    Some((found != negated, at + 1))
+}
@@ -0,0 +1,246 @@
+//! The tests a fix can break: the test files of a package that import the
+//! module fixed, filling in `{tests}` in `--post-fix-cmd`.
+//!
+//! The test files are those `pytest --collect-only` lists, run from the
+//! package root so that its `testpaths`, `python_files` and `conftest.py`
+//! apply; without pytest, they are the files named as pytest collects them
+//! by default. Imports are read from the tests' source, and a module
+//! matches by any tail of its dotted path, so `src/pkg/mod.py` is
+//! `src.pkg.mod`, `pkg.mod` or `mod`: a test importing a module of the same
+//! name elsewhere runs too, which is safer than one left out.
+
+use std::collections::BTreeSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use rustpython_parser::ast::{ExceptHandler, Mod, Stmt};
+use rustpython_parser::{parse, Mode};
+
+use crate::ignore::VENDORED_DIRS;
+use crate::ruff;
+
+/// The test files pytest collects in the package at `root`, or nothing if
+/// pytest isn't installed in its environment.
+pub fn collected_tests(root: &Path) -> io::Result<Option<Vec<PathBuf>>> {
+    let Some(pytest) = ruff::find_in_environment(root, "pytest") else {
+        return Ok(None);
+    };
+    let output = Command::new(&pytest)
+        .args([
+            "--collect-only",
+            "-q",
+            "-p",
+            "no:cacheprovider",
+            "--rootdir",
+            ".",
+        ])
+        .current_dir(root)
+        .output()
+        .map_err(|err| io::Error::other(format!("Failed to run {}: {}", pytest.display(), err)))?;
+    // 5 means there were no tests to collect.
+    if !output.status.success() && output.status.code() != Some(5) {
+        return Err(io::Error::other(format!(
+            "pytest failed to collect the tests with {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stdout).trim()
+        )));
+    }
+    Ok(Some(collected_files(
+        root,
+        &String::from_utf8_lossy(&output.stdout),
+    )))
+}
+
+/// The files of the test IDs `pytest --collect-only -q` printed, such as
+/// `tests/test_a.py::TestA::test_b`, relative to `root`.
+fn collected_files(root: &Path, output: &str) -> Vec<PathBuf> {
+    let files: BTreeSet<PathBuf> = output
+        .lines()
+        .filter_map(|line| line.split_once("::"))
+        .map(|(file, _)| root.join(file))
+        .collect();
+    files.into_iter().collect()
+}
+
+/// The test files under `root`, named as pytest collects them by default.
+pub fn named_tests(root: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut tests = Vec::new();
+    find_tests(root, &mut tests)?;
+    Ok(tests)
+}
+
+/// The files of `tests` importing `file`, a module of the package at
+/// `root`, or `file` itself if it is one of them, sorted.
+pub fn tests_importing(root: &Path, file: &Path, tests: &[PathBuf]) -> io::Result<Vec<PathBuf>> {
+    let file = file.canonicalize()?;
+    let root = root.canonicalize()?;
+    let names = module_names(&root, &file);
+    let mut impacted = Vec::new();
+    for test in tests {
+        let Ok(test) = test.canonicalize() else {
+            continue;
+        };
+        if test == file {
+            impacted.push(test);
+            continue;
+        }
+        let content = match fs::read_to_string(&test) {
+            Ok(content) => content,
+            Err(err) if err.kind() == io::ErrorKind::InvalidData => continue,
+            Err(err) => return Err(err),
+        };
+        let package = module_names(&root, &test)
+            .into_iter()
+            .next()
+            .map(|name| {
+                name.rsplit_once('.')
+                    .map_or("", |(package, _)| package)
+                    .to_string()
+            })
+            .unwrap_or_default();
+        if imports(&content, &package)
+            .iter()
+            .any(|import| names.iter().any(|name| covers(import, name)))
+        {
+            impacted.push(test);
+        }
+    }
+    impacted.sort();
+    Ok(impacted)
+}
+
+/// Whether pytest collects `name` as a test file by default.
+fn is_test_file(name: &str) -> bool {
+    name.ends_with(".py") && (name.starts_with("test_") || name.ends_with("_test.py"))
+}
+
+fn find_tests(dir: &Path, tests: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        if name.starts_with('.') || VENDORED_DIRS.contains(&name.as_ref()) {
+            continue;
+        }
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            find_tests(&entry.path(), tests)?;
+        } else if file_type.is_file() && is_test_file(&name) {
+            tests.push(entry.path());
+        }
+    }
+    Ok(())
+}
+
+/// The names `file` may be imported by, the longest first: its path from
+/// `root` dotted, and every tail of it.
+fn module_names(root: &Path, file: &Path) -> Vec<String> {
+    let Ok(relative) = file.strip_prefix(root) else {
+        return Vec::new();
+    };
+    let mut parts: Vec<String> = relative
+        .with_extension("")
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy().into_owned())
+        .collect();
+    if parts.last().is_some_and(|part| part == "__init__") {
+        parts.pop();
+    }
+    (0..parts.len())
+        .map(|start| parts[start..].join("."))
+        .collect()
+}
+
+/// Whether importing `import` runs the module `name`: it is the module, in
+/// it, or in a package it is in.
+fn covers(import: &str, name: &str) -> bool {
+    let within = |outer: &str, inner: &str| {
+        inner == outer || inner.starts_with(outer) && inner[outer.len()..].starts_with('.')
+    };
+    within(name, import) || within(import, name)
+}
+
+/// The modules `content` imports, at any depth, relative imports resolved
+/// against `package`. `from a import b` imports `a.b` as far as is known.
+fn imports(content: &str, package: &str) -> Vec<String> {
+    let Ok(Mod::Module(module)) = parse(content, Mode::Module, "<test>") else {
+        return Vec::new();
+    };
+    let mut imports = Vec::new();
+    collect_imports(&module.body, package, &mut imports);
+    imports
+}
+
+fn collect_imports(suite: &[Stmt], package: &str, imports: &mut Vec<String>) {
+    for stmt in suite {
+        match stmt {
+            Stmt::Import(import) => {
+                imports.extend(import.names.iter().map(|alias| alias.name.to_string()));
+            }
+            Stmt::ImportFrom(import) => {
+                let level = import.level.map_or(0, |level| level.to_usize());
+                let mut base: Vec<&str> =
+                    package.split('.').filter(|part| !part.is_empty()).collect();
+                if level > 0 {
+                    base.truncate(base.len().saturating_sub(level - 1));
+                } else {
+                    base.clear();
+                }
+                if let Some(module) = &import.module {
+                    base.push(module.as_str());
+                }
+                let base = base.join(".");
+                for alias in &import.names {
+                    imports.push(if base.is_empty() {
+                        alias.name.to_string()
+                    } else {
+                        format!("{}.{}", base, alias.name)
+                    });
+                }
+            }
+            Stmt::FunctionDef(def) => collect_imports(&def.body, package, imports),
+            Stmt::AsyncFunctionDef(def) => collect_imports(&def.body, package, imports),
+            Stmt::ClassDef(def) => collect_imports(&def.body, package, imports),
+            Stmt::If(stmt) => {
+                collect_imports(&stmt.body, package, imports);
+                collect_imports(&stmt.orelse, package, imports);
+            }
+            Stmt::Try(stmt) => {
+                collect_imports(&stmt.body, package, imports);
+                collect_imports(&stmt.orelse, package, imports);
+                collect_imports(&stmt.finalbody, package, imports);
+                for handler in &stmt.handlers {
+                    let ExceptHandler::ExceptHandler(handler) = handler;
+                    collect_imports(&handler.body, package, imports);
+                }
+            }
+            Stmt::With(stmt) => collect_imports(&stmt.body, package, imports),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_the_files_of_the_collected_tests() {
+        let output = "tests/test_a.py::test_a\n\
+                      tests/test_a.py::TestA::test_b[1]\n\
+                      tests/checks/check_b.py::test_b\n\
+                      \n\
+                      3 tests collected in 0.01s\n";
+
+        assert_eq!(
+            collected_files(Path::new("/project"), output),
+            [
+                PathBuf::from("/project/tests/checks/check_b.py"),
+                PathBuf::from("/project/tests/test_a.py"),
+            ]
+        );
+        assert!(collected_files(Path::new("/project"), "no tests ran in 0.01s\n").is_empty());
+    }
+}
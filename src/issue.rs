@@ -0,0 +1,308 @@
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+use std::str::FromStr;
+
+use serde::{Deserialize, Deserializer};
+use sha2::{Digest, Sha256};
+use similar::{DiffOp, DiffTag, TextDiff};
+
+/// Most lines of a multi-line finding quoted in a prompt.
+const MAX_QUOTED_LINES: usize = 10;
+
+/// A single diagnostic as reported by `ruff check --output-format json`.
+///
+/// Fields that older ruff versions don't emit are optional, and the
+/// camelCase names some versions use are understood too.
+#[derive(Deserialize)]
+pub struct Issue {
+    pub filename: String,
+    #[serde(deserialize_with = "code_or_syntax_error")]
+    pub code: String,
+    pub message: String,
+    pub location: Location,
+    #[serde(default, alias = "endLocation")]
+    pub end_location: Option<Location>,
+    /// Line a `# noqa` comment for the finding belongs on.
+    #[serde(default, alias = "noqaRow")]
+    pub noqa_row: Option<u32>,
+    /// Documentation of the rule.
+    #[serde(default)]
+    pub url: Option<String>,
+    /// Ruff's own fix for the finding, when it has one.
+    #[serde(default)]
+    pub fix: Option<Fix>,
+
+    /// Stable identifier of the finding, see [`assign_ids`].
+    #[serde(skip)]
+    pub id: String,
+}
+
+#[derive(Deserialize)]
+pub struct Location {
+    pub row: u32,
+    pub column: u32,
+}
+
+impl Location {
+    /// Byte offset of this one-based position in `content`; positions past
+    /// the end of a line or of the content are clamped to it.
+    pub fn offset_in(&self, content: &str) -> usize {
+        let line_start: usize = content
+            .split_inclusive('\n')
+            .take((self.row as usize).saturating_sub(1))
+            .map(str::len)
+            .sum();
+        let line = content[line_start..].lines().next().unwrap_or_default();
+        let in_line = line
+            .char_indices()
+            .nth((self.column as usize).saturating_sub(1))
+            .map_or(line.len(), |(i, _)| i);
+        line_start + in_line
+    }
+}
+
+impl Issue {
+    /// The lines the finding spans, ending early for long spans.
+    pub fn lines<'a>(&self, content: &'a str) -> &'a str {
+        let row = self.location.row as usize;
+        let end_row = self
+            .end_location
+            .as_ref()
+            // A span ending at the start of a line doesn't include it.
+            .map_or(row, |end| {
+                let end_row = end.row as usize - usize::from(end.column <= 1);
+                end_row.max(row)
+            })
+            .min(row + MAX_QUOTED_LINES - 1);
+        let start = Location {
+            row: row as u32,
+            column: 1,
+        }
+        .offset_in(content);
+        let end = Location {
+            row: end_row as u32 + 1,
+            column: 1,
+        }
+        .offset_in(content);
+        content[start..end.max(start)].trim_end_matches(['\n', '\r'])
+    }
+
+    /// The exact code the finding is about, when ruff reports where it ends.
+    pub fn span<'a>(&self, content: &'a str) -> Option<&'a str> {
+        let end = self.end_location.as_ref()?;
+        let start = self.location.offset_in(content);
+        let end = end.offset_in(content);
+        Some(&content[start..end.max(start)])
+    }
+}
+
+/// Ruff reports syntax errors without a rule code since 0.5; they get
+/// `E999`, the code they had before.
+fn code_or_syntax_error<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    Ok(Option::<String>::deserialize(deserializer)?.unwrap_or_else(|| "E999".to_string()))
+}
+
+#[derive(Deserialize)]
+pub struct Fix {
+    pub applicability: Applicability,
+    #[serde(default)]
+    pub message: Option<String>,
+    pub edits: Vec<Edit>,
+}
+
+/// Replaces the text from `location` up to `end_location` with `content`.
+#[derive(Deserialize)]
+pub struct Edit {
+    #[serde(default)]
+    pub content: Option<String>,
+    pub location: Location,
+    pub end_location: Location,
+}
+
+/// Whether ruff considers a fix safe to apply without review.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(from = "String")]
+pub enum Applicability {
+    Safe,
+    Unsafe,
+    DisplayOnly,
+}
+
+impl From<String> for Applicability {
+    /// Understands both current names and those of ruff before 0.1
+    /// (`Automatic`, `Suggested`, `Manual`).
+    fn from(name: String) -> Self {
+        match name.to_ascii_lowercase().as_str() {
+            "safe" | "automatic" => Applicability::Safe,
+            "display-only" | "displayonly" | "manual" => Applicability::DisplayOnly,
+            _ => Applicability::Unsafe,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+impl FromStr for Severity {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "info" => Ok(Severity::Info),
+            "warning" => Ok(Severity::Warning),
+            "error" => Ok(Severity::Error),
+            other => Err(format!(
+                "unknown severity {:?}, expected error, warning or info",
+                other
+            )),
+        }
+    }
+}
+
+/// How serious a finding of rule `code` is: syntax errors and names that
+/// can't be resolved break the program, other pyflakes and bugbear findings
+/// usually point at bugs, and everything else is style.
+pub fn severity(code: &str) -> Severity {
+    const ERRORS: &[&str] = &["E9", "F63", "F7", "F82", "PLE"];
+    const WARNINGS: &[&str] = &["F", "B", "S", "PLW"];
+    if ERRORS.iter().any(|prefix| code.starts_with(prefix)) {
+        Severity::Error
+    } else if WARNINGS.iter().any(|prefix| code.starts_with(prefix)) {
+        Severity::Warning
+    } else {
+        Severity::Info
+    }
+}
+
+/// Gives every issue of one file its stable ID.
+///
+/// The ID is a hash of the file path (relative to the root of its project,
+/// whichever directory ruffer runs in), the rule code and the
+/// whitespace-normalized content of the offending line, so it survives
+/// unrelated edits that shift line numbers and is the same on every
+/// machine. Identical findings on identical lines are told apart by their
+/// order of appearance.
+pub fn assign_ids(issues: &mut [Issue], file_content: &str) {
+    let lines: Vec<&str> = file_content.lines().collect();
+    let mut seen: HashMap<(String, String), usize> = HashMap::new();
+    let mut paths: HashMap<String, String> = HashMap::new();
+    for issue in issues.iter_mut() {
+        let line = lines
+            .get((issue.location.row as usize).saturating_sub(1))
+            .map(|line| normalize_line(line))
+            .unwrap_or_default();
+        let occurrence = seen.entry((issue.code.clone(), line.clone())).or_insert(0);
+        let path = paths
+            .entry(issue.filename.clone())
+            .or_insert_with(|| project_path(&issue.filename));
+        issue.id = issue_id(path, &issue.code, &line, *occurrence);
+        *occurrence += 1;
+    }
+}
+
+/// Moves the locations of `issues`, found in `before`, to where their lines
+/// are in `after`. An issue on a line the edit rewrote points at the first
+/// line that replaced it.
+pub fn follow_edit(issues: &mut [Issue], before: &str, after: &str) {
+    let diff = TextDiff::from_lines(before, after);
+    for issue in issues.iter_mut() {
+        issue.location.row = follow_row(diff.ops(), issue.location.row, before, after);
+    }
+}
+
+/// Where the one-based `row` of `before` is in `after`, as with
+/// [`follow_edit`].
+pub fn moved_row(row: u32, before: &str, after: &str) -> u32 {
+    follow_row(
+        TextDiff::from_lines(before, after).ops(),
+        row,
+        before,
+        after,
+    )
+}
+
+fn follow_row(ops: &[DiffOp], row: u32, before: &str, after: &str) -> u32 {
+    let line = (row as usize).saturating_sub(1);
+    let moved = match ops.iter().find(|op| op.old_range().contains(&line)) {
+        Some(op) if op.tag() == DiffTag::Equal => {
+            op.new_range().start + (line - op.old_range().start)
+        }
+        Some(op) => op.new_range().start,
+        // Past the end of `before`: keep the distance to the end.
+        None => (line + after.lines().count()).saturating_sub(before.lines().count()),
+    };
+    moved as u32 + 1
+}
+
+fn issue_id(path: &str, code: &str, normalized_line: &str, occurrence: usize) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(path.as_bytes());
+    hasher.update([0]);
+    hasher.update(code.as_bytes());
+    hasher.update([0]);
+    hasher.update(normalized_line.as_bytes());
+    if occurrence > 0 {
+        hasher.update([0]);
+        hasher.update(occurrence.to_string().as_bytes());
+    }
+    let digest = hasher.finalize();
+    digest[..6].iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Path relative to the root of the project the file is in, with `/`
+/// separators: the closest directory above it holding `.git`, or else
+/// `pyproject.toml`. Unlike [`display_path`], it doesn't depend on where
+/// ruffer runs; a file in neither falls back to that.
+fn project_path(filename: &str) -> String {
+    let path = Path::new(filename);
+    let absolute = match std::env::current_dir() {
+        Ok(cwd) if path.is_relative() => cwd.join(path),
+        _ => path.to_path_buf(),
+    };
+    // Lexically, so that `./` and `../` don't tell paths apart.
+    let absolute: PathBuf = absolute
+        .components()
+        .fold(PathBuf::new(), |mut path, part| {
+            match part {
+                Component::CurDir => {}
+                Component::ParentDir => {
+                    path.pop();
+                }
+                part => path.push(part),
+            }
+            path
+        });
+    let root = absolute
+        .ancestors()
+        .skip(1)
+        .find(|dir| dir.join(".git").exists())
+        .or_else(|| {
+            absolute
+                .ancestors()
+                .skip(1)
+                .find(|dir| dir.join("pyproject.toml").is_file())
+        });
+    match root.and_then(|root| absolute.strip_prefix(root).ok()) {
+        Some(relative) => relative.to_string_lossy().replace('\\', "/"),
+        None => display_path(filename),
+    }
+}
+
+/// Path relative to the working directory with `/` separators, falling back
+/// to the path as given when it lies elsewhere.
+pub fn display_path(filename: &str) -> String {
+    let path = Path::new(filename);
+    let relative = std::env::current_dir()
+        .ok()
+        .and_then(|cwd| path.strip_prefix(cwd).ok().map(Path::to_path_buf))
+        .unwrap_or_else(|| path.to_path_buf());
+    relative.to_string_lossy().replace('\\', "/")
+}
+
+fn normalize_line(line: &str) -> String {
+    line.split_whitespace().collect::<Vec<_>>().join(" ")
+}
@@ -0,0 +1,211 @@
+//! The journal of changes made by fixing runs, for `ruffer undo`.
+//!
+//! Every run that changes files gets a JSON Lines file in the journal
+//! directory, named after the run's ID, with one line per change of a
+//! file: its path, hashes of its content before and after, and the patch
+//! between them. Undoing a run checks that every file still has the
+//! content the run left, and only then applies the patches in reverse.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::cache::hex;
+use crate::encoding;
+use crate::patch;
+
+/// Tells apart the runs one process starts within a second.
+static RUNS: AtomicUsize = AtomicUsize::new(0);
+
+/// Where runs are journaled unless `--journal-dir` says otherwise.
+pub const DEFAULT_JOURNAL_DIR: &str = ".ruffer_journal";
+
+#[derive(Serialize, Deserialize)]
+struct Change {
+    filename: String,
+    before: String,
+    after: String,
+    patch: String,
+}
+
+/// The journal of the current run; its file is created with the first
+/// change.
+pub struct Journal {
+    dir: PathBuf,
+    id: String,
+    file: Mutex<Option<File>>,
+}
+
+impl Journal {
+    /// A journal for a new run, with an ID that sorts after those of
+    /// earlier runs.
+    pub fn new(dir: &Path) -> Journal {
+        let seconds = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs());
+        Journal {
+            dir: dir.to_path_buf(),
+            id: format!(
+                "{}-{}-{}",
+                seconds,
+                std::process::id(),
+                RUNS.fetch_add(1, Ordering::Relaxed)
+            ),
+            file: Mutex::new(None),
+        }
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Whether the run has changed any file.
+    pub fn is_empty(&self) -> bool {
+        self.file.lock().unwrap().is_none()
+    }
+
+    /// Records that `filename` was changed from `before` to `after`.
+    pub fn record(&self, filename: &str, before: &str, after: &str) -> io::Result<()> {
+        let change = Change {
+            filename: fs::canonicalize(filename)?.to_string_lossy().into_owned(),
+            before: digest(before),
+            after: digest(after),
+            patch: patch::unified(filename, before, after),
+        };
+        let mut line = serde_json::to_string(&change)?;
+        line.push('\n');
+
+        let mut file = self.file.lock().unwrap();
+        if file.is_none() {
+            fs::create_dir_all(&self.dir)?;
+            *file = Some(
+                OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(run_path(&self.dir, &self.id))?,
+            );
+        }
+        let file = file.as_mut().expect("opened above");
+        file.write_all(line.as_bytes())?;
+        file.sync_data()
+    }
+}
+
+/// What `undo` reverted.
+#[derive(Debug)]
+pub struct Undone {
+    pub run_id: String,
+    pub files: Vec<String>,
+}
+
+/// Reverts the changes of the run `run_id`, or of the latest run still in
+/// the journal, and removes the run from the journal. Nothing is reverted
+/// unless every file the run changed still has the content it left.
+pub fn undo(dir: &Path, run_id: Option<&str>) -> io::Result<Undone> {
+    let run_id = match run_id {
+        Some(run_id) => run_id.to_string(),
+        None => latest_run(dir)?,
+    };
+    let path = run_path(dir, &run_id);
+    let data = fs::read_to_string(&path).map_err(|err| {
+        io::Error::new(
+            err.kind(),
+            format!("No run {} in {}: {}", run_id, dir.display(), err),
+        )
+    })?;
+    let changes = data
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(serde_json::from_str)
+        .collect::<Result<Vec<Change>, _>>()
+        .map_err(|err| io::Error::other(format!("Invalid journal {}: {}", path.display(), err)))?;
+
+    // Work out every file's old content before touching any. A file
+    // changed more than once, by several iterations, has its changes
+    // undone one after the other, the last first.
+    let mut reverted: Vec<(&String, String, encoding::SourceEncoding)> = Vec::new();
+    let mut problems = Vec::new();
+    for change in changes.iter().rev() {
+        let earlier = reverted
+            .iter()
+            .position(|(filename, _, _)| *filename == &change.filename);
+        let (content, source_encoding) = match earlier {
+            Some(i) => {
+                let (_, content, source_encoding) = reverted.remove(i);
+                (content, source_encoding)
+            }
+            None => match encoding::read(Path::new(&change.filename)) {
+                Ok(current) => current,
+                Err(err) => {
+                    problems.push(format!("{}: {}", change.filename, err));
+                    continue;
+                }
+            },
+        };
+        if digest(&content) != change.after {
+            problems.push(format!("{} changed since the run", change.filename));
+            continue;
+        }
+        match patch::apply(&content, &patch::reverse(&change.patch)) {
+            Ok(old) if digest(&old) == change.before => {
+                reverted.push((&change.filename, old, source_encoding));
+            }
+            _ => problems.push(format!(
+                "{}: the patch doesn't restore its old content",
+                change.filename
+            )),
+        }
+    }
+    if !problems.is_empty() {
+        return Err(io::Error::other(format!(
+            "Not undoing run {}:\n{}",
+            run_id,
+            problems.join("\n")
+        )));
+    }
+
+    let mut files = Vec::new();
+    for (filename, old, source_encoding) in reverted {
+        encoding::write(Path::new(filename), &old, source_encoding)?;
+        files.push(filename.clone());
+    }
+    fs::remove_file(&path)?;
+    Ok(Undone { run_id, files })
+}
+
+fn latest_run(dir: &Path) -> io::Result<String> {
+    let mut runs: Vec<String> = fs::read_dir(dir)
+        .map_err(|err| {
+            io::Error::new(
+                err.kind(),
+                format!("No journal in {}: {}", dir.display(), err),
+            )
+        })?
+        .filter_map(|entry| {
+            let name = entry.ok()?.file_name().into_string().ok()?;
+            name.strip_suffix(".jsonl").map(str::to_string)
+        })
+        .collect();
+    runs.sort();
+    runs.pop().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("No runs to undo in {}", dir.display()),
+        )
+    })
+}
+
+fn run_path(dir: &Path, run_id: &str) -> PathBuf {
+    dir.join(format!("{}.jsonl", run_id))
+}
+
+/// The SHA-256 hash of `content`, in hex.
+pub fn digest(content: &str) -> String {
+    hex(&Sha256::digest(content.as_bytes()))
+}
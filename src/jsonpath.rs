@@ -0,0 +1,103 @@
+//! The part of JSONPath the linters of `[tool.ruffer.linter.<name>]` are
+//! declared with: `$` for the whole output and `@` for the finding at
+//! hand, followed by `.name` or `['name']` for a key, `[0]` for an item,
+//! and `.*` or `[*]` for every key or item. Filters, slices and recursive
+//! descent aren't supported.
+
+use std::str::FromStr;
+
+use serde_json::Value;
+
+/// A path parsed.
+#[derive(Clone, Debug)]
+pub struct JsonPath {
+    /// Whether the path starts at `@` rather than `$`.
+    relative: bool,
+    segments: Vec<Segment>,
+}
+
+#[derive(Clone, Debug)]
+enum Segment {
+    Key(String),
+    Index(usize),
+    All,
+}
+
+impl FromStr for JsonPath {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = |reason: &str| format!("invalid JSONPath {:?}: {}", s, reason);
+        let (relative, mut rest) = match s.chars().next() {
+            Some('$') => (false, &s[1..]),
+            Some('@') => (true, &s[1..]),
+            _ => return Err(invalid("expected it to start with $ or @")),
+        };
+        let mut segments = Vec::new();
+        while !rest.is_empty() {
+            if let Some(after) = rest.strip_prefix('.') {
+                let end = after.find(['.', '[']).unwrap_or(after.len());
+                let key = &after[..end];
+                segments.push(match key {
+                    "" => return Err(invalid("expected a key after `.`")),
+                    "*" => Segment::All,
+                    key => Segment::Key(key.to_string()),
+                });
+                rest = &after[end..];
+            } else if let Some(after) = rest.strip_prefix('[') {
+                let end = after.find(']').ok_or_else(|| invalid("unclosed `[`"))?;
+                let inside = after[..end].trim();
+                segments.push(if inside == "*" {
+                    Segment::All
+                } else if let Some(key) = inside
+                    .strip_prefix('\'')
+                    .and_then(|key| key.strip_suffix('\''))
+                    .or_else(|| {
+                        inside
+                            .strip_prefix('"')
+                            .and_then(|key| key.strip_suffix('"'))
+                    })
+                {
+                    Segment::Key(key.to_string())
+                } else {
+                    Segment::Index(
+                        inside
+                            .parse()
+                            .map_err(|_| invalid("expected a key, an index or `*` in `[]`"))?,
+                    )
+                });
+                rest = &after[end + 1..];
+            } else {
+                return Err(invalid("expected `.` or `[`"));
+            }
+        }
+        Ok(JsonPath { relative, segments })
+    }
+}
+
+impl JsonPath {
+    /// The values the path leads to, from `root` for `$` and `current`
+    /// for `@`, in order.
+    pub fn select<'a>(&self, root: &'a Value, current: &'a Value) -> Vec<&'a Value> {
+        let mut values = vec![if self.relative { current } else { root }];
+        for segment in &self.segments {
+            values = values
+                .into_iter()
+                .flat_map(|value| -> Vec<&Value> {
+                    match (segment, value) {
+                        (Segment::Key(key), Value::Object(object)) => {
+                            object.get(key).into_iter().collect()
+                        }
+                        (Segment::Index(index), Value::Array(array)) => {
+                            array.get(*index).into_iter().collect()
+                        }
+                        (Segment::All, Value::Array(array)) => array.iter().collect(),
+                        (Segment::All, Value::Object(object)) => object.values().collect(),
+                        _ => Vec::new(),
+                    }
+                })
+                .collect();
+        }
+        values
+    }
+}
@@ -0,0 +1,71 @@
+use std::path::Path;
+use std::str::FromStr;
+
+/// The language of the code a run fixes, as given with `--language`, and of
+/// each file, by its extension.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Language {
+    /// Checked by ruff, and the linters of `--linter`.
+    Python,
+    /// Checked by `cargo clippy`.
+    Rust,
+    /// Checked by ESLint, with `--linter eslint`.
+    JavaScript,
+    TypeScript,
+}
+
+impl FromStr for Language {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "python" => Ok(Language::Python),
+            "rust" => Ok(Language::Rust),
+            other => Err(format!("unknown language: {}", other)),
+        }
+    }
+}
+
+impl Language {
+    pub const ALL: [Language; 4] = [
+        Language::Python,
+        Language::Rust,
+        Language::JavaScript,
+        Language::TypeScript,
+    ];
+
+    /// The language of the file `filename`; Python unless its extension
+    /// says otherwise.
+    pub fn of(filename: &str) -> Language {
+        match Path::new(filename)
+            .extension()
+            .and_then(|extension| extension.to_str())
+        {
+            Some("rs") => Language::Rust,
+            Some("js" | "jsx" | "mjs" | "cjs") => Language::JavaScript,
+            Some("ts" | "tsx" | "mts" | "cts") => Language::TypeScript,
+            _ => Language::Python,
+        }
+    }
+
+    /// As the prompts call it.
+    pub fn name(self) -> &'static str {
+        match self {
+            Language::Python => "Python",
+            Language::Rust => "Rust",
+            Language::JavaScript => "JavaScript",
+            Language::TypeScript => "TypeScript",
+        }
+    }
+
+    /// Whether `path` is a source file of this language.
+    pub fn is_source(self, path: &Path) -> bool {
+        let extension = path.extension().and_then(|extension| extension.to_str());
+        match self {
+            Language::Python => matches!(extension, Some("py" | "pyi" | "ipynb")),
+            Language::Rust => extension == Some("rs"),
+            Language::JavaScript => matches!(extension, Some("js" | "jsx" | "mjs" | "cjs")),
+            Language::TypeScript => matches!(extension, Some("ts" | "tsx" | "mts" | "cts")),
+        }
+    }
+}
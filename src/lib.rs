@@ -0,0 +1,84 @@
+//! Fixes issues reported by Ruff in Python code with the help of an LLM.
+//!
+//! The binary is a thin command line front end over [`RuffFixer`]; the
+//! model behind the fixes is a [`provider::FixProvider`], so the whole
+//! pipeline can run against the canned providers in [`testing`].
+
+mod annotate;
+mod atomic;
+mod audit;
+mod autofix;
+mod baseline;
+mod budget;
+pub mod cache;
+mod check;
+mod chunk;
+mod ci;
+mod clippy;
+mod commit;
+mod console;
+mod conversation;
+mod dashboard;
+mod dedupe;
+mod definition;
+mod docstring;
+mod encoding;
+mod estimate;
+mod examples;
+mod excerpt;
+mod fixer;
+pub mod git;
+mod github;
+mod gitlab;
+mod glob;
+mod guard;
+mod highlight;
+mod hook;
+mod ignore;
+mod impact;
+pub mod issue;
+pub mod journal;
+mod jsonpath;
+mod language;
+mod linter;
+mod lock;
+mod log;
+mod lsp;
+mod mcp;
+mod merge;
+mod newline;
+mod noqa;
+mod package;
+mod patch;
+mod prompt;
+pub mod provider;
+mod recording;
+mod redact;
+mod report;
+mod review;
+mod ruff;
+mod rules;
+mod sanitize;
+mod sarif;
+mod security;
+mod serve;
+mod state;
+mod suggest;
+mod syntax;
+mod template;
+pub mod testing;
+mod tokens;
+mod typecheck;
+mod verify;
+mod watch;
+
+pub use baseline::BaselineOptions;
+pub use ci::{PolicyFailure, POLICY_EXIT_CODE};
+pub use estimate::{Estimate, EstimateOptions};
+pub use fixer::{RuffFixer, DEFAULT_CACHE_DIR, INTERRUPTED_EXIT_CODE};
+pub use lsp::LspOptions;
+pub use mcp::McpOptions;
+pub use serve::ServeOptions;
+pub use watch::WatchOptions;
+
+pub type Error = Box<dyn std::error::Error + Send + Sync>;
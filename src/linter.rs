@@ -0,0 +1,792 @@
+//! Linters besides ruff, added with `--linter`: what they find joins what
+//! ruff finds and goes through the same pipeline, verification included.
+//!
+//! Codes are given as ruff gives them where ruff has the rule too, so that
+//! severities, categories and the options about rules apply alike: pylint's
+//! `C0114` is `PLC0114`, and bandit's `B101` is `S101`. Flake8's codes are
+//! ruff's already, and mypy's error codes, such as `arg-type`, are kept,
+//! as are ESLint's rule names, such as `no-unused-vars`. A finding
+//! reported by more than one linter is worked on once.
+//!
+//! Any other linter with JSON output can be declared in the project's
+//! `pyproject.toml`, where JSONPaths say where its findings are (see
+//! [`Configured`]):
+//!
+//! ```toml
+//! [tool.ruffer.linter.semgrep]
+//! args = ["scan", "--json", "{paths}"]
+//! issues = "$.results[*]"
+//! file = "@.path"
+//! line = "@.start.line"
+//! column = "@.start.col"
+//! code = "@.check_id"
+//! message = "@.extra.message"
+//! ```
+
+use std::collections::HashSet;
+use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::issue::{Issue, Location};
+use crate::jsonpath::JsonPath;
+use crate::language::Language;
+use crate::package::{self, Table};
+use crate::ruff;
+
+/// Tells apart the temporary files of concurrent checks.
+static CHECKS: AtomicUsize = AtomicUsize::new(0);
+
+/// A linter's command line and output.
+pub trait LintSource: Send + Sync {
+    /// The name given to `--linter`.
+    fn name(&self) -> &str;
+
+    /// The executable run: a name, looked for like ruff, or a path.
+    fn command(&self) -> &str {
+        self.name()
+    }
+
+    /// The languages of the files the linter checks.
+    fn languages(&self) -> &[Language] {
+        &[Language::Python]
+    }
+
+    /// Arguments that make the linter check `paths`.
+    fn args(&self, paths: &[String]) -> Vec<String>;
+
+    /// Arguments that make the linter check `content` as the file
+    /// `filename`; the content is fed on stdin, and is in `copy` as well
+    /// for linters that only read files.
+    fn content_args(&self, filename: &str, copy: &Path) -> Vec<String>;
+
+    /// Whether the linter exiting with `code` ran fine, whether it found
+    /// anything or not.
+    fn succeeded(&self, code: i32) -> bool {
+        matches!(code, 0 | 1)
+    }
+
+    /// The findings in the linter's output, with the paths it printed.
+    fn parse(&self, output: &str) -> Result<Vec<Issue>, String>;
+}
+
+/// The linters `--linter` knows by name, besides those declared.
+pub const BUILTIN: &[&str] = &["pylint", "flake8", "mypy", "bandit", "eslint"];
+
+/// The linter called `name`.
+pub fn builtin(name: &str) -> Option<Box<dyn LintSource>> {
+    match name {
+        "pylint" => Some(Box::new(Pylint)),
+        "flake8" => Some(Box::new(Flake8)),
+        "mypy" => Some(Box::new(Mypy)),
+        "bandit" => Some(Box::new(Bandit)),
+        "eslint" => Some(Box::new(Eslint)),
+        _ => None,
+    }
+}
+
+/// A linter found to run.
+pub struct Linter {
+    source: Box<dyn LintSource>,
+    path: PathBuf,
+}
+
+impl Linter {
+    /// Finds the command of `source`: a path, from the project at `root`
+    /// if relative, or a name in `node_modules/.bin` of the project or its parents, or
+    /// else found the same way ruff is (see [`ruff::find_in_environment`]).
+    pub fn locate(root: &Path, source: Box<dyn LintSource>) -> io::Result<Linter> {
+        let root = root.canonicalize().unwrap_or_else(|_| root.to_path_buf());
+        let command = source.command();
+        let path = if Path::new(command).components().count() > 1 {
+            Some(root.join(command)).filter(|path| path.is_file())
+        } else {
+            root.ancestors()
+                .map(|dir| dir.join("node_modules/.bin").join(command))
+                .find(|path| path.is_file())
+                .or_else(|| ruff::find_in_environment(&root, command))
+        };
+        let path = path.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!(
+                    "{} not found in the project's node_modules, virtualenv or on PATH",
+                    command
+                ),
+            )
+        })?;
+        Ok(Linter { source, path })
+    }
+
+    pub fn name(&self) -> &str {
+        self.source.name()
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn languages(&self) -> &[Language] {
+        self.source.languages()
+    }
+
+    /// The findings in `paths`, running the linter in `dir` if given.
+    pub fn check(&self, dir: Option<&Path>, paths: &[String]) -> io::Result<Vec<Issue>> {
+        tracing::debug!(?paths, "Running {}", self.path.display());
+        let mut issues = self.run(dir, &self.source.args(paths), None)?;
+        let dir = match dir {
+            Some(dir) => dir.to_path_buf(),
+            None => env::current_dir()?,
+        };
+        for issue in &mut issues {
+            // Relative to where the linter ran.
+            issue.filename = std::path::absolute(dir.join(&issue.filename))?
+                .display()
+                .to_string();
+        }
+        Ok(issues)
+    }
+
+    /// Lints `content` as if it were the file `filename`, without touching
+    /// the file, running the linter in `dir` if given.
+    pub fn lint_content(
+        &self,
+        dir: Option<&Path>,
+        filename: &str,
+        content: &str,
+    ) -> io::Result<Vec<Issue>> {
+        let copy = env::temp_dir().join(format!(
+            "ruffer-lint-{}-{}-{}",
+            std::process::id(),
+            CHECKS.fetch_add(1, Ordering::Relaxed),
+            Path::new(filename)
+                .file_name()
+                .map_or("module.py".into(), |name| name.to_string_lossy())
+        ));
+        fs::write(&copy, content)?;
+        let args = self.source.content_args(filename, &copy);
+        let result = self.run(dir, &args, Some(content));
+        let _ = fs::remove_file(&copy);
+        let mut issues = result?;
+        let filename = std::path::absolute(filename)?.display().to_string();
+        for issue in &mut issues {
+            issue.filename.clone_from(&filename);
+        }
+        Ok(issues)
+    }
+
+    fn run(
+        &self,
+        dir: Option<&Path>,
+        args: &[String],
+        stdin: Option<&str>,
+    ) -> io::Result<Vec<Issue>> {
+        let mut command = Command::new(&self.path);
+        if let Some(dir) = dir {
+            command.current_dir(dir);
+        }
+        let mut child = command
+            .args(args)
+            .stdin(if stdin.is_some() {
+                Stdio::piped()
+            } else {
+                Stdio::null()
+            })
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        if let Some(stdin) = stdin {
+            // Linters reading the copy instead close stdin unread.
+            match child
+                .stdin
+                .take()
+                .expect("stdin is piped")
+                .write_all(stdin.as_bytes())
+            {
+                Err(err) if err.kind() != io::ErrorKind::BrokenPipe => return Err(err),
+                _ => {}
+            }
+        }
+        let output = child.wait_with_output()?;
+        let code = output.status.code().unwrap_or(-1);
+        if !self.source.succeeded(code) {
+            return Err(io::Error::other(format!(
+                "{} failed with exit code {}: {}",
+                self.name(),
+                code,
+                String::from_utf8_lossy(&output.stderr)
+            )));
+        }
+        self.source
+            .parse(&String::from_utf8_lossy(&output.stdout))
+            .map_err(|err| io::Error::other(format!("Invalid {} output: {}", self.name(), err)))
+    }
+}
+
+/// Leaves out the findings reported before in `issues`, by another linter
+/// or with another linter's code for the same rule.
+pub fn drop_duplicates(issues: &mut Vec<Issue>) {
+    let mut seen = HashSet::new();
+    issues.retain(|issue| {
+        seen.insert((
+            issue.filename.clone(),
+            issue.code.clone(),
+            issue.location.row,
+            issue.location.column,
+        ))
+    });
+}
+
+/// A finding of another linter; columns are one-based, as ruff's.
+fn found(filename: String, code: String, message: String, row: u32, column: u32) -> Issue {
+    Issue {
+        filename,
+        code,
+        message,
+        location: Location { row, column },
+        end_location: None,
+        noqa_row: None,
+        url: None,
+        fix: None,
+        id: String::new(),
+    }
+}
+
+struct Pylint;
+
+#[derive(Deserialize)]
+struct PylintMessage {
+    path: String,
+    line: u32,
+    column: u32,
+    message: String,
+    #[serde(rename = "message-id")]
+    message_id: String,
+}
+
+impl LintSource for Pylint {
+    fn name(&self) -> &str {
+        "pylint"
+    }
+
+    fn args(&self, paths: &[String]) -> Vec<String> {
+        let mut args = vec![
+            "--output-format=json".to_string(),
+            "--recursive=y".to_string(),
+        ];
+        args.extend(paths.iter().cloned());
+        args
+    }
+
+    fn content_args(&self, filename: &str, _copy: &Path) -> Vec<String> {
+        vec![
+            "--output-format=json".to_string(),
+            "--from-stdin".to_string(),
+            filename.to_string(),
+        ]
+    }
+
+    /// Pylint's exit code is a bit mask of what it found, with fatal
+    /// errors and usage errors among them.
+    fn succeeded(&self, code: i32) -> bool {
+        (0..64).contains(&code) && code & (1 | 32) == 0
+    }
+
+    fn parse(&self, output: &str) -> Result<Vec<Issue>, String> {
+        let messages: Vec<PylintMessage> = if output.trim().is_empty() {
+            Vec::new()
+        } else {
+            serde_json::from_str(output).map_err(|err| err.to_string())?
+        };
+        Ok(messages
+            .into_iter()
+            .map(|message| {
+                found(
+                    message.path,
+                    format!("PL{}", message.message_id),
+                    message.message,
+                    message.line,
+                    message.column + 1,
+                )
+            })
+            .collect())
+    }
+}
+
+struct Flake8;
+
+/// Flake8 has no JSON output of its own; this format is read instead.
+const FLAKE8_FORMAT: &str = "--format=%(path)s\t%(row)d\t%(col)d\t%(code)s\t%(text)s";
+
+impl LintSource for Flake8 {
+    fn name(&self) -> &str {
+        "flake8"
+    }
+
+    fn args(&self, paths: &[String]) -> Vec<String> {
+        let mut args = vec![FLAKE8_FORMAT.to_string()];
+        args.extend(paths.iter().cloned());
+        args
+    }
+
+    fn content_args(&self, filename: &str, _copy: &Path) -> Vec<String> {
+        vec![
+            FLAKE8_FORMAT.to_string(),
+            "--stdin-display-name".to_string(),
+            filename.to_string(),
+            "-".to_string(),
+        ]
+    }
+
+    fn parse(&self, output: &str) -> Result<Vec<Issue>, String> {
+        output
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| {
+                let fields: Vec<&str> = line.splitn(5, '\t').collect();
+                let [path, row, column, code, text] = fields[..] else {
+                    return Err(format!("unexpected line {:?}", line));
+                };
+                let number = |field: &str| {
+                    field
+                        .parse()
+                        .map_err(|_| format!("unexpected line {:?}", line))
+                };
+                Ok(found(
+                    path.to_string(),
+                    code.to_string(),
+                    text.to_string(),
+                    number(row)?,
+                    number(column)?,
+                ))
+            })
+            .collect()
+    }
+}
+
+struct Mypy;
+
+#[derive(Deserialize)]
+struct MypyError {
+    file: String,
+    line: u32,
+    column: u32,
+    message: String,
+    code: Option<String>,
+    severity: String,
+}
+
+impl LintSource for Mypy {
+    fn name(&self) -> &str {
+        "mypy"
+    }
+
+    fn args(&self, paths: &[String]) -> Vec<String> {
+        let mut args = vec!["--output".to_string(), "json".to_string()];
+        args.extend(paths.iter().cloned());
+        args
+    }
+
+    fn content_args(&self, filename: &str, copy: &Path) -> Vec<String> {
+        vec![
+            "--output".to_string(),
+            "json".to_string(),
+            "--shadow-file".to_string(),
+            filename.to_string(),
+            copy.display().to_string(),
+            filename.to_string(),
+        ]
+    }
+
+    /// One JSON object a line; notes only explain the errors before them.
+    fn parse(&self, output: &str) -> Result<Vec<Issue>, String> {
+        let mut issues = Vec::new();
+        for line in output.lines().filter(|line| line.starts_with('{')) {
+            let error: MypyError = serde_json::from_str(line).map_err(|err| err.to_string())?;
+            if error.severity != "error" {
+                continue;
+            }
+            issues.push(found(
+                error.file,
+                error.code.unwrap_or_else(|| "misc".to_string()),
+                error.message,
+                error.line,
+                error.column + 1,
+            ));
+        }
+        Ok(issues)
+    }
+}
+
+struct Bandit;
+
+#[derive(Deserialize)]
+struct BanditReport {
+    results: Vec<BanditResult>,
+}
+
+#[derive(Deserialize)]
+struct BanditResult {
+    filename: String,
+    line_number: u32,
+    #[serde(default)]
+    col_offset: u32,
+    test_id: String,
+    issue_text: String,
+    #[serde(default)]
+    more_info: Option<String>,
+}
+
+impl LintSource for Bandit {
+    fn name(&self) -> &str {
+        "bandit"
+    }
+
+    fn args(&self, paths: &[String]) -> Vec<String> {
+        let mut args = vec![
+            "--format".to_string(),
+            "json".to_string(),
+            "--quiet".to_string(),
+            "--recursive".to_string(),
+        ];
+        args.extend(paths.iter().cloned());
+        args
+    }
+
+    fn content_args(&self, _filename: &str, _copy: &Path) -> Vec<String> {
+        vec![
+            "--format".to_string(),
+            "json".to_string(),
+            "--quiet".to_string(),
+            "-".to_string(),
+        ]
+    }
+
+    fn parse(&self, output: &str) -> Result<Vec<Issue>, String> {
+        let report: BanditReport = serde_json::from_str(output).map_err(|err| err.to_string())?;
+        Ok(report
+            .results
+            .into_iter()
+            .map(|result| {
+                let mut issue = found(
+                    result.filename,
+                    // Ruff has bandit's rules as flake8-bandit's, with S.
+                    format!("S{}", result.test_id.trim_start_matches('B')),
+                    result.issue_text,
+                    result.line_number,
+                    result.col_offset + 1,
+                );
+                issue.url = result.more_info;
+                issue
+            })
+            .collect())
+    }
+}
+
+struct Eslint;
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct EslintFile {
+    file_path: String,
+    messages: Vec<EslintMessage>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct EslintMessage {
+    /// None for files that don't parse.
+    rule_id: Option<String>,
+    message: String,
+    #[serde(default)]
+    line: u32,
+    #[serde(default)]
+    column: u32,
+    #[serde(default)]
+    end_line: Option<u32>,
+    #[serde(default)]
+    end_column: Option<u32>,
+}
+
+impl LintSource for Eslint {
+    fn name(&self) -> &str {
+        "eslint"
+    }
+
+    fn languages(&self) -> &'static [Language] {
+        &[Language::JavaScript, Language::TypeScript]
+    }
+
+    fn args(&self, paths: &[String]) -> Vec<String> {
+        let mut args = vec!["--format".to_string(), "json".to_string()];
+        args.extend(paths.iter().cloned());
+        args
+    }
+
+    fn content_args(&self, filename: &str, _copy: &Path) -> Vec<String> {
+        vec![
+            "--format".to_string(),
+            "json".to_string(),
+            "--stdin".to_string(),
+            "--stdin-filename".to_string(),
+            filename.to_string(),
+        ]
+    }
+
+    fn parse(&self, output: &str) -> Result<Vec<Issue>, String> {
+        let files: Vec<EslintFile> = serde_json::from_str(output).map_err(|err| err.to_string())?;
+        Ok(files
+            .into_iter()
+            .flat_map(|file| {
+                let path = file.file_path;
+                file.messages.into_iter().map(move |message| {
+                    let mut issue = found(
+                        path.clone(),
+                        // ESLint's counterpart of ruff's E999.
+                        message.rule_id.unwrap_or_else(|| "parse-error".to_string()),
+                        message.message,
+                        message.line.max(1),
+                        message.column.max(1),
+                    );
+                    if let (Some(row), Some(column)) = (message.end_line, message.end_column) {
+                        issue.end_location = Some(Location { row, column });
+                    }
+                    issue
+                })
+            })
+            .collect())
+    }
+}
+
+/// A linter declared in a `[tool.ruffer.linter.<name>]` table of the
+/// project's `pyproject.toml`, which replaces a built-in one of the same
+/// name. Its keys are:
+///
+/// - `command`: the executable, the name of the table if not given, or
+///   a path from the `pyproject.toml`;
+/// - `args`: the arguments checking the paths given as `{paths}`;
+/// - `stdin-args`: those checking the code fed on stdin as the file
+///   `{file}`, or else `args` with a copy of the code for `{paths}`;
+/// - `exit-codes`: those of runs that went fine, `0` and `1` if not given;
+/// - `languages`: those of the files checked, Python if not given;
+/// - `issues`: a JSONPath to the findings in the output, `$[*]` if not
+///   given;
+/// - `file`, `line`, `code` and `message`, and optionally `column`,
+///   `end-line` and `end-column`: JSONPaths to those of a finding, from
+///   `@`, the finding, or `$`, the whole output.
+pub struct Configured {
+    name: String,
+    command: String,
+    args: Vec<String>,
+    stdin_args: Option<Vec<String>>,
+    exit_codes: Vec<i32>,
+    languages: Vec<Language>,
+    issues: JsonPath,
+    file: JsonPath,
+    line: JsonPath,
+    column: Option<JsonPath>,
+    end_line: Option<JsonPath>,
+    end_column: Option<JsonPath>,
+    code: JsonPath,
+    message: JsonPath,
+}
+
+/// The linters declared for the project at `root`.
+pub fn configured(root: &Path) -> io::Result<Vec<Configured>> {
+    let (dir, tables) = package::linter_tables(root)?;
+    tables
+        .into_iter()
+        .map(|(name, entries)| {
+            Configured::new(name.clone(), entries, &dir).map_err(|err| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Invalid [tool.ruffer.linter.{}]: {}", name, err),
+                )
+            })
+        })
+        .collect()
+}
+
+impl Configured {
+    /// The linter of the table `name`, in the `pyproject.toml` in `dir`.
+    fn new(name: String, entries: Table, dir: &Path) -> Result<Configured, String> {
+        let mut command = None;
+        let mut args = None;
+        let mut stdin_args = None;
+        let mut exit_codes = None;
+        let mut languages = None;
+        let mut paths: Vec<(String, JsonPath)> = Vec::new();
+        for (key, value) in entries {
+            let single = || match value.as_slice() {
+                [value] => Ok(value.clone()),
+                _ => Err(format!("expected a string for `{}`", key)),
+            };
+            match key.as_str() {
+                "command" => {
+                    // A path is relative to the `pyproject.toml`.
+                    let value = single()?;
+                    command = Some(if Path::new(&value).components().count() > 1 {
+                        dir.join(value).display().to_string()
+                    } else {
+                        value
+                    });
+                }
+                "args" => args = Some(value),
+                "stdin-args" => stdin_args = Some(value),
+                "exit-codes" => {
+                    exit_codes = Some(
+                        value
+                            .iter()
+                            .map(|code| {
+                                code.parse()
+                                    .map_err(|_| format!("invalid exit code {:?}", code))
+                            })
+                            .collect::<Result<_, _>>()?,
+                    )
+                }
+                "languages" => {
+                    languages = Some(
+                        value
+                            .iter()
+                            .map(|name| {
+                                Language::ALL
+                                    .into_iter()
+                                    .find(|language| language.name().eq_ignore_ascii_case(name))
+                                    .ok_or_else(|| format!("unknown language {:?}", name))
+                            })
+                            .collect::<Result<_, _>>()?,
+                    )
+                }
+                "issues" | "file" | "line" | "column" | "end-line" | "end-column" | "code"
+                | "message" => {
+                    let path = single()?.parse()?;
+                    paths.push((key, path));
+                }
+                other => return Err(format!("unknown key `{}`", other)),
+            }
+        }
+        let mut take = |key: &str| {
+            paths
+                .iter()
+                .position(|(name, _)| name == key)
+                .map(|index| paths.swap_remove(index).1)
+        };
+        let required = |path: Option<JsonPath>, key: &str| {
+            path.ok_or_else(|| format!("`{}` is required", key))
+        };
+        Ok(Configured {
+            command: command.unwrap_or_else(|| name.clone()),
+            name,
+            args: args.unwrap_or_else(|| vec!["{paths}".to_string()]),
+            stdin_args,
+            exit_codes: exit_codes.unwrap_or_else(|| vec![0, 1]),
+            languages: languages.unwrap_or_else(|| vec![Language::Python]),
+            issues: match take("issues") {
+                Some(path) => path,
+                None => "$[*]".parse()?,
+            },
+            file: required(take("file"), "file")?,
+            line: required(take("line"), "line")?,
+            column: take("column"),
+            end_line: take("end-line"),
+            end_column: take("end-column"),
+            code: required(take("code"), "code")?,
+            message: required(take("message"), "message")?,
+        })
+    }
+
+    /// The first value of `path` in `finding`, as text.
+    fn text(path: &JsonPath, output: &Value, finding: &Value) -> Option<String> {
+        match path.select(output, finding).first()? {
+            Value::String(text) => Some(text.clone()),
+            Value::Null => None,
+            other => Some(other.to_string()),
+        }
+    }
+
+    fn number(path: Option<&JsonPath>, output: &Value, finding: &Value) -> Option<u32> {
+        Configured::text(path?, output, finding)?.parse().ok()
+    }
+}
+
+impl LintSource for Configured {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn command(&self) -> &str {
+        &self.command
+    }
+
+    fn languages(&self) -> &[Language] {
+        &self.languages
+    }
+
+    fn args(&self, paths: &[String]) -> Vec<String> {
+        self.args
+            .iter()
+            .flat_map(|arg| {
+                if arg == "{paths}" {
+                    paths.to_vec()
+                } else {
+                    vec![arg.clone()]
+                }
+            })
+            .collect()
+    }
+
+    fn content_args(&self, filename: &str, copy: &Path) -> Vec<String> {
+        match &self.stdin_args {
+            Some(args) => args
+                .iter()
+                .map(|arg| arg.replace("{file}", filename))
+                .collect(),
+            None => self.args(&[copy.display().to_string()]),
+        }
+    }
+
+    fn succeeded(&self, code: i32) -> bool {
+        self.exit_codes.contains(&code)
+    }
+
+    fn parse(&self, output: &str) -> Result<Vec<Issue>, String> {
+        if output.trim().is_empty() {
+            return Ok(Vec::new());
+        }
+        let output: Value = serde_json::from_str(output).map_err(|err| err.to_string())?;
+        self.issues
+            .select(&output, &output)
+            .into_iter()
+            .map(|finding| {
+                let field = |path: &JsonPath, key: &str| {
+                    Configured::text(path, &output, finding)
+                        .ok_or_else(|| format!("no `{}` in the finding {}", key, finding))
+                };
+                let line = field(&self.line, "line")?;
+                let line = line
+                    .parse()
+                    .map_err(|_| format!("invalid line {:?} in the finding {}", line, finding))?;
+                let column = Configured::number(self.column.as_ref(), &output, finding);
+                let mut issue = found(
+                    field(&self.file, "file")?,
+                    field(&self.code, "code")?,
+                    field(&self.message, "message")?,
+                    line,
+                    column.unwrap_or(1).max(1),
+                );
+                let end_row = Configured::number(self.end_line.as_ref(), &output, finding);
+                let end_column = Configured::number(self.end_column.as_ref(), &output, finding);
+                if let (Some(row), Some(column)) = (end_row, end_column) {
+                    issue.end_location = Some(Location { row, column });
+                }
+                Ok(issue)
+            })
+            .collect()
+    }
+}
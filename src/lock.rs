@@ -0,0 +1,86 @@
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::io::{self, Read, Seek, Write};
+use std::path::{Path, PathBuf};
+
+/// Name of the lock file a fixing run holds in the root folder.
+pub const LOCK_FILE: &str = ".ruffer.lock";
+
+/// An advisory lock on a project, held for as long as this lives. It is an
+/// `flock(2)` lock on Unix, so shell scripts and git hooks can take it too
+/// with `flock .ruffer.lock <command>`. The lock goes away with the
+/// process, however it ends; the lock file itself is removed when the run
+/// is done with it.
+pub struct ProjectLock {
+    path: PathBuf,
+    _file: File,
+}
+
+impl ProjectLock {
+    /// Locks the project in `dir`, failing at once if another run holds
+    /// the lock.
+    pub fn acquire(dir: &Path) -> io::Result<ProjectLock> {
+        let path = dir.join(LOCK_FILE);
+        loop {
+            let mut file = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .open(&path)?;
+            match file.try_lock() {
+                Ok(()) => {}
+                Err(TryLockError::WouldBlock) => {
+                    let mut holder = String::new();
+                    let _ = file.read_to_string(&mut holder);
+                    let holder = match holder.trim() {
+                        "" => String::new(),
+                        pid => format!(" (pid {})", pid),
+                    };
+                    return Err(io::Error::new(
+                        io::ErrorKind::WouldBlock,
+                        format!(
+                            "Another ruffer run{} is working on {}; wait for it to finish",
+                            holder,
+                            dir.display()
+                        ),
+                    ));
+                }
+                Err(TryLockError::Error(err)) => return Err(err),
+            }
+            // The run holding the lock before may have removed the file
+            // between our opening and locking it; a lock on that file locks
+            // nothing.
+            if !same_file(&file, &path) {
+                continue;
+            }
+            // For the message of a run that finds the project locked.
+            file.set_len(0)?;
+            file.rewind()?;
+            write!(file, "{}", std::process::id())?;
+            return Ok(ProjectLock { path, _file: file });
+        }
+    }
+}
+
+impl Drop for ProjectLock {
+    fn drop(&mut self) {
+        // Removed while still locked, so no one can lock it in between.
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+#[cfg(unix)]
+fn same_file(file: &File, path: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    match (file.metadata(), fs::metadata(path)) {
+        (Ok(open), Ok(named)) => open.dev() == named.dev() && open.ino() == named.ino(),
+        _ => false,
+    }
+}
+
+// Open files can't be removed on other systems, so the file is always the
+// one named.
+#[cfg(not(unix))]
+fn same_file(_file: &File, _path: &Path) -> bool {
+    true
+}
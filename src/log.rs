@@ -0,0 +1,25 @@
+//! Logging of the run with `tracing`, one line per event, prefixed with
+//! the time since the start of the run and the spans it happened in:
+//!
+//! ```text
+//!    1.204318521s DEBUG file{path=src/a.py}:issue{id=3f9a1c0b2d4e code=F401}: Sending 2 messages model=gpt-4o-mini
+//! ```
+
+use std::io::{self, Write};
+use std::sync::Mutex;
+
+use tracing::level_filters::LevelFilter;
+use tracing_subscriber::fmt::time::Uptime;
+
+/// Sends the logs of everything from now on at `level` or above to `out`.
+/// Fails if logging was set up before.
+pub fn init(level: LevelFilter, out: Box<dyn Write + Send>) -> io::Result<()> {
+    tracing_subscriber::fmt()
+        .with_max_level(level)
+        .with_writer(Mutex::new(out))
+        .with_timer(Uptime::default())
+        .with_target(false)
+        .with_ansi(false)
+        .try_init()
+        .map_err(io::Error::other)
+}
@@ -0,0 +1,453 @@
+//! `ruffer lsp`: a minimal language server over stdin and stdout, so that
+//! editors show ruff's diagnostics and offer to fix them with ruffer
+//! without a plugin of their own.
+//!
+//! Documents are synced in full. Every time one is opened or changed, ruff
+//! checks it and its issues are published as diagnostics, each with the ID
+//! of its issue. Code actions offer to fix an issue, or all of those of the
+//! document, with the `ruffer.fix` and `ruffer.fixAll` commands. Running
+//! one fixes the document as it is in the editor, as `--stdin-filename`
+//! does, and asks the editor to apply the fix as a workspace edit; the
+//! files are never written by the server.
+//!
+//! Messages are handled one at a time, so while a fix runs the server
+//! neither answers nor checks documents until it's done. A message that
+//! isn't JSON is answered with a parse error and skipped.
+
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::io::{self, Cursor};
+use std::sync::Arc;
+
+use serde_json::{json, Value};
+use structopt::StructOpt;
+use tokio::io::{
+    AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader,
+};
+
+use crate::cache::ResponseCache;
+use crate::console::Console;
+use crate::fixer::RuffFixer;
+use crate::issue::{self, Issue, Severity};
+use crate::provider::FixProvider;
+use crate::serve::Buffer;
+
+/// JSON-RPC error codes, and the one of LSP for a request that failed.
+const PARSE_ERROR: i64 = -32700;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const REQUEST_FAILED: i64 = -32803;
+
+const FIX_COMMAND: &str = "ruffer.fix";
+const FIX_ALL_COMMAND: &str = "ruffer.fixAll";
+
+/// Options of `ruffer lsp`: those of the runs fixing documents.
+#[derive(StructOpt)]
+pub struct LspOptions {
+    #[structopt(flatten)]
+    fixer: RuffFixer,
+}
+
+struct Server {
+    /// The arguments to parse again for every fix.
+    args: Vec<OsString>,
+    provider: Arc<dyn FixProvider>,
+    cache: Option<Arc<ResponseCache>>,
+    console: Console,
+    /// Checks the documents.
+    linter: RuffFixer,
+    /// The text of the open documents, by URI.
+    documents: HashMap<String, String>,
+    /// The ID of the last request sent to the editor.
+    requests_sent: u64,
+}
+
+impl LspOptions {
+    pub fn init_logging(&self) -> io::Result<()> {
+        self.fixer.init_logging()
+    }
+
+    /// Serves the editor over stdin and stdout with fixes made with the
+    /// OpenAI API, until it says to exit. `args` are the arguments the
+    /// options were parsed from, after the subcommand, to parse again for
+    /// every fix.
+    pub async fn run(self, args: Vec<OsString>) -> io::Result<()> {
+        let provider = self.fixer.provider();
+        self.run_with(args, provider, tokio::io::stdin(), tokio::io::stdout())
+            .await
+    }
+
+    /// Serves the editor over `input` and `output` with fixes made with
+    /// answers from `provider`; see [`Self::run`].
+    pub async fn run_with(
+        self,
+        args: Vec<OsString>,
+        provider: Arc<dyn FixProvider>,
+        input: impl AsyncRead + Unpin,
+        mut output: impl AsyncWrite + Unpin,
+    ) -> io::Result<()> {
+        let console = self.fixer.console().leaving_stdout();
+        let mut server = Server {
+            args,
+            provider,
+            cache: self.fixer.open_cache(&console),
+            console,
+            linter: self.fixer.leaving_stdio(),
+            documents: HashMap::new(),
+            requests_sent: 0,
+        };
+        let mut input = BufReader::new(input);
+        while let Some(body) = read_message(&mut input).await? {
+            let outgoing = match serde_json::from_slice::<Value>(&body) {
+                Ok(message) if message["method"] == "exit" => break,
+                Ok(message) => server.handle(message).await,
+                Err(err) => vec![json!({
+                    "jsonrpc": "2.0",
+                    "id": null,
+                    "error": { "code": PARSE_ERROR, "message": err.to_string() },
+                })],
+            };
+            for outgoing in outgoing {
+                let body = outgoing.to_string();
+                output
+                    .write_all(format!("Content-Length: {}\r\n\r\n{}", body.len(), body).as_bytes())
+                    .await?;
+            }
+            output.flush().await?;
+        }
+        Ok(())
+    }
+}
+
+impl Server {
+    /// The messages to send in answer to `message`: the response to a
+    /// request, and any notifications or requests of the server.
+    async fn handle(&mut self, message: Value) -> Vec<Value> {
+        let Some(method) = message["method"].as_str() else {
+            // The editor's answer to a request of the server.
+            return Vec::new();
+        };
+        let params = &message["params"];
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+        let mut outgoing = Vec::new();
+        let result = match method {
+            "initialize" => Ok(json!({
+                "capabilities": {
+                    "textDocumentSync": 1,
+                    "codeActionProvider": { "codeActionKinds": ["quickfix", "source.fixAll"] },
+                    "executeCommandProvider": { "commands": [FIX_COMMAND, FIX_ALL_COMMAND] },
+                },
+                "serverInfo": { "name": "ruffer", "version": env!("CARGO_PKG_VERSION") },
+            })),
+            "shutdown" => Ok(Value::Null),
+            "textDocument/didOpen" => {
+                let text = params["textDocument"]["text"].as_str().unwrap_or_default();
+                self.documents.insert(uri.to_string(), text.to_string());
+                outgoing.push(self.diagnostics(uri).await);
+                Ok(Value::Null)
+            }
+            "textDocument/didChange" => {
+                // Synced in full, so the last change is the whole text.
+                let changes = params["contentChanges"].as_array();
+                if let Some(text) = changes
+                    .and_then(|changes| changes.last())
+                    .and_then(|change| change["text"].as_str())
+                {
+                    self.documents.insert(uri.to_string(), text.to_string());
+                    outgoing.push(self.diagnostics(uri).await);
+                }
+                Ok(Value::Null)
+            }
+            "textDocument/didClose" => {
+                self.documents.remove(uri);
+                outgoing.push(publish(uri, Vec::new()));
+                Ok(Value::Null)
+            }
+            "textDocument/codeAction" => Ok(code_actions(uri, &params["context"]["diagnostics"])),
+            "workspace/executeCommand" => match self.execute(params).await {
+                Ok(Some(edit)) => {
+                    self.requests_sent += 1;
+                    outgoing.push(json!({
+                        "jsonrpc": "2.0",
+                        "id": format!("ruffer-{}", self.requests_sent),
+                        "method": "workspace/applyEdit",
+                        "params": { "label": "Fix with ruffer", "edit": edit },
+                    }));
+                    Ok(Value::Null)
+                }
+                Ok(None) => Ok(Value::Null),
+                Err(err) => Err(err),
+            },
+            method => Err((METHOD_NOT_FOUND, format!("Unknown method {}", method))),
+        };
+        // Notifications have no ID and get no response.
+        if let Some(id) = message.get("id") {
+            let response = match result {
+                Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+                Err((code, message)) => json!({
+                    "jsonrpc": "2.0",
+                    "id": id,
+                    "error": { "code": code, "message": message },
+                }),
+            };
+            outgoing.insert(0, response);
+        }
+        outgoing
+    }
+
+    /// A notification of the diagnostics of the document `uri`: none if
+    /// ruff can't check it.
+    async fn diagnostics(&self, uri: &str) -> Value {
+        let issues = match self.issues(uri).await {
+            Ok(issues) => issues,
+            Err(err) => {
+                self.console
+                    .error(format!("Error checking {}: {}", uri, err));
+                Vec::new()
+            }
+        };
+        let text = &self.documents[uri];
+        let diagnostics = issues
+            .iter()
+            .map(|issue| {
+                let start = (issue.location.row, issue.location.column);
+                let end = issue
+                    .end_location
+                    .as_ref()
+                    .map_or(start, |end| (end.row, end.column));
+                let mut diagnostic = json!({
+                    "range": {
+                        "start": position(text, start.0, start.1),
+                        "end": position(text, end.0, end.1),
+                    },
+                    "severity": match issue::severity(&issue.code) {
+                        Severity::Error => 1,
+                        Severity::Warning => 2,
+                        Severity::Info => 3,
+                    },
+                    "code": issue.code,
+                    "source": "ruffer",
+                    "message": issue.message,
+                    "data": { "id": issue.id },
+                });
+                if let Some(url) = &issue.url {
+                    diagnostic["codeDescription"] = json!({ "href": url });
+                }
+                diagnostic
+            })
+            .collect();
+        publish(uri, diagnostics)
+    }
+
+    /// The issues ruff finds in the document `uri` as it is now.
+    async fn issues(&self, uri: &str) -> io::Result<Vec<Issue>> {
+        let path = path_of(uri).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "Only file URIs can be checked")
+        })?;
+        self.linter
+            .issues_in(&self.console, &path, &self.documents[uri])
+            .await
+    }
+
+    /// Runs a fix command, answering the workspace edit that applies the
+    /// fix; none if there is nothing to change.
+    async fn execute(&self, params: &Value) -> Result<Option<Value>, (i64, String)> {
+        let command = params["command"].as_str().unwrap_or_default();
+        let arguments = &params["arguments"];
+        let uri = arguments[0].as_str().unwrap_or_default();
+        let (Some(text), Some(path)) = (self.documents.get(uri), path_of(uri)) else {
+            return Err((INVALID_PARAMS, format!("{} isn't an open file", uri)));
+        };
+        let issues = match command {
+            FIX_COMMAND => {
+                let id = arguments[1].as_str().unwrap_or_default();
+                let issues = self
+                    .issues(uri)
+                    .await
+                    .map_err(|err| (REQUEST_FAILED, err.to_string()))?;
+                let issues: Vec<Issue> =
+                    issues.into_iter().filter(|issue| issue.id == id).collect();
+                if issues.is_empty() {
+                    return Err((REQUEST_FAILED, format!("No issue {} in {}", id, uri)));
+                }
+                Some(issues)
+            }
+            FIX_ALL_COMMAND => None,
+            command => return Err((INVALID_PARAMS, format!("Unknown command {}", command))),
+        };
+
+        let out = Buffer::default();
+        let mut fixer = LspOptions::from_iter(&self.args)
+            .fixer
+            .fixing_content(path, issues)
+            .piping(Cursor::new(text.clone()), out.clone());
+        if let Some(cache) = &self.cache {
+            fixer = fixer.sharing_cache(Arc::clone(cache));
+        }
+        Arc::new(fixer)
+            .run_with(Arc::clone(&self.provider))
+            .await
+            .map_err(|err| (REQUEST_FAILED, err.to_string()))?;
+        let fixed = out.text();
+        if fixed == *text {
+            return Ok(None);
+        }
+        Ok(Some(
+            json!({ "changes": { uri: [text_edit(text, &fixed)] } }),
+        ))
+    }
+}
+
+/// The actions for the `diagnostics` of `uri` the editor asks about: a fix
+/// for each of ruffer's, and one for all the issues of the document.
+fn code_actions(uri: &str, diagnostics: &Value) -> Value {
+    let ours: Vec<&Value> = diagnostics
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter(|diagnostic| diagnostic["source"] == "ruffer")
+        .collect();
+    let mut actions: Vec<Value> = ours
+        .iter()
+        .map(|diagnostic| {
+            let title = format!(
+                "Fix {} with ruffer",
+                diagnostic["code"].as_str().unwrap_or("issue")
+            );
+            json!({
+                "title": title,
+                "kind": "quickfix",
+                "diagnostics": [diagnostic],
+                "command": {
+                    "title": title,
+                    "command": FIX_COMMAND,
+                    "arguments": [uri, diagnostic["data"]["id"]],
+                },
+            })
+        })
+        .collect();
+    if !ours.is_empty() {
+        let title = "Fix all issues in the file with ruffer";
+        actions.push(json!({
+            "title": title,
+            "kind": "source.fixAll",
+            "command": { "title": title, "command": FIX_ALL_COMMAND, "arguments": [uri] },
+        }));
+    }
+    Value::from(actions)
+}
+
+fn publish(uri: &str, diagnostics: Vec<Value>) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "method": "textDocument/publishDiagnostics",
+        "params": { "uri": uri, "diagnostics": diagnostics },
+    })
+}
+
+/// One edit turning `before` into `after`, replacing the lines between
+/// those they start and end with in common.
+fn text_edit(before: &str, after: &str) -> Value {
+    let old: Vec<&str> = before.split_inclusive('\n').collect();
+    let new: Vec<&str> = after.split_inclusive('\n').collect();
+    let prefix = old.iter().zip(&new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let start: usize = old[..prefix].iter().map(|line| line.len()).sum();
+    let end: usize = old[..old.len() - suffix]
+        .iter()
+        .map(|line| line.len())
+        .sum();
+    json!({
+        "range": { "start": position_at(before, start), "end": position_at(before, end) },
+        "newText": new[prefix..new.len() - suffix].concat(),
+    })
+}
+
+/// The LSP position of ruff's one-based `row` and `column`, which counts
+/// characters where LSP counts UTF-16 code units.
+fn position(text: &str, row: u32, column: u32) -> Value {
+    let line = text
+        .lines()
+        .nth(row.saturating_sub(1) as usize)
+        .unwrap_or_default();
+    let character: usize = line
+        .chars()
+        .take(column.saturating_sub(1) as usize)
+        .map(char::len_utf16)
+        .sum();
+    json!({ "line": row.saturating_sub(1), "character": character })
+}
+
+/// The LSP position of the byte `offset` in `text`.
+fn position_at(text: &str, offset: usize) -> Value {
+    let before = &text[..offset];
+    let line_start = before.rfind('\n').map_or(0, |newline| newline + 1);
+    json!({
+        "line": before.matches('\n').count(),
+        "character": before[line_start..].encode_utf16().count(),
+    })
+}
+
+/// The path of a `file://` URI.
+fn path_of(uri: &str) -> Option<String> {
+    let encoded = uri.strip_prefix("file://")?.as_bytes();
+    let mut bytes = Vec::with_capacity(encoded.len());
+    let mut i = 0;
+    while i < encoded.len() {
+        let escaped = (encoded[i] == b'%')
+            .then(|| encoded.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+        match escaped {
+            Some(byte) => {
+                bytes.push(byte);
+                i += 3;
+            }
+            None => {
+                bytes.push(encoded[i]);
+                i += 1;
+            }
+        }
+    }
+    let path = String::from_utf8(bytes).ok()?;
+    // `file:///C:/...` on Windows.
+    match path.as_bytes() {
+        [b'/', _, b':', ..] if cfg!(windows) => Some(path[1..].to_string()),
+        _ => Some(path),
+    }
+}
+
+/// The body of the next message of the editor, read past its headers; none
+/// once the input ends.
+async fn read_message(input: &mut (impl AsyncBufRead + Unpin)) -> io::Result<Option<Vec<u8>>> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header).await? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                length = value.trim().parse().ok();
+            }
+        }
+    }
+    let length: usize = length.ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Message without a Content-Length",
+        )
+    })?;
+    let mut body = vec![0; length];
+    input.read_exact(&mut body).await?;
+    Ok(Some(body))
+}
@@ -1,238 +1,215 @@
-use std::collections::HashMap;
-use std::fs;
-use std::io::{self};
-use std::process::Command;
-
-use reqwest::Client;
-use serde::Deserialize;
+use std::ffi::OsString;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use rust_ruffer::cache::ResponseCache;
+use rust_ruffer::git;
+use rust_ruffer::journal::{self, DEFAULT_JOURNAL_DIR};
+use rust_ruffer::{
+    BaselineOptions, EstimateOptions, LspOptions, McpOptions, PolicyFailure, RuffFixer,
+    ServeOptions, WatchOptions, DEFAULT_CACHE_DIR, INTERRUPTED_EXIT_CODE, POLICY_EXIT_CODE,
+};
 use structopt::StructOpt;
 use tokio::runtime::Runtime;
-use tokio::sync::mpsc;
-use tokio::task;
 
+/// Subcommands; anything else as the first argument is the pre-subcommand
+/// `<api_key> <ruff_path> <paths>` invocation and means `fix`.
+const SUBCOMMANDS: &[&str] = &[
+    "fix",
+    "apply",
+    "watch",
+    "serve",
+    "mcp",
+    "lsp",
+    "estimate",
+    "pre-commit",
+    "install-hook",
+    "baseline",
+    "undo",
+    "cache",
+    "help",
+];
+
+// Parsed once at startup, so the size of the `fix` options doesn't matter.
+#[allow(clippy::large_enum_variant)]
 #[derive(StructOpt)]
-struct RuffFixer {
-    #[structopt(help = "OpenAI API Key")]
-    api_key: String,
-
-    #[structopt(help = "Path to ruff tool")]
-    ruff_path: String,
-
-    #[structopt(help = "Root folder to run Ruff check on")]
-    root_folder: String,
+enum Cli {
+    /// Run Ruff on files and folders and fix the reported issues with ChatGPT
+    Fix(RuffFixer),
+    /// Fix the issues in ruff JSON output produced elsewhere, read from stdin or --issues, or in a SARIF file given with --from-sarif
+    Apply(RuffFixer),
+    /// Fix Python files as they are saved, until Ctrl-C
+    Watch(WatchOptions),
+    /// Serve fixes over a local HTTP API, until Ctrl-C
+    Serve(ServeOptions),
+    /// Offer checking and fixing as tools of a Model Context Protocol server over stdin and stdout
+    Mcp(McpOptions),
+    /// Run a language server over stdin and stdout offering to fix ruff's diagnostics with ruffer
+    Lsp(LspOptions),
+    /// Count the tokens a run would send to the model and price them for several models, changing nothing
+    Estimate(EstimateOptions),
+    /// Fix the staged Python files with --staged, or the files given, with the API key in OPENAI_API_KEY, for git hooks and the pre-commit framework
+    PreCommit(RuffFixer),
+    /// Install a git pre-commit hook fixing the Python files being committed and staging the fixes
+    InstallHook {
+        #[structopt(long, help = "Replace an existing pre-commit hook")]
+        force: bool,
+        #[structopt(help = "Options of `fix` for the hook to pass, after --")]
+        args: Vec<String>,
+    },
+    /// Record the issues ruff reports now, for fixing runs to leave alone with --baseline
+    Baseline(BaselineCommand),
+    /// Revert the changes of the last fixing run, or of the given one
+    Undo {
+        #[structopt(help = "ID of the run to revert [default: the last one]")]
+        run_id: Option<String>,
+        #[structopt(
+            long,
+            default_value = DEFAULT_JOURNAL_DIR,
+            parse(from_os_str),
+            help = "Directory holding the journal of runs"
+        )]
+        journal_dir: PathBuf,
+    },
+    /// Manage the cache of fixes from previous runs
+    Cache(CacheCommand),
 }
 
-#[derive(Deserialize)]
-struct Issue {
-    filename: String,
-    code: String,
-    message: String,
-    location: Location,
+#[derive(StructOpt)]
+enum BaselineCommand {
+    /// Write a new baseline of the current issues
+    Create(BaselineOptions),
+    /// Replace the baseline with the current issues, dropping those fixed since
+    Update(BaselineOptions),
 }
 
-#[derive(Deserialize)]
-struct Location {
-    row: u32,
-    column: u32,
+#[derive(StructOpt)]
+enum CacheCommand {
+    /// Remove all cached fixes
+    Clear {
+        #[structopt(long, default_value = DEFAULT_CACHE_DIR, help = "Directory holding the cache")]
+        cache_dir: PathBuf,
+    },
 }
 
-impl RuffFixer {
-    async fn run(&self) -> io::Result<()> {
-        println!("Formatting code in {}...", self.root_folder);
-        self.run_ruff_format(&self.ruff_path, &self.root_folder)?;
-
-        println!("Running Ruff check on {}...", self.root_folder);
-        let issues = match self.run_ruff_check(&self.ruff_path, &self.root_folder) {
-            Ok(issues) => issues,
-            Err(code) => {
-                if code == 0 {
-                    println!("All good");
-                    return Ok(());
-                } else {
-                    return Err(io::Error::new(io::ErrorKind::Other, "Ruff check failed"));
-                }
-            }
-        };
-
-        // Group issues by file
-        let issues_by_file = self.group_issues_by_file(issues);
-
-        let client = Client::new();
-
-        let (tx, mut rx) = mpsc::channel(10);
-        for (filename, file_issues) in issues_by_file {
-            let tx = tx.clone();
-            let client = client.clone();
-            let api_key = self.api_key.clone();
-
-            task::spawn(async move {
-                println!("Processing file: {}", filename);
-
-                // Read the file content
-                match fs::read_to_string(&filename) {
-                    Ok(mut file_content) => {
-                        for issue in file_issues {
-                            println!("Fixing issue in {}: {}", filename, issue.message);
-
-                            // Ask ChatGPT for a fix for the current issue
-                            match RuffFixer::ask_chatgpt_for_fix(
-                                &client,
-                                &api_key,
-                                &filename,
-                                &issue,
-                                &file_content,
-                            )
-                            .await
-                            {
-                                Ok(fixed_content) => {
-                                    // Print diff and update file content
-                                    RuffFixer::print_diff(&file_content, &fixed_content);
-                                    file_content = fixed_content; // Update the file content with the fixed content
-                                }
-                                Err(err) => eprintln!("Error processing {}: {}", filename, err),
-                            }
-                        }
-
-                        // After fixing all issues, write the final fixed content back to the file
-                        if let Err(err) = fs::write(&filename, file_content) {
-                            eprintln!("Error writing to {}: {}", filename, err);
-                        } else {
-                            println!("Fixed issues in {}", filename);
-                        }
-                    }
-                    Err(err) => eprintln!("Error reading {}: {}", filename, err),
-                }
-                tx.send(()).await.unwrap();
-            });
-        }
-
-        drop(tx);
-
-        while let Some(_) = rx.recv().await {}
-
-        Ok(())
-    }
-
-    fn run_ruff_format(&self, ruff_path: &str, folder: &str) -> io::Result<()> {
-        let output = Command::new(ruff_path).args(&["format", folder]).output()?;
-
-        if !output.status.success() {
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            let stdout = String::from_utf8_lossy(&output.stdout);
-            return Err(io::Error::new(
-                io::ErrorKind::Other,
-                format!("Ruff format failed: {}, {}", stderr, stdout),
-            ));
-        }
-
-        Ok(())
-    }
-
-    fn run_ruff_check(&self, ruff_path: &str, folder: &str) -> Result<Vec<Issue>, i32> {
-        let output = Command::new(ruff_path)
-            .args(&["check", "--fix", folder, "--output-format", "json"])
-            .output()
-            .expect("Failed to execute Ruff check");
-
-        let exit_code = output.status.code().unwrap_or(-1);
-
-        if exit_code == 0 {
-            return Err(0); // No issues found
-        } else if exit_code == 1 {
-            // Issues found and handled
-            let data = String::from_utf8_lossy(&output.stdout);
-            let issues: Vec<Issue> =
-                serde_json::from_str(&data).expect("Failed to parse JSON output");
-            return Ok(issues);
-        } else {
-            // Other non-zero exit codes indicate failure
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            let stdout = String::from_utf8_lossy(&output.stdout);
-            eprintln!(
-                "Ruff check failed with exit code {}: {}, {}",
-                exit_code, stderr, stdout
-            );
-            return Err(exit_code);
+fn main() -> io::Result<()> {
+    let mut args: Vec<OsString> = std::env::args_os().collect();
+    if let Some(first) = args.get(1).and_then(|arg| arg.to_str()) {
+        if !first.starts_with('-') && !SUBCOMMANDS.contains(&first) {
+            args.insert(1, "fix".into());
         }
     }
 
-    fn group_issues_by_file(&self, issues: Vec<Issue>) -> HashMap<String, Vec<Issue>> {
-        let mut issues_by_file = HashMap::new();
-        for issue in issues {
-            issues_by_file
-                .entry(issue.filename.clone())
-                .or_insert_with(Vec::new)
-                .push(issue);
-        }
-        issues_by_file
+    // Hooks can't be given the API key on the command line.
+    if args.get(1).is_some_and(|arg| arg == "pre-commit") {
+        let api_key = std::env::var_os("OPENAI_API_KEY").ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                "pre-commit takes the API key from OPENAI_API_KEY, which isn't set",
+            )
+        })?;
+        args.insert(2, api_key);
     }
 
-    async fn ask_chatgpt_for_fix(
-        client: &Client,
-        api_key: &str,
-        filename: &str,
-        issue: &Issue,
-        file_content: &str,
-    ) -> Result<String, Box<dyn std::error::Error>> {
-        let issue_row_content = file_content
-            .lines()
-            .nth(issue.location.row as usize - 1)
-            .unwrap_or_default();
-        let issue_message = format!("{}", issue.message);
-
-        let prompt = format!(
-            "Fix the following issue in the Python code:\n\nIssue description:\n{}\n\nProblematic line:\n{}\n\nHere's the current content of the file:\n\n{}\n\nPlease provide only the entire fixed content of the file addressing the issue listed above, do not provide any explanation, do not wrap the response with backticks.",
-            issue_message, issue_row_content, file_content
-        );
-
-        let request_body = serde_json::json!({
-            "model": "gpt-4o-mini",
-            "messages": [
-                {"role": "system", "content": "You are an automated bot that fixes Python code issues based on the provided issue report."},
-                {"role": "user", "content": prompt}
-            ]
-        });
-
-        let response = client
-            .post("https://api.openai.com/v1/chat/completions")
-            .bearer_auth(api_key)
-            .json(&request_body)
-            .send()
-            .await?;
-
-        let response_json: serde_json::Value = response.json().await?;
-        let content = response_json["choices"][0]["message"]["content"]
-            .as_str()
-            .ok_or_else(|| "Failed to parse response content")?;
-
-        Ok(content.to_string())
+    // Watching and serving parse their arguments again for every run.
+    let mut watch_args = args.clone();
+    if watch_args.len() > 1 {
+        watch_args.remove(1);
     }
 
-    fn print_diff(original: &str, fixed: &str) {
-        let original_lines: Vec<&str> = original.lines().collect();
-        let fixed_lines: Vec<&str> = fixed.lines().collect();
-
-        println!("--- Original");
-        println!("+++ Fixed");
-
-        let max_len = std::cmp::max(original_lines.len(), fixed_lines.len());
-        for i in 0..max_len {
-            let original_line = original_lines.get(i).unwrap_or(&"");
-            let fixed_line = fixed_lines.get(i).unwrap_or(&"");
-            if original_line != fixed_line {
-                if !original_line.is_empty() {
-                    println!("- {}", original_line);
-                }
-                if !fixed_line.is_empty() {
-                    println!("+ {}", fixed_line);
-                }
+    match Cli::from_iter(args) {
+        Cli::Fix(fixer) => {
+            fixer.init_logging()?;
+            let rt = Runtime::new()?;
+            exit_if_interrupted(rt.block_on(Arc::new(fixer).run()))
+        }
+        Cli::Apply(fixer) => {
+            fixer.init_logging()?;
+            let rt = Runtime::new()?;
+            exit_if_interrupted(rt.block_on(Arc::new(fixer.applying_issues()).run()))
+        }
+        Cli::Watch(watch) => {
+            watch.init_logging()?;
+            let rt = Runtime::new()?;
+            exit_if_interrupted(rt.block_on(watch.run(watch_args)))
+        }
+        Cli::Serve(serve) => {
+            serve.init_logging()?;
+            let rt = Runtime::new()?;
+            rt.block_on(serve.run(watch_args))
+        }
+        Cli::Mcp(mcp) => {
+            mcp.init_logging()?;
+            let rt = Runtime::new()?;
+            rt.block_on(mcp.run(watch_args))
+        }
+        Cli::Lsp(lsp) => {
+            lsp.init_logging()?;
+            let rt = Runtime::new()?;
+            rt.block_on(lsp.run(watch_args))
+        }
+        Cli::Estimate(estimate) => {
+            estimate.init_logging()?;
+            let rt = Runtime::new()?;
+            rt.block_on(estimate.run())
+        }
+        Cli::PreCommit(fixer) => {
+            fixer.init_logging()?;
+            let rt = Runtime::new()?;
+            exit_if_interrupted(rt.block_on(Arc::new(fixer).run()))
+        }
+        Cli::InstallHook { force, args } => {
+            let hook = git::install_hook(Path::new("."), &std::env::current_exe()?, &args, force)?;
+            println!("Installed {}", hook.display());
+            Ok(())
+        }
+        Cli::Baseline(BaselineCommand::Create(options)) => {
+            Runtime::new()?.block_on(options.create())
+        }
+        Cli::Baseline(BaselineCommand::Update(options)) => {
+            Runtime::new()?.block_on(options.update())
+        }
+        Cli::Undo {
+            run_id,
+            journal_dir,
+        } => {
+            let undone = journal::undo(&journal_dir, run_id.as_deref())?;
+            for file in &undone.files {
+                println!("Reverted {}", file);
             }
+            println!(
+                "Undid run {}: reverted {} files",
+                undone.run_id,
+                undone.files.len()
+            );
+            Ok(())
+        }
+        Cli::Cache(CacheCommand::Clear { cache_dir }) => {
+            ResponseCache::clear(&cache_dir)?;
+            println!("Cleared cache in {}", cache_dir.display());
+            Ok(())
         }
     }
 }
 
-fn main() -> io::Result<()> {
-    let fixer = RuffFixer::from_args();
-    let rt = Runtime::new()?;
-    rt.block_on(fixer.run())
+/// Exits with [`INTERRUPTED_EXIT_CODE`] if the run was stopped by Ctrl-C,
+/// so scripts can tell it from a failure, and with [`POLICY_EXIT_CODE`]
+/// if `--fail-on` failed it, saying why.
+fn exit_if_interrupted(result: io::Result<()>) -> io::Result<()> {
+    match result {
+        Err(err) if err.kind() == io::ErrorKind::Interrupted => {
+            eprintln!("{}", err);
+            std::process::exit(INTERRUPTED_EXIT_CODE);
+        }
+        Err(err)
+            if err
+                .get_ref()
+                .is_some_and(|inner| inner.is::<PolicyFailure>()) =>
+        {
+            eprintln!("{}", err);
+            std::process::exit(POLICY_EXIT_CODE);
+        }
+        result => result,
+    }
 }
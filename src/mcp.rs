@@ -0,0 +1,295 @@
+//! `ruffer mcp`: a Model Context Protocol server over stdin and stdout, so
+//! that agentic assistants in IDEs can use ruffer as a tool.
+//!
+//! Messages are JSON-RPC 2.0, one per line. The tools are:
+//!
+//! - `check`: the issues ruff finds in `paths`, or in the paths of the
+//!   command line, with their IDs; no file is touched.
+//! - `propose_fix`: the code of `filename` with its issues fixed, the
+//!   patch, and the `json` report of the issues, without writing it. The
+//!   code is `content` if given, the file's otherwise.
+//! - `apply_fix`: fixes the issues in `paths`, or in the paths of the
+//!   command line, writing the files, and answers the `json` report.
+//!
+//! Every call is a run made afresh from the command line.
+
+use std::ffi::OsString;
+use std::fs;
+use std::io::{self, Cursor};
+use std::sync::Arc;
+use std::time::Instant;
+
+use serde_json::{json, Value};
+use structopt::StructOpt;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+
+use crate::cache::ResponseCache;
+use crate::console::Console;
+use crate::fixer::RuffFixer;
+use crate::issue;
+use crate::patch;
+use crate::provider::FixProvider;
+use crate::serve::Buffer;
+
+/// The version of the protocol answered when the client asks for none.
+const PROTOCOL_VERSION: &str = "2024-11-05";
+
+/// JSON-RPC error codes.
+const PARSE_ERROR: i64 = -32700;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+
+/// Options of `ruffer mcp`: those of the runs of its tools.
+#[derive(StructOpt)]
+pub struct McpOptions {
+    #[structopt(flatten)]
+    fixer: RuffFixer,
+}
+
+/// What the calls share.
+struct Server {
+    /// The arguments to parse again for every call.
+    args: Vec<OsString>,
+    provider: Arc<dyn FixProvider>,
+    cache: Option<Arc<ResponseCache>>,
+    console: Console,
+}
+
+impl McpOptions {
+    pub fn init_logging(&self) -> io::Result<()> {
+        self.fixer.init_logging()
+    }
+
+    /// Serves the tools over stdin and stdout with fixes made with the
+    /// OpenAI API, until stdin ends. `args` are the arguments the options
+    /// were parsed from, after the subcommand, to parse again for every
+    /// call.
+    pub async fn run(self, args: Vec<OsString>) -> io::Result<()> {
+        let provider = self.fixer.provider();
+        self.run_with(args, provider, tokio::io::stdin(), tokio::io::stdout())
+            .await
+    }
+
+    /// Serves the tools over `input` and `output` with fixes made with
+    /// answers from `provider`; see [`Self::run`].
+    pub async fn run_with(
+        self,
+        args: Vec<OsString>,
+        provider: Arc<dyn FixProvider>,
+        input: impl AsyncRead + Unpin,
+        mut output: impl AsyncWrite + Unpin,
+    ) -> io::Result<()> {
+        let console = self.fixer.console().leaving_stdout();
+        let server = Server {
+            args,
+            provider,
+            cache: self.fixer.open_cache(&console),
+            console,
+        };
+        let mut lines = BufReader::new(input).lines();
+        while let Some(line) = lines.next_line().await? {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let Some(response) = server.answer(&line).await else {
+                continue;
+            };
+            output
+                .write_all(format!("{}\n", response).as_bytes())
+                .await?;
+            output.flush().await?;
+        }
+        Ok(())
+    }
+}
+
+impl Server {
+    /// The response to the message `line`; none for a notification.
+    async fn answer(&self, line: &str) -> Option<Value> {
+        let message: Value = match serde_json::from_str(line) {
+            Ok(message) => message,
+            Err(err) => {
+                return Some(failure(Value::Null, PARSE_ERROR, err.to_string()));
+            }
+        };
+        let id = message.get("id").cloned()?;
+        let params = message.get("params").cloned().unwrap_or(Value::Null);
+        let result = match message["method"].as_str().unwrap_or_default() {
+            "initialize" => Ok(json!({
+                "protocolVersion": params["protocolVersion"].as_str().unwrap_or(PROTOCOL_VERSION),
+                "capabilities": { "tools": {} },
+                "serverInfo": { "name": "ruffer", "version": env!("CARGO_PKG_VERSION") },
+            })),
+            "ping" => Ok(json!({})),
+            "tools/list" => Ok(json!({ "tools": tools() })),
+            "tools/call" => self.call(&params).await,
+            method => Err((METHOD_NOT_FOUND, format!("Unknown method {}", method))),
+        };
+        Some(match result {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err((code, message)) => failure(id, code, message),
+        })
+    }
+
+    /// The result of `tools/call`: what the tool answers, or the error it
+    /// ran into, for the assistant to read.
+    async fn call(&self, params: &Value) -> Result<Value, (i64, String)> {
+        let arguments = &params["arguments"];
+        let result = match params["name"].as_str().unwrap_or_default() {
+            "check" => self.check(strings(&arguments["paths"])).await,
+            "propose_fix" => {
+                let Some(filename) = arguments["filename"].as_str() else {
+                    return Err((INVALID_PARAMS, "propose_fix needs a filename".to_string()));
+                };
+                self.propose_fix(filename, arguments["content"].as_str())
+                    .await
+            }
+            "apply_fix" => self.apply_fix(strings(&arguments["paths"])).await,
+            name => return Err((INVALID_PARAMS, format!("Unknown tool {}", name))),
+        };
+        Ok(match result {
+            Ok(answer) => json!({
+                "content": [{ "type": "text", "text": answer.to_string() }],
+                "isError": false,
+            }),
+            Err(err) => {
+                self.console.error(&err);
+                json!({
+                    "content": [{ "type": "text", "text": err.to_string() }],
+                    "isError": true,
+                })
+            }
+        })
+    }
+
+    /// A fixer as the command line says, leaving stdin and stdout to the
+    /// protocol.
+    fn fixer(&self) -> RuffFixer {
+        let mut fixer = McpOptions::from_iter(&self.args).fixer.leaving_stdio();
+        if let Some(cache) = &self.cache {
+            fixer = fixer.sharing_cache(Arc::clone(cache));
+        }
+        fixer
+    }
+
+    async fn check(&self, paths: Vec<String>) -> io::Result<Value> {
+        let mut fixer = self.fixer();
+        if !paths.is_empty() {
+            fixer = fixer.checking(paths);
+        }
+        let mut found: Vec<Value> = Vec::new();
+        for (filename, issues) in fixer.issues(&self.console).await? {
+            for issue in issues {
+                found.push(json!({
+                    "file": issue::display_path(&filename),
+                    "id": issue.id,
+                    "code": issue.code,
+                    "message": issue.message,
+                    "row": issue.location.row,
+                    "column": issue.location.column,
+                }));
+            }
+        }
+        found.sort_by_key(|issue| (issue["file"].to_string(), issue["row"].as_u64()));
+        Ok(json!({ "issues": found }))
+    }
+
+    async fn propose_fix(&self, filename: &str, content: Option<&str>) -> io::Result<Value> {
+        let original = match content {
+            Some(content) => content.to_string(),
+            None => fs::read_to_string(filename)?,
+        };
+        let out = Buffer::default();
+        let fixer = Arc::new(
+            self.fixer()
+                .fixing_content(filename.to_string(), None)
+                .piping(Cursor::new(original.clone()), out.clone()),
+        );
+        let started = Instant::now();
+        Arc::clone(&fixer)
+            .run_with(Arc::clone(&self.provider))
+            .await?;
+        let fixed = out.text();
+        Ok(json!({
+            "content": fixed,
+            "patch": patch::unified(&issue::display_path(filename), &original, &fixed),
+            "report": fixer.json_report(started.elapsed()),
+        }))
+    }
+
+    async fn apply_fix(&self, paths: Vec<String>) -> io::Result<Value> {
+        let mut fixer = self.fixer();
+        if !paths.is_empty() {
+            fixer = fixer.checking(paths);
+        }
+        let fixer = Arc::new(fixer);
+        let started = Instant::now();
+        Arc::clone(&fixer)
+            .run_with(Arc::clone(&self.provider))
+            .await?;
+        Ok(fixer.json_report(started.elapsed()))
+    }
+}
+
+/// The tools, with JSON schemas of their arguments.
+fn tools() -> Value {
+    let paths = json!({
+        "type": "array",
+        "items": { "type": "string" },
+        "description": "Files, folders or glob patterns; those ruffer was started with if left out",
+    });
+    json!([
+        {
+            "name": "check",
+            "description": "Run ruff on Python files and list the issues it finds, without changing anything",
+            "inputSchema": {
+                "type": "object",
+                "properties": { "paths": paths },
+            },
+        },
+        {
+            "name": "propose_fix",
+            "description": "Fix the ruff issues in a Python file with an LLM and return the fixed code and patch, without writing the file",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "filename": {
+                        "type": "string",
+                        "description": "The file the code is, or would be, in",
+                    },
+                    "content": {
+                        "type": "string",
+                        "description": "The code to fix; the file's if left out",
+                    },
+                },
+                "required": ["filename"],
+            },
+        },
+        {
+            "name": "apply_fix",
+            "description": "Fix the ruff issues in Python files with an LLM, writing the fixes to the files, and report what became of every issue",
+            "inputSchema": {
+                "type": "object",
+                "properties": { "paths": paths },
+            },
+        },
+    ])
+}
+
+fn failure(id: Value, code: i64, message: String) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": { "code": code, "message": message },
+    })
+}
+
+/// The strings of an array argument; none if it is missing.
+fn strings(value: &Value) -> Vec<String> {
+    value
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|value| value.as_str().map(str::to_string))
+        .collect()
+}
@@ -0,0 +1,128 @@
+use std::ops::Range;
+
+use similar::{capture_diff_slices, Algorithm, DiffOp};
+
+/// A region of the original that both sides changed in different ways.
+pub struct Conflict {
+    /// One-based lines of the original, `start..=end`; `start > end` means
+    /// both sides inserted different lines before `start`.
+    pub start: usize,
+    pub end: usize,
+}
+
+/// A change of `old` lines of the original into `new` lines of a side.
+struct Hunk {
+    old: Range<usize>,
+    new: Range<usize>,
+}
+
+/// Merges the changes from `base` to `ours` and from `base` to `theirs`.
+///
+/// Changes to different lines are combined and identical changes are taken
+/// once. When both sides changed the same lines differently nothing is
+/// merged and the conflicting regions are returned instead.
+pub fn merge(base: &str, ours: &str, theirs: &str) -> Result<String, Vec<Conflict>> {
+    let base_lines: Vec<&str> = base.split_inclusive('\n').collect();
+    let our_lines: Vec<&str> = ours.split_inclusive('\n').collect();
+    let their_lines: Vec<&str> = theirs.split_inclusive('\n').collect();
+    let our_hunks = hunks(&base_lines, &our_lines);
+    let their_hunks = hunks(&base_lines, &their_lines);
+
+    let mut merged = String::new();
+    let mut conflicts = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    let mut position = 0;
+    while i < our_hunks.len() || j < their_hunks.len() {
+        let ours_first = match (our_hunks.get(i), their_hunks.get(j)) {
+            (Some(a), Some(b)) => a.old.start <= b.old.start,
+            (Some(_), None) => true,
+            _ => false,
+        };
+        let first = if ours_first {
+            &our_hunks[i]
+        } else {
+            &their_hunks[j]
+        };
+        let (start, mut end) = (first.old.start, first.old.end);
+
+        // Grow the group for as long as a hunk from either side overlaps it
+        // or inserts at the same place.
+        let (mut next_i, mut next_j) = (i, j);
+        loop {
+            let before = (next_i, next_j);
+            for (hunks, next) in [(&our_hunks, &mut next_i), (&their_hunks, &mut next_j)] {
+                while let Some(hunk) = hunks.get(*next) {
+                    if hunk.old.start < end || hunk.old.start == start {
+                        end = end.max(hunk.old.end);
+                        *next += 1;
+                    } else {
+                        break;
+                    }
+                }
+            }
+            if (next_i, next_j) == before {
+                break;
+            }
+        }
+
+        merged.push_str(&base_lines[position..start].concat());
+        let ours_text = side_text(&base_lines, &our_lines, &our_hunks[i..next_i], start, end);
+        let theirs_text = side_text(
+            &base_lines,
+            &their_lines,
+            &their_hunks[j..next_j],
+            start,
+            end,
+        );
+        if next_i == i {
+            merged.push_str(&theirs_text);
+        } else if next_j == j || ours_text == theirs_text {
+            merged.push_str(&ours_text);
+        } else {
+            conflicts.push(Conflict {
+                start: start + 1,
+                end,
+            });
+        }
+        position = end;
+        (i, j) = (next_i, next_j);
+    }
+    merged.push_str(&base_lines[position..].concat());
+
+    if conflicts.is_empty() {
+        Ok(merged)
+    } else {
+        Err(conflicts)
+    }
+}
+
+/// Lines `start..end` of the original with one side's `hunks` applied.
+fn side_text(base: &[&str], side: &[&str], hunks: &[Hunk], start: usize, end: usize) -> String {
+    let mut text = String::new();
+    let mut position = start;
+    for hunk in hunks {
+        text.push_str(&base[position..hunk.old.start].concat());
+        text.push_str(&side[hunk.new.clone()].concat());
+        position = hunk.old.end;
+    }
+    text.push_str(&base[position..end].concat());
+    text
+}
+
+fn hunks(base: &[&str], side: &[&str]) -> Vec<Hunk> {
+    let mut hunks: Vec<Hunk> = Vec::new();
+    for op in capture_diff_slices(Algorithm::Myers, base, side) {
+        if let DiffOp::Equal { .. } = op {
+            continue;
+        }
+        let (old, new) = (op.old_range(), op.new_range());
+        match hunks.last_mut() {
+            Some(last) if last.old.end == old.start && last.new.end == new.start => {
+                last.old.end = old.end;
+                last.new.end = new.end;
+            }
+            _ => hunks.push(Hunk { old, new }),
+        }
+    }
+    hunks
+}
@@ -0,0 +1,29 @@
+/// Gives `fixed` the line endings of `original`, and a final line ending
+/// only if `original` has one, so that a model answering with `\n` on a
+/// Windows project doesn't change every line. A file mixing `\r\n` and `\n`
+/// keeps whatever line endings the fix has.
+pub fn match_original(original: &str, fixed: &str) -> String {
+    let crlf = original.matches("\r\n").count();
+    let lf = original.matches('\n').count() - crlf;
+    let (mut text, ending) = match (crlf, lf) {
+        (0, 0) => (fixed.to_string(), "\n"),
+        (_, 0) => (fixed.replace("\r\n", "\n").replace('\n', "\r\n"), "\r\n"),
+        (0, _) => (fixed.replace("\r\n", "\n"), "\n"),
+        _ => return fixed.to_string(),
+    };
+
+    if original.ends_with('\n') {
+        if !text.is_empty() && !text.ends_with('\n') {
+            text.push_str(ending);
+        }
+    } else if !original.is_empty() {
+        let trimmed = text
+            .strip_suffix("\r\n")
+            .or_else(|| text.strip_suffix('\n'))
+            .map(str::len);
+        if let Some(len) = trimmed {
+            text.truncate(len);
+        }
+    }
+    text
+}
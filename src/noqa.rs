@@ -0,0 +1,137 @@
+use std::collections::{BTreeMap, HashMap};
+
+use crate::issue::Issue;
+
+/// Longest justification put into a noqa comment.
+const MAX_REASON_CHARS: usize = 100;
+
+/// Suppresses `issues` with `# noqa: <code>` comments on the lines ruff
+/// looks for them (`noqa_row`), adding to comments already there. A new
+/// comment is followed by the `reasons` given for its issues, by issue ID.
+/// Returns the new content and the IDs of the issues that were suppressed.
+///
+/// Nothing is added after a line continuation, where a comment would break
+/// the code.
+pub fn insert<'a>(
+    content: &str,
+    issues: impl IntoIterator<Item = &'a Issue>,
+    reasons: &HashMap<String, String>,
+) -> (String, Vec<String>) {
+    let mut lines: Vec<String> = content.split_inclusive('\n').map(String::from).collect();
+    let mut by_row: BTreeMap<usize, Vec<&Issue>> = BTreeMap::new();
+    for issue in issues {
+        let row = issue.noqa_row.unwrap_or(issue.location.row) as usize;
+        by_row.entry(row).or_default().push(issue);
+    }
+
+    let mut suppressed = Vec::new();
+    for (row, issues) in by_row {
+        let Some(line) = lines.get_mut(row.saturating_sub(1)) else {
+            continue;
+        };
+        let codes: Vec<&str> = issues.iter().map(|issue| issue.code.as_str()).collect();
+        let reasons: Vec<&str> = issues
+            .iter()
+            .filter_map(|issue| reasons.get(&issue.id).map(String::as_str))
+            .collect();
+        if let Some(with_codes) = add_codes(line, &codes, &reasons.join("; ")) {
+            *line = with_codes;
+            suppressed.extend(issues.iter().map(|issue| issue.id.clone()));
+        }
+    }
+    (lines.concat(), suppressed)
+}
+
+/// `line` with `codes` in its noqa comment, or `None` if none can be added.
+/// A non-empty `reason` follows a new comment.
+fn add_codes(line: &str, codes: &[&str], reason: &str) -> Option<String> {
+    let body = line.trim_end_matches(['\n', '\r']);
+    let ending = &line[body.len()..];
+    let missing = |existing: &[&str]| {
+        let mut missing: Vec<&str> = Vec::new();
+        for code in codes {
+            if !existing.contains(code) && !missing.contains(code) {
+                missing.push(code);
+            }
+        }
+        missing
+    };
+
+    let Some(start) = body.to_ascii_lowercase().find("# noqa") else {
+        if body.trim_end().ends_with('\\') {
+            return None;
+        }
+        let reason = if reason.is_empty() {
+            String::new()
+        } else {
+            format!("  # {}", reason)
+        };
+        return Some(format!(
+            "{}  # noqa: {}{}{}",
+            body.trim_end(),
+            missing(&[]).join(", "),
+            reason,
+            ending
+        ));
+    };
+
+    let directive_end = start + "# noqa".len();
+    let Some(list) = body[directive_end..].strip_prefix(':') else {
+        // A bare `# noqa` already suppresses everything on the line.
+        return Some(line.to_string());
+    };
+
+    // The codes are the leading words that look like rule codes; whatever
+    // follows is a free-form explanation.
+    let mut existing = Vec::new();
+    let mut list_end = directive_end + 1;
+    let mut offset = list_end;
+    for word in list.split_inclusive([',', ' ']) {
+        let code = word.trim_matches([',', ' ']);
+        if !code.is_empty() {
+            if !is_code(code) {
+                break;
+            }
+            existing.push(code);
+            list_end = offset + word.trim_end_matches([',', ' ']).len();
+        }
+        offset += word.len();
+    }
+
+    let missing = missing(&existing);
+    if missing.is_empty() {
+        return Some(line.to_string());
+    }
+    let separator = if existing.is_empty() { " " } else { ", " };
+    Some(format!(
+        "{}{}{}{}{}",
+        &body[..list_end],
+        separator,
+        missing.join(", "),
+        &body[list_end..],
+        ending
+    ))
+}
+
+fn is_code(word: &str) -> bool {
+    let letters = word.chars().take_while(char::is_ascii_uppercase).count();
+    letters > 0 && word.len() > letters && word[letters..].chars().all(|c| c.is_ascii_digit())
+}
+
+/// Makes a model's answer fit into a comment: its first line without
+/// comment markers or quotes, at most `MAX_REASON_CHARS` long.
+pub fn reason_from_answer(answer: &str) -> Option<String> {
+    let line = answer
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty())?;
+    let line = line
+        .trim_start_matches('#')
+        .trim()
+        .trim_matches(['"', '\'', '`']);
+    let mut reason: String = line.chars().take(MAX_REASON_CHARS).collect();
+    if line.chars().count() > MAX_REASON_CHARS {
+        reason.push_str("...");
+    }
+    Some(reason).filter(|reason| !reason.is_empty())
+}
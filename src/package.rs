@@ -0,0 +1,281 @@
+//! The packages of a monorepo: directories with a ruff configuration of
+//! their own, in a `pyproject.toml`, `ruff.toml` or `.ruff.toml`. Ruff is
+//! run in the directory of each package on the files inside it, so that
+//! paths in its configuration mean what they mean to the package.
+//!
+//! A package's `pyproject.toml` can override options of ruffer for its
+//! files in a `[tool.ruffer]` table:
+//!
+//! ```toml
+//! [tool.ruffer]
+//! select = ["F", "E"]
+//! ignore = ["E501"]
+//! llm-rules = ["F401"]
+//! no-llm-rules = ["S"]
+//! severity = ["E501=info", "B=error"]
+//! ```
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::ignore::VENDORED_DIRS;
+use crate::rules::RuleSeverity;
+
+/// The files that make a directory a package.
+pub const CONFIG_FILES: &[&str] = &["pyproject.toml", "ruff.toml", ".ruff.toml"];
+
+/// Options of ruffer set in a package's `[tool.ruffer]`, replacing those of
+/// the command line for its files.
+#[derive(Default)]
+pub struct Overrides {
+    pub select: Option<Vec<String>>,
+    pub ignore: Option<Vec<String>>,
+    pub llm_rules: Option<Vec<String>>,
+    pub no_llm_rules: Option<Vec<String>>,
+    pub severities: Option<Vec<RuleSeverity>>,
+}
+
+pub struct Package {
+    /// The directory holding the configuration, canonical.
+    pub root: PathBuf,
+    pub overrides: Overrides,
+}
+
+/// The packages `dir` is in or holds: the nearest directory with a
+/// configuration at or above `dir`, and every one below it, leaving out
+/// hidden and vendored directories.
+pub fn discover(dir: &Path) -> io::Result<Vec<Package>> {
+    let dir = dir.canonicalize()?;
+    let mut roots = Vec::new();
+    if let Some(root) = dir.ancestors().find(|dir| is_package(dir)) {
+        roots.push(root.to_path_buf());
+    }
+    find_nested(&dir, &mut roots)?;
+    roots.sort();
+    roots
+        .into_iter()
+        .map(|root| {
+            let overrides = read_overrides(&root)?;
+            Ok(Package { root, overrides })
+        })
+        .collect()
+}
+
+/// The innermost of `packages` holding `path`.
+pub fn containing<'a>(packages: &'a [Package], path: &Path) -> Option<&'a Package> {
+    let path = path.canonicalize().ok()?;
+    packages
+        .iter()
+        .filter(|package| path.starts_with(&package.root))
+        .max_by_key(|package| package.root.components().count())
+}
+
+fn is_package(dir: &Path) -> bool {
+    CONFIG_FILES.iter().any(|file| dir.join(file).is_file())
+}
+
+fn find_nested(dir: &Path, roots: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        if name.starts_with('.') || VENDORED_DIRS.contains(&name.as_ref()) {
+            continue;
+        }
+        if entry.file_type()?.is_dir() {
+            let path = entry.path();
+            if is_package(&path) {
+                roots.push(path.clone());
+            }
+            find_nested(&path, roots)?;
+        }
+    }
+    Ok(())
+}
+
+/// The `[tool.ruffer]` table of the `pyproject.toml` in `root`, if any.
+fn read_overrides(root: &Path) -> io::Result<Overrides> {
+    let path = root.join("pyproject.toml");
+    let content = match fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Overrides::default()),
+        Err(err) => return Err(err),
+    };
+    let invalid = |err: String| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Invalid [tool.ruffer] in {}: {}", path.display(), err),
+        )
+    };
+    let mut overrides = Overrides::default();
+    for (key, value) in table(&content, "tool.ruffer").map_err(invalid)? {
+        if key == "severity" {
+            let severities = value
+                .iter()
+                .map(|entry| entry.parse())
+                .collect::<Result<_, _>>()
+                .map_err(invalid)?;
+            overrides.severities = Some(severities);
+            continue;
+        }
+        let field = match key.as_str() {
+            "select" => &mut overrides.select,
+            "ignore" => &mut overrides.ignore,
+            "llm-rules" => &mut overrides.llm_rules,
+            "no-llm-rules" => &mut overrides.no_llm_rules,
+            other => return Err(invalid(format!("unknown key `{}`", other))),
+        };
+        *field = Some(value);
+    }
+    Ok(overrides)
+}
+
+/// The keys of a table with their values.
+pub type Table = Vec<(String, Vec<String>)>;
+
+/// The `[tool.ruffer.linter.<name>]` tables of the `pyproject.toml` in
+/// `root` or the nearest directory above it with one, by name, declaring
+/// the linters of [`crate::linter::Configured`], and the directory of that
+/// `pyproject.toml`.
+pub fn linter_tables(root: &Path) -> io::Result<(PathBuf, Vec<(String, Table)>)> {
+    let Some(path) = root
+        .ancestors()
+        .map(|dir| dir.join("pyproject.toml"))
+        .find(|path| path.is_file())
+    else {
+        return Ok((root.to_path_buf(), Vec::new()));
+    };
+    let content = fs::read_to_string(&path)?;
+    let mut tables = Vec::new();
+    for line in content.lines() {
+        let line = line.trim();
+        let Some(name) = line
+            .strip_prefix("[tool.ruffer.linter.")
+            .and_then(|rest| rest.strip_suffix(']'))
+        else {
+            continue;
+        };
+        let name = name.trim_matches('"').to_string();
+        let entries = table(&content, &format!("tool.ruffer.linter.{}", name)).map_err(|err| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Invalid [tool.ruffer.linter.{}] in {}: {}",
+                    name,
+                    path.display(),
+                    err
+                ),
+            )
+        })?;
+        tables.push((name, entries));
+    }
+    let dir = path
+        .parent()
+        .map_or_else(|| root.to_path_buf(), Path::to_path_buf);
+    Ok((dir, tables))
+}
+
+/// The keys of the table `name`, such as `tool.ruffer`, in a
+/// `pyproject.toml` with their values. Only as much TOML is understood as
+/// ruffer's tables need: strings, and arrays of them, which may span
+/// several lines.
+fn table(content: &str, name: &str) -> Result<Table, String> {
+    let header = format!("[{}]", name);
+    let mut entries = Vec::new();
+    let mut in_table = false;
+    let mut lines = content.lines();
+    while let Some(line) = lines.next() {
+        let line = line.trim();
+        if line.starts_with('[') && !line.contains('=') {
+            in_table = line.trim_end_matches(|c| c != ']').replace('"', "") == header;
+            continue;
+        }
+        if !in_table || line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| format!("expected `key = value`, found `{}`", line))?;
+        let key = key.trim().trim_matches('"').to_string();
+        let mut value = value.trim().to_string();
+        if value.starts_with('[') {
+            while !closed(&value) {
+                let next = lines
+                    .next()
+                    .ok_or_else(|| format!("unclosed array for `{}`", key))?;
+                value.push('\n');
+                value.push_str(next);
+            }
+        }
+        let strings = strings(&value).map_err(|err| format!("{} for `{}`", err, key))?;
+        entries.push((key, strings));
+    }
+    Ok(entries)
+}
+
+/// Whether the array starting `value` is closed, with `]` outside strings
+/// and comments.
+fn closed(value: &str) -> bool {
+    let mut quote = None;
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some('"'), '\\') => {
+                chars.next();
+            }
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '#') => {
+                // The rest of the line is a comment.
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        break;
+                    }
+                }
+            }
+            (None, ']') => return true,
+            (None, _) => {}
+        }
+    }
+    false
+}
+
+/// The strings of a value that is a string or an array of strings.
+fn strings(value: &str) -> Result<Vec<String>, String> {
+    let mut strings = Vec::new();
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '[' | ']' | ',' => {}
+            c if c.is_whitespace() => {}
+            '#' => {
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        break;
+                    }
+                }
+            }
+            '"' | '\'' => {
+                let mut string = String::new();
+                loop {
+                    match chars.next() {
+                        None => return Err("unclosed string".to_string()),
+                        Some(end) if end == c => break,
+                        Some('\\') if c == '"' => match chars.next() {
+                            Some('n') => string.push('\n'),
+                            Some('t') => string.push('\t'),
+                            Some(escaped) => string.push(escaped),
+                            None => return Err("unclosed string".to_string()),
+                        },
+                        Some(other) => string.push(other),
+                    }
+                }
+                strings.push(string);
+            }
+            _ => return Err("expected a string or an array of strings".to_string()),
+        }
+    }
+    Ok(strings)
+}
@@ -0,0 +1,244 @@
+use similar::TextDiff;
+
+/// How many context lines at the edges of a hunk may be ignored when the
+/// hunk doesn't match exactly, like `patch --fuzz`.
+const MAX_FUZZ: usize = 2;
+
+type LineMatcher = fn(&str, &str) -> bool;
+
+struct Hunk {
+    header: String,
+    /// One-based start line in the original, as claimed by the header.
+    old_start: usize,
+    lines: Vec<HunkLine>,
+}
+
+enum HunkLine {
+    Context(String),
+    Delete(String),
+    Insert(String),
+}
+
+/// A unified diff from `before` to `after` of the file at `path`, with
+/// `a/` and `b/` prefixes like `git diff` puts on them.
+pub fn unified(path: &str, before: &str, after: &str) -> String {
+    let path = path.trim_start_matches('/');
+    TextDiff::from_lines(before, after)
+        .unified_diff()
+        .header(&format!("a/{}", path), &format!("b/{}", path))
+        .to_string()
+}
+
+/// The unified diff that undoes `diff`: removed and added lines, the old
+/// and new ranges of every hunk, and the two file headers trade places.
+pub fn reverse(diff: &str) -> String {
+    let lines: Vec<&str> = diff.split_inclusive('\n').collect();
+    let mut reversed = String::new();
+    let mut in_hunks = false;
+    let mut i = 0;
+    while i < lines.len() {
+        let line = lines[i];
+        i += 1;
+        if let Some(header) = line.strip_prefix("@@ ") {
+            in_hunks = true;
+            let mut parts = header.splitn(3, ' ');
+            if let (Some(old), Some(new), Some(rest)) = (parts.next(), parts.next(), parts.next()) {
+                reversed.push_str(&format!(
+                    "@@ -{} +{} {}",
+                    new.trim_start_matches('+'),
+                    old.trim_start_matches('-'),
+                    rest
+                ));
+                continue;
+            }
+        } else if !in_hunks {
+            let new = lines.get(i).and_then(|next| next.strip_prefix("+++ "));
+            if let (Some(old), Some(new)) = (line.strip_prefix("--- "), new) {
+                reversed.push_str(&format!("--- {}+++ {}", new, old));
+                i += 1;
+                continue;
+            }
+        } else if let Some(text) = line.strip_prefix('-') {
+            reversed.push_str(&format!("+{}", text));
+            continue;
+        } else if let Some(text) = line.strip_prefix('+') {
+            reversed.push_str(&format!("-{}", text));
+            continue;
+        }
+        reversed.push_str(line);
+    }
+    reversed
+}
+
+/// Applies a unified diff to `original`.
+///
+/// Hunks are located by content rather than trusting their line numbers:
+/// the nearest exact match of the context and deleted lines wins, then the
+/// same with up to [`MAX_FUZZ`] edge context lines dropped, then a match
+/// that ignores trailing whitespace. A hunk that can't be placed fails the
+/// whole patch, so a bad diff never half-applies.
+pub fn apply(original: &str, diff: &str) -> Result<String, String> {
+    let hunks = parse(diff)?;
+    let mut lines: Vec<String> = original.lines().map(str::to_string).collect();
+    let trailing_newline = original.ends_with('\n') || original.is_empty();
+
+    let mut offset: isize = 0;
+    let mut earliest = 0;
+    for (number, hunk) in hunks.iter().enumerate() {
+        let expected = (hunk.old_start.max(1) as isize - 1 + offset).max(0) as usize;
+        let (position, old, new) = locate(&lines, hunk, expected, earliest).ok_or_else(|| {
+            format!(
+                "Hunk {} ({}) does not match the file; make sure its context and removed lines are copied exactly.",
+                number + 1,
+                hunk.header
+            )
+        })?;
+        let inserted = new.len();
+        lines.splice(position..position + old.len(), new);
+        offset += inserted as isize - old.len() as isize;
+        earliest = position + inserted;
+    }
+
+    let mut patched = lines.join("\n");
+    if trailing_newline && !lines.is_empty() {
+        patched.push('\n');
+    }
+    Ok(patched)
+}
+
+/// Finds where `hunk` applies, returning the position along with the old
+/// and new lines that were actually matched (fewer when fuzz was needed).
+fn locate(
+    lines: &[String],
+    hunk: &Hunk,
+    expected: usize,
+    earliest: usize,
+) -> Option<(usize, Vec<String>, Vec<String>)> {
+    let exact: LineMatcher = |a, b| a == b;
+    let loose: LineMatcher = |a, b| a.trim_end() == b.trim_end();
+    for matches in [exact, loose] {
+        for fuzz in 0..=MAX_FUZZ {
+            let (old, new) = hunk.sides(fuzz);
+            if let Some(position) = find_near(lines, &old, expected, earliest, matches) {
+                return Some((position, old, new));
+            }
+        }
+    }
+    None
+}
+
+fn find_near(
+    lines: &[String],
+    old: &[String],
+    expected: usize,
+    earliest: usize,
+    matches: LineMatcher,
+) -> Option<usize> {
+    if lines.len() < old.len() {
+        return None;
+    }
+    let last = lines.len() - old.len();
+    if old.is_empty() {
+        return Some(expected.clamp(earliest.min(last), last));
+    }
+    let fits = |position: usize| {
+        lines[position..position + old.len()]
+            .iter()
+            .zip(old)
+            .all(|(line, expected)| matches(line, expected))
+    };
+    let expected = expected.min(last);
+    for distance in 0..=lines.len() {
+        let below = expected + distance;
+        if below <= last && below >= earliest && fits(below) {
+            return Some(below);
+        }
+        if let Some(above) = expected.checked_sub(distance) {
+            if distance > 0 && above >= earliest && fits(above) {
+                return Some(above);
+            }
+        }
+        if below > last && expected < distance {
+            break;
+        }
+    }
+    None
+}
+
+impl Hunk {
+    /// Old and new lines of the hunk, with up to `fuzz` context lines
+    /// removed from each end.
+    fn sides(&self, fuzz: usize) -> (Vec<String>, Vec<String>) {
+        let leading = self
+            .lines
+            .iter()
+            .take_while(|line| matches!(line, HunkLine::Context(_)))
+            .count()
+            .min(fuzz);
+        let trailing = self
+            .lines
+            .iter()
+            .rev()
+            .take_while(|line| matches!(line, HunkLine::Context(_)))
+            .count()
+            .min(fuzz);
+        let end = self.lines.len().saturating_sub(trailing).max(leading);
+        let (mut old, mut new) = (Vec::new(), Vec::new());
+        for line in &self.lines[leading..end] {
+            match line {
+                HunkLine::Context(text) => {
+                    old.push(text.clone());
+                    new.push(text.clone());
+                }
+                HunkLine::Delete(text) => old.push(text.clone()),
+                HunkLine::Insert(text) => new.push(text.clone()),
+            }
+        }
+        (old, new)
+    }
+}
+
+fn parse(diff: &str) -> Result<Vec<Hunk>, String> {
+    let mut hunks: Vec<Hunk> = Vec::new();
+    for line in diff.lines() {
+        if let Some(header) = line.strip_prefix("@@") {
+            let old_start = header
+                .trim()
+                .strip_prefix('-')
+                .and_then(|range| range.split([',', ' ']).next())
+                .and_then(|start| start.parse().ok())
+                .unwrap_or(1);
+            hunks.push(Hunk {
+                header: line.to_string(),
+                old_start,
+                lines: Vec::new(),
+            });
+            continue;
+        }
+        let Some(hunk) = hunks.last_mut() else {
+            // File headers (`---`/`+++`, `diff --git`) and anything else
+            // before the first hunk.
+            continue;
+        };
+        if line.starts_with("--- ") || line.starts_with("+++ ") || line.starts_with('\\') {
+            continue;
+        }
+        let parsed = match line.chars().next() {
+            Some('+') => HunkLine::Insert(line[1..].to_string()),
+            Some('-') => HunkLine::Delete(line[1..].to_string()),
+            Some(' ') => HunkLine::Context(line[1..].to_string()),
+            // Models like to drop the space of empty context lines.
+            None => HunkLine::Context(String::new()),
+            Some(_) => return Err(format!("Unexpected line in the diff: {}", line)),
+        };
+        hunk.lines.push(parsed);
+    }
+
+    if hunks.is_empty() {
+        return Err(
+            "The answer contains no diff hunks; reply with a unified diff with @@ hunk headers."
+                .to_string(),
+        );
+    }
+    Ok(hunks)
+}
@@ -0,0 +1,477 @@
+use std::str::FromStr;
+
+use crate::chunk::Chunk;
+use crate::docstring::Style;
+use crate::examples::Example;
+use crate::excerpt::Excerpt;
+use crate::issue::Issue;
+use crate::language::Language;
+use crate::template::Template;
+
+/// The variables of `--system-prompt-template`.
+pub const SYSTEM_VARIABLES: &[&str] = &["language"];
+
+/// The variables of `--prompt-template`; see [`IssuePrompt::render`].
+pub const ISSUE_VARIABLES: &[&str] = &[
+    "rule_code",
+    "message",
+    "filename",
+    "language",
+    "line",
+    "lines",
+    "span",
+    "snippet",
+    "snippet_lines",
+    "file_content",
+    "feedback",
+    "examples",
+    "instructions",
+];
+
+/// The system message of every request, for code of `languages`, from
+/// `template` if given, followed by the team's `style_guide` if any.
+pub fn system_prompt(
+    languages: &[Language],
+    template: Option<&Template>,
+    style_guide: Option<&str>,
+) -> String {
+    let names: Vec<&str> = languages.iter().map(|language| language.name()).collect();
+    let names = match names.split_last() {
+        Some((last, rest)) if !rest.is_empty() => format!("{} and {}", rest.join(", "), last),
+        _ => names.concat(),
+    };
+    let mut prompt = match template {
+        Some(template) => template.render(&[("language", &names)]),
+        None => format!(
+            "You are an automated bot that fixes {} code issues based on the provided issue report.",
+            names
+        ),
+    };
+    if let Some(guide) = style_guide.filter(|guide| !guide.trim().is_empty()) {
+        prompt.push_str(&format!(
+            "\n\nThe code you write must follow the team's style guide, such as its naming, docstring style and preferred idioms:\n\n{}",
+            guide.trim_end()
+        ));
+    }
+    prompt
+}
+
+/// The part of a file that is sent to the model and replaced by its answer.
+pub enum Scope {
+    File,
+    Excerpt(Excerpt),
+    Chunk(Chunk),
+}
+
+impl Scope {
+    pub fn text<'a>(&'a self, file_content: &'a str) -> &'a str {
+        match self {
+            Scope::File => file_content,
+            Scope::Excerpt(excerpt) => &excerpt.text,
+            Scope::Chunk(chunk) => chunk.text(file_content),
+        }
+    }
+}
+
+/// What the model is asked to answer with.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum ResponseFormat {
+    /// The whole fixed scope.
+    File,
+    /// A unified diff against the scope, applied by [`crate::patch::apply`].
+    Diff,
+}
+
+impl FromStr for ResponseFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "file" => Ok(ResponseFormat::File),
+            "diff" => Ok(ResponseFormat::Diff),
+            other => Err(format!("unknown response format: {}", other)),
+        }
+    }
+}
+
+pub struct IssuePrompt<'a> {
+    pub filename: &'a str,
+    pub issue_code: &'a str,
+    pub issue_row: u32,
+    pub issue_message: &'a str,
+    /// The lines the issue spans.
+    pub issue_lines: &'a str,
+    /// The exact code the issue is about, if ruff reports it.
+    pub issue_span: Option<&'a str>,
+    /// Why the previous attempt at this fix was rejected.
+    pub feedback: Option<&'a str>,
+    /// Fixes of the same rule, from `--examples`.
+    pub examples: &'a [Example],
+    pub format: ResponseFormat,
+    /// That of `--prompt-template`, replacing the built-in prompt.
+    pub template: Option<&'a Template>,
+}
+
+impl IssuePrompt<'_> {
+    /// Asks for a fix of this issue in a conversation that already holds
+    /// the whole file, as it was after the model's last answer.
+    pub fn render_follow_up(&self) -> String {
+        let mut prompt = format!(
+            "Also fix the following issue in the file:\n\n{}{}",
+            self.describe_issue(),
+            describe_examples(self.examples)
+        );
+        if let Some(feedback) = self.feedback {
+            prompt.push_str(&format!(
+                "A previous attempt to fix this issue was rejected:\n{}\n\n",
+                feedback
+            ));
+        }
+        prompt.push_str(&instruction(
+            self.format,
+            &Scope::File,
+            "this issue and keeping the earlier fixes",
+        ));
+        prompt
+    }
+
+    fn describe_issue(&self) -> String {
+        let mut description = format!(
+            "Issue description:\n{}\n\nProblematic line:\n{}\n\n",
+            self.issue_message, self.issue_lines
+        );
+        if let Some(span) = self
+            .issue_span
+            .filter(|span| !span.trim().is_empty() && span.trim() != self.issue_lines.trim())
+        {
+            description.push_str(&format!(
+                "The issue is reported for this code:\n{}\n\n",
+                span
+            ));
+        }
+        description
+    }
+
+    /// The prompt for fixing the issue in `scope` of the file. A template
+    /// gets the issue's `rule_code`, `message`, `line` and `lines`, with
+    /// `span` for the exact code reported if there is one; the `filename`
+    /// and its `language`; the text to fix as `snippet`, which is part of
+    /// the file for large files, with `snippet_lines` saying which lines;
+    /// the whole `file_content`; the `feedback` on a rejected attempt; the
+    /// `examples` of fixes of the rule; and the `instructions` the answer
+    /// must follow to be read.
+    pub fn render(&self, scope: &Scope, file_content: &str) -> String {
+        if let Some(template) = self.template {
+            return self.render_template(template, scope, file_content);
+        }
+        let mut header = format!(
+            "Fix the following issue in the {} code:\n\n{}{}",
+            Language::of(self.filename).name(),
+            self.describe_issue(),
+            describe_examples(self.examples)
+        );
+        if let Some(feedback) = self.feedback {
+            header.push_str(&format!(
+                "A previous attempt to fix this issue was rejected:\n{}\n\n",
+                feedback
+            ));
+        }
+        let content = scope.text(file_content);
+        let body = match scope {
+            Scope::File => format!(
+                "Here's the current content of the file {}:\n\n{}\n\n",
+                self.filename, content
+            ),
+            Scope::Excerpt(excerpt) => format!(
+                "The file {} is too large to include in full. Here are lines {} of it:\n\n{}\n\n",
+                self.filename,
+                excerpt.line_range(),
+                content
+            ),
+            Scope::Chunk(chunk) => format!(
+                "The file {} is too large to process at once, so it was split into overlapping parts. Here is the part containing the issue:\n\n{}\n\n{}",
+                self.filename,
+                content,
+                shared_text(chunk, file_content)
+            ),
+        };
+        format!(
+            "{}{}{}",
+            header,
+            body,
+            instruction(self.format, scope, "the issue listed above")
+        )
+    }
+
+    fn render_template(&self, template: &Template, scope: &Scope, file_content: &str) -> String {
+        let line = self.issue_row.to_string();
+        let (snippet_lines, shared) = match scope {
+            Scope::File => (String::new(), String::new()),
+            Scope::Excerpt(excerpt) => (excerpt.line_range(), String::new()),
+            Scope::Chunk(chunk) => (String::new(), shared_text(chunk, file_content)),
+        };
+        let instructions = format!("{}{}", shared, instruction(self.format, scope, "the issue"));
+        let examples = describe_examples(self.examples);
+        template.render(&[
+            ("rule_code", self.issue_code),
+            ("message", self.issue_message),
+            ("filename", self.filename),
+            ("language", Language::of(self.filename).name()),
+            ("line", &line),
+            ("lines", self.issue_lines),
+            ("span", self.issue_span.unwrap_or_default()),
+            ("snippet", scope.text(file_content)),
+            ("snippet_lines", &snippet_lines),
+            ("file_content", file_content),
+            ("feedback", self.feedback.unwrap_or_default()),
+            ("examples", &examples),
+            ("instructions", &instructions),
+        ])
+    }
+}
+
+/// `examples` as a part of a prompt, empty if there are none.
+fn describe_examples(examples: &[Example]) -> String {
+    if examples.is_empty() {
+        return String::new();
+    }
+    let mut description =
+        "Here are examples of how issues of the same rule were fixed:\n\n".to_string();
+    for example in examples {
+        description.push_str(&format!(
+            "Before:\n{}\n\nAfter:\n{}\n\n",
+            example.before.trim_end(),
+            example.after.trim_end()
+        ));
+    }
+    description
+}
+
+/// What a part of a file split into chunks shares with its neighbours,
+/// which the answer must keep.
+fn shared_text(chunk: &Chunk, file_content: &str) -> String {
+    let mut text = String::new();
+    if chunk.lead > 0 {
+        text.push_str(&format!(
+            "The part begins with text shared with the previous part, which must be kept exactly as it is:\n{}\n\n",
+            chunk.lead_text(file_content)
+        ));
+    }
+    if chunk.trail > 0 {
+        text.push_str(&format!(
+            "The part ends with text shared with the next part, which must be kept exactly as it is:\n{}\n\n",
+            chunk.trail_text(file_content)
+        ));
+    }
+    text
+}
+
+/// Asks for one fix of several issues in a whole file.
+pub struct BatchPrompt<'a> {
+    pub filename: &'a str,
+    pub issues: &'a [&'a Issue],
+    pub format: ResponseFormat,
+}
+
+impl BatchPrompt<'_> {
+    pub fn render(&self, file_content: &str) -> String {
+        let mut prompt = format!(
+            "Fix all of the following issues in the {} code:\n\n",
+            Language::of(self.filename).name()
+        );
+        for issue in self.issues {
+            prompt.push_str(&format!(
+                "- Line {}: {}\n  {}\n",
+                issue.location.row,
+                issue.message,
+                issue.lines(file_content).trim()
+            ));
+        }
+        prompt.push_str(&format!(
+            "\nHere's the current content of the file {}:\n\n{}\n\n{}",
+            self.filename,
+            file_content,
+            instruction(self.format, &Scope::File, "all of the issues listed above")
+        ));
+        prompt
+    }
+}
+
+fn instruction(format: ResponseFormat, scope: &Scope, addressing: &str) -> String {
+    let subject = match scope {
+        Scope::File => "the file",
+        Scope::Excerpt(_) => "these lines",
+        Scope::Chunk(_) => "this part",
+    };
+    let answer = match (format, scope) {
+        (ResponseFormat::File, Scope::File) => "the entire fixed content of the file".to_string(),
+        (ResponseFormat::File, _) => format!("the fixed version of {}", subject),
+        (ResponseFormat::Diff, _) => format!("a unified diff of {}", subject),
+    };
+    let diff_rules = match format {
+        ResponseFormat::File => "",
+        ResponseFormat::Diff => ", with @@ hunk headers numbering lines from the start of the text above and a few unchanged lines of context around each change",
+    };
+    format!(
+        "Please provide only {} addressing {}{}, do not provide any explanation, do not wrap the response with backticks.",
+        answer, addressing, diff_rules
+    )
+}
+
+/// Asks why an issue that is being suppressed with `# noqa` is acceptable.
+pub struct JustificationPrompt<'a> {
+    pub issue_message: &'a str,
+    pub issue_lines: &'a str,
+}
+
+impl JustificationPrompt<'_> {
+    pub fn render(&self) -> String {
+        format!(
+            "The following issue in the Python code is going to be suppressed with a noqa comment instead of being fixed:\n\nIssue description:\n{}\n\nProblematic line:\n{}\n\nPlease provide only a short justification for suppressing it, in one line of at most 80 characters, that will be added to the comment, do not provide any other explanation.",
+            self.issue_message, self.issue_lines
+        )
+    }
+}
+
+/// Asks for the docstring a definition (or module) is missing.
+pub struct DocstringPrompt<'a> {
+    pub filename: &'a str,
+    pub issue_message: &'a str,
+    /// What is documented: a module, class, method or function.
+    pub kind: &'a str,
+    /// The code to document, see [`crate::docstring::Target::context`].
+    pub code: &'a str,
+    pub style: Style,
+    /// Why the previous docstring was rejected.
+    pub feedback: Option<&'a str>,
+}
+
+impl DocstringPrompt<'_> {
+    pub fn render(&self) -> String {
+        let style = match self.style {
+            Style::Google => "Google style: a one-line summary ending with a period, a blank line, an optional longer description, then `Args:`, `Returns:`, `Yields:`, `Raises:` or `Attributes:` sections as they apply, each entry on its own line indented by 4 spaces as `name: description`",
+            Style::Numpy => "NumPy style: a one-line summary ending with a period, a blank line, an optional longer description, then `Parameters`, `Returns`, `Yields`, `Raises` or `Attributes` sections as they apply, each title underlined with dashes and each entry written as `name : type` followed by its description indented by 4 spaces",
+        };
+        let code = match self.kind {
+            "module" => format!("Here's the beginning of the module {}:", self.filename),
+            kind => format!("Here's the {} from the file {}:", kind, self.filename),
+        };
+        let mut prompt = format!(
+            "Write the missing docstring of the following Python {}.\n\nIssue description:\n{}\n\n{}\n\n{}\n\n",
+            self.kind, self.issue_message, code, self.code
+        );
+        if let Some(feedback) = self.feedback {
+            prompt.push_str(&format!(
+                "A previous docstring was rejected:\n{}\n\n",
+                feedback
+            ));
+        }
+        prompt.push_str(&format!(
+            "Follow the {}. Describe what the code does based on its signature and body, without inventing behavior it doesn't have. Please provide only the docstring, including its triple quotes and without indentation, do not repeat the code, do not provide any explanation, do not wrap the response with backticks.",
+            style
+        ));
+        prompt
+    }
+}
+
+/// Asks for a function's signature with a missing annotation added.
+pub struct AnnotationPrompt<'a> {
+    pub filename: &'a str,
+    pub issue_message: &'a str,
+    /// The function, see [`crate::annotate::Target`].
+    pub definition: &'a str,
+    /// Lines it returns or yields at.
+    pub exits: &'a [String],
+    /// Lines that call it.
+    pub call_sites: &'a [String],
+    /// Why the previous signature was rejected.
+    pub feedback: Option<&'a str>,
+}
+
+impl AnnotationPrompt<'_> {
+    pub fn render(&self) -> String {
+        let mut prompt = format!(
+            "Add the missing type annotation to the following Python function.\n\nIssue description:\n{}\n\nHere's the function from the file {}:\n\n{}\n\n",
+            self.issue_message, self.filename, self.definition
+        );
+        if !self.exits.is_empty() {
+            prompt.push_str(&format!(
+                "It returns at these lines:\n{}\n\n",
+                self.exits.join("\n")
+            ));
+        }
+        if !self.call_sites.is_empty() {
+            prompt.push_str(&format!(
+                "It is called like this in the file:\n{}\n\n",
+                self.call_sites.join("\n")
+            ));
+        }
+        if let Some(feedback) = self.feedback {
+            prompt.push_str(&format!(
+                "A previous signature was rejected:\n{}\n\n",
+                feedback
+            ));
+        }
+        prompt.push_str("Please provide only the signature of the function, from `def` to the colon, with the annotation added and the parameter names, defaults and order unchanged. Put the imports the annotation needs, if any, on lines of their own before the signature. Do not provide the body, do not provide any explanation, do not wrap the response with backticks.");
+        prompt
+    }
+}
+
+/// Asks what a reviewer of a proposed fix of a security issue needs to know.
+pub struct RiskPrompt<'a> {
+    pub issue_code: &'a str,
+    pub issue_message: &'a str,
+    pub issue_lines: &'a str,
+    /// The proposed fix, as a unified diff.
+    pub patch: &'a str,
+}
+
+impl RiskPrompt<'_> {
+    pub fn render(&self) -> String {
+        format!(
+            "Ruff reported the following security issue ({}) in Python code, and a fix has been proposed for it. The fix will be reviewed by a person before it is applied.\n\nIssue description:\n{}\n\nProblematic line:\n{}\n\nProposed fix:\n{}\n\nPlease explain in a few short sentences the risk the issue poses and how the fix addresses it, and point out anything the reviewer should check, such as behavior the fix may break or cases it doesn't cover. Provide only plain text, do not repeat the code.",
+            self.issue_code, self.issue_message, self.issue_lines, self.patch
+        )
+    }
+}
+
+/// Asks why a fix that was just made changes what it does.
+pub struct ExplanationPrompt<'a> {
+    /// The issues the fix addresses, one `CODE: message` per line.
+    pub issues: &'a str,
+    /// The fix, as a unified diff.
+    pub patch: &'a str,
+}
+
+impl ExplanationPrompt<'_> {
+    pub fn render(&self) -> String {
+        format!(
+            "The following change was made to fix these issues reported by ruff in Python code:\n{}\n\nChange:\n{}\n\nPlease explain to a reviewer in one or two short sentences why the change was made this way and how it fixes the issues. Provide only plain text, do not repeat the code.",
+            self.issues, self.patch
+        )
+    }
+}
+
+/// Asks how an issue should be fixed, for a review instead of a fix.
+pub struct ReviewPrompt<'a> {
+    pub filename: &'a str,
+    pub issue_code: &'a str,
+    pub issue_message: &'a str,
+    pub issue_lines: &'a str,
+    /// The lines around the issue.
+    pub excerpt: &'a Excerpt,
+}
+
+impl ReviewPrompt<'_> {
+    pub fn render(&self) -> String {
+        format!(
+            "Review the following issue reported by ruff ({}) in Python code. Do not fix it; describe how it should be fixed.\n\nIssue description:\n{}\n\nProblematic line:\n{}\n\nHere are lines {} of the file {}:\n\n{}\n\nPlease answer with a line starting with `Approach:` followed by the suggested way to fix the issue, and a line starting with `Caveats:` followed by what to watch out for when fixing it, such as behavior that could change, or `None`. Keep each to a few sentences, do not provide the fixed code, do not wrap the response with backticks.",
+            self.issue_code,
+            self.issue_message,
+            self.issue_lines,
+            self.excerpt.line_range(),
+            self.filename,
+            self.excerpt.text
+        )
+    }
+}
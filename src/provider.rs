@@ -0,0 +1,166 @@
+use std::future::Future;
+use std::path::PathBuf;
+use std::pin::Pin;
+
+use reqwest::Client;
+use serde_json::json;
+
+use crate::redact::{redact, redact_value};
+use crate::{recording, Error};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Role {
+    System,
+    User,
+    Assistant,
+}
+
+impl Role {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Role::System => "system",
+            Role::User => "user",
+            Role::Assistant => "assistant",
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Message {
+    pub role: Role,
+    pub content: String,
+}
+
+impl Message {
+    pub fn system(content: impl Into<String>) -> Self {
+        Message {
+            role: Role::System,
+            content: content.into(),
+        }
+    }
+
+    pub fn user(content: impl Into<String>) -> Self {
+        Message {
+            role: Role::User,
+            content: content.into(),
+        }
+    }
+
+    pub fn assistant(content: impl Into<String>) -> Self {
+        Message {
+            role: Role::Assistant,
+            content: content.into(),
+        }
+    }
+}
+
+/// One chat completion request, as sent for a single fix attempt.
+#[derive(Clone, Debug)]
+pub struct CompletionRequest {
+    pub model: String,
+    pub messages: Vec<Message>,
+}
+
+impl CompletionRequest {
+    /// Content of the last user message, i.e. the prompt being answered.
+    pub fn prompt(&self) -> &str {
+        self.messages
+            .iter()
+            .rev()
+            .find(|message| message.role == Role::User)
+            .map_or("", |message| message.content.as_str())
+    }
+}
+
+pub type Completion<'a> = Pin<Box<dyn Future<Output = Result<String, Error>> + Send + 'a>>;
+
+/// Answers the prompts the fixer sends; the answer is the fixed code.
+pub trait FixProvider: Send + Sync {
+    fn complete<'a>(&'a self, request: &'a CompletionRequest) -> Completion<'a>;
+}
+
+/// The OpenAI chat completions API, optionally recording every exchange
+/// or answering from earlier recordings. The API key never shows in its
+/// errors or recordings.
+pub struct OpenAiProvider {
+    client: Client,
+    api_key: String,
+    /// Where chat completions are requested.
+    url: String,
+    record: Option<PathBuf>,
+    replay: Option<PathBuf>,
+}
+
+impl OpenAiProvider {
+    pub fn new(api_key: String, record: Option<PathBuf>, replay: Option<PathBuf>) -> Self {
+        OpenAiProvider {
+            client: Client::new(),
+            api_key,
+            url: "https://api.openai.com/v1/chat/completions".to_string(),
+            record,
+            replay,
+        }
+    }
+
+    /// Sends requests to the API at `base`, such as `http://localhost:8080/v1`,
+    /// instead of OpenAI's.
+    pub fn with_base_url(mut self, base: &str) -> Self {
+        self.url = format!("{}/chat/completions", base.trim_end_matches('/'));
+        self
+    }
+
+    async fn send(&self, request: &CompletionRequest) -> Result<String, Error> {
+        let messages: Vec<_> = request
+            .messages
+            .iter()
+            .map(|message| json!({"role": message.role.as_str(), "content": message.content}))
+            .collect();
+        let request_body = json!({
+            "model": request.model,
+            "messages": messages,
+        });
+
+        let response_json: serde_json::Value = match &self.replay {
+            Some(dir) => recording::replay(dir, &request_body)?,
+            None => {
+                let response = self
+                    .client
+                    .post(&self.url)
+                    .bearer_auth(&self.api_key)
+                    .json(&request_body)
+                    .send()
+                    .await?;
+                let status = response.status();
+                let response_json = response.json().await?;
+                if let Some(dir) = &self.record {
+                    let response_json = redact_value(&response_json, &self.api_key);
+                    recording::record(dir, &request_body, status.as_u16(), &response_json)?;
+                }
+                response_json
+            }
+        };
+        if let Some(message) = response_json["error"]["message"].as_str() {
+            return Err(format!("The API answered with an error: {}", message).into());
+        }
+        if response_json["choices"][0]["finish_reason"] == "length" {
+            return Err("Response was cut off at the model's output limit".into());
+        }
+        let content = response_json["choices"][0]["message"]["content"]
+            .as_str()
+            .ok_or("Failed to parse response content")?;
+
+        Ok(content.to_string())
+    }
+}
+
+impl FixProvider for OpenAiProvider {
+    fn complete<'a>(&'a self, request: &'a CompletionRequest) -> Completion<'a> {
+        Box::pin(async move {
+            // Errors end up on the console and in logs; reqwest's and the
+            // API's may quote the key.
+            self.send(request)
+                .await
+                .map_err(|err| redact(&err.to_string(), &self.api_key).into())
+        })
+    }
+}
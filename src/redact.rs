@@ -0,0 +1,129 @@
+//! Keeps the API key out of everything ruffer writes: error messages, logs
+//! and recordings. Besides the key itself, anything that looks like a
+//! bearer token or an OpenAI key is masked, as the API quotes keys back in
+//! its errors.
+
+use serde_json::Value;
+
+const REDACTED: &str = "[REDACTED]";
+
+/// `text` with `secret`, bearer tokens and API keys masked.
+pub fn redact(text: &str, secret: &str) -> String {
+    let mut text = if secret.is_empty() {
+        text.to_string()
+    } else {
+        text.replace(secret, REDACTED)
+    };
+    for prefix in ["Bearer ", "bearer ", "sk-"] {
+        text = mask_after(&text, prefix);
+    }
+    text
+}
+
+/// `value` with every string in it redacted.
+pub fn redact_value(value: &Value, secret: &str) -> Value {
+    match value {
+        Value::String(text) => Value::String(redact(text, secret)),
+        Value::Array(items) => items
+            .iter()
+            .map(|item| redact_value(item, secret))
+            .collect(),
+        Value::Object(fields) => fields
+            .iter()
+            .map(|(name, field)| (name.clone(), redact_value(field, secret)))
+            .collect(),
+        other => other.clone(),
+    }
+}
+
+/// Masks the token following each `prefix` in `text`; `sk-` starts the
+/// token, so it goes too.
+fn mask_after(text: &str, prefix: &str) -> String {
+    let is_token = |c: char| c.is_ascii_alphanumeric() || "-_.*".contains(c);
+    let keep_prefix = prefix.ends_with(' ');
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(at) = rest.find(prefix) {
+        let after = &rest[at + prefix.len()..];
+        let len = after.find(|c| !is_token(c)).unwrap_or(after.len());
+        // Part of a longer word, too short to be a key, or already masked.
+        if rest[..at].ends_with(is_token) || len < 8 {
+            out.push_str(&rest[..at + prefix.len()]);
+            rest = after;
+            continue;
+        }
+        out.push_str(&rest[..at]);
+        if keep_prefix {
+            out.push_str(prefix);
+        }
+        out.push_str(REDACTED);
+        rest = &after[len..];
+    }
+    out.push_str(rest);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn masks_the_key_and_anything_shaped_like_one() {
+        assert_eq!(
+            redact("key my-secret-key failed", "my-secret-key"),
+            "key [REDACTED] failed"
+        );
+        assert_eq!(
+            redact("Incorrect API key provided: sk-proj-0123456789, see", ""),
+            "Incorrect API key provided: [REDACTED], see"
+        );
+        assert_eq!(
+            redact("Authorization: Bearer abcdef0123456789", ""),
+            "Authorization: Bearer [REDACTED]"
+        );
+        assert_eq!(
+            redact("authorization: bearer abcdef0123456789", ""),
+            "authorization: bearer [REDACTED]"
+        );
+    }
+
+    #[test]
+    fn leaves_what_only_looks_like_a_prefix() {
+        // `sk-` inside a word is no key.
+        assert_eq!(redact("task-0123456789abcdef", ""), "task-0123456789abcdef");
+        assert_eq!(
+            redact("disk-usage-report-2024", ""),
+            "disk-usage-report-2024"
+        );
+        // Too short to be a key or a token.
+        assert_eq!(redact("sk-1234567", ""), "sk-1234567");
+        assert_eq!(redact("Bearer abc", ""), "Bearer abc");
+    }
+
+    #[test]
+    fn leaves_masked_text_as_it_is() {
+        let masked = redact("Bearer abcdef0123456789 and sk-proj-0123456789", "");
+        assert_eq!(masked, "Bearer [REDACTED] and [REDACTED]");
+        assert_eq!(redact(&masked, ""), masked);
+        assert_eq!(
+            redact("Bearer [REDACTED]", "secret-key"),
+            "Bearer [REDACTED]"
+        );
+    }
+
+    #[test]
+    fn masks_every_string_of_a_value() {
+        let value = serde_json::json!({
+            "error": {"message": "bad key sk-proj-0123456789", "code": 401},
+            "echo": ["Bearer abcdef0123456789"],
+        });
+
+        assert_eq!(
+            redact_value(&value, ""),
+            serde_json::json!({
+                "error": {"message": "bad key [REDACTED]", "code": 401},
+                "echo": ["Bearer [REDACTED]"],
+            })
+        );
+    }
+}
@@ -0,0 +1,924 @@
+//! Reports of what a run did with every issue, for `--report`.
+
+use std::cell::Cell;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::future::Future;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use crate::atomic;
+use crate::highlight::{self, Kind};
+use crate::hook::HookRun;
+use crate::issue::{self, Issue, Severity};
+use crate::journal::digest;
+use crate::patch;
+use crate::state::Outcome;
+
+tokio::task_local! {
+    /// Tokens sent to and received from the model by the issue being
+    /// measured.
+    static TOKENS: Cell<usize>;
+}
+
+/// A report format and where to write it, given as `<format>=<path>`, or
+/// as `<format>` alone for stdout.
+#[derive(Clone, Debug)]
+pub struct Target {
+    pub format: Format,
+    pub path: Option<PathBuf>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Json,
+    /// SARIF 2.1.0, for GitHub code scanning and other SARIF consumers.
+    Sarif,
+    /// JUnit XML, for the test views of CI systems.
+    Junit,
+    /// A self-contained HTML page with the changes, for reviewers.
+    Html,
+    /// A summary to post as a pull request comment.
+    Markdown,
+}
+
+impl FromStr for Target {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (format, path) = match s.split_once('=') {
+            Some((format, path)) => (format, Some(path)),
+            None => (s, None),
+        };
+        let format = match format {
+            "json" => Format::Json,
+            "sarif" => Format::Sarif,
+            "junit" => Format::Junit,
+            "html" => Format::Html,
+            "markdown" => Format::Markdown,
+            other => return Err(format!("unknown report format: {}", other)),
+        };
+        if path.is_some_and(str::is_empty) {
+            return Err(format!("no path for the {} report", format));
+        }
+        Ok(Target {
+            format,
+            path: path.map(PathBuf::from),
+        })
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Format::Json => "json",
+            Format::Sarif => "sarif",
+            Format::Junit => "junit",
+            Format::Html => "html",
+            Format::Markdown => "markdown",
+        })
+    }
+}
+
+/// What became of an issue, in more detail than the [`Outcome`] kept for
+/// `--resume`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Resolution {
+    /// Fixed with ruff's own fix.
+    AutoFixed,
+    /// Fixed by the model.
+    LlmFixed,
+    /// Suppressed with a `noqa` comment.
+    Suppressed,
+    /// Skipped as asked, or the fix was declined.
+    Skipped,
+    /// The model didn't come up with an acceptable fix, or couldn't be
+    /// asked.
+    Failed,
+    /// Left for someone to fix or review.
+    NeedsHuman,
+    /// The run ended before getting to it.
+    NotReached,
+}
+
+impl Resolution {
+    /// Every resolution, in the order summaries list them.
+    pub const ALL: [Resolution; 7] = [
+        Resolution::AutoFixed,
+        Resolution::LlmFixed,
+        Resolution::Suppressed,
+        Resolution::Failed,
+        Resolution::Skipped,
+        Resolution::NeedsHuman,
+        Resolution::NotReached,
+    ];
+
+    /// The name of the outcome in reports.
+    pub fn name(self) -> &'static str {
+        match self {
+            Resolution::AutoFixed => "auto-fixed",
+            Resolution::LlmFixed => "llm-fixed",
+            Resolution::Suppressed => "suppressed",
+            Resolution::Skipped => "skipped",
+            Resolution::Failed => "failed",
+            Resolution::NeedsHuman => "needs-human",
+            Resolution::NotReached => "not-reached",
+        }
+    }
+
+    pub fn outcome(self) -> Outcome {
+        match self {
+            Resolution::AutoFixed | Resolution::LlmFixed | Resolution::Suppressed => Outcome::Fixed,
+            Resolution::Failed | Resolution::NotReached => Outcome::Failed,
+            Resolution::Skipped | Resolution::NeedsHuman => Outcome::LeftAlone,
+        }
+    }
+}
+
+/// What resolving an issue took.
+#[derive(Clone, Default)]
+pub struct Effort {
+    pub duration: Duration,
+    pub tokens: usize,
+    /// Hash of the patch that fixed the issue.
+    pub diff_hash: Option<String>,
+}
+
+impl Effort {
+    /// Effort that changed `filename` from `before` to `after`.
+    pub fn with_diff(mut self, filename: &str, before: &str, after: &str) -> Self {
+        self.diff_hash = Some(digest(&patch::unified(filename, before, after)));
+        self
+    }
+
+    /// This effort shared among `n` issues.
+    pub fn split(&self, n: usize) -> Effort {
+        let n = n.max(1);
+        Effort {
+            duration: self.duration / n as u32,
+            tokens: self.tokens / n,
+            diff_hash: self.diff_hash.clone(),
+        }
+    }
+}
+
+/// Runs `work`, measuring how long it takes and how many tokens the
+/// requests made by it cost.
+pub async fn measure<F: Future>(work: F) -> (F::Output, Effort) {
+    let started = Instant::now();
+    let (output, tokens) = TOKENS
+        .scope(Cell::new(0), async {
+            let output = work.await;
+            (output, TOKENS.with(Cell::get))
+        })
+        .await;
+    let effort = Effort {
+        duration: started.elapsed(),
+        tokens,
+        diff_hash: None,
+    };
+    (output, effort)
+}
+
+/// Counts `tokens` towards the issue being measured, if any.
+pub fn count(tokens: usize) {
+    let _ = TOKENS.try_with(|counted| counted.set(counted.get() + tokens));
+}
+
+#[derive(Clone, Serialize)]
+struct Entry {
+    file: String,
+    id: String,
+    code: String,
+    message: String,
+    row: u32,
+    column: u32,
+    outcome: Resolution,
+    /// Why the issue was skipped, when not as asked.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reason: Option<String>,
+    seconds: f64,
+    tokens: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    diff_hash: Option<String>,
+    #[serde(skip)]
+    end: Option<(u32, u32)>,
+    /// Documentation of the rule.
+    #[serde(skip)]
+    url: Option<String>,
+}
+
+/// An issue fixed in a file the run changed.
+pub struct Fixed {
+    pub code: String,
+    pub message: String,
+    pub row: u32,
+}
+
+/// How many issues a rule or a file had, and how many of them were fixed.
+#[derive(Serialize)]
+pub struct Tally {
+    pub name: String,
+    pub issues: usize,
+    pub fixed: usize,
+}
+
+/// Every issue the run worked on and what became of it.
+#[derive(Default)]
+pub struct Report {
+    /// By file and issue ID.
+    entries: Mutex<BTreeMap<(String, String), Entry>>,
+    /// The patch of every file the run changed, by display path.
+    patches: Mutex<BTreeMap<String, String>>,
+    /// Tokens of the prompts answered, and of the answers.
+    prompt_tokens: AtomicUsize,
+    answer_tokens: AtomicUsize,
+    /// Every run of `--post-fix-cmd`.
+    post_fix: Mutex<Vec<HookRun>>,
+}
+
+impl Report {
+    /// Adds `issue`, not reached yet.
+    pub fn discover(&self, filename: &str, issue: &Issue) {
+        let entry = Entry {
+            file: issue::display_path(filename),
+            id: issue.id.clone(),
+            code: issue.code.clone(),
+            message: issue.message.clone(),
+            row: issue.location.row,
+            column: issue.location.column,
+            outcome: Resolution::NotReached,
+            reason: None,
+            seconds: 0.0,
+            tokens: 0,
+            diff_hash: None,
+            end: issue.end_location.as_ref().map(|end| (end.row, end.column)),
+            url: issue.url.clone(),
+        };
+        self.entries
+            .lock()
+            .unwrap()
+            .insert((filename.to_string(), issue.id.clone()), entry);
+    }
+
+    /// Records a run of `--post-fix-cmd`.
+    pub fn post_fix(&self, run: HookRun) {
+        self.post_fix.lock().unwrap().push(run);
+    }
+
+    /// Whether the issue `id` of `filename` was discovered before.
+    pub fn knows(&self, filename: &str, id: &str) -> bool {
+        self.entries
+            .lock()
+            .unwrap()
+            .contains_key(&(filename.to_string(), id.to_string()))
+    }
+
+    pub fn resolve(&self, filename: &str, id: &str, resolution: Resolution, effort: &Effort) {
+        let mut entries = self.entries.lock().unwrap();
+        if let Some(entry) = entries.get_mut(&(filename.to_string(), id.to_string())) {
+            entry.outcome = resolution;
+            entry.seconds = effort.duration.as_secs_f64();
+            entry.tokens = effort.tokens;
+            entry.diff_hash = effort.diff_hash.clone();
+        }
+    }
+
+    /// Records that the issue `id` of `filename` was skipped for `reason`.
+    pub fn skip(&self, filename: &str, id: &str, reason: &str) {
+        let mut entries = self.entries.lock().unwrap();
+        if let Some(entry) = entries.get_mut(&(filename.to_string(), id.to_string())) {
+            entry.outcome = Resolution::Skipped;
+            entry.reason = Some(reason.to_string());
+        }
+    }
+
+    /// Records a request to the model answered, of `prompt` tokens, with
+    /// an answer of `answer` tokens.
+    pub fn spent(&self, prompt: usize, answer: usize) {
+        self.prompt_tokens.fetch_add(prompt, Ordering::Relaxed);
+        self.answer_tokens.fetch_add(answer, Ordering::Relaxed);
+    }
+
+    /// Tokens of the prompts answered so far, and of the answers.
+    pub fn tokens(&self) -> (usize, usize) {
+        (
+            self.prompt_tokens.load(Ordering::Relaxed),
+            self.answer_tokens.load(Ordering::Relaxed),
+        )
+    }
+
+    /// How many issues ended each way, in the order of
+    /// [`Resolution::ALL`].
+    pub fn tally(&self) -> Vec<(Resolution, usize)> {
+        let entries = self.entries.lock().unwrap();
+        Resolution::ALL
+            .iter()
+            .map(|&resolution| {
+                let count = entries
+                    .values()
+                    .filter(|entry| entry.outcome == resolution)
+                    .count();
+                (resolution, count)
+            })
+            .collect()
+    }
+
+    /// The rules and the files with the most issues, at most `n` of each,
+    /// the noisiest first.
+    pub fn top(&self, n: usize) -> (Vec<Tally>, Vec<Tally>) {
+        let entries: Vec<Entry> = self.entries.lock().unwrap().values().cloned().collect();
+        let mut rules = tallies(&entries, |entry| &entry.code);
+        let mut files = tallies(&entries, |entry| &entry.file);
+        rules.truncate(n);
+        files.truncate(n);
+        (rules, files)
+    }
+
+    /// Records that the run changed `filename` from `before` to `after`.
+    pub fn written(&self, filename: &str, before: &str, after: &str) {
+        if before != after {
+            let path = issue::display_path(filename);
+            let patch = patch::unified(&path, before, after);
+            self.patches.lock().unwrap().insert(path, patch);
+        }
+    }
+
+    /// Takes back the change of `filename`, put back as it was: its patch
+    /// is dropped, and the issues fixed in it are left for people.
+    pub fn roll_back(&self, filename: &str) {
+        self.patches
+            .lock()
+            .unwrap()
+            .remove(&issue::display_path(filename));
+        for ((file, _), entry) in self.entries.lock().unwrap().iter_mut() {
+            if file == filename && entry.outcome.outcome() == Outcome::Fixed {
+                entry.outcome = Resolution::NeedsHuman;
+                entry.diff_hash = None;
+            }
+        }
+    }
+
+    /// The files the run changed, by display path, with the issues fixed
+    /// in each.
+    pub fn changes(&self) -> BTreeMap<String, Vec<Fixed>> {
+        let mut changes: BTreeMap<String, Vec<Fixed>> = self
+            .patches
+            .lock()
+            .unwrap()
+            .keys()
+            .map(|path| (path.clone(), Vec::new()))
+            .collect();
+        for entry in self.entries.lock().unwrap().values() {
+            if let Some(fixed) = changes.get_mut(&entry.file) {
+                if entry.outcome.outcome() == Outcome::Fixed {
+                    fixed.push(Fixed {
+                        code: entry.code.clone(),
+                        message: entry.message.clone(),
+                        row: entry.row,
+                    });
+                }
+            }
+        }
+        for fixed in changes.values_mut() {
+            fixed.sort_by_key(|fixed| fixed.row);
+        }
+        changes
+    }
+
+    /// How many issues are left in the code.
+    pub fn unfixed(&self) -> usize {
+        self.entries
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|entry| entry.outcome.outcome() != Outcome::Fixed)
+            .count()
+    }
+
+    /// A GitHub Actions workflow command for every issue: an error where
+    /// an issue is left in the code, a notice where one was fixed.
+    pub fn annotations(&self) -> String {
+        let entries = self.entries.lock().unwrap();
+        let mut commands = String::new();
+        for entry in entries.values() {
+            let (command, message) = if entry.outcome.outcome() == Outcome::Fixed {
+                (
+                    "notice",
+                    format!("Fixed ({}): {}", entry.outcome.name(), entry.message),
+                )
+            } else {
+                (
+                    "error",
+                    format!("{} ({})", entry.message, entry.outcome.name()),
+                )
+            };
+            commands.push_str(&format!(
+                "::{} file={},line={},col={},title={}::{}\n",
+                command,
+                workflow_property(&entry.file),
+                entry.row,
+                entry.column,
+                workflow_property(&format!("ruffer {}", entry.code)),
+                workflow_data(&message)
+            ));
+        }
+        commands
+    }
+
+    /// The `markdown` report.
+    pub fn markdown(&self, duration: Duration) -> String {
+        let entries: Vec<Entry> = self.entries.lock().unwrap().values().cloned().collect();
+        markdown(&entries, &self.patches.lock().unwrap(), duration)
+    }
+
+    /// The `json` report, as a value.
+    pub fn json(&self, run_id: &str, duration: Duration) -> Value {
+        let entries: Vec<Entry> = self.entries.lock().unwrap().values().cloned().collect();
+        json_value(&entries, &self.post_fix.lock().unwrap(), run_id, duration)
+    }
+
+    /// Writes the report as `target` asks.
+    pub fn write(&self, target: &Target, run_id: &str, duration: Duration) -> io::Result<()> {
+        let entries: Vec<Entry> = self.entries.lock().unwrap().values().cloned().collect();
+        let data = match target.format {
+            Format::Json => json(&entries, &self.post_fix.lock().unwrap(), run_id, duration)?,
+            Format::Sarif => sarif(&entries, run_id)?,
+            Format::Junit => junit(&entries, duration),
+            Format::Html => html(&entries, &self.patches.lock().unwrap(), run_id, duration),
+            Format::Markdown => self.markdown(duration),
+        };
+        match &target.path {
+            Some(path) => atomic::write(path, data.as_bytes()),
+            None => {
+                let mut stdout = io::stdout().lock();
+                stdout.write_all(data.as_bytes())?;
+                stdout.flush()
+            }
+        }
+    }
+}
+
+fn json(
+    entries: &[Entry],
+    post_fix: &[HookRun],
+    run_id: &str,
+    duration: Duration,
+) -> io::Result<String> {
+    Ok(serde_json::to_string_pretty(&json_value(entries, post_fix, run_id, duration))? + "\n")
+}
+
+fn json_value(entries: &[Entry], post_fix: &[HookRun], run_id: &str, duration: Duration) -> Value {
+    let mut summary: BTreeMap<Resolution, usize> = BTreeMap::new();
+    for entry in entries {
+        *summary.entry(entry.outcome).or_default() += 1;
+    }
+    json!({
+        "run_id": run_id,
+        "seconds": duration.as_secs_f64(),
+        "tokens": entries.iter().map(|entry| entry.tokens).sum::<usize>(),
+        "summary": summary,
+        "rules": tallies(entries, |entry| &entry.code),
+        "files": tallies(entries, |entry| &entry.file),
+        "issues": entries,
+        "post_fix": post_fix,
+    })
+}
+
+/// The issues of `entries` by what `key` says they belong to, a rule code
+/// or a file, the one with the most first.
+fn tallies(entries: &[Entry], key: fn(&Entry) -> &String) -> Vec<Tally> {
+    let mut by_key: BTreeMap<&String, Tally> = BTreeMap::new();
+    for entry in entries {
+        let tally = by_key.entry(key(entry)).or_insert_with(|| Tally {
+            name: key(entry).clone(),
+            issues: 0,
+            fixed: 0,
+        });
+        tally.issues += 1;
+        if entry.outcome.outcome() == Outcome::Fixed {
+            tally.fixed += 1;
+        }
+    }
+    let mut tallies: Vec<Tally> = by_key.into_values().collect();
+    // Stable, so ties stay in order of name.
+    tallies.sort_by_key(|tally| Reverse(tally.issues));
+    tallies
+}
+
+/// One SARIF run with a result for every issue. Issues still in the code
+/// fail at the level of their severity; fixed ones pass, and those
+/// suppressed with `noqa` are suppressed in source. What became of each is
+/// in its `outcome` property.
+fn sarif(entries: &[Entry], run_id: &str) -> io::Result<String> {
+    let mut rules = BTreeMap::new();
+    for entry in entries {
+        rules.entry(entry.code.as_str()).or_insert_with(|| {
+            let mut rule = json!({"id": entry.code});
+            if let Some(url) = &entry.url {
+                rule["helpUri"] = json!(url);
+            }
+            rule
+        });
+    }
+    let results: Vec<Value> = entries
+        .iter()
+        .map(|entry| {
+            let mut region = json!({
+                "startLine": entry.row,
+                "startColumn": entry.column,
+            });
+            if let Some((row, column)) = entry.end {
+                region["endLine"] = json!(row);
+                region["endColumn"] = json!(column);
+            }
+            let (kind, level) = match entry.outcome {
+                Resolution::AutoFixed | Resolution::LlmFixed => ("pass", "none"),
+                _ => match issue::severity(&entry.code) {
+                    Severity::Error => ("fail", "error"),
+                    Severity::Warning => ("fail", "warning"),
+                    Severity::Info => ("fail", "note"),
+                },
+            };
+            let mut result = json!({
+                "ruleId": entry.code,
+                "kind": kind,
+                "level": level,
+                "message": {"text": entry.message},
+                "locations": [{
+                    "physicalLocation": {
+                        "artifactLocation": {"uri": entry.file.replace('\\', "/")},
+                        "region": region,
+                    },
+                }],
+                "partialFingerprints": {"ruffer/v1": entry.id},
+                "properties": {"outcome": entry.outcome},
+            });
+            if entry.outcome == Resolution::Suppressed {
+                result["suppressions"] = json!([{"kind": "inSource"}]);
+            }
+            result
+        })
+        .collect();
+    let report = json!({
+        "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+        "version": "2.1.0",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": "ruffer",
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "rules": rules.into_values().collect::<Vec<_>>(),
+                },
+            },
+            "automationDetails": {"id": run_id},
+            "results": results,
+        }],
+    });
+    Ok(serde_json::to_string_pretty(&report)? + "\n")
+}
+
+/// A test suite per file with a test case per issue, which passes if the
+/// issue was fixed, is skipped if it was skipped, and fails otherwise.
+fn junit(entries: &[Entry], duration: Duration) -> String {
+    let mut files: BTreeMap<&str, Vec<&Entry>> = BTreeMap::new();
+    for entry in entries {
+        files.entry(&entry.file).or_default().push(entry);
+    }
+    let count = |entries: &[&Entry], pick: fn(Resolution) -> bool| {
+        entries.iter().filter(|entry| pick(entry.outcome)).count()
+    };
+    let failed =
+        |outcome: Resolution| outcome.outcome() != Outcome::Fixed && outcome != Resolution::Skipped;
+    let skipped = |outcome: Resolution| outcome == Resolution::Skipped;
+
+    let all: Vec<&Entry> = entries.iter().collect();
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str(&format!(
+        "<testsuites name=\"ruffer\" tests=\"{}\" failures=\"{}\" skipped=\"{}\" time=\"{:.3}\">\n",
+        all.len(),
+        count(&all, failed),
+        count(&all, skipped),
+        duration.as_secs_f64()
+    ));
+    for (file, entries) in &files {
+        xml.push_str(&format!(
+            "  <testsuite name=\"{}\" tests=\"{}\" failures=\"{}\" skipped=\"{}\" time=\"{:.3}\">\n",
+            escape(file),
+            entries.len(),
+            count(entries, failed),
+            count(entries, skipped),
+            entries.iter().map(|entry| entry.seconds).sum::<f64>()
+        ));
+        for entry in entries {
+            xml.push_str(&format!(
+                "    <testcase classname=\"{}\" name=\"{}\" time=\"{:.3}\"",
+                escape(file),
+                escape(&format!(
+                    "{} at {}:{} [{}]",
+                    entry.code, entry.row, entry.column, entry.id
+                )),
+                entry.seconds
+            ));
+            if failed(entry.outcome) {
+                xml.push_str(&format!(
+                    ">\n      <failure message=\"{}\" type=\"{}\">{}:{}:{}: {} {}</failure>\n    </testcase>\n",
+                    escape(&entry.message),
+                    entry.outcome.name(),
+                    escape(file),
+                    entry.row,
+                    entry.column,
+                    escape(&entry.code),
+                    escape(&entry.message)
+                ));
+            } else if skipped(entry.outcome) {
+                match &entry.reason {
+                    Some(reason) => xml.push_str(&format!(
+                        ">\n      <skipped message=\"{}\"/>\n    </testcase>\n",
+                        escape(reason)
+                    )),
+                    None => xml.push_str(">\n      <skipped/>\n    </testcase>\n"),
+                }
+            } else {
+                xml.push_str("/>\n");
+            }
+        }
+        xml.push_str("  </testsuite>\n");
+    }
+    xml.push_str("</testsuites>\n");
+    xml
+}
+
+/// `text` escaped for XML attributes and text.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            // Not allowed in XML 1.0 at all.
+            c if c < ' ' && !matches!(c, '\t' | '\n' | '\r') => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+const HTML_STYLE: &str = "
+body { font-family: system-ui, sans-serif; margin: 2em; color: #1f2328; }
+table { border-collapse: collapse; margin: 0.5em 0; }
+td, th { padding: 0.2em 0.8em; text-align: left; border-bottom: 1px solid #d0d7de; }
+summary { cursor: pointer; font-weight: 600; padding: 0.3em 0; }
+details { margin: 0.5em 0; }
+.auto-fixed, .llm-fixed, .suppressed { color: #1a7f37; }
+.failed, .not-reached { color: #cf222e; }
+.skipped, .needs-human { color: #9a6700; }
+pre { background: #f6f8fa; padding: 0.5em; overflow-x: auto; }
+pre span { display: block; }
+.add { background: #dafbe1; }
+.del { background: #ffebe9; }
+.hunk { color: #8250df; }
+.kw { color: #cf222e; font-weight: 600; }
+.str { color: #0a3069; }
+.com { color: #6e7781; font-style: italic; }
+.num { color: #0550ae; }
+";
+
+/// Shows only the files and issues of the rule picked in the filter.
+const HTML_SCRIPT: &str = "
+document.getElementById('rule').addEventListener('change', function (event) {
+  var code = event.target.value;
+  document.querySelectorAll('tr[data-code]').forEach(function (row) {
+    row.hidden = code !== '' && row.dataset.code !== code;
+  });
+  document.querySelectorAll('details[data-codes]').forEach(function (file) {
+    file.hidden = code !== '' && file.dataset.codes.split(' ').indexOf(code) < 0;
+  });
+});
+";
+
+/// A page with a section per file, folded, holding its issues and the
+/// diff of the changes made to it, and a filter by rule code.
+fn html(
+    entries: &[Entry],
+    patches: &BTreeMap<String, String>,
+    run_id: &str,
+    duration: Duration,
+) -> String {
+    let mut files: BTreeMap<&str, Vec<&Entry>> = BTreeMap::new();
+    for entry in entries {
+        files.entry(&entry.file).or_default().push(entry);
+    }
+    for file in patches.keys() {
+        files.entry(file).or_default();
+    }
+    let mut codes: Vec<&str> = entries.iter().map(|entry| entry.code.as_str()).collect();
+    codes.sort_unstable();
+    codes.dedup();
+    let fixed = entries
+        .iter()
+        .filter(|entry| entry.outcome.outcome() == Outcome::Fixed)
+        .count();
+
+    let mut page =
+        String::from("<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n");
+    page.push_str(&format!(
+        "<title>ruffer run {}</title>\n<style>{}</style>\n</head>\n<body>\n",
+        escape(run_id),
+        HTML_STYLE
+    ));
+    page.push_str(&format!("<h1>ruffer run {}</h1>\n", escape(run_id)));
+    page.push_str(&format!(
+        "<p>{} issues in {} files: {} fixed. {} files changed. {} tokens estimated, {:.1}s.</p>\n",
+        entries.len(),
+        files.len(),
+        fixed,
+        patches.len(),
+        entries.iter().map(|entry| entry.tokens).sum::<usize>(),
+        duration.as_secs_f64()
+    ));
+    page.push_str("<p><label>Rule <select id=\"rule\"><option value=\"\">All</option>");
+    for code in &codes {
+        page.push_str(&format!("<option>{}</option>", escape(code)));
+    }
+    page.push_str("</select></label></p>\n");
+
+    for (file, entries) in &files {
+        let mut file_codes: Vec<&str> = entries.iter().map(|entry| entry.code.as_str()).collect();
+        file_codes.sort_unstable();
+        file_codes.dedup();
+        let fixed = entries
+            .iter()
+            .filter(|entry| entry.outcome.outcome() == Outcome::Fixed)
+            .count();
+        page.push_str(&format!(
+            "<details data-codes=\"{}\">\n<summary>{}: {} issues, {} fixed</summary>\n",
+            escape(&file_codes.join(" ")),
+            escape(file),
+            entries.len(),
+            fixed
+        ));
+        if !entries.is_empty() {
+            page.push_str(
+                "<table>\n<tr><th>Line</th><th>Rule</th><th>Message</th><th>Outcome</th><th>Tokens</th><th>Time</th></tr>\n",
+            );
+            for entry in entries {
+                let code = match &entry.url {
+                    Some(url) => format!("<a href=\"{}\">{}</a>", escape(url), escape(&entry.code)),
+                    None => escape(&entry.code),
+                };
+                page.push_str(&format!(
+                    "<tr data-code=\"{}\"><td>{}:{}</td><td>{}</td><td>{}</td><td class=\"{}\">{}</td><td>{}</td><td>{:.1}s</td></tr>\n",
+                    escape(&entry.code),
+                    entry.row,
+                    entry.column,
+                    code,
+                    escape(&entry.message),
+                    entry.outcome.name(),
+                    match &entry.reason {
+                        Some(reason) => format!("{}: {}", entry.outcome.name(), escape(reason)),
+                        None => entry.outcome.name().to_string(),
+                    },
+                    entry.tokens,
+                    entry.seconds
+                ));
+            }
+            page.push_str("</table>\n");
+        }
+        if let Some(patch) = patches.get(*file) {
+            page.push_str("<pre>");
+            let mut in_hunks = false;
+            for line in patch.lines() {
+                in_hunks |= line.starts_with("@@");
+                let (class, marker, code) = if !in_hunks || line.starts_with("@@") {
+                    ("hunk", "", line)
+                } else if let Some(code) = line.strip_prefix('+') {
+                    ("add", "+", code)
+                } else if let Some(code) = line.strip_prefix('-') {
+                    ("del", "-", code)
+                } else {
+                    ("", " ", line.strip_prefix(' ').unwrap_or(line))
+                };
+                let code = if class == "hunk" {
+                    escape(code)
+                } else {
+                    highlight(code)
+                };
+                page.push_str(&format!(
+                    "<span class=\"{}\">{}{}</span>",
+                    class, marker, code
+                ));
+            }
+            page.push_str("</pre>\n");
+        }
+        page.push_str("</details>\n");
+    }
+    page.push_str(&format!(
+        "<script>{}</script>\n</body>\n</html>\n",
+        HTML_SCRIPT
+    ));
+    page
+}
+
+/// A line of Python as HTML, highlighted.
+fn highlight(line: &str) -> String {
+    highlight::python(line)
+        .into_iter()
+        .map(|(kind, text)| match kind {
+            Kind::Keyword => format!("<span class=\"kw\">{}</span>", escape(text)),
+            Kind::String => format!("<span class=\"str\">{}</span>", escape(text)),
+            Kind::Comment => format!("<span class=\"com\">{}</span>", escape(text)),
+            Kind::Number => format!("<span class=\"num\">{}</span>", escape(text)),
+            Kind::Other => escape(text),
+        })
+        .collect()
+}
+
+/// Counts of the run, fixes and remaining issues by rule, and the
+/// remaining issues themselves folded away, short enough for a pull
+/// request comment.
+fn markdown(entries: &[Entry], patches: &BTreeMap<String, String>, duration: Duration) -> String {
+    let is_fixed = |entry: &&Entry| entry.outcome.outcome() == Outcome::Fixed;
+    let fixed = entries.iter().filter(is_fixed).count();
+    let files: BTreeSet<&str> = entries.iter().map(|entry| entry.file.as_str()).collect();
+    let mut rules: BTreeMap<&str, (usize, usize)> = BTreeMap::new();
+    for entry in entries {
+        let (fixed, remaining) = rules.entry(&entry.code).or_default();
+        if is_fixed(&entry) {
+            *fixed += 1;
+        } else {
+            *remaining += 1;
+        }
+    }
+
+    let mut md = String::from("### ruffer\n\n");
+    md.push_str("| | |\n|---|---:|\n");
+    md.push_str(&format!(
+        "| Files touched | {} of {} |\n",
+        patches.len(),
+        files.len()
+    ));
+    md.push_str(&format!(
+        "| Issues fixed | {} of {} |\n",
+        fixed,
+        entries.len()
+    ));
+    md.push_str(&format!(
+        "| Remaining issues | {} |\n",
+        entries.len() - fixed
+    ));
+    md.push_str(&format!(
+        "| Tokens (estimated) | {} |\n",
+        entries.iter().map(|entry| entry.tokens).sum::<usize>()
+    ));
+    md.push_str(&format!("| Time | {:.1}s |\n", duration.as_secs_f64()));
+
+    if !rules.is_empty() {
+        md.push_str("\n| Rule | Fixed | Remaining |\n|---|---:|---:|\n");
+        for (code, (fixed, remaining)) in &rules {
+            md.push_str(&format!("| {} | {} | {} |\n", code, fixed, remaining));
+        }
+    }
+
+    let remaining: Vec<&Entry> = entries.iter().filter(|entry| !is_fixed(entry)).collect();
+    if !remaining.is_empty() {
+        md.push_str("\n<details><summary>Remaining issues</summary>\n\n");
+        for entry in remaining {
+            md.push_str(&format!(
+                "- `{}:{}:{}` {} {} ({})\n",
+                entry.file,
+                entry.row,
+                entry.column,
+                entry.code,
+                entry.message,
+                entry.outcome.name()
+            ));
+        }
+        md.push_str("\n</details>\n");
+    }
+    md
+}
+
+/// `text` escaped for the message of a workflow command.
+fn workflow_data(text: &str) -> String {
+    text.replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+/// `text` escaped for a property of a workflow command.
+fn workflow_property(text: &str) -> String {
+    workflow_data(text).replace(':', "%3A").replace(',', "%2C")
+}
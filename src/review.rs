@@ -0,0 +1,80 @@
+use crate::issue::{display_path, Issue};
+
+/// The model's comment on one issue, for `--review`.
+pub struct Comment {
+    pub filename: String,
+    pub id: String,
+    pub code: String,
+    pub message: String,
+    pub row: u32,
+    /// The lines the issue spans.
+    pub lines: String,
+    /// How the issue should be fixed.
+    pub approach: String,
+    /// What to watch out for when fixing it, if anything.
+    pub caveats: Option<String>,
+}
+
+impl Comment {
+    /// Reads the `Approach:` and `Caveats:` parts of the model's `answer`.
+    /// An answer without them is taken as the approach as a whole.
+    pub fn new(filename: &str, issue: &Issue, lines: &str, answer: &str) -> Self {
+        let mut approach = Vec::new();
+        let mut caveats = Vec::new();
+        let mut current = &mut approach;
+        for line in answer.lines() {
+            let trimmed = line.trim().trim_start_matches(['*', '#', '-', ' ']);
+            if let Some(rest) = strip_label(trimmed, "Approach") {
+                current = &mut approach;
+                current.push(rest);
+            } else if let Some(rest) = strip_label(trimmed, "Caveats") {
+                current = &mut caveats;
+                current.push(rest);
+            } else {
+                current.push(line.trim());
+            }
+        }
+        let join = |parts: Vec<&str>| parts.join("\n").trim().to_string();
+        let caveats = join(caveats);
+        Comment {
+            filename: filename.to_string(),
+            id: issue.id.clone(),
+            code: issue.code.clone(),
+            message: issue.message.clone(),
+            row: issue.location.row,
+            lines: lines.to_string(),
+            approach: join(approach),
+            caveats: Some(caveats).filter(|caveats| {
+                !caveats.is_empty() && !caveats.trim_end_matches('.').eq_ignore_ascii_case("none")
+            }),
+        }
+    }
+}
+
+/// `line` after `label:`, with any Markdown emphasis around the label.
+fn strip_label<'a>(line: &'a str, label: &str) -> Option<&'a str> {
+    let rest = line.strip_prefix(label)?;
+    let rest = rest.trim_start_matches('*').strip_prefix(':')?;
+    Some(rest.trim_start_matches('*').trim())
+}
+
+/// The review document: a Markdown section per file, and in it one per
+/// issue with the offending lines, the suggested approach and caveats.
+pub fn render(comments: &[Comment]) -> String {
+    let mut out = String::from("# Ruffer review\n");
+    let mut current_file = None;
+    for comment in comments {
+        if current_file != Some(&comment.filename) {
+            out.push_str(&format!("\n## {}\n", display_path(&comment.filename)));
+            current_file = Some(&comment.filename);
+        }
+        out.push_str(&format!(
+            "\n### [{}] {} at line {}: {}\n\n```python\n{}\n```\n\n**Suggested approach:** {}\n",
+            comment.id, comment.code, comment.row, comment.message, comment.lines, comment.approach
+        ));
+        if let Some(caveats) = &comment.caveats {
+            out.push_str(&format!("\n**Caveats:** {}\n", caveats));
+        }
+    }
+    out
+}
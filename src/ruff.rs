@@ -0,0 +1,249 @@
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use sha2::{Digest, Sha256};
+
+use crate::cache::hex;
+
+/// Virtualenv directories looked for in the project and its parents.
+const VENV_DIRS: &[&str] = &[".venv", "venv"];
+
+#[cfg(windows)]
+const BINARY: &str = "ruff.exe";
+#[cfg(not(windows))]
+const BINARY: &str = "ruff";
+
+/// Finds the ruff to run: a pinned `version` is downloaded into
+/// `cache_dir` (once), otherwise the first ruff in a virtualenv of the
+/// project at `root` or its parents, in the active virtualenv, or on `PATH`.
+pub async fn locate(root: &Path, version: Option<&str>, cache_dir: &Path) -> io::Result<PathBuf> {
+    if let Some(version) = version {
+        return install(version, cache_dir).await;
+    }
+
+    find_in_environment(root, "ruff").ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            "Ruff not found in the project's virtualenv or on PATH; pass its path or use --ruff-version to download it",
+        )
+    })
+}
+
+/// Finds the executable `name` in a virtualenv of the project at `root` or
+/// its parents, in the active virtualenv, or on `PATH`, in that order.
+pub fn find_in_environment(root: &Path, name: &str) -> Option<PathBuf> {
+    let binary = if cfg!(windows) {
+        format!("{}.exe", name)
+    } else {
+        name.to_string()
+    };
+    let mut candidates = Vec::new();
+    let root = root.canonicalize().unwrap_or_else(|_| root.to_path_buf());
+    for dir in root.ancestors() {
+        for venv in VENV_DIRS {
+            candidates.push(venv_binary(&dir.join(venv), &binary));
+        }
+    }
+    if let Some(venv) = env::var_os("VIRTUAL_ENV") {
+        candidates.push(venv_binary(Path::new(&venv), &binary));
+    }
+    if let Some(path) = env::var_os("PATH") {
+        candidates.extend(env::split_paths(&path).map(|dir| dir.join(&binary)));
+    }
+
+    candidates.into_iter().find(|candidate| candidate.is_file())
+}
+
+fn venv_binary(venv: &Path, binary: &str) -> PathBuf {
+    if cfg!(windows) {
+        venv.join("Scripts").join(binary)
+    } else {
+        venv.join("bin").join(binary)
+    }
+}
+
+/// Downloads the release `version` of ruff from GitHub into `cache_dir`,
+/// unless it is there already. The archive has to match the SHA-256
+/// checksum published next to it, and the binary has to report exactly
+/// `version`. It is unpacked next to its place and moved there once
+/// checked, so a failed or concurrent install never leaves half a binary
+/// where the next run looks.
+async fn install(version: &str, cache_dir: &Path) -> io::Result<PathBuf> {
+    let dir = cache_dir.join("ruff").join(version);
+    if let Some(binary) = find_binary(&dir) {
+        return Ok(binary);
+    }
+
+    let target = target().ok_or_else(|| {
+        io::Error::other(format!(
+            "No ruff release for {}-{}; install ruff and pass its path",
+            env::consts::ARCH,
+            env::consts::OS
+        ))
+    })?;
+    let extension = if cfg!(windows) { "zip" } else { "tar.gz" };
+    let archive_name = format!("ruff-{}.{}", target, extension);
+    // Releases before 0.5 are tagged with a `v` prefix.
+    let mut download = None;
+    for tag in [version.to_string(), format!("v{}", version)] {
+        let url = format!(
+            "https://github.com/astral-sh/ruff/releases/download/{}/{}",
+            tag, archive_name
+        );
+        if let Some(archive) = fetch(&url).await? {
+            download = Some((url, archive));
+            break;
+        }
+    }
+    let (url, archive) = download.ok_or_else(|| {
+        io::Error::other(format!("Ruff {} has no release for {}", version, target))
+    })?;
+    let checksum = fetch(&format!("{}.sha256", url)).await?.ok_or_else(|| {
+        io::Error::other(format!(
+            "Ruff {} publishes no checksum of {}, refusing to install it",
+            version, archive_name
+        ))
+    })?;
+    verify_checksum(&archive, &String::from_utf8_lossy(&checksum), &archive_name)?;
+
+    let parent = cache_dir.join("ruff");
+    fs::create_dir_all(&parent)?;
+    let unpacked = parent.join(format!(".{}.{}", version, std::process::id()));
+    let _ = fs::remove_dir_all(&unpacked);
+    fs::create_dir_all(&unpacked)?;
+    if let Err(err) = unpack(&archive, &archive_name, &unpacked, version) {
+        let _ = fs::remove_dir_all(&unpacked);
+        return Err(err);
+    }
+    if fs::rename(&unpacked, &dir).is_err() {
+        // Another run installed it meanwhile.
+        let _ = fs::remove_dir_all(&unpacked);
+    }
+    find_binary(&dir)
+        .ok_or_else(|| io::Error::other(format!("{} contains no ruff binary", archive_name)))
+}
+
+/// The body of `url`, or nothing if there is none.
+async fn fetch(url: &str) -> io::Result<Option<Vec<u8>>> {
+    let response = reqwest::get(url).await.map_err(io::Error::other)?;
+    if !response.status().is_success() {
+        return Ok(None);
+    }
+    let body = response.bytes().await.map_err(io::Error::other)?;
+    Ok(Some(body.to_vec()))
+}
+
+/// Checks `archive` against `checksum`, the contents of the `.sha256` file
+/// published with it: the hash in hex, optionally followed by the name.
+fn verify_checksum(archive: &[u8], checksum: &str, archive_name: &str) -> io::Result<()> {
+    let expected = checksum.split_whitespace().next().unwrap_or_default();
+    let actual = hex(&Sha256::digest(archive));
+    if !expected.eq_ignore_ascii_case(&actual) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "{} doesn't match its published checksum (expected {}, got {}), refusing to install it",
+                archive_name, expected, actual
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// Unpacks `archive` into `dir` and checks that the ruff in it is
+/// `version`.
+fn unpack(archive: &[u8], archive_name: &str, dir: &Path, version: &str) -> io::Result<()> {
+    let archive_path = dir.join(archive_name);
+    fs::write(&archive_path, archive)?;
+    let status = Command::new("tar")
+        .arg("-xf")
+        .arg(&archive_path)
+        .arg("-C")
+        .arg(dir)
+        .status()?;
+    fs::remove_file(&archive_path)?;
+    if !status.success() {
+        return Err(io::Error::other(format!(
+            "Unpacking {} failed",
+            archive_name
+        )));
+    }
+
+    let binary = find_binary(dir)
+        .ok_or_else(|| io::Error::other(format!("{} contains no ruff binary", archive_name)))?;
+    let output = Command::new(&binary).arg("--version").output()?;
+    let reported = String::from_utf8_lossy(&output.stdout);
+    if reported_version(&reported) != Some(version.trim_start_matches('v')) {
+        return Err(io::Error::other(format!(
+            "Downloaded ruff reports version {}, expected {}",
+            reported.trim(),
+            version
+        )));
+    }
+    Ok(())
+}
+
+/// The version in the output of `ruff --version`, such as `ruff 0.6.9`.
+fn reported_version(output: &str) -> Option<&str> {
+    match output.split_whitespace().collect::<Vec<_>>()[..] {
+        ["ruff", version, ..] => Some(version),
+        _ => None,
+    }
+}
+
+/// The ruff binary in `dir`, which newer archives put in a subdirectory.
+fn find_binary(dir: &Path) -> Option<PathBuf> {
+    let direct = dir.join(BINARY);
+    if direct.is_file() {
+        return Some(direct);
+    }
+    fs::read_dir(dir)
+        .ok()?
+        .filter_map(Result::ok)
+        .map(|entry| entry.path().join(BINARY))
+        .find(|binary| binary.is_file())
+}
+
+/// Target triple of the ruff release that runs here.
+fn target() -> Option<&'static str> {
+    Some(match (env::consts::ARCH, env::consts::OS) {
+        ("x86_64", "linux") => "x86_64-unknown-linux-gnu",
+        ("aarch64", "linux") => "aarch64-unknown-linux-gnu",
+        ("x86_64", "macos") => "x86_64-apple-darwin",
+        ("aarch64", "macos") => "aarch64-apple-darwin",
+        ("x86_64", "windows") => "x86_64-pc-windows-msvc",
+        ("aarch64", "windows") => "aarch64-pc-windows-msvc",
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn verifies_the_published_checksum() {
+        let archive = b"archive";
+        let checksum = hex(&Sha256::digest(archive));
+
+        assert!(verify_checksum(archive, &checksum, "ruff.tar.gz").is_ok());
+        let published = format!("{}  ruff.tar.gz\n", checksum.to_uppercase());
+        assert!(verify_checksum(archive, &published, "ruff.tar.gz").is_ok());
+        assert!(verify_checksum(b"tampered", &published, "ruff.tar.gz").is_err());
+        assert!(verify_checksum(archive, "", "ruff.tar.gz").is_err());
+    }
+
+    #[test]
+    fn reads_the_exact_version() {
+        assert_eq!(reported_version("ruff 0.6.9\n"), Some("0.6.9"));
+        assert_eq!(
+            reported_version("ruff 0.6.9 (abc123 2024-10-01)"),
+            Some("0.6.9")
+        );
+        assert_eq!(reported_version("0.6.9"), None);
+        assert_ne!(reported_version("ruff 0.6.9"), Some("0.6"));
+    }
+}
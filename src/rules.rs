@@ -0,0 +1,163 @@
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use crate::issue::{self, Severity};
+
+/// Whether rule `code` is selected by `selector`, which like in ruff's
+/// `--select` is a rule code, a prefix of codes (`D1`, `ANN`) or `ALL`.
+/// Placeholder digits are allowed as well, so `D1xx` means `D1`.
+pub fn matches(code: &str, selector: &str) -> bool {
+    let prefix = selector.trim().trim_end_matches(['x', 'X']);
+    prefix.eq_ignore_ascii_case("ALL") || (!prefix.is_empty() && code.starts_with(prefix))
+}
+
+/// Whether `code` is selected by any of `selectors`, each of which may
+/// also be a comma-separated list.
+pub fn any_matches(code: &str, selectors: &[String]) -> bool {
+    selectors
+        .iter()
+        .flat_map(|selectors| selectors.split(','))
+        .any(|selector| matches(code, selector))
+}
+
+/// How the issues of a rule are dealt with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Strategy {
+    /// Only ruff's own fix, even an unsafe one; never the model.
+    Autofix,
+    /// Always the model, even if ruff has a fix.
+    Llm,
+    /// A `# noqa` comment instead of a fix.
+    Noqa,
+    /// Left alone.
+    Skip,
+}
+
+impl FromStr for Strategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "autofix" => Ok(Strategy::Autofix),
+            "llm" => Ok(Strategy::Llm),
+            "noqa" => Ok(Strategy::Noqa),
+            "skip" => Ok(Strategy::Skip),
+            other => Err(format!(
+                "unknown strategy {:?}, expected autofix, llm, noqa or skip",
+                other
+            )),
+        }
+    }
+}
+
+/// A `RULE=STRATEGY` entry of `--strategy`.
+pub struct RuleStrategy {
+    selector: String,
+    strategy: Strategy,
+}
+
+impl FromStr for RuleStrategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (selector, strategy) = s
+            .split_once('=')
+            .ok_or_else(|| format!("expected RULE=STRATEGY, got {:?}", s))?;
+        Ok(RuleStrategy {
+            selector: selector.trim().to_string(),
+            strategy: strategy.parse()?,
+        })
+    }
+}
+
+/// A `RULE=LEVEL` entry of `--severity`, or of `severity` in a package's
+/// `[tool.ruffer]`.
+pub struct RuleSeverity {
+    selector: String,
+    severity: Severity,
+}
+
+impl FromStr for RuleSeverity {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (selector, severity) = s
+            .split_once('=')
+            .ok_or_else(|| format!("expected RULE=LEVEL, got {:?}", s))?;
+        Ok(RuleSeverity {
+            selector: selector.trim().to_string(),
+            severity: severity.parse()?,
+        })
+    }
+}
+
+/// A `RULE=PATH` entry of `--rule-prompt`: the template of the prompts
+/// fixing the issues of a rule.
+pub struct RulePrompt {
+    pub selector: String,
+    pub path: PathBuf,
+}
+
+impl FromStr for RulePrompt {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (selector, path) = s
+            .split_once('=')
+            .ok_or_else(|| format!("expected RULE=PATH, got {:?}", s))?;
+        Ok(RulePrompt {
+            selector: selector.trim().to_string(),
+            path: PathBuf::from(path),
+        })
+    }
+}
+
+/// The value of those of `entries` whose selector is the most specific
+/// matching rule `code`, if any.
+pub fn entry_for<'a, T>(code: &str, entries: &'a [(String, T)]) -> Option<&'a T> {
+    most_specific(
+        code,
+        entries
+            .iter()
+            .map(|(selector, value)| (selector.as_str(), value)),
+    )
+}
+
+/// The strategy for rule `code`: that of the most specific selector
+/// matching it, if any.
+pub fn strategy_for(code: &str, strategies: &[RuleStrategy]) -> Option<Strategy> {
+    most_specific(
+        code,
+        strategies
+            .iter()
+            .map(|entry| (entry.selector.as_str(), entry.strategy)),
+    )
+}
+
+/// The severity of rule `code`: that `severities` give it with the most
+/// specific selector matching it, or else its usual one.
+pub fn severity_for(code: &str, severities: &[RuleSeverity]) -> Severity {
+    most_specific(
+        code,
+        severities
+            .iter()
+            .map(|entry| (entry.selector.as_str(), entry.severity)),
+    )
+    .unwrap_or_else(|| issue::severity(code))
+}
+
+/// The value of the most specific of `entries` whose selector matches
+/// `code`: the longest prefix, and `ALL` least of all.
+fn most_specific<'a, T>(code: &str, entries: impl Iterator<Item = (&'a str, T)>) -> Option<T> {
+    entries
+        .filter(|(selector, _)| matches(code, selector))
+        .max_by_key(|(selector, _)| {
+            let prefix = selector.trim_end_matches(['x', 'X']);
+            if prefix.eq_ignore_ascii_case("ALL") {
+                0
+            } else {
+                prefix.len()
+            }
+        })
+        .map(|(_, value)| value)
+}
@@ -0,0 +1,145 @@
+/// Openers of chatty preambles models put before the code despite being
+/// told not to.
+const PREAMBLE_OPENERS: &[&str] = &[
+    "here is",
+    "here's",
+    "sure",
+    "certainly",
+    "below is",
+    "the fixed",
+    "the corrected",
+    "i've",
+    "i have",
+];
+
+/// Strips what the model wraps around the code: Markdown code fences and
+/// any prose before or after them, or a leading "Here is the fixed code:"
+/// line when there are no fences.
+pub fn clean(response: &str) -> String {
+    let lines: Vec<&str> = response.split_inclusive('\n').collect();
+    let is_fence = |line: &str| line.trim_start().starts_with("```");
+
+    if let Some(open) = lines.iter().position(|line| is_fence(line)) {
+        let body = &lines[open + 1..];
+        let close = body
+            .iter()
+            .rposition(|line| is_fence(line))
+            .unwrap_or(body.len());
+        return body[..close].concat();
+    }
+
+    let mut start = 0;
+    while start < lines.len() && is_preamble(lines[start]) {
+        start += 1;
+    }
+    // An empty line usually separates the preamble from the code.
+    if start > 0 && lines.get(start).is_some_and(|line| line.trim().is_empty()) {
+        start += 1;
+    }
+    lines[start..].concat()
+}
+
+fn is_preamble(line: &str) -> bool {
+    let lower = line.trim().to_lowercase();
+    PREAMBLE_OPENERS
+        .iter()
+        .any(|opener| lower.starts_with(opener))
+        && (lower.ends_with(':') || lower.ends_with('.'))
+}
+
+/// Detects answers that were cut off: brackets left open that weren't open
+/// in the original, or a last line that obviously continues somewhere.
+pub fn check_complete(original: &str, fixed: &str) -> Result<(), String> {
+    if fixed.trim().is_empty() && !original.trim().is_empty() {
+        return Err("The answer was empty; return the complete fixed code.".to_string());
+    }
+
+    let (original_depth, _) = scan(original);
+    let (fixed_depth, fixed_in_string) = scan(fixed);
+    if fixed_in_string && !scan(original).1 {
+        return Err(
+            "The answer ends inside a string literal and looks truncated; return the complete fixed code."
+                .to_string(),
+        );
+    }
+    if fixed_depth > original_depth {
+        return Err(
+            "The answer leaves brackets open and looks truncated; return the complete fixed code."
+                .to_string(),
+        );
+    }
+
+    let last_line = |text: &str| {
+        text.lines()
+            .rev()
+            .find(|line| !line.trim().is_empty())
+            .map(|line| line.trim_end().to_string())
+            .unwrap_or_default()
+    };
+    let fixed_last = last_line(fixed);
+    if dangles(&fixed_last) && !dangles(&last_line(original)) {
+        return Err(format!(
+            "The answer stops in the middle of a statement (`{}`) and looks truncated; return the complete fixed code.",
+            fixed_last.trim()
+        ));
+    }
+
+    Ok(())
+}
+
+/// Whether a line can't be the last one of a complete piece of code.
+fn dangles(line: &str) -> bool {
+    let code = line.split(" #").next().unwrap_or_default().trim_end();
+    [",", "\\", "(", "[", "{", "=", "+", "-", "/", " and", " or"]
+        .iter()
+        .any(|end| code.ends_with(end))
+}
+
+/// Net bracket depth of Python source at its end, ignoring brackets in
+/// strings and comments, and whether it ends inside a string.
+fn scan(source: &str) -> (i64, bool) {
+    let chars: Vec<char> = source.chars().collect();
+    let mut depth = 0i64;
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            '#' => {
+                while i < chars.len() && chars[i] != '\n' {
+                    i += 1;
+                }
+            }
+            quote @ ('"' | '\'') => {
+                let triple = chars.get(i + 1) == Some(&quote) && chars.get(i + 2) == Some(&quote);
+                i += if triple { 3 } else { 1 };
+                let mut closed = false;
+                while i < chars.len() {
+                    if chars[i] == '\\' {
+                        i += 2;
+                        continue;
+                    }
+                    if chars[i] == '\n' && !triple {
+                        break;
+                    }
+                    if chars[i] == quote
+                        && (!triple
+                            || (chars.get(i + 1) == Some(&quote)
+                                && chars.get(i + 2) == Some(&quote)))
+                    {
+                        i += if triple { 2 } else { 0 };
+                        closed = true;
+                        break;
+                    }
+                    i += 1;
+                }
+                if !closed && i >= chars.len() {
+                    return (depth, true);
+                }
+            }
+            '(' | '[' | '{' => depth += 1,
+            ')' | ']' | '}' => depth -= 1,
+            _ => {}
+        }
+        i += 1;
+    }
+    (depth, false)
+}
@@ -0,0 +1,187 @@
+//! Issues read from a SARIF 2.1.0 log, with `--from-sarif`, so that any
+//! scanner writing one, such as semgrep or CodeQL, can drive the fixing.
+//!
+//! Each result is an issue with the rule's id as its code, where its first
+//! location is. Results that aren't failures, such as those of kind `pass`
+//! or level `none`, and those suppressed are left out. A fix the scanner
+//! proposes with regions of lines and columns is kept as an unsafe fix.
+
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+use serde_json::Value;
+
+use crate::issue::{Applicability, Edit, Fix, Issue, Location};
+
+/// The issues of the SARIF log `source`, `-` for stdin.
+pub fn read(source: &Path) -> io::Result<Vec<Issue>> {
+    let (name, data) = if source == Path::new("-") {
+        let mut data = String::new();
+        io::stdin().read_to_string(&mut data)?;
+        ("stdin".to_string(), data)
+    } else {
+        (source.display().to_string(), fs::read_to_string(source)?)
+    };
+    let invalid = |err: String| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Invalid SARIF in {}: {}", name, err),
+        )
+    };
+    let log: Value = serde_json::from_str(&data).map_err(|err| invalid(err.to_string()))?;
+    let runs = log["runs"]
+        .as_array()
+        .ok_or_else(|| invalid("no runs".to_string()))?;
+    let mut issues = Vec::new();
+    for run in runs {
+        for result in run["results"].as_array().into_iter().flatten() {
+            if let Some(issue) = to_issue(run, result) {
+                issues.push(issue);
+            }
+        }
+    }
+    Ok(issues)
+}
+
+fn to_issue(run: &Value, result: &Value) -> Option<Issue> {
+    if result["kind"].as_str().is_some_and(|kind| kind != "fail")
+        || result["level"].as_str() == Some("none")
+        || result["suppressions"]
+            .as_array()
+            .is_some_and(|suppressions| !suppressions.is_empty())
+    {
+        return None;
+    }
+    let rules = &run["tool"]["driver"]["rules"];
+    let rule = result["ruleIndex"]
+        .as_u64()
+        .and_then(|index| rules.get(index as usize))
+        .or_else(|| {
+            let id = result["ruleId"].as_str()?;
+            rules.as_array()?.iter().find(|rule| rule["id"] == id)
+        });
+    let code = result["ruleId"]
+        .as_str()
+        .or_else(|| result["rule"]["id"].as_str())
+        .or_else(|| rule?["id"].as_str())
+        .unwrap_or("sarif")
+        .to_string();
+    let message = result["message"]["text"]
+        .as_str()
+        .or_else(|| {
+            let id = result["message"]["id"].as_str()?;
+            rule?["messageStrings"][id]["text"].as_str()
+        })
+        .or_else(|| rule?["shortDescription"]["text"].as_str())
+        .unwrap_or(&code)
+        .to_string();
+    let location = &result["locations"][0]["physicalLocation"];
+    let filename = path(run, &location["artifactLocation"])?;
+    let region = &location["region"];
+    let (start, end) = span(region).unwrap_or((Location { row: 1, column: 1 }, None));
+    Some(Issue {
+        fix: fix(run, result, &filename),
+        filename: filename.display().to_string(),
+        code,
+        message,
+        location: start,
+        end_location: end,
+        noqa_row: None,
+        url: rule
+            .and_then(|rule| rule["helpUri"].as_str())
+            .map(str::to_string),
+        id: String::new(),
+    })
+}
+
+/// Where a region starts, and ends if it says so; columns are one-based
+/// and the end is past the last character, as ruff's.
+fn span(region: &Value) -> Option<(Location, Option<Location>)> {
+    let row = region["startLine"].as_u64()? as u32;
+    let start = Location {
+        row,
+        column: region["startColumn"].as_u64().unwrap_or(1) as u32,
+    };
+    let end = region["endColumn"].as_u64().map(|column| Location {
+        row: region["endLine"].as_u64().map_or(row, |row| row as u32),
+        column: column as u32,
+    });
+    Some((start, end))
+}
+
+/// The first fix of `result` changing `filename`, if every replacement of
+/// it says which lines and columns it replaces.
+fn fix(run: &Value, result: &Value, filename: &Path) -> Option<Fix> {
+    let proposed = &result["fixes"][0];
+    let change = proposed["artifactChanges"]
+        .as_array()?
+        .iter()
+        .find(|change| path(run, &change["artifactLocation"]).as_deref() == Some(filename))?;
+    let edits = change["replacements"]
+        .as_array()?
+        .iter()
+        .map(|replacement| {
+            let (location, end_location) = span(&replacement["deletedRegion"])?;
+            Some(Edit {
+                content: Some(
+                    replacement["insertedContent"]["text"]
+                        .as_str()
+                        .unwrap_or_default()
+                        .to_string(),
+                ),
+                location,
+                end_location: end_location?,
+            })
+        })
+        .collect::<Option<Vec<_>>>()?;
+    Some(Fix {
+        applicability: Applicability::Unsafe,
+        message: proposed["description"]["text"].as_str().map(str::to_string),
+        edits,
+    })
+}
+
+/// The path of an artifact location: its URI, resolved against the base
+/// it names in the run's `originalUriBaseIds`, or else relative to the
+/// current directory, where scanners are usually run.
+fn path(run: &Value, artifact: &Value) -> Option<PathBuf> {
+    let uri = artifact["uri"].as_str()?;
+    let path = PathBuf::from(decode(uri.strip_prefix("file://").unwrap_or(uri)));
+    if path.is_absolute() {
+        return Some(path);
+    }
+    let base = artifact["uriBaseId"]
+        .as_str()
+        .and_then(|id| run["originalUriBaseIds"][id]["uri"].as_str());
+    Some(match base {
+        Some(base) => {
+            PathBuf::from(decode(base.strip_prefix("file://").unwrap_or(base))).join(path)
+        }
+        None => path,
+    })
+}
+
+/// `uri` with its percent-escapes, such as `%20`, decoded.
+fn decode(uri: &str) -> String {
+    let bytes = uri.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = (bytes[i] == b'%')
+            .then(|| uri.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
@@ -0,0 +1,82 @@
+use crate::issue::{display_path, Issue};
+use crate::merge;
+use crate::patch;
+
+/// Whether ruff's rule `code` is one of flake8-bandit's: `S` followed by
+/// digits, unlike `SIM` or `SLF`.
+pub fn is_security_rule(code: &str) -> bool {
+    code.strip_prefix('S')
+        .is_some_and(|rest| rest.starts_with(|c: char| c.is_ascii_digit()))
+}
+
+/// A fix of a security issue, put up for review instead of being written.
+pub struct Proposal {
+    pub filename: String,
+    pub id: String,
+    pub code: String,
+    pub message: String,
+    pub row: u32,
+    /// The model's explanation of the risk and of what the fix changes.
+    pub risk: String,
+    /// The file content the fix was made for.
+    before: String,
+    /// That content with the fix.
+    after: String,
+}
+
+impl Proposal {
+    /// A proposal without an explanation yet.
+    pub fn new(filename: &str, issue: &Issue, before: &str, after: String) -> Self {
+        Proposal {
+            filename: filename.to_string(),
+            id: issue.id.clone(),
+            code: issue.code.clone(),
+            message: issue.message.clone(),
+            row: issue.location.row,
+            risk: String::new(),
+            before: before.to_string(),
+            after,
+        }
+    }
+
+    /// Moves the fix onto `content`, the file as the run's other fixes left
+    /// it, so the patch applies to the file on disk. A fix that conflicts
+    /// with those fixes stays a patch against the content it was made for.
+    pub fn rebase(&mut self, content: &str) {
+        if let Ok(merged) = merge::merge(&self.before, content, &self.after) {
+            self.before = content.to_string();
+            self.after = merged;
+        }
+    }
+
+    /// The fix as a unified diff, with paths relative to the working
+    /// directory like `git diff` makes them.
+    pub fn patch(&self) -> String {
+        patch::unified(&display_path(&self.filename), &self.before, &self.after)
+    }
+}
+
+/// The review file: every proposal as a patch of its own, preceded by `#`
+/// lines describing the issue and the risk. `git apply` and `patch` skip
+/// those lines, so the fixes a reviewer keeps can be applied directly.
+pub fn render(proposals: &[Proposal]) -> String {
+    let mut out = String::from(
+        "# Fixes of security issues proposed by ruffer. None of them has been applied;\n# review each one and apply those you accept, e.g. with `git apply`.\n",
+    );
+    for proposal in proposals {
+        out.push_str(&format!(
+            "\n# [{}] {} at {}:{}: {}\n#\n",
+            proposal.id,
+            proposal.code,
+            display_path(&proposal.filename),
+            proposal.row,
+            proposal.message
+        ));
+        for line in proposal.risk.lines() {
+            out.push_str(format!("# {}", line).trim_end());
+            out.push('\n');
+        }
+        out.push_str(&proposal.patch());
+    }
+    out
+}
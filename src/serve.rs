@@ -0,0 +1,256 @@
+//! `ruffer serve`: a local HTTP API over a process that stays up, so that
+//! editors and other services share its cache of fixes and its limit on
+//! requests to the model instead of starting a run each time.
+//!
+//! - `POST /fix` takes `{"filename": ..., "content": ..., "issues": [...]}`
+//!   and answers `{"content": ..., "report": ...}`: the code with the fixes
+//!   made, and the `json` report of the issues. `issues` are in the format
+//!   of `ruff check --output-format json` and may be left out to have ruff
+//!   find them. No file is touched.
+//! - `GET /status` answers how long the server has been up and the
+//!   requests it has served.
+//!
+//! Each request is fixed by a run made afresh from the command line, as
+//! with `--stdin-filename`.
+
+use std::convert::Infallible;
+use std::ffi::OsString;
+use std::io::{self, Cursor, Write};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, StatusCode};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use structopt::StructOpt;
+use tokio::sync::Semaphore;
+
+use crate::cache::ResponseCache;
+use crate::console::Console;
+use crate::fixer::RuffFixer;
+use crate::issue::Issue;
+use crate::provider::{Completion, CompletionRequest, FixProvider};
+
+/// Options of `ruffer serve`: those of the fixing runs, and how to serve.
+#[derive(StructOpt)]
+pub struct ServeOptions {
+    #[structopt(flatten)]
+    fixer: RuffFixer,
+
+    #[structopt(long, default_value = "127.0.0.1:8765", help = "Address to listen on")]
+    listen: SocketAddr,
+
+    #[structopt(
+        long,
+        default_value = "4",
+        help = "Most requests to the model at a time, across all the requests served"
+    )]
+    max_concurrent_requests: usize,
+}
+
+#[derive(Deserialize)]
+struct FixRequest {
+    filename: String,
+    content: String,
+    #[serde(default)]
+    issues: Option<Vec<Issue>>,
+}
+
+/// What the requests served share.
+struct Server {
+    /// The arguments to parse again for every run.
+    args: Vec<OsString>,
+    provider: Arc<dyn FixProvider>,
+    cache: Option<Arc<ResponseCache>>,
+    console: Console,
+    started: Instant,
+    served: AtomicU64,
+    in_flight: AtomicU64,
+    failed: AtomicU64,
+}
+
+impl ServeOptions {
+    pub fn init_logging(&self) -> io::Result<()> {
+        self.fixer.init_logging()
+    }
+
+    /// Serves fixes made with the OpenAI API, until Ctrl-C. `args` are the
+    /// arguments the options were parsed from, after the subcommand, to
+    /// parse again for every run.
+    pub async fn run(self, args: Vec<OsString>) -> io::Result<()> {
+        let provider = self.fixer.provider();
+        self.run_with(args, provider).await
+    }
+
+    /// Serves fixes made with answers from `provider`; see [`Self::run`].
+    pub async fn run_with(
+        self,
+        args: Vec<OsString>,
+        provider: Arc<dyn FixProvider>,
+    ) -> io::Result<()> {
+        let console = self.fixer.console();
+        let server = Arc::new(Server {
+            args,
+            provider: Arc::new(Limited {
+                provider,
+                permits: Semaphore::new(self.max_concurrent_requests.max(1)),
+            }),
+            cache: self.fixer.open_cache(&console),
+            console: console.clone(),
+            started: Instant::now(),
+            served: AtomicU64::new(0),
+            in_flight: AtomicU64::new(0),
+            failed: AtomicU64::new(0),
+        });
+        let make_service = make_service_fn(move |_| {
+            let server = Arc::clone(&server);
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request| {
+                    Arc::clone(&server).handle(request)
+                }))
+            }
+        });
+        let bound = hyper::Server::try_bind(&self.listen).map_err(|err| {
+            io::Error::new(
+                io::ErrorKind::AddrInUse,
+                format!("Error listening on {}: {}", self.listen, err),
+            )
+        })?;
+        console.status(format!(
+            "Serving fixes on http://{}; POST /fix, GET /status",
+            self.listen
+        ));
+        bound
+            .serve(make_service)
+            .with_graceful_shutdown(async {
+                let _ = tokio::signal::ctrl_c().await;
+            })
+            .await
+            .map_err(io::Error::other)
+    }
+}
+
+impl Server {
+    async fn handle(self: Arc<Self>, request: Request<Body>) -> Result<Response<Body>, Infallible> {
+        let response = match (request.method(), request.uri().path()) {
+            (&Method::GET, "/status") => respond(StatusCode::OK, self.status()),
+            (&Method::POST, "/fix") => {
+                self.served.fetch_add(1, Ordering::SeqCst);
+                self.in_flight.fetch_add(1, Ordering::SeqCst);
+                let result = Arc::clone(&self).fix(request.into_body()).await;
+                self.in_flight.fetch_sub(1, Ordering::SeqCst);
+                match result {
+                    Ok(fixed) => respond(StatusCode::OK, fixed),
+                    Err((status, message)) => {
+                        self.failed.fetch_add(1, Ordering::SeqCst);
+                        self.console.error(&message);
+                        respond(status, json!({ "error": message }))
+                    }
+                }
+            }
+            (_, "/status" | "/fix") => respond(
+                StatusCode::METHOD_NOT_ALLOWED,
+                json!({ "error": format!("{} not allowed", request.method()) }),
+            ),
+            (_, path) => respond(
+                StatusCode::NOT_FOUND,
+                json!({ "error": format!("No endpoint {}", path) }),
+            ),
+        };
+        Ok(response)
+    }
+
+    fn status(&self) -> Value {
+        json!({
+            "uptime_seconds": self.started.elapsed().as_secs_f64(),
+            "served": self.served.load(Ordering::SeqCst),
+            "in_flight": self.in_flight.load(Ordering::SeqCst),
+            "failed": self.failed.load(Ordering::SeqCst),
+            "cache": self.cache.is_some(),
+        })
+    }
+
+    async fn fix(self: Arc<Self>, body: Body) -> Result<Value, (StatusCode, String)> {
+        let body = hyper::body::to_bytes(body)
+            .await
+            .map_err(|err| (StatusCode::BAD_REQUEST, err.to_string()))?;
+        let request: FixRequest = serde_json::from_slice(&body).map_err(|err| {
+            (
+                StatusCode::BAD_REQUEST,
+                format!("Invalid fix request: {}", err),
+            )
+        })?;
+
+        let out = Buffer::default();
+        let mut fixer = ServeOptions::from_iter(&self.args)
+            .fixer
+            .fixing_content(request.filename, request.issues)
+            .piping(Cursor::new(request.content), out.clone());
+        if let Some(cache) = &self.cache {
+            fixer = fixer.sharing_cache(Arc::clone(cache));
+        }
+        let fixer = Arc::new(fixer);
+        let started = Instant::now();
+        Arc::clone(&fixer)
+            .run_with(Arc::clone(&self.provider))
+            .await
+            .map_err(|err| match err.kind() {
+                io::ErrorKind::InvalidInput => (StatusCode::BAD_REQUEST, err.to_string()),
+                _ => (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()),
+            })?;
+        Ok(json!({
+            "content": out.text(),
+            "report": fixer.json_report(started.elapsed()),
+        }))
+    }
+}
+
+fn respond(status: StatusCode, body: Value) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header("content-type", "application/json")
+        .body(Body::from(body.to_string() + "\n"))
+        .expect("the response is well formed")
+}
+
+/// Where a run writes the fixed code, to be answered with.
+#[derive(Clone, Default)]
+pub struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+impl Buffer {
+    /// The code written so far.
+    pub fn text(&self) -> String {
+        String::from_utf8(self.0.lock().unwrap().clone())
+            .expect("the fixed code is written as UTF-8")
+    }
+}
+
+impl Write for Buffer {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(data);
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A provider taking at most as many requests at a time as it has permits,
+/// the others waiting their turn.
+struct Limited {
+    provider: Arc<dyn FixProvider>,
+    permits: Semaphore,
+}
+
+impl FixProvider for Limited {
+    fn complete<'a>(&'a self, request: &'a CompletionRequest) -> Completion<'a> {
+        Box::pin(async move {
+            let _permit = self.permits.acquire().await?;
+            self.provider.complete(request).await
+        })
+    }
+}
@@ -0,0 +1,129 @@
+//! Progress of a fixing run, kept on disk so that `--resume` can pick up
+//! where a crashed or interrupted run stopped.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+
+use crate::atomic;
+use crate::journal::digest;
+
+/// Where progress is kept unless `--state-file` says otherwise.
+pub const DEFAULT_STATE_FILE: &str = ".ruffer_state.json";
+
+/// What became of an issue.
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    /// Fixed by the model or ruff, or suppressed with a `noqa` comment.
+    Fixed,
+    /// Skipped or left for someone to review.
+    LeftAlone,
+    /// The model didn't come up with an acceptable fix, or couldn't be
+    /// asked.
+    Failed,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct FileProgress {
+    /// Hash of the file as the run left it, once it was written.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    finished: Option<String>,
+    /// Outcomes by issue ID.
+    #[serde(default)]
+    issues: BTreeMap<String, Outcome>,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct Progress {
+    files: BTreeMap<String, FileProgress>,
+}
+
+/// The progress of the current run, saved after every change.
+pub struct RunState {
+    path: PathBuf,
+    progress: Mutex<Progress>,
+}
+
+impl RunState {
+    /// Progress of a fresh run, replacing whatever `path` holds once
+    /// anything is recorded.
+    pub fn start(path: &Path) -> RunState {
+        RunState {
+            path: path.to_path_buf(),
+            progress: Mutex::new(Progress::default()),
+        }
+    }
+
+    /// Progress of the run that last saved to `path`, or of a fresh run if
+    /// there is none.
+    pub fn resume(path: &Path) -> io::Result<RunState> {
+        let progress = match fs::read_to_string(path) {
+            Ok(data) => serde_json::from_str(&data).map_err(|err| {
+                io::Error::other(format!("Invalid state file {}: {}", path.display(), err))
+            })?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Progress::default(),
+            Err(err) => return Err(err),
+        };
+        Ok(RunState {
+            path: path.to_path_buf(),
+            progress: Mutex::new(progress),
+        })
+    }
+
+    /// Whether an earlier run finished `filename` without failures and
+    /// left it with `content`, so there is nothing more to do.
+    pub fn finished(&self, filename: &str, content: &str) -> bool {
+        let progress = self.progress.lock().unwrap();
+        progress.files.get(&key(filename)).is_some_and(|file| {
+            file.finished.as_deref() == Some(digest(content).as_str())
+                && !file
+                    .issues
+                    .values()
+                    .any(|outcome| *outcome == Outcome::Failed)
+        })
+    }
+
+    pub fn record(&self, filename: &str, id: &str, outcome: Outcome) -> io::Result<()> {
+        let mut progress = self.progress.lock().unwrap();
+        let file = progress.files.entry(key(filename)).or_default();
+        file.issues.insert(id.to_string(), outcome);
+        file.finished = None;
+        self.save(&progress)
+    }
+
+    /// Records that `filename` was written with `content`.
+    pub fn finish(&self, filename: &str, content: &str) -> io::Result<()> {
+        let mut progress = self.progress.lock().unwrap();
+        progress.files.entry(key(filename)).or_default().finished = Some(digest(content));
+        self.save(&progress)
+    }
+
+    /// How many issues failed.
+    pub fn failed(&self) -> usize {
+        let progress = self.progress.lock().unwrap();
+        progress
+            .files
+            .values()
+            .flat_map(|file| file.issues.values())
+            .filter(|outcome| **outcome == Outcome::Failed)
+            .count()
+    }
+
+    fn save(&self, progress: &Progress) -> io::Result<()> {
+        let data = serde_json::to_string_pretty(progress)?;
+        atomic::write(&self.path, data.as_bytes())
+    }
+}
+
+/// Files are recorded by their canonical path, so that the state file
+/// doesn't depend on how the root folder was given.
+fn key(filename: &str) -> String {
+    fs::canonicalize(filename)
+        .map(|path| path.to_string_lossy().into_owned())
+        .unwrap_or_else(|_| filename.to_string())
+}
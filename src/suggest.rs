@@ -0,0 +1,74 @@
+//! The review comments of `--suggest-on-pr`: the fixes of a file as
+//! GitHub suggestions, each replacing a span of lines of the file as it is
+//! in the pull request, to be applied with one click.
+
+use similar::TextDiff;
+
+use crate::report::Fixed;
+
+/// Lines `start` to `end` of a file, one-based and inclusive, and what
+/// the fixes replace them with.
+pub struct Suggestion {
+    pub start: u32,
+    pub end: u32,
+    pub replacement: String,
+}
+
+impl Suggestion {
+    /// The comment suggesting the replacement, saying which of the issues
+    /// `fixed` in the file it fixes.
+    pub fn body(&self, fixed: &[Fixed]) -> String {
+        let mut body = String::new();
+        for fixed in fixed
+            .iter()
+            .filter(|fixed| (self.start..=self.end).contains(&fixed.row))
+        {
+            body.push_str(&format!("{}: {}\n", fixed.code, fixed.message));
+        }
+        if body.is_empty() {
+            body.push_str("Fixed by ruffer\n");
+        }
+        body.push_str("\n```suggestion\n");
+        body.push_str(&self.replacement);
+        if !self.replacement.is_empty() && !self.replacement.ends_with('\n') {
+            body.push('\n');
+        }
+        body.push_str("```\n");
+        body
+    }
+}
+
+/// The suggestions turning `before` into `after`, one per run of changed
+/// lines. Lines only inserted are suggested along with the line before
+/// them, or after them at the top of the file, since a suggestion replaces
+/// at least one line.
+pub fn suggestions(before: &str, after: &str) -> Vec<Suggestion> {
+    let old: Vec<&str> = before.split_inclusive('\n').collect();
+    let diff = TextDiff::from_lines(before, after);
+    let new: Vec<&str> = after.split_inclusive('\n').collect();
+    let mut suggestions = Vec::new();
+    for group in diff.grouped_ops(0) {
+        let (Some(first), Some(last)) = (group.first(), group.last()) else {
+            continue;
+        };
+        let mut old_range = first.old_range().start..last.old_range().end;
+        let mut new_range = first.new_range().start..last.new_range().end;
+        if old_range.is_empty() {
+            if old.is_empty() {
+                continue;
+            } else if old_range.start > 0 {
+                old_range.start -= 1;
+                new_range.start -= 1;
+            } else {
+                old_range.end += 1;
+                new_range.end += 1;
+            }
+        }
+        suggestions.push(Suggestion {
+            start: old_range.start as u32 + 1,
+            end: old_range.end as u32,
+            replacement: new[new_range].concat(),
+        });
+    }
+    suggestions
+}
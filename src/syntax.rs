@@ -0,0 +1,25 @@
+use rustpython_parser::{parse, Mode};
+
+use crate::language::Language;
+
+/// Parses `content` as a Python module, describing the first syntax error
+/// if there is one. The code of other languages passes.
+pub fn check(filename: &str, content: &str) -> Result<(), String> {
+    if Language::of(filename) != Language::Python {
+        return Ok(());
+    }
+    parse(content, Mode::Module, filename)
+        .map(|_| ())
+        .map_err(|err| {
+            let offset = usize::from(err.offset).min(content.len());
+            let line = content.as_bytes()[..offset]
+                .iter()
+                .filter(|&&b| b == b'\n')
+                .count()
+                + 1;
+            format!(
+                "The fixed code is not valid Python: syntax error at line {}: {}",
+                line, err.error
+            )
+        })
+}
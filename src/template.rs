@@ -0,0 +1,210 @@
+//! Prompts read from template files, with `--prompt-template` and
+//! `--system-prompt-template`, so that the prompting can be tuned without
+//! changing ruffer.
+//!
+//! Templates are rendered by Handlebars in strict mode, limited to what
+//! prompts need: `{{name}}` is replaced by the variable `name`, and
+//! `{{#if name}}...{{else}}...{{/if}}` keeps its first part when the
+//! variable isn't empty and the second, which may be left out, when it is,
+//! as `{{#unless}}` does the other way round. Values are put in as they
+//! are, without escaping, and an unknown variable is an error when the
+//! template is read rather than when it is used.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use handlebars::template::{HelperTemplate, Parameter, TemplateElement};
+use handlebars::{no_escape, Handlebars, Path as Variable};
+use serde_json::{Map, Value};
+
+use crate::rules;
+
+/// The templates of a run, once read.
+pub struct Templates {
+    /// That of `--prompt-template`.
+    pub issue: Option<Template>,
+    /// Those of `--rule-prompt`, by rule selector.
+    pub rules: Vec<(String, Template)>,
+    /// That of `--system-prompt-template`.
+    pub system: Option<Template>,
+}
+
+impl Templates {
+    /// The template of the prompts fixing the issues of rule `code`: that
+    /// of the most specific `--rule-prompt` matching it, or else that of
+    /// `--prompt-template`, if any.
+    pub fn for_rule(&self, code: &str) -> Option<&Template> {
+        rules::entry_for(code, &self.rules).or(self.issue.as_ref())
+    }
+}
+
+pub struct Template {
+    registry: Handlebars<'static>,
+    /// The variables the template may use, all of which are given a value
+    /// when it's rendered, as strict mode wants.
+    variables: Vec<String>,
+}
+
+/// The name of the one template of a registry.
+const NAME: &str = "prompt";
+
+/// The block helpers a template may use.
+const BLOCKS: &[&str] = &["if", "unless"];
+
+impl Template {
+    /// Reads the template in `path`, which may use `variables`.
+    pub fn load(path: &Path, variables: &[&str]) -> io::Result<Template> {
+        let text = fs::read_to_string(path)?;
+        Template::parse(&text, variables).map_err(|err| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Invalid template {}: {}", path.display(), err),
+            )
+        })
+    }
+
+    pub fn parse(text: &str, variables: &[&str]) -> Result<Template, String> {
+        let template =
+            handlebars::Template::compile(text).map_err(|err| err.reason().to_string())?;
+        check_elements(&template.elements, variables)?;
+        let mut registry = Handlebars::new();
+        registry.set_strict_mode(true);
+        registry.register_escape_fn(no_escape);
+        registry.register_template(NAME, template);
+        Ok(Template {
+            registry,
+            variables: variables.iter().map(|name| name.to_string()).collect(),
+        })
+    }
+
+    /// The template with `values` put in; variables without a value are
+    /// empty.
+    pub fn render(&self, values: &[(&str, &str)]) -> String {
+        let mut data: Map<String, Value> = self
+            .variables
+            .iter()
+            .map(|name| (name.clone(), Value::from("")))
+            .collect();
+        for (name, value) in values {
+            data.insert(name.to_string(), Value::from(*value));
+        }
+        self.registry
+            .render(NAME, &data)
+            .expect("the variables and helpers of the template were checked when it was read")
+    }
+}
+
+/// Checks that `elements` use only `variables` and the helpers of
+/// [`BLOCKS`], so that a template fails when it's read rather than in the
+/// middle of a run.
+fn check_elements(elements: &[TemplateElement], variables: &[&str]) -> Result<(), String> {
+    for element in elements {
+        match element {
+            TemplateElement::RawString(_) | TemplateElement::Comment(_) => {}
+            TemplateElement::Expression(expression)
+            | TemplateElement::HtmlExpression(expression) => {
+                if !expression.params.is_empty() || !expression.hash.is_empty() {
+                    return Err(format!("unknown helper `{}`", name_of(&expression.name)));
+                }
+                check_variable(&expression.name, variables)?;
+            }
+            TemplateElement::HelperBlock(block) => check_block(block, variables)?,
+            TemplateElement::DecoratorExpression(_)
+            | TemplateElement::DecoratorBlock(_)
+            | TemplateElement::PartialExpression(_)
+            | TemplateElement::PartialBlock(_) => {
+                return Err("decorators and partials aren't supported".to_string());
+            }
+            _ => return Err("unsupported template syntax".to_string()),
+        }
+    }
+    Ok(())
+}
+
+fn check_block(block: &HelperTemplate, variables: &[&str]) -> Result<(), String> {
+    let name = name_of(&block.name);
+    if !BLOCKS.contains(&name) {
+        return Err(format!(
+            "unknown block `{{{{#{}}}}}`; expected one of {}",
+            name,
+            BLOCKS.join(", ")
+        ));
+    }
+    let [variable] = block.params.as_slice() else {
+        return Err(format!("`{{{{#{}}}}}` takes one variable", name));
+    };
+    check_variable(variable, variables)?;
+    for branch in [&block.template, &block.inverse].into_iter().flatten() {
+        check_elements(&branch.elements, variables)?;
+    }
+    Ok(())
+}
+
+fn check_variable(parameter: &Parameter, variables: &[&str]) -> Result<(), String> {
+    let name = name_of(parameter);
+    if variables.contains(&name) {
+        Ok(())
+    } else {
+        Err(format!(
+            "unknown variable `{}`; expected one of {}",
+            name,
+            variables.join(", ")
+        ))
+    }
+}
+
+/// The name of a variable or helper as written in the template.
+fn name_of(parameter: &Parameter) -> &str {
+    match parameter {
+        Parameter::Name(name) => name,
+        Parameter::Path(Variable::Relative((_, raw)))
+        | Parameter::Path(Variable::Local((_, _, raw))) => raw,
+        _ => "",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_variables_and_branches_without_escaping() {
+        let template = Template::parse(
+            "{{#if feedback}}Not {{feedback}}{{else}}First{{/if}}: {{{code}}}{{#unless code}}none{{/unless}}",
+            &["feedback", "code"],
+        )
+        .unwrap();
+
+        assert_eq!(
+            template.render(&[("code", "a < b && c")]),
+            "First: a < b && c"
+        );
+        assert_eq!(
+            template.render(&[("feedback", "\"this\"")]),
+            "Not \"this\": none"
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_variables_and_helpers_when_read() {
+        let variables = &["code"];
+        for (text, error) in [
+            (
+                "{{#if code}}{{else}}{{rule}}{{/if}}",
+                "unknown variable `rule`",
+            ),
+            ("{{#unless rule}}{{/unless}}", "unknown variable `rule`"),
+            (
+                "{{#each code}}{{this}}{{/each}}",
+                "unknown block `{{#each}}`",
+            ),
+            ("{{lookup code 0}}", "unknown helper `lookup`"),
+            ("{{> header}}", "partials aren't supported"),
+        ] {
+            let err = Template::parse(text, variables).err().unwrap();
+            assert!(err.contains(error), "{}: {}", text, err);
+        }
+        assert!(Template::parse("{{#if code}}", variables).is_err());
+    }
+}
@@ -0,0 +1,88 @@
+//! Providers with canned answers, for running the whole pipeline in tests
+//! without network access or an API key.
+//!
+//! ```no_run
+//! use std::sync::Arc;
+//! use rust_ruffer::testing::ScriptedProvider;
+//! use rust_ruffer::RuffFixer;
+//! use structopt::StructOpt;
+//!
+//! # async fn example() -> std::io::Result<()> {
+//! let fixer = RuffFixer::from_iter(["ruffer", "unused-key", "ruff", "project/"]);
+//! let provider = Arc::new(ScriptedProvider::new(["print('fixed')\n"]));
+//! Arc::new(fixer).run_with(provider.clone()).await?;
+//! assert_eq!(provider.requests().len(), 1);
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+use crate::provider::{Completion, CompletionRequest, FixProvider};
+
+/// Answers every request with the same text.
+pub struct StaticFixProvider {
+    response: String,
+}
+
+impl StaticFixProvider {
+    pub fn new(response: impl Into<String>) -> Self {
+        StaticFixProvider {
+            response: response.into(),
+        }
+    }
+}
+
+impl FixProvider for StaticFixProvider {
+    fn complete<'a>(&'a self, _request: &'a CompletionRequest) -> Completion<'a> {
+        let response = self.response.clone();
+        Box::pin(async move { Ok(response) })
+    }
+}
+
+/// Answers requests from a script, one entry per request in the order they
+/// arrive, and remembers every request for later assertions. Files are
+/// fixed concurrently, so the order is only well defined within one file.
+pub struct ScriptedProvider {
+    script: Mutex<VecDeque<Result<String, String>>>,
+    requests: Mutex<Vec<CompletionRequest>>,
+}
+
+impl ScriptedProvider {
+    pub fn new<I, S>(responses: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        ScriptedProvider {
+            script: Mutex::new(responses.into_iter().map(|r| Ok(r.into())).collect()),
+            requests: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Appends a failing answer to the script.
+    pub fn then_fail(self, message: impl Into<String>) -> Self {
+        self.script.lock().unwrap().push_back(Err(message.into()));
+        self
+    }
+
+    /// Requests received so far.
+    pub fn requests(&self) -> Vec<CompletionRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+impl FixProvider for ScriptedProvider {
+    fn complete<'a>(&'a self, request: &'a CompletionRequest) -> Completion<'a> {
+        self.requests.lock().unwrap().push(request.clone());
+        let next = self.script.lock().unwrap().pop_front();
+        Box::pin(async move {
+            match next {
+                Some(Ok(response)) => Ok(response),
+                Some(Err(message)) => Err(message.into()),
+                None => Err("ScriptedProvider ran out of responses".into()),
+            }
+        })
+    }
+}
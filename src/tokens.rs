@@ -0,0 +1,160 @@
+/// Context limits of a chat model, in tokens.
+#[derive(Clone, Copy)]
+pub struct ModelLimits {
+    pub context_window: usize,
+    pub max_output: usize,
+}
+
+/// Looks up the limits of a model by name. Dated snapshots such as
+/// `gpt-4o-2024-08-06` resolve to their family; unknown models get a
+/// conservative default.
+pub fn model_limits(model: &str) -> ModelLimits {
+    // Longest prefixes first so `gpt-4o-mini` doesn't match `gpt-4o` or `gpt-4`.
+    const KNOWN: &[(&str, usize, usize)] = &[
+        ("gpt-4o-mini", 128_000, 16_384),
+        ("gpt-4o", 128_000, 16_384),
+        ("gpt-4-turbo", 128_000, 4_096),
+        ("gpt-4.1", 1_047_576, 32_768),
+        ("gpt-4", 8_192, 4_096),
+        ("gpt-3.5-turbo", 16_385, 4_096),
+        ("o1-mini", 128_000, 65_536),
+        ("o1", 200_000, 100_000),
+        ("o3-mini", 200_000, 100_000),
+    ];
+    KNOWN
+        .iter()
+        .find(|(prefix, _, _)| model.starts_with(prefix))
+        .map(|&(_, context_window, max_output)| ModelLimits {
+            context_window,
+            max_output,
+        })
+        .unwrap_or(ModelLimits {
+            context_window: 8_192,
+            max_output: 4_096,
+        })
+}
+
+/// What a model costs, in US dollars per million tokens.
+#[derive(Clone, Copy)]
+pub struct Price {
+    pub input: f64,
+    pub output: f64,
+}
+
+impl Price {
+    /// What `prompt` tokens sent and `answer` tokens received cost.
+    pub fn cost(self, prompt: usize, answer: usize) -> f64 {
+        (prompt as f64 * self.input + answer as f64 * self.output) / 1_000_000.0
+    }
+}
+
+/// Looks up the list price of a model by name, as with [`model_limits`];
+/// none for models whose price isn't known.
+pub fn model_price(model: &str) -> Option<Price> {
+    // Longest prefixes first, as above.
+    const KNOWN: &[(&str, f64, f64)] = &[
+        ("gpt-4o-mini", 0.15, 0.60),
+        ("gpt-4o", 2.50, 10.00),
+        ("gpt-4-turbo", 10.00, 30.00),
+        ("gpt-4.1-nano", 0.10, 0.40),
+        ("gpt-4.1-mini", 0.40, 1.60),
+        ("gpt-4.1", 2.00, 8.00),
+        ("gpt-4", 30.00, 60.00),
+        ("gpt-3.5-turbo", 0.50, 1.50),
+        ("o1-mini", 1.10, 4.40),
+        ("o1", 15.00, 60.00),
+        ("o3-mini", 1.10, 4.40),
+    ];
+    KNOWN
+        .iter()
+        .find(|(prefix, _, _)| model.starts_with(prefix))
+        .map(|&(_, input, output)| Price { input, output })
+}
+
+/// Estimates how many tokens `text` encodes to.
+///
+/// Mirrors the way tiktoken pre-splits text (letter runs, digit groups,
+/// punctuation runs, whitespace) and charges each piece roughly what the
+/// BPE merges would. It errs on the high side, which is the safe direction
+/// when deciding whether a prompt fits.
+pub fn count_tokens(text: &str) -> usize {
+    let mut tokens: usize = 0;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        let mut len: usize = 1;
+        if c.is_alphabetic() || c == '_' {
+            while chars.next_if(|n| n.is_alphabetic() || *n == '_').is_some() {
+                len += 1;
+            }
+            tokens += len.div_ceil(4);
+        } else if c.is_ascii_digit() {
+            while chars.next_if(|n| n.is_ascii_digit()).is_some() {
+                len += 1;
+            }
+            tokens += len.div_ceil(3);
+        } else if c == '\n' {
+            while chars.next_if(|n| *n == '\n').is_some() {}
+            tokens += 1;
+        } else if c.is_whitespace() {
+            // Indentation and the space before a word are merged into
+            // neighbouring tokens most of the time.
+            while chars.next_if(|n| n.is_whitespace() && *n != '\n').is_some() {
+                len += 1;
+            }
+            tokens += len / 8;
+        } else {
+            while chars
+                .next_if(|n| !n.is_alphanumeric() && !n.is_whitespace() && *n != '_')
+                .is_some()
+            {
+                len += 1;
+            }
+            tokens += len.div_ceil(2);
+        }
+    }
+    tokens
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_words_digits_punctuation_and_newlines() {
+        assert_eq!(count_tokens(""), 0);
+        // `print` counts two, `hi` one, the runs of punctuation `("` and
+        // `")` one each, and the newline one.
+        assert_eq!(count_tokens("print(\"hi\")\n"), 6);
+        assert_eq!(count_tokens("1234567"), 3);
+        assert_eq!(count_tokens("\n\n\n"), 1);
+        // Indentation is mostly merged into its neighbours.
+        assert_eq!(count_tokens("        x"), 2);
+        assert_eq!(count_tokens("    x"), 1);
+    }
+
+    #[test]
+    fn counts_windows_line_endings_like_unix_ones() {
+        assert_eq!(count_tokens("a\r\n"), count_tokens("a\n"));
+        assert_eq!(count_tokens("a\r\nb\r\n"), count_tokens("a\nb\n"));
+    }
+
+    #[test]
+    fn looks_up_models_by_their_longest_prefix() {
+        assert_eq!(model_limits("gpt-4o-mini").max_output, 16_384);
+        assert_eq!(model_limits("gpt-4o-2024-08-06").context_window, 128_000);
+        assert_eq!(model_limits("gpt-4-0613").context_window, 8_192);
+        assert_eq!(model_limits("gpt-4.1-mini").context_window, 1_047_576);
+        assert_eq!(model_limits("o1-mini").max_output, 65_536);
+        assert_eq!(model_limits("o1-preview").max_output, 100_000);
+        let unknown = model_limits("llama3");
+        assert_eq!((unknown.context_window, unknown.max_output), (8_192, 4_096));
+    }
+
+    #[test]
+    fn prices_known_models_only() {
+        assert!(model_price("llama3").is_none());
+        let price = model_price("gpt-4o-mini-2024-07-18").unwrap();
+        assert_eq!(price.input, 0.15);
+        assert_eq!(price.cost(1_000_000, 1_000_000), 0.75);
+    }
+}
@@ -0,0 +1,232 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use serde::Deserialize;
+
+use crate::ruff;
+
+/// Tells apart the temporary directories of concurrent checks.
+static CHECKS: AtomicUsize = AtomicUsize::new(0);
+
+/// Which type checker validates added annotations, and every fix with
+/// `--type-check-fixes`, as given with `--type-checker`.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Choice {
+    /// mypy or pyright, whichever is found first; none if neither is.
+    Auto,
+    Mypy,
+    Pyright,
+    None,
+}
+
+impl FromStr for Choice {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(Choice::Auto),
+            "mypy" => Ok(Choice::Mypy),
+            "pyright" => Ok(Choice::Pyright),
+            "none" => Ok(Choice::None),
+            other => Err(format!("unknown type checker: {}", other)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Mypy,
+    Pyright,
+}
+
+impl Kind {
+    fn name(self) -> &'static str {
+        match self {
+            Kind::Mypy => "mypy",
+            Kind::Pyright => "pyright",
+        }
+    }
+}
+
+/// An error reported by the type checker.
+#[derive(Debug, PartialEq, Eq)]
+pub struct TypeError {
+    /// One-based line of the checked content.
+    pub line: usize,
+    pub message: String,
+}
+
+pub struct TypeChecker {
+    kind: Kind,
+    path: PathBuf,
+}
+
+impl TypeChecker {
+    /// Finds the type checker `choice` asks for the same way ruff is found
+    /// (see [`ruff::find_in_environment`]). A checker asked for by name
+    /// must exist; with `auto`, it's fine if none does.
+    pub fn locate(root: &Path, choice: Choice) -> io::Result<Option<TypeChecker>> {
+        let kinds: &[Kind] = match choice {
+            Choice::Auto => &[Kind::Mypy, Kind::Pyright],
+            Choice::Mypy => &[Kind::Mypy],
+            Choice::Pyright => &[Kind::Pyright],
+            Choice::None => &[],
+        };
+        let found = kinds.iter().find_map(|&kind| {
+            ruff::find_in_environment(root, kind.name()).map(|path| TypeChecker { kind, path })
+        });
+        match (found, choice) {
+            (None, Choice::Mypy | Choice::Pyright) => Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!(
+                    "{} not found in the project's virtualenv or on PATH",
+                    kinds[0].name()
+                ),
+            )),
+            (found, _) => Ok(found),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Type checks `content` as a file named like `filename`, without
+    /// touching the file itself. The content is checked on its own, so
+    /// imports of the project's other modules may not resolve; compare
+    /// against the errors of the original content with [`check_introduced`].
+    pub fn errors(&self, filename: &str, content: &str) -> io::Result<Vec<TypeError>> {
+        let dir = std::env::temp_dir().join(format!(
+            "ruffer-typecheck-{}-{}",
+            std::process::id(),
+            CHECKS.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::create_dir_all(&dir)?;
+        let file = dir.join(
+            Path::new(filename)
+                .file_name()
+                .unwrap_or("module.py".as_ref()),
+        );
+        let result = fs::write(&file, content).and_then(|()| self.run(&dir, &file));
+        let _ = fs::remove_dir_all(&dir);
+        result
+    }
+
+    fn run(&self, dir: &Path, file: &Path) -> io::Result<Vec<TypeError>> {
+        let mut command = Command::new(&self.path);
+        match self.kind {
+            Kind::Mypy => command
+                .args([
+                    "--no-error-summary",
+                    "--no-color-output",
+                    "--ignore-missing-imports",
+                    "--follow-imports=silent",
+                    "--cache-dir",
+                ])
+                .arg(dir.join(".mypy_cache")),
+            Kind::Pyright => command.arg("--outputjson"),
+        };
+        let output = command.arg(file).output()?;
+        if !matches!(output.status.code(), Some(0) | Some(1)) {
+            return Err(io::Error::other(format!(
+                "{} failed with {}: {}",
+                self.kind.name(),
+                output.status,
+                String::from_utf8_lossy(&output.stderr)
+            )));
+        }
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        match self.kind {
+            Kind::Mypy => Ok(parse_mypy(&stdout)),
+            Kind::Pyright => parse_pyright(&stdout),
+        }
+    }
+}
+
+/// Rejects fixes, such as annotations, that made the type checker report
+/// errors it didn't report for the original content. Errors are compared
+/// by message, since the fix may have moved lines.
+pub fn check_introduced(before: &[TypeError], after: &[TypeError]) -> Result<(), String> {
+    let mut known: HashMap<&str, usize> = HashMap::new();
+    for error in before {
+        *known.entry(&error.message).or_default() += 1;
+    }
+    let introduced: Vec<String> = after
+        .iter()
+        .filter(|error| match known.get_mut(error.message.as_str()) {
+            Some(count) if *count > 0 => {
+                *count -= 1;
+                false
+            }
+            _ => true,
+        })
+        .map(|error| format!("line {}: {}", error.line, error.message))
+        .collect();
+    if introduced.is_empty() {
+        Ok(())
+    } else {
+        Err(format!(
+            "The type checker reports new errors with this fix:\n{}",
+            introduced.join("\n")
+        ))
+    }
+}
+
+/// Reads `file:line: error: message` lines, with or without a column.
+fn parse_mypy(output: &str) -> Vec<TypeError> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let (location, message) = line.split_once(": error: ")?;
+            let mut parts = location.rsplit(':').filter_map(|part| part.parse().ok());
+            // The last number is the column when there are two.
+            let (last, previous) = (parts.next()?, parts.next());
+            Some(TypeError {
+                line: previous.unwrap_or(last),
+                message: message.trim().to_string(),
+            })
+        })
+        .collect()
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PyrightReport {
+    general_diagnostics: Vec<PyrightDiagnostic>,
+}
+
+#[derive(Deserialize)]
+struct PyrightDiagnostic {
+    severity: String,
+    message: String,
+    range: PyrightRange,
+}
+
+#[derive(Deserialize)]
+struct PyrightRange {
+    start: PyrightPosition,
+}
+
+#[derive(Deserialize)]
+struct PyrightPosition {
+    /// Zero-based.
+    line: usize,
+}
+
+fn parse_pyright(output: &str) -> io::Result<Vec<TypeError>> {
+    let report: PyrightReport = serde_json::from_str(output)?;
+    Ok(report
+        .general_diagnostics
+        .into_iter()
+        .filter(|diagnostic| diagnostic.severity == "error")
+        .map(|diagnostic| TypeError {
+            line: diagnostic.range.start.line + 1,
+            message: diagnostic.message,
+        })
+        .collect())
+}
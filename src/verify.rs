@@ -0,0 +1,146 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::io::{self, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use crate::issue::{self, severity, Issue, Severity};
+
+/// A candidate fix that must not be written. The reason is phrased for the
+/// model, since it goes into the prompt of the next attempt.
+#[derive(Debug)]
+pub struct Rejected(pub String);
+
+impl fmt::Display for Rejected {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for Rejected {}
+
+/// Lints `content` as if it were the file `filename`, without touching the
+/// file on disk, running ruff in `dir` if given. `args` are added to the
+/// `ruff check` command line.
+pub fn lint_content(
+    ruff_path: &Path,
+    dir: Option<&Path>,
+    args: &[String],
+    filename: &str,
+    content: &str,
+) -> io::Result<Vec<Issue>> {
+    let mut command = Command::new(ruff_path);
+    if let Some(dir) = dir {
+        command.current_dir(dir);
+    }
+    let mut child = command
+        .args([
+            "check",
+            "--no-fix",
+            "--output-format",
+            "json",
+            "--stdin-filename",
+        ])
+        .arg(filename)
+        .args(args)
+        .arg("-")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    child
+        .stdin
+        .take()
+        .expect("stdin is piped")
+        .write_all(content.as_bytes())?;
+    let output = child.wait_with_output()?;
+
+    match output.status.code() {
+        Some(0) | Some(1) => {
+            let mut issues: Vec<Issue> = serde_json::from_slice(&output.stdout)?;
+            issue::assign_ids(&mut issues, content);
+            Ok(issues)
+        }
+        code => Err(io::Error::other(format!(
+            "Ruff check of the fixed content failed with exit code {:?}: {}",
+            code,
+            String::from_utf8_lossy(&output.stderr)
+        ))),
+    }
+}
+
+/// Decides whether a candidate fix can be written: the targeted issue must
+/// be gone, that is, no longer reported with its ID, nor with its rule on
+/// the line it is on once fixed, `row_after`, so fixing another occurrence
+/// of the rule doesn't count. A target that wasn't reported before either,
+/// as the issues of a scanner that isn't run again, such as those of a
+/// SARIF file, can't be checked. None of the findings that appeared with
+/// the fix may be more severe than the one being fixed. The error
+/// describes the problem in terms the model can act on.
+pub fn check_fix(
+    target: &Issue,
+    row_after: u32,
+    before: &[Issue],
+    after: &[Issue],
+) -> Result<(), String> {
+    let reports = |issues: &[Issue], row: u32| {
+        issues
+            .iter()
+            .any(|i| i.id == target.id || (i.code == target.code && i.location.row == row))
+    };
+    if reports(before, target.location.row) && reports(after, row_after) {
+        return Err(format!(
+            "Ruff still reports {} ({}) in the fixed code.",
+            target.code, target.message
+        ));
+    }
+
+    check_introduced(severity(&target.code), before, after)
+}
+
+/// Checks a file with all its fixes, as it is about to be written: none
+/// of the findings that appeared with them may be an error.
+pub fn check_file(before: &[Issue], after: &[Issue]) -> Result<(), String> {
+    check_introduced(Severity::Warning, before, after)
+}
+
+/// Checks a fix of several issues at once, returning the IDs of the
+/// targets ruff still reports. The fix is rejected outright if it
+/// introduced findings more severe than the worst of the targets.
+pub fn check_batch(
+    targets: &[&Issue],
+    before: &[Issue],
+    after: &[Issue],
+) -> Result<Vec<String>, String> {
+    let worst = targets
+        .iter()
+        .map(|target| severity(&target.code))
+        .max()
+        .unwrap_or(Severity::Info);
+    check_introduced(worst, before, after)?;
+
+    let reported: HashSet<&str> = after.iter().map(|i| i.id.as_str()).collect();
+    Ok(targets
+        .iter()
+        .filter(|target| reported.contains(target.id.as_str()))
+        .map(|target| target.id.clone())
+        .collect())
+}
+
+fn check_introduced(allowed: Severity, before: &[Issue], after: &[Issue]) -> Result<(), String> {
+    let known: HashSet<&str> = before.iter().map(|i| i.id.as_str()).collect();
+    let introduced: Vec<String> = after
+        .iter()
+        .filter(|i| !known.contains(i.id.as_str()))
+        .filter(|i| severity(&i.code) > allowed)
+        .map(|i| format!("{} at line {}: {}", i.code, i.location.row, i.message))
+        .collect();
+    if !introduced.is_empty() {
+        return Err(format!(
+            "The fix introduced new problems:\n{}",
+            introduced.join("\n")
+        ));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,189 @@
+//! `ruffer watch`: fixes files as they are saved, for a loop where issues
+//! disappear while you work.
+//!
+//! Changes are noticed with the file system notifications of the platform,
+//! through `notify`. Once saves have stopped for the debounce time, the
+//! Python files saved are fixed in one run, made afresh from the command
+//! line. Files the run writes itself don't start another one.
+
+use std::collections::{BTreeSet, HashMap};
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use notify::event::{AccessKind, AccessMode, ModifyKind};
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use structopt::StructOpt;
+use tokio::sync::mpsc;
+
+use crate::fixer::RuffFixer;
+use crate::ignore::{IgnoreFile, VENDORED_DIRS};
+use crate::journal::digest;
+use crate::provider::FixProvider;
+
+/// Options of `ruffer watch`: those of a fixing run, and how to watch.
+#[derive(StructOpt)]
+pub struct WatchOptions {
+    #[structopt(flatten)]
+    fixer: RuffFixer,
+
+    #[structopt(
+        long,
+        default_value = "500",
+        help = "Fix saved files once nothing has been saved for this many milliseconds"
+    )]
+    debounce_ms: u64,
+
+    #[structopt(
+        long,
+        number_of_values = 1,
+        help = "Don't fix files matching this pattern, in .rufferignore syntax, when they are saved; may be repeated"
+    )]
+    watch_ignore: Vec<String>,
+}
+
+impl WatchOptions {
+    pub fn init_logging(&self) -> io::Result<()> {
+        self.fixer.init_logging()
+    }
+
+    /// Fixes files with the OpenAI API as they are saved, until Ctrl-C.
+    /// `args` are the arguments the options were parsed from, after the
+    /// subcommand, to parse again for every run.
+    pub async fn run(self, args: Vec<OsString>) -> io::Result<()> {
+        let provider = self.fixer.provider();
+        self.run_with(args, provider).await
+    }
+
+    /// Fixes files with answers from `provider` as they are saved; see
+    /// [`Self::run`].
+    pub async fn run_with(
+        self,
+        args: Vec<OsString>,
+        provider: Arc<dyn FixProvider>,
+    ) -> io::Result<()> {
+        let console = self.fixer.console();
+        let (paths, root) = self.fixer.targets()?;
+        let ignore = IgnoreFile::from_patterns(&root, &self.watch_ignore)?;
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let _watcher = watch(&root, tx)?;
+        console.status(format!(
+            "Watching {}; fixing Python files as they are saved",
+            paths.join(" ")
+        ));
+
+        // What the files were after the last run, so that its own writes
+        // don't count as saves.
+        let mut fixed: HashMap<PathBuf, String> = HashMap::new();
+        let debounce = Duration::from_millis(self.debounce_ms);
+        loop {
+            let first = tokio::select! {
+                path = rx.recv() => match path {
+                    Some(path) => path,
+                    None => return Ok(()),
+                },
+                _ = tokio::signal::ctrl_c() => return Ok(()),
+            };
+            let mut saved = BTreeSet::from([first]);
+            while let Ok(Some(path)) = tokio::time::timeout(debounce, rx.recv()).await {
+                saved.insert(path);
+            }
+
+            // Paths are checked again, as globs may match new files.
+            let (paths, _) = self.fixer.targets()?;
+            let targets: Vec<PathBuf> = paths
+                .iter()
+                .filter_map(|path| fs::canonicalize(path).ok())
+                .collect();
+            let files: Vec<String> = saved
+                .into_iter()
+                .filter(|path| {
+                    is_python(path)
+                        && targets.iter().any(|target| path.starts_with(target))
+                        && !ignore.is_ignored(path)
+                        && fs::read_to_string(path)
+                            .is_ok_and(|content| fixed.get(path) != Some(&digest(&content)))
+                })
+                .map(|path| path.display().to_string())
+                .collect();
+            if files.is_empty() {
+                continue;
+            }
+
+            console.status(format!("Saved: {}", files.join(" ")));
+            let fixer = WatchOptions::from_iter(&args).fixer.checking(files.clone());
+            let result = Arc::new(fixer).run_with(Arc::clone(&provider)).await;
+            for file in &files {
+                if let Ok(content) = fs::read_to_string(file) {
+                    fixed.insert(PathBuf::from(file), digest(&content));
+                }
+            }
+            match result {
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => return Err(err),
+                Err(err) => console.error(err),
+                Ok(()) => {}
+            }
+        }
+    }
+}
+
+fn is_python(path: &Path) -> bool {
+    matches!(
+        path.extension().and_then(|extension| extension.to_str()),
+        Some("py" | "pyi")
+    )
+}
+
+/// Whether the directory `name` is left unwatched: hidden directories,
+/// such as `.git` and ruffer's own, and those of other people's code.
+fn skipped(name: &str) -> bool {
+    name.starts_with('.') || VENDORED_DIRS.contains(&name)
+}
+
+/// Sends the paths of the files saved under `root` to `tx`, for as long as
+/// the watcher returned is kept.
+fn watch(root: &Path, tx: mpsc::UnboundedSender<PathBuf>) -> io::Result<RecommendedWatcher> {
+    let root = root.canonicalize()?;
+    let under = root.clone();
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        let event = match event {
+            Ok(event) if saves(&event.kind) => event,
+            Ok(_) => return,
+            Err(err) => {
+                tracing::warn!("Error watching {}: {}", under.display(), err);
+                return;
+            }
+        };
+        for path in event.paths {
+            let watched = path.strip_prefix(&under).is_ok_and(|relative| {
+                relative
+                    .parent()
+                    .into_iter()
+                    .flat_map(Path::components)
+                    .all(|dir| !skipped(&dir.as_os_str().to_string_lossy()))
+            });
+            if watched {
+                let _ = tx.send(path);
+            }
+        }
+    })
+    .map_err(io::Error::other)?;
+    watcher
+        .watch(&root, RecursiveMode::Recursive)
+        .map_err(io::Error::other)?;
+    Ok(watcher)
+}
+
+/// Whether an event of `kind` may leave a file with new content: it was
+/// created, written, or moved into place.
+fn saves(kind: &EventKind) -> bool {
+    matches!(
+        kind,
+        EventKind::Create(_)
+            | EventKind::Modify(ModifyKind::Any | ModifyKind::Data(_) | ModifyKind::Name(_))
+            | EventKind::Access(AccessKind::Close(AccessMode::Write))
+    )
+}
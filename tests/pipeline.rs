@@ -0,0 +1,3934 @@
+//! Runs the whole fixing pipeline against a stand-in `ruff` script and
+//! canned model answers.
+#![cfg(unix)]
+
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use rust_ruffer::provider::{Completion, CompletionRequest, FixProvider, Message, OpenAiProvider};
+use rust_ruffer::testing::{ScriptedProvider, StaticFixProvider};
+use rust_ruffer::{git, journal};
+use rust_ruffer::{
+    BaselineOptions, EstimateOptions, LspOptions, McpOptions, PolicyFailure, RuffFixer,
+    ServeOptions, WatchOptions,
+};
+use structopt::StructOpt;
+
+const UNFIXED: &str = "import os\n\nprint(\"hi\")\n";
+const FIXED: &str = "print(\"hi\")\n";
+
+/// A project directory with one Python file and a fake `ruff` that reports
+/// an unused import for every `import` line in the file (or in the content
+/// piped to it with `-`). If the project has an `applicability` file, each
+/// finding comes with a fix of that applicability that deletes the line.
+/// Every invocation is logged to `ruff.log`, and where it ran to
+/// `ruff.cwd`. The file is excluded from the
+/// project (left out of `--show-files`) if there is an `excluded` file.
+struct Project {
+    dir: PathBuf,
+    /// The Python file, `src/a.py` unless made with [`Project::with_file`].
+    file: PathBuf,
+}
+
+impl Project {
+    fn new(name: &str) -> Self {
+        Self::with_file(name, "src/a.py")
+    }
+
+    /// A project whose Python file is at `file`, relative to the project.
+    fn with_file(name: &str, file: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("ruffer-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let file = dir.join(file);
+        fs::create_dir_all(dir.join("src")).unwrap();
+        fs::create_dir_all(file.parent().unwrap()).unwrap();
+        fs::write(&file, UNFIXED).unwrap();
+
+        let script = format!(
+            r#"#!/bin/sh
+file='{file}'
+applicability=$(cat '{dir}/applicability' 2>/dev/null)
+echo "$@" >> '{dir}/ruff.log'
+pwd >> '{dir}/ruff.cwd'
+case "$1" in
+  format)
+    # Formats nothing, echoing the code piped to it with `-`.
+    for arg in "$@"; do
+      [ "$arg" = "-" ] && cat
+    done
+    exit 0 ;;
+  check)
+    case " $* " in
+      *" --show-files "*)
+        [ -e '{dir}/excluded' ] || echo "$file"
+        exit 0 ;;
+      *" --fix "*)
+        # Fixes nothing, echoing the code piped to it.
+        cat
+        exit 0 ;;
+    esac
+    source="$file"
+    for arg in "$@"; do
+      [ "$arg" = "-" ] && source=/dev/stdin
+    done
+    awk -v file="$file" -v applicability="$applicability" '
+      BEGIN {{ printf "[" }}
+      /^import / {{
+        if (n++) printf ","
+        printf "{{\"filename\":\"%s\",\"code\":\"F401\",\"message\":\"`%s` imported but unused\",\"location\":{{\"row\":%d,\"column\":8}}", file, $2, NR
+        if (applicability != "")
+          printf ",\"fix\":{{\"applicability\":\"%s\",\"edits\":[{{\"content\":\"\",\"location\":{{\"row\":%d,\"column\":1}},\"end_location\":{{\"row\":%d,\"column\":1}}}}]}}", applicability, NR, NR + 1
+        printf "}}"
+      }}
+      END {{ print "]"; exit n ? 1 : 0 }}
+    ' "$source" ;;
+esac
+"#,
+            file = file.display(),
+            dir = dir.display()
+        );
+        let ruff = dir.join("ruff");
+        fs::write(&ruff, script).unwrap();
+        fs::set_permissions(&ruff, fs::Permissions::from_mode(0o755)).unwrap();
+
+        Project { dir, file }
+    }
+
+    fn fixer(&self, extra_args: &[&str]) -> Arc<RuffFixer> {
+        let ruff = self.dir.join("ruff");
+        let root = self.dir.join("src");
+        let journal = self.journal_dir();
+        let state = self.state_file();
+        let mut args = vec![
+            "ruffer",
+            "unused-key",
+            path(&ruff),
+            path(&root),
+            "--no-cache",
+            "--journal-dir",
+            path(&journal),
+            "--state-file",
+            path(&state),
+        ];
+        args.extend_from_slice(extra_args);
+        Arc::new(RuffFixer::from_iter(args))
+    }
+
+    /// A fixer like that of `fixer`, but reusing and storing fixes in the
+    /// cache in the project's directory.
+    fn caching_fixer(&self, extra_args: &[&str]) -> Arc<RuffFixer> {
+        let ruff = self.dir.join("ruff");
+        let root = self.dir.join("src");
+        let cache = self.dir.join("cache");
+        let journal = self.journal_dir();
+        let state = self.state_file();
+        let mut args = vec![
+            "ruffer",
+            "unused-key",
+            path(&ruff),
+            path(&root),
+            "--cache-dir",
+            path(&cache),
+            "--journal-dir",
+            path(&journal),
+            "--state-file",
+            path(&state),
+        ];
+        args.extend_from_slice(extra_args);
+        Arc::new(RuffFixer::from_iter(args))
+    }
+
+    fn journal_dir(&self) -> PathBuf {
+        self.dir.join("journal")
+    }
+
+    fn state_file(&self) -> PathBuf {
+        self.dir.join("state.json")
+    }
+
+    fn write_source(&self, content: &str) {
+        fs::write(&self.file, content).unwrap();
+    }
+
+    fn offer_ruff_fixes(&self, applicability: &str) {
+        fs::write(self.dir.join("applicability"), applicability).unwrap();
+    }
+
+    fn ruff_invocations(&self) -> Vec<String> {
+        let log = fs::read_to_string(self.dir.join("ruff.log")).unwrap_or_default();
+        log.lines().map(str::to_string).collect()
+    }
+
+    fn source(&self) -> String {
+        fs::read_to_string(&self.file).unwrap()
+    }
+}
+
+impl Drop for Project {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+fn path(path: &Path) -> &str {
+    path.to_str().unwrap()
+}
+
+#[tokio::test]
+async fn applies_scripted_fix() {
+    let project = Project::new("applies-scripted-fix");
+    let provider = Arc::new(ScriptedProvider::new([FIXED]));
+
+    project.fixer(&[]).run_with(provider.clone()).await.unwrap();
+
+    assert_eq!(project.source(), FIXED);
+    let requests = provider.requests();
+    assert_eq!(requests.len(), 1);
+    assert!(requests[0].prompt().contains("`os` imported but unused"));
+    assert!(requests[0].prompt().contains("import os"));
+}
+
+#[tokio::test]
+async fn strips_code_fences_from_answers() {
+    let project = Project::new("strips-code-fences");
+    let answer = format!("Here is the fixed code:\n\n```python\n{}```\n", FIXED);
+
+    project
+        .fixer(&[])
+        .run_with(Arc::new(StaticFixProvider::new(answer)))
+        .await
+        .unwrap();
+
+    assert_eq!(project.source(), FIXED);
+}
+
+#[tokio::test]
+async fn retries_fix_rejected_by_verification() {
+    let project = Project::new("retries-rejected-fix");
+    let provider = Arc::new(ScriptedProvider::new([UNFIXED, FIXED]));
+
+    project.fixer(&[]).run_with(provider.clone()).await.unwrap();
+
+    assert_eq!(project.source(), FIXED);
+    let requests = provider.requests();
+    assert_eq!(requests.len(), 2);
+    assert!(requests[1].prompt().contains("Ruff still reports F401"));
+}
+
+#[tokio::test]
+async fn rejects_a_fix_of_another_occurrence_of_the_rule() {
+    let project = Project::new("other-occurrence");
+    project.write_source("import os\nimport sys\n\nprint(\"hi\")\n");
+    // Issues are fixed from the bottom up: `sys` first, then `os`.
+    let provider = Arc::new(ScriptedProvider::new([
+        "import sys\n\nprint(\"hi\")\n",
+        UNFIXED,
+        FIXED,
+    ]));
+
+    project.fixer(&[]).run_with(provider.clone()).await.unwrap();
+
+    assert_eq!(project.source(), FIXED);
+    let requests = provider.requests();
+    assert_eq!(requests.len(), 3);
+    assert!(requests[0].prompt().contains("`sys` imported but unused"));
+    assert!(requests[1]
+        .prompt()
+        .contains("Ruff still reports F401 (`sys` imported but unused)"));
+}
+
+/// `import os` above twenty prints, the only line needing a fix.
+fn long_file() -> (String, String) {
+    let prints: String = (1..=20)
+        .map(|n| format!("print(\"this is line {} of a longer file\")\n", n))
+        .collect();
+    (format!("import os\n{}", prints), prints)
+}
+
+#[tokio::test]
+async fn rejects_a_fix_rewriting_too_many_lines() {
+    let project = Project::new("guard-lines");
+    let (unfixed, fixed) = long_file();
+    project.write_source(&unfixed);
+    // Same size, but every line requoted.
+    let rewritten = fixed.replace('"', "'");
+    let provider = Arc::new(ScriptedProvider::new([rewritten.as_str(), fixed.as_str()]));
+
+    project.fixer(&[]).run_with(provider.clone()).await.unwrap();
+
+    assert_eq!(project.source(), fixed);
+    let requests = provider.requests();
+    assert_eq!(requests.len(), 2);
+    assert!(requests[1]
+        .prompt()
+        .contains("The fix changed 21 of 21 lines (100%)"));
+}
+
+#[tokio::test]
+async fn rejects_a_fix_truncating_the_file() {
+    let project = Project::new("guard-size");
+    let (unfixed, fixed) = long_file();
+    project.write_source(&unfixed);
+    let truncated: String = fixed
+        .lines()
+        .take(10)
+        .map(|line| line.to_string() + "\n")
+        .collect();
+    let provider = Arc::new(ScriptedProvider::new([truncated.as_str(), fixed.as_str()]));
+
+    // Only the size limit applies.
+    project
+        .fixer(&["--max-changed-lines", "100"])
+        .run_with(provider.clone())
+        .await
+        .unwrap();
+
+    assert_eq!(project.source(), fixed);
+    let requests = provider.requests();
+    assert_eq!(requests.len(), 2);
+    assert!(requests[1]
+        .prompt()
+        .contains("The file shrank by 51% with the fix"));
+}
+
+#[tokio::test]
+async fn accepts_large_fixes_within_raised_limits() {
+    let project = Project::new("guard-raised");
+    let (unfixed, fixed) = long_file();
+    project.write_source(&unfixed);
+    let rewritten = fixed.replace('"', "'");
+    let provider = Arc::new(ScriptedProvider::new([rewritten.as_str()]));
+
+    project
+        .fixer(&["--max-changed-lines", "100"])
+        .run_with(provider.clone())
+        .await
+        .unwrap();
+
+    assert_eq!(project.source(), rewritten);
+    assert_eq!(provider.requests().len(), 1);
+}
+
+#[tokio::test]
+async fn never_accepts_invalid_python() {
+    let project = Project::new("invalid-python");
+    let provider = Arc::new(ScriptedProvider::new(["print \"hi\"\n", FIXED]));
+
+    project
+        .fixer(&["--no-verify"])
+        .run_with(provider.clone())
+        .await
+        .unwrap();
+
+    assert_eq!(project.source(), FIXED);
+    let requests = provider.requests();
+    assert_eq!(requests.len(), 2);
+    assert!(requests[1].prompt().contains("not valid Python"));
+}
+
+#[tokio::test]
+async fn leaves_file_alone_when_provider_fails() {
+    let project = Project::new("provider-fails");
+    let provider = Arc::new(ScriptedProvider::new(Vec::<String>::new()).then_fail("offline"));
+
+    project.fixer(&[]).run_with(provider).await.unwrap();
+
+    assert_eq!(project.source(), UNFIXED);
+}
+
+#[tokio::test]
+async fn static_provider_answers_every_request() {
+    let project = Project::new("static-provider");
+
+    project
+        .fixer(&[])
+        .run_with(Arc::new(StaticFixProvider::new(FIXED)))
+        .await
+        .unwrap();
+
+    assert_eq!(project.source(), FIXED);
+}
+
+#[tokio::test]
+async fn applies_diff_answers_as_patches() {
+    let project = Project::new("diff-answers");
+    let stale = "@@ -1,2 +1,1 @@\n-import sys\n \n";
+    let patch =
+        "```diff\n--- a/a.py\n+++ b/a.py\n@@ -1,3 +1,1 @@\n-import os\n-\n print(\"hi\")\n```\n";
+    let provider = Arc::new(ScriptedProvider::new([stale, patch]));
+
+    project
+        .fixer(&["--response-format", "diff"])
+        .run_with(provider.clone())
+        .await
+        .unwrap();
+
+    assert_eq!(project.source(), FIXED);
+    let requests = provider.requests();
+    assert_eq!(requests.len(), 2);
+    assert!(requests[0].prompt().contains("unified diff"));
+    assert!(requests[1].prompt().contains("does not match the file"));
+}
+
+/// Answers with a fix after changing the file on disk, as an editor would
+/// while ruffer is waiting for the model.
+struct EditingProvider {
+    file: PathBuf,
+    edited: &'static str,
+    answer: &'static str,
+}
+
+impl FixProvider for EditingProvider {
+    fn complete<'a>(&'a self, _request: &'a CompletionRequest) -> Completion<'a> {
+        Box::pin(async move {
+            fs::write(&self.file, self.edited)?;
+            Ok(self.answer.to_string())
+        })
+    }
+}
+
+#[tokio::test]
+async fn merges_fixes_into_changes_made_during_the_run() {
+    let project = Project::new("merges-changes");
+    let provider = EditingProvider {
+        file: project.dir.join("src/a.py"),
+        edited: "import os\n\nprint(\"hi\")\nprint(\"bye\")\n",
+        answer: FIXED,
+    };
+
+    project
+        .fixer(&["--no-verify"])
+        .run_with(Arc::new(provider))
+        .await
+        .unwrap();
+
+    assert_eq!(project.source(), "print(\"hi\")\nprint(\"bye\")\n");
+}
+
+#[tokio::test]
+async fn keeps_conflicting_changes_made_during_the_run() {
+    let project = Project::new("conflicting-changes");
+    let edited = "import sys\n\nprint(\"hi\")\n";
+    let provider = EditingProvider {
+        file: project.dir.join("src/a.py"),
+        edited,
+        answer: FIXED,
+    };
+
+    project
+        .fixer(&["--no-verify"])
+        .run_with(Arc::new(provider))
+        .await
+        .unwrap();
+
+    assert_eq!(project.source(), edited);
+}
+
+#[tokio::test]
+async fn leaves_files_changed_during_the_run_alone_with_no_merge() {
+    let project = Project::new("no-merge");
+    let edited = "import os\n\nprint(\"hi\")\nprint(\"bye\")\n";
+    let provider = EditingProvider {
+        file: project.dir.join("src/a.py"),
+        edited,
+        answer: FIXED,
+    };
+
+    project
+        .fixer(&["--no-verify", "--no-merge"])
+        .run_with(Arc::new(provider))
+        .await
+        .unwrap();
+
+    assert_eq!(project.source(), edited);
+}
+
+#[tokio::test]
+async fn batch_fixes_all_issues_with_one_request() {
+    let project = Project::new("batch");
+    project.write_source("import os\nimport sys\n\nprint(\"hi\")\n");
+    let provider = Arc::new(ScriptedProvider::new([FIXED]));
+
+    project
+        .fixer(&["--batch"])
+        .run_with(provider.clone())
+        .await
+        .unwrap();
+
+    assert_eq!(project.source(), FIXED);
+    let requests = provider.requests();
+    assert_eq!(requests.len(), 1);
+    assert!(requests[0].prompt().contains("`os` imported but unused"));
+    assert!(requests[0].prompt().contains("`sys` imported but unused"));
+}
+
+#[tokio::test]
+async fn batch_leaves_unfixed_issues_to_single_fixes() {
+    let project = Project::new("batch-partial");
+    project.write_source("import os\nimport sys\n\nprint(\"hi\")\n");
+    let provider = Arc::new(ScriptedProvider::new([
+        "import sys\n\nprint(\"hi\")\n",
+        FIXED,
+    ]));
+
+    project
+        .fixer(&["--batch"])
+        .run_with(provider.clone())
+        .await
+        .unwrap();
+
+    assert_eq!(project.source(), FIXED);
+    let requests = provider.requests();
+    assert_eq!(requests.len(), 2);
+    assert!(requests[1].prompt().contains("`sys` imported but unused"));
+    assert!(!requests[1].prompt().contains("`os` imported but unused"));
+}
+
+#[tokio::test]
+async fn conversation_asks_about_later_issues_as_follow_ups() {
+    let project = Project::new("conversation");
+    project.write_source("import os\nimport sys\n\nprint(\"hi\")\n");
+    let provider = Arc::new(ScriptedProvider::new([UNFIXED, FIXED]));
+
+    project
+        .fixer(&["--conversation"])
+        .run_with(provider.clone())
+        .await
+        .unwrap();
+
+    assert_eq!(project.source(), FIXED);
+    let requests = provider.requests();
+    assert_eq!(requests.len(), 2);
+    assert_eq!(requests[0].messages.len(), 2);
+    assert_eq!(requests[1].messages.len(), 4);
+    assert_eq!(requests[1].messages[2].content, UNFIXED);
+    assert!(requests[1].prompt().starts_with("Also fix"));
+    assert!(!requests[1].prompt().contains("print(\"hi\")"));
+}
+
+#[tokio::test]
+async fn applies_safe_ruff_fixes_without_the_model() {
+    let project = Project::new("ruff-fixes");
+    project.offer_ruff_fixes("safe");
+    let provider = Arc::new(ScriptedProvider::new(Vec::<String>::new()));
+
+    project.fixer(&[]).run_with(provider.clone()).await.unwrap();
+
+    assert_eq!(project.source(), "\nprint(\"hi\")\n");
+    assert!(provider.requests().is_empty());
+}
+
+#[tokio::test]
+async fn asks_the_model_about_unsafe_ruff_fixes() {
+    let project = Project::new("unsafe-ruff-fixes");
+    project.offer_ruff_fixes("unsafe");
+    let provider = Arc::new(ScriptedProvider::new([FIXED]));
+
+    project.fixer(&[]).run_with(provider.clone()).await.unwrap();
+
+    assert_eq!(project.source(), FIXED);
+    assert_eq!(provider.requests().len(), 1);
+}
+
+#[tokio::test]
+async fn fixes_issues_from_a_report_file() {
+    let project = Project::new("report-file");
+    let report = project.dir.join("issues.json");
+    fs::write(
+        &report,
+        format!(
+            r#"[{{"filename":"{}","code":"F401","message":"`os` imported but unused","location":{{"row":1,"column":8}}}}]"#,
+            project.dir.join("src/a.py").display()
+        ),
+    )
+    .unwrap();
+    let provider = Arc::new(ScriptedProvider::new([FIXED]));
+
+    project
+        .fixer(&["--issues", path(&report)])
+        .run_with(provider.clone())
+        .await
+        .unwrap();
+
+    assert_eq!(project.source(), FIXED);
+    assert_eq!(provider.requests().len(), 1);
+}
+
+#[tokio::test]
+async fn asks_with_the_prompts_of_templates() {
+    let project = Project::new("templates");
+    let system = project.dir.join("system.hbs");
+    fs::write(&system, "You fix {{ language }} code for the ACME team.").unwrap();
+    let template = project.dir.join("fix.hbs");
+    fs::write(
+        &template,
+        "Rule {{rule_code}} at line {{line}} of {{filename}}: {{message}}\n{{#if feedback}}Rejected before: {{feedback}}\n{{else}}First attempt.\n{{/if}}\n{{snippet}}\n{{instructions}}",
+    )
+    .unwrap();
+    let provider = Arc::new(ScriptedProvider::new([FIXED]));
+
+    project
+        .fixer(&[
+            "--prompt-template",
+            path(&template),
+            "--system-prompt-template",
+            path(&system),
+        ])
+        .run_with(provider.clone())
+        .await
+        .unwrap();
+
+    assert_eq!(project.source(), FIXED);
+    let requests = provider.requests();
+    assert_eq!(requests.len(), 1);
+    assert_eq!(
+        requests[0].messages[0].content,
+        "You fix Python code for the ACME team."
+    );
+    let prompt = requests[0].prompt();
+    assert!(prompt.starts_with(&format!(
+        "Rule F401 at line 1 of {}: `os` imported but unused\nFirst attempt.\n{}\n",
+        project.dir.join("src/a.py").display(),
+        UNFIXED
+    )));
+    assert!(prompt.ends_with("do not wrap the response with backticks."));
+
+    fs::write(&template, "Fix {{rule}}").unwrap();
+    let err = project
+        .fixer(&["--prompt-template", path(&template)])
+        .run_with(Arc::new(ScriptedProvider::new([FIXED])))
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("unknown variable `rule`"));
+}
+
+#[tokio::test]
+async fn reuses_cached_fixes_only_for_the_same_prompts() {
+    let project = Project::new("template-cache");
+    let template = project.dir.join("fix.hbs");
+    fs::write(&template, "Fix {{rule_code}}:\n{{snippet}}").unwrap();
+    let run = || async {
+        project.write_source(UNFIXED);
+        let provider = Arc::new(ScriptedProvider::new([FIXED]));
+        project
+            .caching_fixer(&["--prompt-template", path(&template)])
+            .run_with(provider.clone())
+            .await
+            .unwrap();
+        assert_eq!(project.source(), FIXED);
+        provider.requests().len()
+    };
+
+    assert_eq!(run().await, 1);
+    assert_eq!(run().await, 0);
+    fs::write(&template, "Please fix {{rule_code}}:\n{{snippet}}").unwrap();
+    assert_eq!(run().await, 1);
+}
+
+#[tokio::test]
+async fn asks_with_the_prompt_template_of_each_rule() {
+    let project = Project::new("rule-prompts");
+    let report = project.dir.join("issues.json");
+    let file = project.dir.join("src/a.py");
+    fs::write(
+        &report,
+        format!(
+            r#"[{{"filename":"{0}","code":"F401","message":"`os` imported but unused","location":{{"row":1,"column":8}}}},{{"filename":"{0}","code":"E501","message":"Line too long","location":{{"row":3,"column":1}}}}]"#,
+            file.display()
+        ),
+    )
+    .unwrap();
+    let templates = [
+        (
+            "default.hbs",
+            "Default prompt for {{rule_code}}\n{{snippet}}",
+        ),
+        (
+            "pyflakes.hbs",
+            "Pyflakes prompt for {{rule_code}}\n{{snippet}}",
+        ),
+        (
+            "unused.hbs",
+            "Unused import prompt for {{lines}}\n{{snippet}}",
+        ),
+    ];
+    for (name, template) in templates {
+        fs::write(project.dir.join(name), template).unwrap();
+    }
+    let template = |name: &str| path(&project.dir.join(name)).to_string();
+    // Issues are fixed from the bottom of the file up.
+    let provider = Arc::new(ScriptedProvider::new([
+        "import os\n\nprint(\n    \"hi\"\n)\n",
+        "print(\n    \"hi\"\n)\n",
+    ]));
+
+    project
+        .fixer(&[
+            "--issues",
+            path(&report),
+            "--prompt-template",
+            &template("default.hbs"),
+            "--rule-prompt",
+            &format!("F={}", template("pyflakes.hbs")),
+            "--rule-prompt",
+            &format!("F401={}", template("unused.hbs")),
+        ])
+        .run_with(provider.clone())
+        .await
+        .unwrap();
+
+    let prompts: Vec<String> = provider
+        .requests()
+        .iter()
+        .map(|request| request.prompt().lines().next().unwrap().to_string())
+        .collect();
+    assert_eq!(
+        prompts,
+        [
+            "Default prompt for E501",
+            "Unused import prompt for import os"
+        ]
+    );
+}
+
+#[tokio::test]
+async fn shows_the_model_examples_of_fixes_of_the_rule() {
+    let project = Project::new("examples");
+    let examples = project.dir.join("examples");
+    for (file, content) in [
+        ("F401/sys.before.py", "import sys\nprint(1)\n"),
+        ("F401/sys.after.py", "print(1)\n"),
+        ("F/any.before.py", "from os import *\n"),
+        ("F/any.after.py", "from os import path\n"),
+        ("E501/long.before.py", "x = 'long'\n"),
+        ("E501/long.after.py", "x = (\n    'long'\n)\n"),
+        ("README.md", "Examples of fixes by rule.\n"),
+    ] {
+        let path = examples.join(file);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+    let provider = Arc::new(ScriptedProvider::new([FIXED]));
+
+    project
+        .fixer(&["--examples", path(&examples)])
+        .run_with(provider.clone())
+        .await
+        .unwrap();
+
+    assert_eq!(project.source(), FIXED);
+    let requests = provider.requests();
+    assert_eq!(requests.len(), 1);
+    let prompt = requests[0].prompt();
+    assert!(prompt.contains(
+        "Here are examples of how issues of the same rule were fixed:\n\nBefore:\nimport sys\nprint(1)\n\nAfter:\nprint(1)\n\n"
+    ));
+    assert!(!prompt.contains("from os import"));
+    assert!(!prompt.contains("'long'"));
+}
+
+#[tokio::test]
+async fn follows_the_style_guide_given() {
+    let project = Project::new("style-guide");
+    let guide = project.dir.join("STYLE.md");
+    fs::write(
+        &guide,
+        "# Style\n\n- Name booleans `is_*`.\n- Use f-strings.\n",
+    )
+    .unwrap();
+    let provider = Arc::new(ScriptedProvider::new([FIXED]));
+
+    project
+        .fixer(&["--style-guide", path(&guide)])
+        .run_with(provider.clone())
+        .await
+        .unwrap();
+
+    assert_eq!(project.source(), FIXED);
+    let requests = provider.requests();
+    assert_eq!(requests.len(), 1);
+    let system = &requests[0].messages[0].content;
+    assert!(system.starts_with("You are an automated bot that fixes Python code issues"));
+    assert!(system.ends_with("\n\n# Style\n\n- Name booleans `is_*`.\n- Use f-strings."));
+}
+
+#[tokio::test]
+async fn fixes_issues_from_a_sarif_file() {
+    let project = Project::new("sarif-file");
+    let sarif = project.dir.join("results.sarif");
+    fs::write(
+        &sarif,
+        format!(
+            r#"{{"version":"2.1.0","runs":[{{
+  "tool":{{"driver":{{"name":"CodeQL","rules":[{{"id":"py/unused-import","helpUri":"https://codeql.github.com/codeql-query-help/python/py-unused-import/"}}]}}}},
+  "originalUriBaseIds":{{"%SRCROOT%":{{"uri":"file://{}/"}}}},
+  "results":[
+    {{"ruleId":"py/unused-import","ruleIndex":0,"message":{{"text":"Import of 'os' is not used."}},"locations":[{{"physicalLocation":{{"artifactLocation":{{"uri":"src/a.py","uriBaseId":"%SRCROOT%"}},"region":{{"startLine":1,"startColumn":1,"endColumn":10}}}}}}]}},
+    {{"ruleId":"py/unused-import","kind":"pass","message":{{"text":"Fine."}},"locations":[{{"physicalLocation":{{"artifactLocation":{{"uri":"src/a.py","uriBaseId":"%SRCROOT%"}},"region":{{"startLine":3}}}}}}]}}
+  ]
+}}]}}"#,
+            project.dir.display()
+        ),
+    )
+    .unwrap();
+    let report = project.dir.join("report.json");
+    let provider = Arc::new(ScriptedProvider::new([FIXED]));
+
+    project
+        .fixer(&[
+            "--from-sarif",
+            path(&sarif),
+            "--report",
+            &format!("json={}", path(&report)),
+        ])
+        .run_with(provider.clone())
+        .await
+        .unwrap();
+
+    assert_eq!(project.source(), FIXED);
+    let requests = provider.requests();
+    assert_eq!(requests.len(), 1);
+    assert!(requests[0].prompt().contains("Import of 'os' is not used."));
+    let report: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&report).unwrap()).unwrap();
+    assert_eq!(report["issues"].as_array().unwrap().len(), 1);
+    assert_eq!(report["issues"][0]["code"], "py/unused-import");
+}
+
+#[tokio::test]
+async fn passes_rule_selection_to_every_ruff_check() {
+    let project = Project::new("ruff-args");
+    let provider = Arc::new(ScriptedProvider::new([FIXED]));
+
+    project
+        .fixer(&[
+            "--select",
+            "F401",
+            "--select",
+            "E",
+            "--ignore",
+            "E501",
+            "--config",
+            "line-length = 100",
+            "--ruff-arg",
+            "--preview",
+        ])
+        .run_with(provider)
+        .await
+        .unwrap();
+
+    assert_eq!(project.source(), FIXED);
+    let invocations = project.ruff_invocations();
+    assert!(invocations
+        .iter()
+        .any(|invocation| invocation.starts_with("format --config line-length = 100")));
+    let checks: Vec<&String> = invocations
+        .iter()
+        .filter(|line| line.starts_with("check"))
+        .collect();
+    assert_eq!(checks.len(), 4);
+    for check in checks {
+        assert!(
+            check.contains("--config line-length = 100 --select F401,E --ignore E501 --preview")
+        );
+    }
+}
+
+#[tokio::test]
+async fn finds_ruff_in_the_project_virtualenv() {
+    let project = Project::new("venv-ruff");
+    let bin = project.dir.join(".venv/bin");
+    fs::create_dir_all(&bin).unwrap();
+    fs::rename(project.dir.join("ruff"), bin.join("ruff")).unwrap();
+    let root = project.dir.join("src");
+    let journal = project.journal_dir();
+    let state = project.state_file();
+    let fixer = RuffFixer::from_iter([
+        "ruffer",
+        "unused-key",
+        path(&root),
+        "--no-cache",
+        "--journal-dir",
+        path(&journal),
+        "--state-file",
+        path(&state),
+    ]);
+    let provider = Arc::new(ScriptedProvider::new([FIXED]));
+
+    Arc::new(fixer).run_with(provider).await.unwrap();
+
+    assert_eq!(project.source(), FIXED);
+}
+
+#[tokio::test]
+async fn fails_the_run_when_ruff_fails_or_reports_no_json() {
+    let project = Project::new("ruff-fails");
+    let ruff = project.dir.join("ruff");
+    for (script, expected) in [
+        (
+            "echo 'unexpected argument' >&2; exit 2",
+            "Ruff check failed with exit status: 2: unexpected argument",
+        ),
+        (
+            "echo 'a.py:1:8: F401'; exit 1",
+            "didn't report its issues as JSON",
+        ),
+    ] {
+        fs::write(&ruff, format!("#!/bin/sh\n{}\n", script)).unwrap();
+        let provider = Arc::new(ScriptedProvider::new([FIXED]));
+
+        let err = project
+            .fixer(&[])
+            .run_with(provider.clone())
+            .await
+            .unwrap_err();
+
+        assert!(err.to_string().contains(expected), "{}", err);
+        assert_eq!(project.source(), UNFIXED);
+        assert!(provider.requests().is_empty());
+    }
+}
+
+#[test]
+fn gives_issues_the_same_ids_wherever_ruffer_runs() {
+    let project = Project::new("ids-cwd");
+    fs::write(project.dir.join("pyproject.toml"), "").unwrap();
+    let ruff = project.dir.join("ruff");
+    let root = project.dir.join("src");
+    let ids = |cwd: &Path, baseline: &str| -> Vec<serde_json::Value> {
+        let baseline = project.dir.join(baseline);
+        let status = std::process::Command::new(env!("CARGO_BIN_EXE_rust_ruffer"))
+            .args(["baseline", "create", "--baseline"])
+            .args([&baseline, &ruff, &root])
+            .current_dir(cwd)
+            .status()
+            .unwrap();
+        assert!(status.success());
+        let baseline: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(baseline).unwrap()).unwrap();
+        baseline["issues"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|issue| issue["id"].clone())
+            .collect()
+    };
+
+    let from_root = ids(&project.dir, "root.json");
+    let from_src = ids(&root, "src.json");
+
+    assert_eq!(from_root.len(), 1);
+    assert_eq!(from_root, from_src);
+}
+
+#[tokio::test]
+async fn never_touches_files_the_project_excludes() {
+    let project = Project::new("excluded");
+    fs::write(project.dir.join("excluded"), "").unwrap();
+    let provider = Arc::new(ScriptedProvider::new([FIXED]));
+
+    project.fixer(&[]).run_with(provider.clone()).await.unwrap();
+
+    assert_eq!(project.source(), UNFIXED);
+    assert!(provider.requests().is_empty());
+}
+
+#[tokio::test]
+async fn only_sends_selected_rules_to_the_model() {
+    for args in [["--llm-rules", "D1xx"], ["--no-llm-rules", "F4"]] {
+        let project = Project::new(&format!("llm-rules{}", args[0]));
+        let provider = Arc::new(ScriptedProvider::new([FIXED]));
+
+        project
+            .fixer(&args)
+            .run_with(provider.clone())
+            .await
+            .unwrap();
+
+        assert_eq!(project.source(), UNFIXED);
+        assert!(provider.requests().is_empty());
+    }
+
+    let project = Project::new("llm-rules-match");
+    let provider = Arc::new(ScriptedProvider::new([FIXED]));
+    project
+        .fixer(&["--llm-rules", "D1,F401"])
+        .run_with(provider)
+        .await
+        .unwrap();
+    assert_eq!(project.source(), FIXED);
+}
+
+#[tokio::test]
+async fn follows_per_rule_strategies() {
+    let project = Project::new("strategy-noqa");
+    let provider = Arc::new(ScriptedProvider::new([FIXED]));
+    project
+        .fixer(&["--strategy", "F=skip,F401=noqa"])
+        .run_with(provider.clone())
+        .await
+        .unwrap();
+    assert_eq!(
+        project.source(),
+        "import os  # noqa: F401\n\nprint(\"hi\")\n"
+    );
+    assert!(provider.requests().is_empty());
+
+    let project = Project::new("strategy-autofix");
+    project.offer_ruff_fixes("unsafe");
+    let provider = Arc::new(ScriptedProvider::new([FIXED]));
+    project
+        .fixer(&["--strategy", "F4=autofix"])
+        .run_with(provider.clone())
+        .await
+        .unwrap();
+    assert_eq!(project.source(), "\nprint(\"hi\")\n");
+    assert!(provider.requests().is_empty());
+
+    let project = Project::new("strategy-llm");
+    project.offer_ruff_fixes("safe");
+    let provider = Arc::new(ScriptedProvider::new([FIXED]));
+    project
+        .fixer(&["--strategy", "F401=llm"])
+        .run_with(provider.clone())
+        .await
+        .unwrap();
+    assert_eq!(project.source(), FIXED);
+    assert_eq!(provider.requests().len(), 1);
+}
+
+#[tokio::test]
+async fn suppresses_issues_with_justified_noqa_comments() {
+    let project = Project::new("noqa-justify");
+    let provider = Arc::new(ScriptedProvider::new([
+        "# Re-exported for plugins\nIt is used elsewhere.",
+    ]));
+    project
+        .fixer(&["--noqa", "--noqa-justify"])
+        .run_with(provider.clone())
+        .await
+        .unwrap();
+    assert_eq!(
+        project.source(),
+        "import os  # noqa: F401  # Re-exported for plugins\n\nprint(\"hi\")\n"
+    );
+    let requests = provider.requests();
+    assert_eq!(requests.len(), 1);
+    assert!(requests[0].prompt().contains("justification"));
+}
+
+#[tokio::test]
+async fn writes_missing_docstrings_from_the_definition() {
+    let project = Project::new("docstring");
+    project.write_source(
+        "class Calc:\n    @staticmethod\n    def add(a, b):\n        # Plain addition.\n        return a + b\n",
+    );
+    let report = project.dir.join("issues.json");
+    fs::write(
+        &report,
+        format!(
+            r#"[{{"filename":"{}","code":"D102","message":"Missing docstring in public method","location":{{"row":3,"column":9}}}}]"#,
+            project.dir.join("src/a.py").display()
+        ),
+    )
+    .unwrap();
+    let provider = Arc::new(ScriptedProvider::new([
+        "```python\n\"\"\"Add two numbers.\n\n    Args:\n        a: The first number.\n        b: The second number.\n    \"\"\"\n```",
+    ]));
+
+    project
+        .fixer(&["--issues", path(&report), "--no-verify"])
+        .run_with(provider.clone())
+        .await
+        .unwrap();
+
+    assert_eq!(
+        project.source(),
+        "class Calc:\n    @staticmethod\n    def add(a, b):\n        \"\"\"Add two numbers.\n\n        Args:\n            a: The first number.\n            b: The second number.\n        \"\"\"\n        # Plain addition.\n        return a + b\n"
+    );
+    let requests = provider.requests();
+    assert_eq!(requests.len(), 1);
+    let prompt = requests[0].prompt();
+    assert!(prompt.contains("Google style"));
+    assert!(
+        prompt.contains("    def add(a, b):\n        # Plain addition.\n        return a + b\n")
+    );
+    assert!(!prompt.contains("class Calc"));
+}
+
+#[tokio::test]
+async fn annotates_functions_and_type_checks_the_annotations() {
+    let project = Project::new("annotations");
+    project.write_source("def add(a, b):\n    return a + b\n\n\nprint(add(1, 2))\n");
+    let report = project.dir.join("issues.json");
+    fs::write(
+        &report,
+        format!(
+            r#"[{{"filename":"{}","code":"ANN201","message":"Missing return type annotation for public function `add`","location":{{"row":1,"column":5}}}}]"#,
+            project.dir.join("src/a.py").display()
+        ),
+    )
+    .unwrap();
+    // A mypy that only objects to functions returning `str`.
+    let bin = project.dir.join(".venv/bin");
+    fs::create_dir_all(&bin).unwrap();
+    let mypy = bin.join("mypy");
+    fs::write(
+        &mypy,
+        r#"#!/bin/sh
+for file; do :; done
+if grep -q -- '-> str' "$file"; then
+  echo "$file:2: error: Incompatible return value type (got \"int\", expected \"str\")  [return-value]"
+  exit 1
+fi
+"#,
+    )
+    .unwrap();
+    fs::set_permissions(&mypy, fs::Permissions::from_mode(0o755)).unwrap();
+    let provider = Arc::new(ScriptedProvider::new([
+        "def add(a, b) -> str:",
+        "```python\nfrom numbers import Number\n\ndef add(a, b) -> Number:\n```",
+    ]));
+
+    project
+        .fixer(&[
+            "--issues",
+            path(&report),
+            "--no-verify",
+            "--type-checker",
+            "mypy",
+        ])
+        .run_with(provider.clone())
+        .await
+        .unwrap();
+
+    assert_eq!(
+        project.source(),
+        "from numbers import Number\ndef add(a, b) -> Number:\n    return a + b\n\n\nprint(add(1, 2))\n"
+    );
+    let requests = provider.requests();
+    assert_eq!(requests.len(), 2);
+    assert!(requests[0].prompt().contains("line 2: return a + b"));
+    assert!(requests[0].prompt().contains("line 5: print(add(1, 2))"));
+    assert!(requests[1]
+        .prompt()
+        .contains("line 2: Incompatible return value type"));
+}
+
+#[tokio::test]
+async fn type_checks_every_fix_when_asked_to() {
+    let project = Project::new("type-check-fixes");
+    // A mypy that only objects to `BAD`.
+    let bin = project.dir.join(".venv/bin");
+    fs::create_dir_all(&bin).unwrap();
+    let mypy = bin.join("mypy");
+    fs::write(
+        &mypy,
+        r#"#!/bin/sh
+for file; do :; done
+if grep -q BAD "$file"; then
+  echo "$file:1: error: Name \"BAD\" is not defined  [name-defined]"
+  exit 1
+fi
+"#,
+    )
+    .unwrap();
+    fs::set_permissions(&mypy, fs::Permissions::from_mode(0o755)).unwrap();
+    let typed = "print(\"hi\", BAD)\n";
+
+    let provider = Arc::new(ScriptedProvider::new([typed, FIXED]));
+    project
+        .fixer(&["--no-verify", "--type-checker", "mypy"])
+        .run_with(provider.clone())
+        .await
+        .unwrap();
+    assert_eq!(project.source(), typed);
+    assert_eq!(provider.requests().len(), 1);
+
+    project.write_source(UNFIXED);
+    let provider = Arc::new(ScriptedProvider::new([typed, FIXED]));
+    project
+        .fixer(&["--no-verify", "--type-check-fixes"])
+        .run_with(provider.clone())
+        .await
+        .unwrap();
+    assert_eq!(project.source(), FIXED);
+    let requests = provider.requests();
+    assert_eq!(requests.len(), 2);
+    assert!(requests[1]
+        .prompt()
+        .contains("line 1: Name \"BAD\" is not defined"));
+}
+
+#[tokio::test]
+async fn fixes_the_issues_other_linters_find() {
+    let project = Project::new("pylint");
+    project.write_source("x = eval(\"1\")\nprint(x)\n");
+    // A pylint that only objects to `eval`, in the files given or on stdin.
+    let bin = project.dir.join(".venv/bin");
+    fs::create_dir_all(&bin).unwrap();
+    let pylint = bin.join("pylint");
+    fs::write(
+        &pylint,
+        r#"#!/bin/sh
+shift
+if [ "$1" = "--from-stdin" ]; then
+  input=$(cat)
+  files="$2"
+else
+  shift
+  files=$(find "$@" -name '*.py')
+fi
+echo "$files" | while read -r file; do
+  if [ -n "$input" ]; then echo "$input"; else cat "$file"; fi | grep -n 'eval(' | while IFS=: read -r line rest; do
+    printf '{"type":"warning","path":"%s","line":%s,"column":4,"message":"Use of eval","message-id":"W0123"}\n' "$file" "$line"
+  done
+done | { printf '['; paste -sd, -; printf ']'; }
+"#,
+    )
+    .unwrap();
+    fs::set_permissions(&pylint, fs::Permissions::from_mode(0o755)).unwrap();
+    let report = project.dir.join("report.json");
+    let provider = Arc::new(ScriptedProvider::new(["x = 1\nprint(x)\n"]));
+
+    project
+        .fixer(&[
+            "--linter",
+            "pylint",
+            "--report",
+            &format!("json={}", path(&report)),
+        ])
+        .run_with(provider.clone())
+        .await
+        .unwrap();
+
+    assert_eq!(project.source(), "x = 1\nprint(x)\n");
+    let requests = provider.requests();
+    assert_eq!(requests.len(), 1);
+    assert!(requests[0].prompt().contains("Use of eval"));
+    let report: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&report).unwrap()).unwrap();
+    assert_eq!(report["issues"][0]["code"], "PLW0123");
+    assert_eq!(report["issues"][0]["outcome"], "llm-fixed");
+}
+
+#[tokio::test]
+async fn fixes_the_issues_of_a_linter_declared_in_pyproject() {
+    let project = Project::new("declared-linter");
+    project.write_source("x = eval(\"1\")\nprint(x)\n");
+    fs::write(
+        project.dir.join("pyproject.toml"),
+        r#"[tool.ruffer.linter.evalcheck]
+command = "tools/evalcheck"
+args = ["--json", "{paths}"]
+issues = "$.results[*]"
+file = "@.location.path"
+line = "@.location.line"
+column = "@['location']['col']"
+code = "@.rule"
+message = "@.text"
+"#,
+    )
+    .unwrap();
+    // A linter that only objects to `eval`, in the files or directories
+    // given, with findings nested in its own JSON.
+    let tools = project.dir.join("tools");
+    fs::create_dir_all(&tools).unwrap();
+    let evalcheck = tools.join("evalcheck");
+    fs::write(
+        &evalcheck,
+        r#"#!/bin/sh
+shift
+find "$@" -type f | while read -r file; do
+  grep -n 'eval(' "$file" | while IFS=: read -r line rest; do
+    printf '{"location":{"path":"%s","line":%s,"col":5},"rule":"no-eval","text":"Use of eval"}\n' "$file" "$line"
+  done
+done | { printf '{"results":['; paste -sd, -; printf ']}'; }
+"#,
+    )
+    .unwrap();
+    fs::set_permissions(&evalcheck, fs::Permissions::from_mode(0o755)).unwrap();
+    let report = project.dir.join("report.json");
+    let provider = Arc::new(ScriptedProvider::new(["x = 1\nprint(x)\n"]));
+
+    project
+        .fixer(&[
+            "--linter",
+            "evalcheck",
+            "--report",
+            &format!("json={}", path(&report)),
+        ])
+        .run_with(provider.clone())
+        .await
+        .unwrap();
+
+    assert_eq!(project.source(), "x = 1\nprint(x)\n");
+    let requests = provider.requests();
+    assert_eq!(requests.len(), 1);
+    assert!(requests[0].prompt().contains("Use of eval"));
+    let report: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&report).unwrap()).unwrap();
+    assert_eq!(report["issues"][0]["code"], "no-eval");
+    assert_eq!(report["issues"][0]["outcome"], "llm-fixed");
+}
+
+#[tokio::test]
+async fn fixes_javascript_with_eslint() {
+    let project = Project::with_file("eslint", "src/app.js");
+    project.write_source("var x = 1;\nconsole.log(x);\n");
+    // An ESLint installed in the project that only objects to `var`, in the
+    // files given or on stdin.
+    let bin = project.dir.join("node_modules/.bin");
+    fs::create_dir_all(&bin).unwrap();
+    let eslint = bin.join("eslint");
+    fs::write(
+        &eslint,
+        r#"#!/bin/sh
+shift 2
+if [ "$1" = "--stdin" ]; then
+  input=$(cat)
+  files="$3"
+else
+  files=$(find "$@" -name '*.js')
+fi
+echo "$files" | while read -r file; do
+  messages=$(if [ -n "$input" ]; then echo "$input"; else cat "$file"; fi | grep -n '^var ' | while IFS=: read -r line rest; do
+    printf '{"ruleId":"no-var","message":"Unexpected var, use let or const instead.","line":%s,"column":1,"endLine":%s,"endColumn":10}\n' "$line" "$line"
+  done | paste -sd, -)
+  printf '{"filePath":"%s","messages":[%s]}\n' "$file" "$messages"
+done | { printf '['; paste -sd, -; printf ']'; }
+"#,
+    )
+    .unwrap();
+    fs::set_permissions(&eslint, fs::Permissions::from_mode(0o755)).unwrap();
+    let fixed = "let x = 1;\nconsole.log(x);\n";
+    let provider = Arc::new(ScriptedProvider::new([fixed]));
+
+    project
+        .fixer(&["--linter", "eslint"])
+        .run_with(provider.clone())
+        .await
+        .unwrap();
+
+    assert_eq!(project.source(), fixed);
+    let requests = provider.requests();
+    assert_eq!(requests.len(), 1);
+    assert!(requests[0].messages[0]
+        .content
+        .contains("Python, JavaScript and TypeScript code"));
+    let prompt = requests[0].prompt();
+    assert!(prompt.contains("Fix the following issue in the JavaScript code"));
+    assert!(prompt.contains("Unexpected var"));
+}
+
+#[tokio::test]
+async fn fixes_rust_code_with_clippy() {
+    let project = Project::with_file("clippy", "src/lib.rs");
+    project.write_source(
+        "fn answer() -> i32 {\n    return 42;\n}\n\nfn main() {\n    let x = 1;\n}\n",
+    );
+    // A cargo whose clippy objects to `return 42;`, with a fix, and to
+    // `let x`, without one; and a rustfmt leaving the code as it is.
+    let bin = project.dir.join(".venv/bin");
+    fs::create_dir_all(&bin).unwrap();
+    fs::write(
+        bin.join("cargo"),
+        r#"#!/bin/sh
+dir=$(cd "$(dirname "$0")/../.." && pwd)
+case "$1" in
+locate-project) echo "$dir/Cargo.toml" ;;
+clippy)
+  grep -n 'return 42;' "$dir/src/lib.rs" | cut -d: -f1 | while read -r n; do
+    printf '{"reason":"compiler-message","message":{"message":"unneeded `return` statement","level":"warning","code":{"code":"clippy::needless_return"},"spans":[{"file_name":"src/lib.rs","line_start":%s,"line_end":%s,"column_start":5,"column_end":15,"is_primary":true}],"children":[{"message":"remove `return`","level":"help","spans":[{"file_name":"src/lib.rs","line_start":%s,"line_end":%s,"column_start":5,"column_end":15,"is_primary":true,"suggested_replacement":"42","suggestion_applicability":"MachineApplicable"}],"children":[]}]}}\n' $n $n $n $n
+  done
+  grep -n 'let x' "$dir/src/lib.rs" | cut -d: -f1 | while read -r n; do
+    printf '{"reason":"compiler-message","message":{"message":"unused variable: `x`","level":"warning","code":{"code":"unused_variables"},"spans":[{"file_name":"src/lib.rs","line_start":%s,"line_end":%s,"column_start":9,"column_end":10,"is_primary":true}],"children":[]}}\n' $n $n
+  done
+  echo '{"reason":"build-finished","success":true}'
+  ;;
+esac
+"#,
+    )
+    .unwrap();
+    fs::write(bin.join("rustfmt"), "#!/bin/sh\ncat\n").unwrap();
+    for tool in ["cargo", "rustfmt"] {
+        fs::set_permissions(bin.join(tool), fs::Permissions::from_mode(0o755)).unwrap();
+    }
+    let fixed = "fn answer() -> i32 {\n    42\n}\n\nfn main() {\n    let _x = 1;\n}\n";
+    let provider = Arc::new(ScriptedProvider::new([fixed]));
+
+    project
+        .fixer(&["--language", "rust"])
+        .run_with(provider.clone())
+        .await
+        .unwrap();
+
+    assert_eq!(project.source(), fixed);
+    let requests = provider.requests();
+    assert_eq!(requests.len(), 1);
+    assert!(requests[0].messages[0].content.contains("Rust code"));
+    let prompt = requests[0].prompt();
+    assert!(prompt.contains("Fix the following issue in the Rust code"));
+    assert!(prompt.contains("unused variable: `x`"));
+    assert!(!prompt.contains("return 42"));
+}
+
+#[tokio::test]
+async fn refuses_linters_when_fixing_rust() {
+    let project = Project::with_file("rust-linter", "src/main.rs");
+    let provider = Arc::new(ScriptedProvider::new([FIXED]));
+
+    let err = project
+        .fixer(&["--language", "rust", "--linter", "flake8"])
+        .run_with(provider.clone())
+        .await
+        .unwrap_err();
+
+    assert!(
+        err.to_string()
+            .contains("--linter can't be used with --language rust"),
+        "{}",
+        err
+    );
+    assert!(provider.requests().is_empty());
+}
+
+#[tokio::test]
+async fn proposes_fixes_of_security_issues_for_review() {
+    let project = Project::new("security");
+    project.write_source("password = \"hunter2\"\n");
+    let report = project.dir.join("issues.json");
+    fs::write(
+        &report,
+        format!(
+            r#"[{{"filename":"{}","code":"S105","message":"Possible hardcoded password assigned to: \"password\"","location":{{"row":1,"column":12}}}}]"#,
+            project.dir.join("src/a.py").display()
+        ),
+    )
+    .unwrap();
+    let review = project.dir.join("review.patch");
+    let provider = Arc::new(ScriptedProvider::new([
+        "import os\n\npassword = os.environ[\"PASSWORD\"]\n",
+        "The password is readable by anyone with the code.\nThe fix reads it from the environment; make sure PASSWORD is set.",
+    ]));
+
+    project
+        .fixer(&[
+            "--issues",
+            path(&report),
+            "--no-verify",
+            "--security-report",
+            path(&review),
+        ])
+        .run_with(provider.clone())
+        .await
+        .unwrap();
+
+    assert_eq!(project.source(), "password = \"hunter2\"\n");
+    let review = fs::read_to_string(review).unwrap();
+    assert!(review.contains("] S105 at "));
+    assert!(review.contains("\n# The password is readable by anyone with the code.\n"));
+    assert!(review.contains(
+        "\n-password = \"hunter2\"\n+import os\n+\n+password = os.environ[\"PASSWORD\"]\n"
+    ));
+    let requests = provider.requests();
+    assert_eq!(requests.len(), 2);
+    assert!(requests[1].prompt().contains("+password = os.environ"));
+}
+
+#[tokio::test]
+async fn writes_no_security_review_when_checking() {
+    let project = Project::new("security-check");
+    project.write_source("password = \"hunter2\"\n");
+    let report = project.dir.join("issues.json");
+    fs::write(
+        &report,
+        format!(
+            r#"[{{"filename":"{}","code":"S105","message":"Possible hardcoded password assigned to: \"password\"","location":{{"row":1,"column":12}}}}]"#,
+            project.dir.join("src/a.py").display()
+        ),
+    )
+    .unwrap();
+    let review = project.dir.join("review.patch");
+    let provider = Arc::new(ScriptedProvider::new([
+        "import os\n\npassword = os.environ[\"PASSWORD\"]\n",
+        "The password is readable by anyone with the code.",
+    ]));
+
+    let err = project
+        .fixer(&[
+            "--check",
+            "--issues",
+            path(&report),
+            "--no-verify",
+            "--security-report",
+            path(&review),
+        ])
+        .run_with(provider.clone())
+        .await
+        .unwrap_err();
+
+    assert_eq!(err.to_string(), "Failing the run: 1 issues left unfixed");
+    assert_eq!(project.source(), "password = \"hunter2\"\n");
+    assert!(!review.exists());
+}
+
+#[tokio::test]
+async fn explains_fixes_on_request() {
+    let project = Project::new("explain");
+    let provider = Arc::new(ScriptedProvider::new([
+        FIXED,
+        "The import of `os` was never used, so it was removed.",
+    ]));
+
+    project
+        .fixer(&["--explain"])
+        .run_with(provider.clone())
+        .await
+        .unwrap();
+
+    assert_eq!(project.source(), FIXED);
+    let requests = provider.requests();
+    assert_eq!(requests.len(), 2);
+    let prompt = requests[1].prompt();
+    assert!(prompt.contains("F401: `os` imported but unused"));
+    assert!(prompt.contains("\n-import os\n"));
+}
+
+#[tokio::test]
+async fn writes_review_comments_instead_of_fixing() {
+    let project = Project::new("review");
+    let review = project.dir.join("review.md");
+    let provider = Arc::new(ScriptedProvider::new([
+        "Approach: Remove the unused import.\nCaveats: None",
+    ]));
+
+    project
+        .fixer(&["--review", "--review-file", path(&review)])
+        .run_with(provider.clone())
+        .await
+        .unwrap();
+
+    assert_eq!(project.source(), UNFIXED);
+    let review = fs::read_to_string(review).unwrap();
+    assert!(review.contains("] F401 at line 1: `os` imported but unused\n"));
+    assert!(review.contains("```python\nimport os\n```"));
+    assert!(review.contains("**Suggested approach:** Remove the unused import.\n"));
+    assert!(!review.contains("**Caveats:**"));
+    assert!(provider.requests()[0].prompt().contains("Approach:"));
+    assert!(!project
+        .ruff_invocations()
+        .iter()
+        .any(|invocation| invocation.starts_with("format")));
+}
+
+#[tokio::test]
+async fn leaves_issues_of_the_baseline_alone() {
+    let project = Project::new("baseline");
+    let baseline = project.dir.join("baseline.json");
+    let ruff = project.dir.join("ruff");
+    let root = project.dir.join("src");
+    let options = BaselineOptions::from_iter([
+        "baseline",
+        path(&ruff),
+        path(&root),
+        "--baseline",
+        path(&baseline),
+    ]);
+    options.create().await.unwrap();
+    assert!(fs::read_to_string(&baseline)
+        .unwrap()
+        .contains("\"message\": \"`os` imported but unused\""));
+    assert!(options.create().await.is_err());
+
+    project.write_source("import os\nimport sys\n\nprint(\"hi\")\n");
+    let provider = Arc::new(ScriptedProvider::new([UNFIXED]));
+    project
+        .fixer(&["--baseline", path(&baseline)])
+        .run_with(provider.clone())
+        .await
+        .unwrap();
+
+    assert_eq!(project.source(), UNFIXED);
+    let requests = provider.requests();
+    assert_eq!(requests.len(), 1);
+    assert!(requests[0].prompt().contains("`sys` imported but unused"));
+}
+
+#[tokio::test]
+async fn never_reads_files_listed_in_rufferignore() {
+    let project = Project::new("rufferignore");
+    fs::write(project.dir.join("src/.rufferignore"), "# generated\n*.py\n").unwrap();
+    let provider = Arc::new(ScriptedProvider::new([FIXED]));
+
+    project.fixer(&[]).run_with(provider.clone()).await.unwrap();
+
+    assert_eq!(project.source(), UNFIXED);
+    assert!(provider.requests().is_empty());
+
+    fs::write(project.dir.join("src/.rufferignore"), "*.py\n!/a.py\n").unwrap();
+    project.fixer(&[]).run_with(provider.clone()).await.unwrap();
+
+    assert_eq!(project.source(), FIXED);
+}
+
+#[tokio::test]
+async fn skips_files_git_ignores_unless_asked_not_to() {
+    let project = Project::new("gitignore");
+    let git = std::process::Command::new("git")
+        .args(["init", "-q"])
+        .arg(&project.dir)
+        .status();
+    if !git.is_ok_and(|status| status.success()) {
+        return;
+    }
+    fs::write(project.dir.join(".gitignore"), "src/a.py\n").unwrap();
+    let provider = Arc::new(ScriptedProvider::new([FIXED]));
+
+    project.fixer(&[]).run_with(provider.clone()).await.unwrap();
+
+    assert_eq!(project.source(), UNFIXED);
+    assert!(provider.requests().is_empty());
+
+    project
+        .fixer(&["--no-respect-gitignore"])
+        .run_with(provider.clone())
+        .await
+        .unwrap();
+
+    assert_eq!(project.source(), FIXED);
+}
+
+#[tokio::test]
+async fn fixes_and_restages_only_staged_files() {
+    let project = Project::new("staged");
+    let git = |args: &[&str]| {
+        std::process::Command::new("git")
+            .arg("-C")
+            .arg(&project.dir)
+            .args(args)
+            .output()
+    };
+    if !git(&["init", "-q"]).is_ok_and(|output| output.status.success()) {
+        return;
+    }
+    let provider = Arc::new(ScriptedProvider::new([FIXED]));
+
+    project
+        .fixer(&["--staged"])
+        .run_with(provider.clone())
+        .await
+        .unwrap();
+
+    assert_eq!(project.source(), UNFIXED);
+    assert!(project.ruff_invocations().is_empty());
+
+    git(&["add", "src/a.py"]).unwrap();
+    project
+        .fixer(&["--staged"])
+        .run_with(provider.clone())
+        .await
+        .unwrap();
+
+    assert_eq!(project.source(), FIXED);
+    let staged = git(&["show", ":src/a.py"]).unwrap().stdout;
+    assert_eq!(String::from_utf8(staged).unwrap(), FIXED);
+}
+
+#[tokio::test]
+async fn only_checks_files_changed_since_the_diff_base() {
+    let project = Project::new("diff-base");
+    let git = |args: &[&str]| {
+        std::process::Command::new("git")
+            .arg("-C")
+            .arg(&project.dir)
+            .args([
+                "-c",
+                "user.name=ruffer",
+                "-c",
+                "user.email=ruffer@example.com",
+            ])
+            .args(args)
+            .output()
+    };
+    if !git(&["init", "-q"]).is_ok_and(|output| output.status.success()) {
+        return;
+    }
+    project.write_source(FIXED);
+    git(&["add", "src/a.py"]).unwrap();
+    git(&["commit", "-q", "-m", "base"]).unwrap();
+    let provider = Arc::new(ScriptedProvider::new([FIXED]));
+
+    project
+        .fixer(&["--diff-base", "HEAD"])
+        .run_with(provider.clone())
+        .await
+        .unwrap();
+
+    assert!(project.ruff_invocations().is_empty());
+
+    project.write_source(UNFIXED);
+    project
+        .fixer(&["--diff-base", "HEAD"])
+        .run_with(provider.clone())
+        .await
+        .unwrap();
+
+    assert_eq!(project.source(), FIXED);
+    assert_eq!(provider.requests().len(), 1);
+}
+
+#[tokio::test]
+async fn only_fixes_issues_in_changed_lines() {
+    let project = Project::new("changed-lines");
+    let git = |args: &[&str]| {
+        std::process::Command::new("git")
+            .arg("-C")
+            .arg(&project.dir)
+            .args([
+                "-c",
+                "user.name=ruffer",
+                "-c",
+                "user.email=ruffer@example.com",
+            ])
+            .args(args)
+            .output()
+    };
+    if !git(&["init", "-q"]).is_ok_and(|output| output.status.success()) {
+        return;
+    }
+    git(&["add", "src/a.py"]).unwrap();
+    git(&["commit", "-q", "-m", "base"]).unwrap();
+    project.write_source("import os\nimport sys\n\nprint(\"hi\")\n");
+    let provider = Arc::new(ScriptedProvider::new([UNFIXED]));
+
+    project
+        .fixer(&["--diff-base", "HEAD", "--changed-lines-only"])
+        .run_with(provider.clone())
+        .await
+        .unwrap();
+
+    assert_eq!(project.source(), UNFIXED);
+    let requests = provider.requests();
+    assert_eq!(requests.len(), 1);
+    assert!(requests[0].prompt().contains("`sys` imported but unused"));
+    assert!(!requests[0].prompt().contains("`os` imported but unused"));
+}
+
+#[tokio::test]
+async fn commits_the_fixes_of_each_file() {
+    let project = Project::new("commit");
+    let git = |args: &[&str]| {
+        std::process::Command::new("git")
+            .arg("-C")
+            .arg(&project.dir)
+            .args(args)
+            .output()
+    };
+    if !git(&["init", "-q"]).is_ok_and(|output| output.status.success()) {
+        return;
+    }
+    git(&["config", "user.name", "ruffer"]).unwrap();
+    git(&["config", "user.email", "ruffer@example.com"]).unwrap();
+    git(&["add", "src/a.py"]).unwrap();
+    git(&["commit", "-q", "-m", "base"]).unwrap();
+    let provider = Arc::new(ScriptedProvider::new([FIXED]));
+
+    project
+        .fixer(&["--commit"])
+        .run_with(provider)
+        .await
+        .unwrap();
+
+    assert_eq!(project.source(), FIXED);
+    let log = git(&["log", "-1", "--format=%B"]).unwrap();
+    let message = String::from_utf8_lossy(&log.stdout);
+    assert!(message.starts_with("Fix F401 in "));
+    assert!(message.contains("- F401 at line 1: `os` imported but unused"));
+    let dirty = git(&["diff", "HEAD", "--name-only"]).unwrap();
+    assert!(dirty.stdout.is_empty());
+}
+
+#[tokio::test]
+async fn opens_a_pull_request_with_the_fixes() {
+    let project = Project::new("open-pr");
+    let remote = project.dir.with_extension("remote.git");
+    let git = |dir: &Path, args: &[&str]| {
+        std::process::Command::new("git")
+            .arg("-C")
+            .arg(dir)
+            .args(args)
+            .output()
+    };
+    if !git(&project.dir, &["init", "-q", "-b", "main"]).is_ok_and(|output| output.status.success())
+    {
+        return;
+    }
+    git(&project.dir, &["init", "-q", "--bare", path(&remote)]).unwrap();
+    git(&project.dir, &["config", "user.name", "ruffer"]).unwrap();
+    git(
+        &project.dir,
+        &["config", "user.email", "ruffer@example.com"],
+    )
+    .unwrap();
+    git(&project.dir, &["remote", "add", "origin", path(&remote)]).unwrap();
+    git(&project.dir, &["add", "src/a.py"]).unwrap();
+    git(&project.dir, &["commit", "-q", "-m", "base"]).unwrap();
+
+    let (api_url, requests) = fake_api(&[(
+        "/",
+        r#"{"html_url": "https://github.com/owner/name/pull/1"}"#,
+    )]);
+    std::env::set_var("GITHUB_TOKEN", "token");
+
+    project
+        .fixer(&[
+            "--open-pr",
+            "--pr-branch",
+            "ruffer/fixes",
+            "--github-repo",
+            "owner/name",
+            "--github-api-url",
+            &api_url,
+        ])
+        .run_with(Arc::new(ScriptedProvider::new([FIXED])))
+        .await
+        .unwrap();
+
+    // The fixes are on the branch pushed, and the branch checked out is
+    // left as it was.
+    assert_eq!(project.source(), UNFIXED);
+    let head = git(&project.dir, &["symbolic-ref", "--short", "HEAD"]).unwrap();
+    assert_eq!(String::from_utf8_lossy(&head.stdout).trim(), "main");
+    let pushed = git(&remote, &["show", "ruffer/fixes:src/a.py"]).unwrap();
+    assert_eq!(String::from_utf8_lossy(&pushed.stdout), FIXED);
+    let requests = requests.lock().unwrap();
+    let (path, body) = &requests[0];
+    assert_eq!(path, "/repos/owner/name/pulls");
+    assert_eq!(body["head"], "ruffer/fixes");
+    assert_eq!(body["base"], "main");
+    assert_eq!(body["title"], "Fix 1 ruff issues in 1 files");
+    assert!(body["body"].as_str().unwrap().contains("F401"));
+    fs::remove_dir_all(&remote).unwrap();
+}
+
+#[tokio::test]
+async fn opens_a_merge_request_with_the_fixes() {
+    let project = Project::new("open-mr");
+    let remote = project.dir.with_extension("remote.git");
+    let git = |dir: &Path, args: &[&str]| {
+        std::process::Command::new("git")
+            .arg("-C")
+            .arg(dir)
+            .args(args)
+            .output()
+    };
+    if !git(&project.dir, &["init", "-q", "-b", "main"]).is_ok_and(|output| output.status.success())
+    {
+        return;
+    }
+    git(&project.dir, &["init", "-q", "--bare", path(&remote)]).unwrap();
+    git(&project.dir, &["config", "user.name", "ruffer"]).unwrap();
+    git(
+        &project.dir,
+        &["config", "user.email", "ruffer@example.com"],
+    )
+    .unwrap();
+    let url = "git@gitlab.example.com:group/sub/name.git";
+    git(&project.dir, &["remote", "add", "origin", url]).unwrap();
+    git(
+        &project.dir,
+        &["remote", "set-url", "--push", "origin", path(&remote)],
+    )
+    .unwrap();
+    git(&project.dir, &["add", "src/a.py"]).unwrap();
+    git(&project.dir, &["commit", "-q", "-m", "base"]).unwrap();
+    git(&project.dir, &["branch", "develop"]).unwrap();
+    let (gitlab_url, requests) = fake_api(&[
+        ("/api/v4/users", r#"[{"id": 7}]"#),
+        (
+            "/api/v4/projects/",
+            r#"{"web_url": "https://gitlab.example.com/group/sub/name/-/merge_requests/1"}"#,
+        ),
+    ]);
+    std::env::set_var("GITLAB_TOKEN", "token");
+
+    project
+        .fixer(&[
+            "--open-mr",
+            "--pr-branch",
+            "ruffer/fixes",
+            "--pr-base",
+            "develop",
+            "--mr-label",
+            "hygiene",
+            "--mr-label",
+            "bot",
+            "--mr-reviewer",
+            "alice",
+            "--gitlab-url",
+            &gitlab_url,
+        ])
+        .run_with(Arc::new(ScriptedProvider::new([FIXED])))
+        .await
+        .unwrap();
+
+    let pushed = git(&remote, &["show", "ruffer/fixes:src/a.py"]).unwrap();
+    assert_eq!(String::from_utf8_lossy(&pushed.stdout), FIXED);
+    let requests = requests.lock().unwrap();
+    assert_eq!(requests[0].0, "/api/v4/users?username=alice");
+    let (path, body) = &requests[1];
+    assert_eq!(path, "/api/v4/projects/group%2Fsub%2Fname/merge_requests");
+    assert_eq!(body["source_branch"], "ruffer/fixes");
+    assert_eq!(body["target_branch"], "develop");
+    assert_eq!(body["labels"], "hygiene,bot");
+    assert_eq!(body["reviewer_ids"], serde_json::json!([7]));
+    fs::remove_dir_all(&remote).unwrap();
+}
+
+#[tokio::test]
+async fn suggests_the_fixes_on_a_pull_request() {
+    let project = Project::new("suggest");
+    let init = std::process::Command::new("git")
+        .args(["init", "-q"])
+        .arg(&project.dir)
+        .status();
+    if !init.is_ok_and(|status| status.success()) {
+        return;
+    }
+    let (api_url, requests) = fake_api(&[(
+        "/",
+        r#"{"html_url": "https://github.com/owner/name/pull/5#pullrequestreview-1"}"#,
+    )]);
+    std::env::set_var("GITHUB_TOKEN", "token");
+
+    project
+        .fixer(&[
+            "--suggest-on-pr",
+            "5",
+            "--github-repo",
+            "owner/name",
+            "--github-api-url",
+            &api_url,
+        ])
+        .run_with(Arc::new(ScriptedProvider::new([FIXED])))
+        .await
+        .unwrap();
+
+    assert_eq!(project.source(), UNFIXED);
+    let requests = requests.lock().unwrap();
+    let (path, body) = &requests[0];
+    assert_eq!(path, "/repos/owner/name/pulls/5/reviews");
+    assert_eq!(body["event"], "COMMENT");
+    let comment = &body["comments"][0];
+    assert_eq!(comment["path"], "src/a.py");
+    assert_eq!(comment["start_line"], 1);
+    assert_eq!(comment["line"], 2);
+    assert_eq!(
+        comment["body"],
+        "F401: `os` imported but unused\n\n```suggestion\n```\n"
+    );
+}
+
+/// A web API on a free port answering each request with the answer of the
+/// first of `answers` whose path its path starts with. Its address, and
+/// the path, query included, and JSON body of every request, in order.
+#[allow(clippy::type_complexity)]
+fn fake_api(
+    answers: &[(&'static str, &'static str)],
+) -> (
+    String,
+    Arc<std::sync::Mutex<Vec<(String, serde_json::Value)>>>,
+) {
+    let answers = answers.to_vec();
+    let requests = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let recorded = Arc::clone(&requests);
+    let make_service = hyper::service::make_service_fn(move |_| {
+        let (answers, recorded) = (answers.clone(), Arc::clone(&recorded));
+        async move {
+            Ok::<_, std::convert::Infallible>(hyper::service::service_fn(
+                move |request: hyper::Request<hyper::Body>| {
+                    let (answers, recorded) = (answers.clone(), Arc::clone(&recorded));
+                    async move {
+                        let path = request.uri().to_string();
+                        let body = hyper::body::to_bytes(request.into_body()).await?;
+                        let body = serde_json::from_slice(&body).unwrap_or_default();
+                        let answer = answers
+                            .iter()
+                            .find(|(prefix, _)| path.starts_with(prefix))
+                            .map_or("{}", |(_, answer)| answer);
+                        recorded.lock().unwrap().push((path, body));
+                        Ok::<_, hyper::Error>(hyper::Response::new(hyper::Body::from(answer)))
+                    }
+                },
+            ))
+        }
+    });
+    let server = hyper::Server::bind(&"127.0.0.1:0".parse().unwrap()).serve(make_service);
+    let url = format!("http://{}", server.local_addr());
+    tokio::spawn(server);
+    (url, requests)
+}
+
+#[test]
+fn installs_a_pre_commit_hook() {
+    let project = Project::new("install-hook");
+    let init = std::process::Command::new("git")
+        .args(["init", "-q"])
+        .arg(&project.dir)
+        .status();
+    if !init.is_ok_and(|status| status.success()) {
+        return;
+    }
+    let exe = Path::new("/usr/local/bin/ruffer");
+    let args = ["--model".to_string(), "gpt 4".to_string()];
+
+    let hook = git::install_hook(&project.dir, exe, &args, false).unwrap();
+
+    let script = fs::read_to_string(&hook).unwrap();
+    assert!(script.starts_with("#!/bin/sh\n"));
+    assert!(script.ends_with("exec /usr/local/bin/ruffer pre-commit --staged --model 'gpt 4'\n"));
+    assert_eq!(
+        fs::metadata(&hook).unwrap().permissions().mode() & 0o111,
+        0o111
+    );
+    let err = git::install_hook(&project.dir, exe, &args, false).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::AlreadyExists);
+    git::install_hook(&project.dir, exe, &[], true).unwrap();
+}
+
+#[tokio::test]
+async fn fails_ci_runs_as_the_policy_says() {
+    let project = Project::new("ci");
+    let failure = |err: std::io::Error| {
+        err.get_ref()
+            .and_then(|inner| inner.downcast_ref::<PolicyFailure>())
+            .map(ToString::to_string)
+    };
+
+    let err = project
+        .fixer(&["--ci"])
+        .run_with(Arc::new(
+            ScriptedProvider::new(Vec::<String>::new()).then_fail("down"),
+        ))
+        .await
+        .unwrap_err();
+    assert_eq!(
+        failure(err).as_deref(),
+        Some("Failing the run: 1 issues left unfixed, 1 requests to the model failed")
+    );
+
+    let err = project
+        .fixer(&["--fail-on", "any-change"])
+        .run_with(Arc::new(ScriptedProvider::new([FIXED])))
+        .await
+        .unwrap_err();
+    assert_eq!(
+        failure(err).as_deref(),
+        Some("Failing the run: 1 files with fixes")
+    );
+
+    project.write_source(UNFIXED);
+    project
+        .fixer(&["--ci"])
+        .run_with(Arc::new(ScriptedProvider::new([FIXED])))
+        .await
+        .unwrap();
+    assert_eq!(project.source(), FIXED);
+}
+
+#[tokio::test]
+async fn checks_without_writing_and_fails_if_there_is_anything_to_fix() {
+    let project = Project::new("check-gate");
+    let provider = Arc::new(ScriptedProvider::new([FIXED]));
+
+    let err = project
+        .fixer(&["--check"])
+        .run_with(provider.clone())
+        .await
+        .unwrap_err();
+
+    assert_eq!(err.to_string(), "Failing the run: 1 files with fixes");
+    assert_eq!(project.source(), UNFIXED);
+    assert_eq!(provider.requests().len(), 1);
+
+    project.write_source(FIXED);
+    project
+        .fixer(&["--check"])
+        .run_with(provider.clone())
+        .await
+        .unwrap();
+    assert_eq!(provider.requests().len(), 1);
+}
+
+#[tokio::test]
+async fn runs_the_post_fix_command_and_reports_its_output() {
+    let project = Project::new("post-fix");
+    let report = project.dir.join("report.json");
+    let read_report = || -> serde_json::Value {
+        serde_json::from_str(&fs::read_to_string(&report).unwrap()).unwrap()
+    };
+
+    project
+        .fixer(&[
+            "--post-fix-cmd",
+            "cat {file}",
+            "--report",
+            &format!("json={}", path(&report)),
+        ])
+        .run_with(Arc::new(ScriptedProvider::new([FIXED])))
+        .await
+        .unwrap();
+    let runs = read_report()["post_fix"].clone();
+    assert_eq!(runs.as_array().unwrap().len(), 1);
+    assert_eq!(runs[0]["passed"], true);
+    assert_eq!(runs[0]["output"], FIXED);
+    assert_eq!(runs[0]["files"][0], path(&project.file));
+
+    project.write_source(UNFIXED);
+    project
+        .fixer(&[
+            "--post-fix-cmd",
+            "echo testing {files}; exit 3",
+            "--report",
+            &format!("json={}", path(&report)),
+        ])
+        .run_with(Arc::new(ScriptedProvider::new([FIXED])))
+        .await
+        .unwrap();
+    let runs = read_report()["post_fix"].clone();
+    assert_eq!(runs.as_array().unwrap().len(), 1);
+    assert_eq!(runs[0]["passed"], false);
+    assert_eq!(runs[0]["exit_code"], 3);
+    assert_eq!(
+        runs[0]["output"],
+        format!("testing {}\n", path(&project.file))
+    );
+}
+
+#[tokio::test]
+async fn rolls_back_the_files_whose_post_fix_command_fails() {
+    let project = Project::new("roll-back");
+    let report = project.dir.join("report.json");
+    let read_report = || -> serde_json::Value {
+        serde_json::from_str(&fs::read_to_string(&report).unwrap()).unwrap()
+    };
+
+    for command in ["test ! -s {file}", "exit 1"] {
+        project.write_source(UNFIXED);
+        project
+            .fixer(&[
+                "--post-fix-cmd",
+                command,
+                "--report",
+                &format!("json={}", path(&report)),
+            ])
+            .run_with(Arc::new(ScriptedProvider::new([FIXED])))
+            .await
+            .unwrap();
+        assert_eq!(project.source(), UNFIXED, "{}", command);
+        let report = read_report();
+        assert_eq!(report["issues"][0]["outcome"], "needs-human", "{}", command);
+    }
+
+    journal::undo(&project.journal_dir(), None).unwrap();
+    assert_eq!(project.source(), UNFIXED);
+}
+
+#[tokio::test]
+async fn runs_no_post_fix_command_for_files_left_unchanged() {
+    let project = Project::new("post-fix-unchanged");
+    let report = project.dir.join("report.json");
+
+    for command in ["exit 1 # {file}", "exit 1 # {files}"] {
+        project
+            .fixer(&[
+                "--post-fix-cmd",
+                command,
+                "--report",
+                &format!("json={}", path(&report)),
+            ])
+            .run_with(Arc::new(
+                ScriptedProvider::new(Vec::<String>::new()).then_fail("offline"),
+            ))
+            .await
+            .unwrap();
+
+        assert_eq!(project.source(), UNFIXED, "{}", command);
+        let report: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&report).unwrap()).unwrap();
+        assert!(
+            report["post_fix"].as_array().unwrap().is_empty(),
+            "{}",
+            command
+        );
+        // Failed, not rolled back.
+        assert_eq!(report["issues"][0]["outcome"], "failed", "{}", command);
+    }
+}
+
+#[tokio::test]
+async fn runs_only_the_tests_importing_the_file_fixed() {
+    let project = Project::new("impacted-tests");
+    let report = project.dir.join("report.json");
+    let read_report = || -> serde_json::Value {
+        serde_json::from_str(&fs::read_to_string(&report).unwrap()).unwrap()
+    };
+    fs::write(project.dir.join("pyproject.toml"), "").unwrap();
+    fs::create_dir_all(project.dir.join("tests")).unwrap();
+    fs::write(
+        project.dir.join("tests/test_a.py"),
+        "from src import a\n\ndef test_a():\n    pass\n",
+    )
+    .unwrap();
+    fs::write(
+        project.dir.join("tests/test_other.py"),
+        "import json\n\ndef test_other():\n    pass\n",
+    )
+    .unwrap();
+    let fix = || {
+        project
+            .fixer(&[
+                "--post-fix-cmd",
+                "echo {tests}",
+                "--report",
+                &format!("json={}", path(&report)),
+            ])
+            .run_with(Arc::new(ScriptedProvider::new([FIXED])))
+    };
+
+    fix().await.unwrap();
+    assert_eq!(project.source(), FIXED);
+    let runs = read_report()["post_fix"].clone();
+    assert_eq!(runs.as_array().unwrap().len(), 1);
+    let tests = runs[0]["tests"].as_array().unwrap();
+    assert_eq!(tests.len(), 1);
+    assert!(tests[0].as_str().unwrap().ends_with("tests/test_a.py"));
+    assert!(runs[0]["output"].as_str().unwrap().contains("test_a.py"));
+
+    fs::remove_file(project.dir.join("tests/test_a.py")).unwrap();
+    project.write_source(UNFIXED);
+    fix().await.unwrap();
+    assert_eq!(project.source(), FIXED);
+    assert!(read_report()["post_fix"].as_array().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn takes_the_tests_pytest_collects() {
+    let project = Project::new("collected-tests");
+    let report = project.dir.join("report.json");
+    fs::write(project.dir.join("pyproject.toml"), "").unwrap();
+    for (test, content) in [
+        ("checks/check_a.py", "from src import a\n"),
+        ("tests/test_a.py", "from src import a\n"),
+    ] {
+        let test = project.dir.join(test);
+        fs::create_dir_all(test.parent().unwrap()).unwrap();
+        fs::write(test, content).unwrap();
+    }
+    // Collects only `checks/`, as `python_files = check_*.py` would.
+    let pytest = project.dir.join(".venv/bin/pytest");
+    fs::create_dir_all(pytest.parent().unwrap()).unwrap();
+    fs::write(
+        &pytest,
+        format!(
+            "#!/bin/sh\npwd > '{}/pytest.cwd'\necho 'checks/check_a.py::test_a'\necho\necho '1 test collected'\n",
+            path(&project.dir)
+        ),
+    )
+    .unwrap();
+    fs::set_permissions(&pytest, fs::Permissions::from_mode(0o755)).unwrap();
+
+    project
+        .fixer(&[
+            "--post-fix-cmd",
+            "echo {tests}",
+            "--report",
+            &format!("json={}", path(&report)),
+        ])
+        .run_with(Arc::new(ScriptedProvider::new([FIXED])))
+        .await
+        .unwrap();
+
+    let report: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&report).unwrap()).unwrap();
+    let tests = report["post_fix"][0]["tests"].as_array().unwrap();
+    assert_eq!(tests.len(), 1, "{:?}", tests);
+    assert!(tests[0].as_str().unwrap().ends_with("checks/check_a.py"));
+    let cwd = fs::read_to_string(project.dir.join("pytest.cwd")).unwrap();
+    assert_eq!(Path::new(cwd.trim()), project.dir.canonicalize().unwrap());
+}
+
+#[tokio::test]
+async fn formats_only_the_files_fixed() {
+    let project = Project::new("format-fixed");
+    let clean = project.dir.join("src/b.py");
+    fs::write(&clean, "print(1)\n").unwrap();
+
+    project
+        .fixer(&["--sort-imports"])
+        .run_with(Arc::new(ScriptedProvider::new([FIXED])))
+        .await
+        .unwrap();
+
+    assert_eq!(project.source(), FIXED);
+    let invocations = project.ruff_invocations();
+    let formats: Vec<&String> = invocations
+        .iter()
+        .filter(|invocation| invocation.starts_with("format"))
+        .collect();
+    assert_eq!(formats.len(), 1, "{:?}", invocations);
+    assert!(formats[0].ends_with(&format!("--stdin-filename {} -", path(&project.file))));
+    assert!(invocations
+        .iter()
+        .any(|invocation| invocation
+            .starts_with("check --fix --exit-zero --select I --stdin-filename")));
+}
+
+#[tokio::test]
+async fn fixes_the_issues_fixes_bring_until_the_tree_is_clean() {
+    let project = Project::new("iterations");
+    let provider = Arc::new(ScriptedProvider::new([
+        "import sys\n\nprint(\"hi\")\n",
+        FIXED,
+    ]));
+
+    // Unverified, a fix may bring an issue of its own.
+    project
+        .fixer(&["--max-iterations", "3", "--no-verify"])
+        .run_with(Arc::clone(&provider) as Arc<dyn FixProvider>)
+        .await
+        .unwrap();
+
+    assert_eq!(provider.requests().len(), 2);
+    assert_eq!(project.source(), FIXED);
+
+    // Undoing the run undoes both iterations.
+    journal::undo(&project.journal_dir(), None).unwrap();
+    assert_eq!(project.source(), UNFIXED);
+}
+
+#[tokio::test]
+async fn leaves_files_it_cannot_read_again_to_later_iterations() {
+    let project = Project::new("iterations-unreadable");
+    // Declaring an encoding ruffer can't read, the fixed file can't be read
+    // again for the next iteration.
+    let unreadable = "# coding: utf-16\nimport sys\n\nprint(\"hi\")\n";
+    let provider = Arc::new(ScriptedProvider::new([unreadable, FIXED]));
+
+    project
+        .fixer(&["--max-iterations", "3", "--no-verify"])
+        .run_with(Arc::clone(&provider) as Arc<dyn FixProvider>)
+        .await
+        .unwrap();
+
+    assert_eq!(provider.requests().len(), 1);
+    assert_eq!(project.source(), unreadable);
+}
+
+#[tokio::test]
+async fn skips_files_with_too_many_issues_unless_forced() {
+    let project = Project::new("max-issues");
+    let source = "import os\nimport sys\n\nprint(\"hi\")\n";
+    project.write_source(source);
+    let provider = Arc::new(ScriptedProvider::new([
+        "import os\n\nprint(\"hi\")\n",
+        FIXED,
+    ]));
+
+    project
+        .fixer(&["--max-issues-per-file", "1"])
+        .run_with(Arc::clone(&provider) as Arc<dyn FixProvider>)
+        .await
+        .unwrap();
+    assert!(provider.requests().is_empty());
+    assert_eq!(project.source(), source);
+
+    project
+        .fixer(&["--max-issues-per-file", "1", "--force"])
+        .run_with(Arc::clone(&provider) as Arc<dyn FixProvider>)
+        .await
+        .unwrap();
+    assert_eq!(provider.requests().len(), 2);
+    assert_eq!(project.source(), FIXED);
+}
+
+#[tokio::test]
+async fn only_asks_the_model_about_issues_of_the_minimum_severity() {
+    let project = Project::new("min-severity");
+    let provider = Arc::new(ScriptedProvider::new([FIXED]));
+
+    // Unused imports are warnings.
+    project
+        .fixer(&["--min-severity", "error"])
+        .run_with(Arc::clone(&provider) as Arc<dyn FixProvider>)
+        .await
+        .unwrap();
+    assert!(provider.requests().is_empty());
+    assert_eq!(project.source(), UNFIXED);
+
+    fs::write(
+        project.dir.join("src/pyproject.toml"),
+        "[tool.ruffer]\nseverity = [\"F4=error\"]\n",
+    )
+    .unwrap();
+    project
+        .fixer(&["--min-severity", "error", "--severity", "F=info"])
+        .run_with(Arc::clone(&provider) as Arc<dyn FixProvider>)
+        .await
+        .unwrap();
+    assert_eq!(provider.requests().len(), 1);
+    assert_eq!(project.source(), FIXED);
+}
+
+#[tokio::test]
+async fn repeats_the_fix_of_an_issue_for_identical_ones() {
+    let project = Project::new("dedupe");
+    project.write_source("import os\nx = 1\nimport os\n\nprint(x)\n");
+    let provider = Arc::new(ScriptedProvider::new(["import os\nx = 1\n\nprint(x)\n"]));
+
+    project
+        .fixer(&[])
+        .run_with(Arc::clone(&provider) as Arc<dyn FixProvider>)
+        .await
+        .unwrap();
+
+    assert_eq!(provider.requests().len(), 1);
+    assert_eq!(project.source(), "x = 1\n\nprint(x)\n");
+}
+
+#[tokio::test]
+async fn stops_asking_the_model_once_the_budget_is_spent() {
+    let project = Project::new("budget");
+    project.write_source("import os\nimport sys\n\nprint(\"hi\")\n");
+    let report = project.dir.join("report.json");
+    let provider = Arc::new(ScriptedProvider::new(["import os\n\nprint(\"hi\")\n"]));
+
+    project
+        .fixer(&[
+            "--max-tokens",
+            "1",
+            "--report",
+            &format!("json={}", path(&report)),
+        ])
+        .run_with(Arc::clone(&provider) as Arc<dyn FixProvider>)
+        .await
+        .unwrap();
+
+    assert_eq!(provider.requests().len(), 1);
+    assert_eq!(project.source(), "import os\n\nprint(\"hi\")\n");
+    let report: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(report).unwrap()).unwrap();
+    assert_eq!(report["summary"]["llm-fixed"], 1);
+    assert_eq!(report["summary"]["skipped"], 1);
+    let skipped: Vec<&serde_json::Value> = report["issues"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter(|issue| issue["outcome"] == "skipped")
+        .collect();
+    assert_eq!(skipped[0]["reason"], "budget exhausted");
+    assert_eq!(skipped[0]["message"], "`os` imported but unused");
+
+    let err = project
+        .fixer(&["--max-cost", "1", "--model", "unheard-of"])
+        .run_with(Arc::clone(&provider) as Arc<dyn FixProvider>)
+        .await
+        .unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+}
+
+#[tokio::test]
+async fn estimates_the_cost_of_a_run_without_sending_anything() {
+    let project = Project::new("estimate");
+    let args: Vec<OsString> = [
+        "ruffer",
+        "unused-key",
+        path(&project.dir.join("ruff")),
+        path(&project.dir.join("src")),
+        "--no-cache",
+        "--journal-dir",
+        path(&project.journal_dir()),
+        "--state-file",
+        path(&project.state_file()),
+        "--compare",
+        "gpt-4o,made-up-model",
+    ]
+    .iter()
+    .map(OsString::from)
+    .collect();
+
+    let estimate = EstimateOptions::from_iter(&args).estimate().await.unwrap();
+
+    assert_eq!(project.source(), UNFIXED);
+    assert_eq!(estimate.requests, 1);
+    assert!(estimate.prompt_tokens > estimate.answer_tokens);
+    assert!(estimate.answer_tokens > 0);
+    let models: Vec<&str> = estimate
+        .costs
+        .iter()
+        .map(|(model, _)| model.as_str())
+        .collect();
+    assert_eq!(models, ["gpt-4o-mini", "gpt-4o", "made-up-model"]);
+    let (mini, full) = (estimate.costs[0].1.unwrap(), estimate.costs[1].1.unwrap());
+    assert!(mini > 0.0 && mini < full);
+    assert!(estimate.costs[2].1.is_none());
+}
+
+#[tokio::test]
+async fn skips_vendored_files_unless_asked_not_to() {
+    let project = Project::with_file("vendored", "src/.venv/lib/site-packages/a.py");
+    let provider = Arc::new(ScriptedProvider::new([FIXED]));
+
+    project.fixer(&[]).run_with(provider.clone()).await.unwrap();
+
+    assert_eq!(project.source(), UNFIXED);
+    assert!(provider.requests().is_empty());
+
+    project
+        .fixer(&["--include-vendored"])
+        .run_with(provider.clone())
+        .await
+        .unwrap();
+
+    assert_eq!(project.source(), FIXED);
+}
+
+#[tokio::test]
+async fn skips_files_larger_than_max_file_bytes() {
+    let project = Project::new("max-file-bytes");
+    let provider = Arc::new(ScriptedProvider::new([FIXED]));
+
+    project
+        .fixer(&["--max-file-bytes", "10"])
+        .run_with(provider.clone())
+        .await
+        .unwrap();
+
+    assert_eq!(project.source(), UNFIXED);
+    assert!(provider.requests().is_empty());
+}
+
+#[tokio::test]
+async fn fixes_files_in_legacy_encodings_without_reencoding_them() {
+    let project = Project::new("encoding");
+    // "Привет" in windows-1251, without a coding declaration.
+    let cyrillic = b"\xcf\xf0\xe8\xe2\xe5\xf2";
+    let mut source = b"import os\n\nprint(\"".to_vec();
+    source.extend_from_slice(cyrillic);
+    source.extend_from_slice(b"\")\n");
+    fs::write(&project.file, &source).unwrap();
+    let provider = Arc::new(ScriptedProvider::new(["print(\"Привет\")\n"]));
+
+    project.fixer(&[]).run_with(provider.clone()).await.unwrap();
+
+    let mut fixed = b"print(\"".to_vec();
+    fixed.extend_from_slice(cyrillic);
+    fixed.extend_from_slice(b"\")\n");
+    assert_eq!(fs::read(&project.file).unwrap(), fixed);
+    assert!(provider.requests()[0].prompt().contains("Привет"));
+}
+
+#[tokio::test]
+async fn keeps_line_endings_and_missing_final_newline() {
+    let project = Project::new("line-endings");
+    project.write_source("import os as os\r\nprint(\"hi\")\r\nprint(\"bye\")");
+    let provider = Arc::new(ScriptedProvider::new(["print(\"hi\")\nprint(\"bye\")\n"]));
+
+    project.fixer(&[]).run_with(provider).await.unwrap();
+
+    assert_eq!(project.source(), "print(\"hi\")\r\nprint(\"bye\")");
+}
+
+#[tokio::test]
+async fn replaces_files_atomically_keeping_their_permissions() {
+    let project = Project::new("atomic");
+    fs::set_permissions(&project.file, fs::Permissions::from_mode(0o640)).unwrap();
+
+    project
+        .fixer(&[])
+        .run_with(Arc::new(ScriptedProvider::new([FIXED])))
+        .await
+        .unwrap();
+
+    assert_eq!(project.source(), FIXED);
+    let mode = fs::metadata(&project.file).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o640);
+    let leftovers: Vec<_> = fs::read_dir(project.dir.join("src"))
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name())
+        .filter(|name| name != "a.py")
+        .collect();
+    assert!(leftovers.is_empty(), "{:?}", leftovers);
+}
+
+#[tokio::test]
+async fn backs_up_files_before_changing_them() {
+    let project = Project::new("backup");
+    let backups = project.dir.join("backups");
+
+    project
+        .fixer(&["--backup-dir", path(&backups)])
+        .run_with(Arc::new(ScriptedProvider::new([FIXED])))
+        .await
+        .unwrap();
+
+    assert_eq!(project.source(), FIXED);
+    let backup: PathBuf = project
+        .file
+        .components()
+        .filter(|component| matches!(component, std::path::Component::Normal(_)))
+        .collect();
+    assert_eq!(fs::read_to_string(backups.join(backup)).unwrap(), UNFIXED);
+}
+
+#[tokio::test]
+async fn backs_up_the_content_before_the_run_across_iterations() {
+    let project = Project::new("backup-iterations");
+    let backups = project.dir.join("backups");
+    let provider = Arc::new(ScriptedProvider::new([
+        "import sys\n\nprint(\"hi\")\n",
+        FIXED,
+    ]));
+
+    project
+        .fixer(&[
+            "--max-iterations",
+            "2",
+            "--no-verify",
+            "--backup-dir",
+            path(&backups),
+        ])
+        .run_with(Arc::clone(&provider) as Arc<dyn FixProvider>)
+        .await
+        .unwrap();
+
+    assert_eq!(provider.requests().len(), 2);
+    assert_eq!(project.source(), FIXED);
+    let backup: PathBuf = project
+        .file
+        .components()
+        .filter(|component| matches!(component, std::path::Component::Normal(_)))
+        .collect();
+    assert_eq!(fs::read_to_string(backups.join(backup)).unwrap(), UNFIXED);
+}
+
+#[tokio::test]
+async fn undoes_a_run_unless_the_file_changed_since() {
+    let project = Project::new("undo");
+    let journal = project.journal_dir();
+    let run = || {
+        project
+            .fixer(&[])
+            .run_with(Arc::new(ScriptedProvider::new([FIXED])))
+    };
+
+    run().await.unwrap();
+    assert_eq!(project.source(), FIXED);
+    let undone = journal::undo(&journal, None).unwrap();
+    assert_eq!(undone.files.len(), 1);
+    assert_eq!(project.source(), UNFIXED);
+    assert!(journal::undo(&journal, None).is_err());
+
+    run().await.unwrap();
+    let edited = format!("{}x = 1\n", FIXED);
+    project.write_source(&edited);
+    let err = journal::undo(&journal, None).unwrap_err();
+    assert!(err.to_string().contains("changed since the run"), "{}", err);
+    assert_eq!(project.source(), edited);
+}
+
+#[tokio::test]
+async fn resumes_leaving_alone_files_finished_before() {
+    let project = Project::new("resume");
+
+    // A run that finishes the file, leaving its issue alone, ...
+    project
+        .fixer(&["--no-llm-rules", "F401"])
+        .run_with(Arc::new(StaticFixProvider::new(FIXED)))
+        .await
+        .unwrap();
+    // ... isn't repeated when resumed.
+    let provider = Arc::new(ScriptedProvider::new([FIXED]));
+    project
+        .fixer(&["--resume"])
+        .run_with(provider.clone())
+        .await
+        .unwrap();
+    assert!(provider.requests().is_empty());
+    assert_eq!(project.source(), UNFIXED);
+
+    // Files with failed issues are tried again.
+    project
+        .fixer(&[])
+        .run_with(Arc::new(ScriptedProvider::new(Vec::<String>::new())))
+        .await
+        .unwrap();
+    let provider = Arc::new(ScriptedProvider::new([FIXED]));
+    project
+        .fixer(&["--resume"])
+        .run_with(provider.clone())
+        .await
+        .unwrap();
+    assert_eq!(provider.requests().len(), 1);
+    assert_eq!(project.source(), FIXED);
+}
+
+/// Answers like a user pressing Ctrl-C while the model works on the first
+/// request.
+struct InterruptingProvider {
+    fixer: Arc<RuffFixer>,
+    answer: &'static str,
+    requests: AtomicUsize,
+}
+
+impl FixProvider for InterruptingProvider {
+    fn complete<'a>(&'a self, _request: &'a CompletionRequest) -> Completion<'a> {
+        self.requests.fetch_add(1, Ordering::SeqCst);
+        self.fixer.interrupt();
+        Box::pin(async move { Ok(self.answer.to_string()) })
+    }
+}
+
+#[tokio::test]
+async fn stops_asking_when_interrupted_but_writes_the_fixes_made() {
+    let project = Project::new("interrupt");
+    project.write_source("import os\nimport sys\n\nprint(\"hi\")\n");
+    let fixer = project.fixer(&[]);
+    let provider = Arc::new(InterruptingProvider {
+        fixer: Arc::clone(&fixer),
+        answer: "import os\n\nprint(\"hi\")\n",
+        requests: Default::default(),
+    });
+
+    let err = fixer.run_with(provider.clone()).await.unwrap_err();
+
+    assert_eq!(err.kind(), std::io::ErrorKind::Interrupted);
+    assert_eq!(provider.requests.load(Ordering::SeqCst), 1);
+    assert_eq!(project.source(), "import os\n\nprint(\"hi\")\n");
+
+    // The file isn't taken for finished.
+    let provider = Arc::new(ScriptedProvider::new([FIXED]));
+    project
+        .fixer(&["--resume"])
+        .run_with(provider.clone())
+        .await
+        .unwrap();
+    assert_eq!(project.source(), FIXED);
+}
+
+#[tokio::test]
+async fn refuses_to_run_while_another_run_holds_the_lock() {
+    let project = Project::new("lock");
+    let held = fs::File::create(project.dir.join("src/.ruffer.lock")).unwrap();
+    held.lock().unwrap();
+    let provider = Arc::new(ScriptedProvider::new([FIXED]));
+
+    let err = project
+        .fixer(&[])
+        .run_with(provider.clone())
+        .await
+        .unwrap_err();
+
+    assert!(err.to_string().contains("Another ruffer run"), "{}", err);
+    assert!(provider.requests().is_empty());
+    assert_eq!(project.source(), UNFIXED);
+
+    drop(held);
+    project
+        .fixer(&[])
+        .run_with(Arc::new(ScriptedProvider::new([FIXED])))
+        .await
+        .unwrap();
+    assert_eq!(project.source(), FIXED);
+}
+
+#[tokio::test]
+async fn asks_before_applying_each_fix_when_interactive() {
+    let project = Project::new("interactive");
+    project.write_source("import os\nimport sys\n\nprint(\"hi\")\n");
+    let provider = Arc::new(ScriptedProvider::new([
+        "import os\nimport sys\n\nprint(\"hi\")\n\n",
+        "import os\n\nprint(\"hi\")\n",
+        FIXED,
+    ]));
+    let fixer = RuffFixer::from_iter([
+        "ruffer",
+        "unused-key",
+        path(&project.dir.join("ruff")),
+        path(&project.dir.join("src")),
+        "--no-cache",
+        "--no-verify",
+        "--interactive",
+        "--journal-dir",
+        path(&project.journal_dir()),
+        "--state-file",
+        path(&project.state_file()),
+    ])
+    // Another fix for `sys`, applied; the fix for `os` declined.
+    .answering_from(std::io::Cursor::new("r\ny\nn\n"));
+
+    Arc::new(fixer).run_with(provider.clone()).await.unwrap();
+
+    assert_eq!(provider.requests().len(), 3);
+    assert_eq!(project.source(), "import os\n\nprint(\"hi\")\n");
+}
+
+#[tokio::test]
+async fn refuses_the_dashboard_where_the_terminal_is_taken() {
+    let project = Project::new("tui-output");
+    let provider = Arc::new(ScriptedProvider::new([FIXED]));
+
+    let err = project
+        .fixer(&["--tui", "--output", "jsonl"])
+        .run_with(provider.clone())
+        .await
+        .unwrap_err();
+
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+    assert!(err.to_string().contains("--tui"), "{}", err);
+    assert!(provider.requests().is_empty());
+    assert_eq!(project.source(), UNFIXED);
+}
+
+#[tokio::test]
+async fn logs_each_request_within_spans_of_its_file_and_issue() {
+    let project = Project::new("log");
+    let log = project.dir.join("ruffer.log");
+    let fixer = project.fixer(&["--log-level", "debug", "--log-file", path(&log)]);
+    // Tests share one process, and so the one logger.
+    fixer.init_logging().unwrap();
+
+    fixer
+        .run_with(Arc::new(ScriptedProvider::new([FIXED])))
+        .await
+        .unwrap();
+
+    let log = fs::read_to_string(log).unwrap();
+    let request = log
+        .lines()
+        .find(|line| line.contains("Sending 2 messages") && line.contains("ruffer-log-"))
+        .unwrap_or_else(|| panic!("no request logged in:\n{}", log));
+    assert!(request.contains(" DEBUG file{path="), "{}", request);
+    assert!(request.contains(":issue{id="), "{}", request);
+    assert!(request.contains("code=F401}:"), "{}", request);
+}
+
+#[tokio::test]
+async fn streams_each_step_of_the_run_as_a_json_line() {
+    let project = Project::new("events");
+    let events = project.dir.join("events.jsonl");
+    let fixer = RuffFixer::from_iter([
+        "ruffer",
+        "unused-key",
+        path(&project.dir.join("ruff")),
+        path(&project.dir.join("src")),
+        "--no-cache",
+        "--output",
+        "jsonl",
+        "--journal-dir",
+        path(&project.journal_dir()),
+        "--state-file",
+        path(&project.state_file()),
+    ])
+    .writing_events_to(fs::File::create(&events).unwrap());
+
+    Arc::new(fixer)
+        .run_with(Arc::new(ScriptedProvider::new([FIXED])))
+        .await
+        .unwrap();
+
+    let events: Vec<serde_json::Value> = fs::read_to_string(events)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    let names: Vec<&str> = events
+        .iter()
+        .map(|event| event["event"].as_str().unwrap())
+        .collect();
+    assert_eq!(
+        names,
+        [
+            "issue_discovered",
+            "prompt_sent",
+            "fix_applied",
+            "file_written",
+            "run_finished"
+        ]
+    );
+    assert_eq!(events[0]["code"], "F401");
+    assert_eq!(events[0]["row"], 1);
+    assert_eq!(events[4]["fixed"], 1);
+    let tokens = events[4]["tokens"].as_u64().unwrap();
+    assert!(tokens > 0);
+    let cost = events[4]["cost"].as_f64().unwrap();
+    // gpt-4o-mini: $0.15 per million prompt tokens, $0.60 per million answer ones.
+    assert!(cost >= tokens as f64 * 0.15 / 1e6 && cost <= tokens as f64 * 0.60 / 1e6);
+}
+
+#[tokio::test]
+async fn leaves_a_file_without_accepted_fixes_untouched() {
+    use std::os::unix::fs::MetadataExt;
+
+    let project = Project::new("untouched");
+    let events = project.dir.join("events.jsonl");
+    let before = fs::metadata(&project.file).unwrap();
+    let fixer = RuffFixer::from_iter([
+        "ruffer",
+        "unused-key",
+        path(&project.dir.join("ruff")),
+        path(&project.dir.join("src")),
+        "--no-cache",
+        "--output",
+        "jsonl",
+        "--journal-dir",
+        path(&project.journal_dir()),
+        "--state-file",
+        path(&project.state_file()),
+    ])
+    .writing_events_to(fs::File::create(&events).unwrap());
+
+    Arc::new(fixer)
+        .run_with(Arc::new(
+            ScriptedProvider::new(Vec::<String>::new()).then_fail("offline"),
+        ))
+        .await
+        .unwrap();
+
+    let after = fs::metadata(&project.file).unwrap();
+    assert_eq!(project.source(), UNFIXED);
+    assert_eq!(after.ino(), before.ino());
+    assert_eq!(after.modified().unwrap(), before.modified().unwrap());
+    let events = fs::read_to_string(events).unwrap();
+    assert!(events.contains("\"fix_failed\""), "{}", events);
+    assert!(!events.contains("\"file_written\""), "{}", events);
+}
+
+#[tokio::test]
+async fn logs_every_prompt_and_answer_to_the_audit_dir() {
+    let project = Project::new("audit");
+    let audit = project.dir.join("audit");
+
+    project
+        .fixer(&["--audit-dir", path(&audit)])
+        .run_with(Arc::new(ScriptedProvider::new([FIXED])))
+        .await
+        .unwrap();
+
+    let runs: Vec<_> = fs::read_dir(&audit).unwrap().collect();
+    assert_eq!(runs.len(), 1);
+    let log = fs::read_to_string(runs[0].as_ref().unwrap().path()).unwrap();
+    let exchanges: Vec<serde_json::Value> = log
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(exchanges.len(), 1);
+    let exchange = &exchanges[0];
+    assert_eq!(exchange["model"], "gpt-4o-mini");
+    assert_eq!(exchange["response"], FIXED);
+    assert!(exchange["sent_at"].as_u64().unwrap() > 0);
+    assert!(exchange["prompt_tokens"].as_u64().unwrap() > 0);
+    assert!(exchange["response_tokens"].as_u64().unwrap() > 0);
+    let messages = exchange["messages"].as_array().unwrap();
+    assert_eq!(messages[0]["role"], "system");
+    assert!(messages.last().unwrap()["content"]
+        .as_str()
+        .unwrap()
+        .contains("import os"));
+}
+
+#[tokio::test]
+async fn keeps_the_api_key_out_of_errors() {
+    let project = Project::new("redact");
+    let recordings = project.dir.join("recordings");
+    fs::create_dir_all(&recordings).unwrap();
+    let key = "sk-proj-0123456789abcdef";
+    let provider = OpenAiProvider::new(key.to_string(), None, Some(recordings.clone()));
+    let request = CompletionRequest {
+        model: "gpt-4o-mini".to_string(),
+        messages: vec![Message::user("Fix this")],
+    };
+
+    // Learn where the recording of the request goes from the error for
+    // its absence, then record the API rejecting the key.
+    let missing = provider.complete(&request).await.unwrap_err().to_string();
+    let recording = missing
+        .split_whitespace()
+        .find(|word| word.ends_with(".json:"))
+        .unwrap()
+        .trim_end_matches(':');
+    let recording = recordings.join(Path::new(recording).file_name().unwrap());
+    let response = serde_json::json!({
+        "request": {},
+        "status": 401,
+        "response": {"error": {"message": format!(
+            "Incorrect API key provided: {}. Sent with header Authorization: Bearer {}",
+            key, key
+        )}},
+    });
+    fs::write(&recording, response.to_string()).unwrap();
+
+    let err = provider.complete(&request).await.unwrap_err().to_string();
+    assert!(err.contains("Incorrect API key provided"), "{}", err);
+    assert!(!err.contains(key), "{}", err);
+    assert!(!err.contains("0123456789abcdef"), "{}", err);
+    assert!(err.contains("Bearer [REDACTED]"), "{}", err);
+}
+
+#[tokio::test]
+async fn keeps_the_api_key_out_of_recordings_and_logs() {
+    let project = Project::new("redact-run");
+    let key = "sk-proj-0123456789abcdef";
+    let (url, requests) = fake_api(&[(
+        "/v1/chat/completions",
+        r#"{"error": {"message": "Incorrect API key provided: sk-proj-0123456789abcdef. Sent as Bearer sk-proj-0123456789abcdef"}}"#,
+    )]);
+    let recordings = project.dir.join("recordings");
+    let log = project.dir.join("ruffer.log");
+
+    let output = tokio::process::Command::new(env!("CARGO_BIN_EXE_rust_ruffer"))
+        .args([
+            "fix",
+            key,
+            path(&project.dir.join("ruff")),
+            path(&project.dir.join("src")),
+            "--no-cache",
+            "--journal-dir",
+            path(&project.journal_dir()),
+            "--state-file",
+            path(&project.state_file()),
+            "--record",
+            path(&recordings),
+            "--log-level",
+            "debug",
+            "--log-file",
+            path(&log),
+        ])
+        .env("OPENAI_BASE_URL", format!("{}/v1", url))
+        .output()
+        .await
+        .unwrap();
+
+    assert!(!requests.lock().unwrap().is_empty());
+    assert_eq!(project.source(), UNFIXED);
+    let console = format!(
+        "{}{}",
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(
+        console.contains("Incorrect API key provided"),
+        "{}",
+        console
+    );
+    let log = fs::read_to_string(log).unwrap();
+    assert!(log.contains("Incorrect API key provided"), "{}", log);
+    let mut recorded = String::new();
+    for entry in fs::read_dir(&recordings).unwrap() {
+        recorded.push_str(&fs::read_to_string(entry.unwrap().path()).unwrap());
+    }
+    assert!(
+        recorded.contains("Incorrect API key provided"),
+        "{}",
+        recorded
+    );
+    for (name, text) in [
+        ("console", &console),
+        ("log", &log),
+        ("recording", &recorded),
+    ] {
+        assert!(!text.contains("0123456789abcdef"), "{}: {}", name, text);
+    }
+}
+
+#[tokio::test]
+async fn exits_with_a_status_of_its_own_when_the_policy_fails_the_run() {
+    let project = Project::new("policy-exit");
+    let (url, _) = fake_api(&[("/v1/chat/completions", r#"{"error": {"message": "Down"}}"#)]);
+
+    let status = tokio::process::Command::new(env!("CARGO_BIN_EXE_rust_ruffer"))
+        .args([
+            "fix",
+            "unused-key",
+            path(&project.dir.join("ruff")),
+            path(&project.dir.join("src")),
+            "--no-cache",
+            "--journal-dir",
+            path(&project.journal_dir()),
+            "--state-file",
+            path(&project.state_file()),
+            "--fail-on",
+            "error",
+        ])
+        .env("OPENAI_BASE_URL", format!("{}/v1", url))
+        .output()
+        .await
+        .unwrap()
+        .status;
+
+    assert_eq!(status.code(), Some(rust_ruffer::POLICY_EXIT_CODE));
+    assert_eq!(rust_ruffer::POLICY_EXIT_CODE, 2);
+}
+
+#[tokio::test]
+async fn reports_what_became_of_every_issue_as_json() {
+    let project = Project::new("report");
+    project.write_source("import os\nimport sys\n\nprint(\"hi\")\n");
+    let report = project.dir.join("report.json");
+
+    project
+        .fixer(&["--report", &format!("json={}", path(&report))])
+        .run_with(Arc::new(ScriptedProvider::new([
+            "import os\n\nprint(\"hi\")\n",
+            "import os\nimport sys\n\nprint(\"hi\")\n",
+        ])))
+        .await
+        .unwrap();
+
+    let report: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(report).unwrap()).unwrap();
+    let issues = report["issues"].as_array().unwrap();
+    assert_eq!(issues.len(), 2);
+    let by_row = |row: u64| {
+        issues
+            .iter()
+            .find(|issue| issue["row"] == row)
+            .unwrap_or_else(|| panic!("no issue on row {} in {}", row, report))
+    };
+    let fixed = by_row(2);
+    assert_eq!(fixed["code"], "F401");
+    assert_eq!(fixed["outcome"], "llm-fixed");
+    assert!(fixed["tokens"].as_u64().unwrap() > 0);
+    assert_eq!(fixed["diff_hash"].as_str().unwrap().len(), 64);
+    assert_eq!(by_row(1)["outcome"], "failed");
+    assert_eq!(report["summary"]["llm-fixed"], 1);
+    assert_eq!(report["summary"]["failed"], 1);
+    assert_eq!(
+        report["rules"],
+        serde_json::json!([{"name": "F401", "issues": 2, "fixed": 1}])
+    );
+    let files = report["files"].as_array().unwrap();
+    assert_eq!(files.len(), 1);
+    assert_eq!(files[0]["name"], fixed["file"]);
+    assert_eq!(files[0]["issues"], 2);
+    assert_eq!(files[0]["fixed"], 1);
+}
+
+#[tokio::test]
+async fn reports_fixed_and_remaining_issues_as_sarif() {
+    let project = Project::new("sarif");
+    project.write_source("import os\nimport sys\n\nprint(\"hi\")\n");
+    let report = project.dir.join("ruffer.sarif");
+
+    project
+        .fixer(&["--report", &format!("sarif={}", path(&report))])
+        .run_with(Arc::new(ScriptedProvider::new([
+            "import os\n\nprint(\"hi\")\n",
+        ])))
+        .await
+        .unwrap();
+
+    let report: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(report).unwrap()).unwrap();
+    assert_eq!(report["version"], "2.1.0");
+    let run = &report["runs"][0];
+    assert_eq!(run["tool"]["driver"]["name"], "ruffer");
+    assert_eq!(run["tool"]["driver"]["rules"][0]["id"], "F401");
+    let results = run["results"].as_array().unwrap();
+    assert_eq!(results.len(), 2);
+    let on_row = |row: u64| {
+        results
+            .iter()
+            .find(|result| result["locations"][0]["physicalLocation"]["region"]["startLine"] == row)
+            .unwrap()
+    };
+    let fixed = on_row(2);
+    assert_eq!(fixed["ruleId"], "F401");
+    assert_eq!(fixed["kind"], "pass");
+    assert_eq!(fixed["properties"]["outcome"], "llm-fixed");
+    let remaining = on_row(1);
+    assert_eq!(remaining["kind"], "fail");
+    assert_eq!(remaining["level"], "warning");
+    assert!(
+        remaining["locations"][0]["physicalLocation"]["artifactLocation"]["uri"]
+            .as_str()
+            .unwrap()
+            .ends_with("src/a.py")
+    );
+}
+
+#[tokio::test]
+async fn reports_each_issue_as_a_junit_test_case() {
+    let project = Project::new("junit");
+    project.write_source("import os\nimport sys\n\nprint(\"hi\")\n");
+    let report = project.dir.join("ruffer.xml");
+
+    project
+        .fixer(&["--report", &format!("junit={}", path(&report))])
+        .run_with(Arc::new(ScriptedProvider::new([
+            "import os\n\nprint(\"hi\")\n",
+        ])))
+        .await
+        .unwrap();
+
+    let report = fs::read_to_string(report).unwrap();
+    assert!(report.starts_with("<?xml"), "{}", report);
+    assert!(
+        report.contains("<testsuites name=\"ruffer\" tests=\"2\" failures=\"1\" skipped=\"0\""),
+        "{}",
+        report
+    );
+    assert!(report.contains("name=\"F401 at 2:8 ["), "{}", report);
+    assert_eq!(report.matches("<failure ").count(), 1, "{}", report);
+    assert!(report.contains("type=\"failed\""), "{}", report);
+    assert!(report.contains("`os` imported but unused"), "{}", report);
+}
+
+#[tokio::test]
+async fn reports_the_changes_of_the_run_as_a_page() {
+    let project = Project::new("html");
+    let report = project.dir.join("ruffer.html");
+
+    project
+        .fixer(&["--report", &format!("html={}", path(&report))])
+        .run_with(Arc::new(ScriptedProvider::new([FIXED])))
+        .await
+        .unwrap();
+
+    let report = fs::read_to_string(report).unwrap();
+    assert!(report.starts_with("<!DOCTYPE html>"), "{}", report);
+    assert!(report.contains("<option>F401</option>"), "{}", report);
+    assert!(
+        report.contains("<details data-codes=\"F401\">"),
+        "{}",
+        report
+    );
+    assert!(
+        report.contains("<td class=\"llm-fixed\">llm-fixed</td>"),
+        "{}",
+        report
+    );
+    assert!(
+        report.contains("<span class=\"del\">-<span class=\"kw\">import</span> os</span>"),
+        "{}",
+        report
+    );
+    assert!(
+        report.contains("<span class=\"str\">&quot;hi&quot;</span>"),
+        "{}",
+        report
+    );
+}
+
+#[tokio::test]
+async fn summarizes_the_run_in_markdown_for_pull_requests() {
+    let project = Project::new("markdown");
+    project.write_source("import os\nimport sys\n\nprint(\"hi\")\n");
+    let report = project.dir.join("summary.md");
+
+    project
+        .fixer(&["--report", &format!("markdown={}", path(&report))])
+        .run_with(Arc::new(ScriptedProvider::new([
+            "import os\n\nprint(\"hi\")\n",
+        ])))
+        .await
+        .unwrap();
+
+    let report = fs::read_to_string(report).unwrap();
+    assert!(report.contains("| Files touched | 1 of 1 |"), "{}", report);
+    assert!(report.contains("| Issues fixed | 1 of 2 |"), "{}", report);
+    assert!(report.contains("| F401 | 1 | 1 |"), "{}", report);
+    assert!(
+        report.contains(":1:8` F401 `os` imported but unused (failed)"),
+        "{}",
+        report
+    );
+}
+
+#[tokio::test]
+async fn annotates_issues_for_github_actions() {
+    let project = Project::new("github");
+    project.write_source("import os\nimport sys\n\nprint(\"hi\")\n");
+    let annotations = project.dir.join("annotations");
+    let summary = project.dir.join("step-summary.md");
+    // The only test reading the variable.
+    std::env::set_var("GITHUB_STEP_SUMMARY", &summary);
+    let fixer = RuffFixer::from_iter([
+        "ruffer",
+        "unused-key",
+        path(&project.dir.join("ruff")),
+        path(&project.dir.join("src")),
+        "--no-cache",
+        "--output",
+        "github",
+        "--journal-dir",
+        path(&project.journal_dir()),
+        "--state-file",
+        path(&project.state_file()),
+    ])
+    .writing_events_to(fs::File::create(&annotations).unwrap());
+
+    Arc::new(fixer)
+        .run_with(Arc::new(ScriptedProvider::new([
+            "import os\n\nprint(\"hi\")\n",
+        ])))
+        .await
+        .unwrap();
+
+    let annotations = fs::read_to_string(annotations).unwrap();
+    let file = path(&project.file);
+    assert!(
+        annotations.contains(&format!(
+            "::notice file={},line=2,col=8,title=ruffer F401::Fixed (llm-fixed): `sys` imported but unused\n",
+            file
+        )),
+        "{}",
+        annotations
+    );
+    assert!(
+        annotations.contains(&format!(
+            "::error file={},line=1,col=8,title=ruffer F401::`os` imported but unused (failed)\n",
+            file
+        )),
+        "{}",
+        annotations
+    );
+    let summary = fs::read_to_string(summary).unwrap();
+    assert!(summary.contains("| Issues fixed | 1 of 2 |"), "{}", summary);
+}
+
+#[tokio::test]
+async fn writes_patches_instead_of_changing_files() {
+    let project = Project::new("patch-out");
+    let patches = project.dir.join("patches");
+
+    project
+        .fixer(&["--patch-out", path(&patches)])
+        .run_with(Arc::new(ScriptedProvider::new([FIXED])))
+        .await
+        .unwrap();
+
+    assert_eq!(project.source(), UNFIXED);
+    assert!(!project
+        .ruff_invocations()
+        .iter()
+        .any(|invocation| invocation.starts_with("format")));
+    let relative = project.file.strip_prefix("/").unwrap();
+    let mut patch_file = patches.join(relative).into_os_string();
+    patch_file.push(".patch");
+    let patch = fs::read_to_string(patch_file).unwrap();
+    assert!(patch.starts_with("--- a/"), "{}", patch);
+    assert!(patch.contains("-import os\n"), "{}", patch);
+}
+
+/// A fixer for `project` that checks `targets` instead of the `src`
+/// folder.
+fn fixer_for(project: &Project, targets: &[&str]) -> Arc<RuffFixer> {
+    let ruff = project.dir.join("ruff");
+    let journal = project.journal_dir();
+    let state = project.state_file();
+    let mut args = vec!["ruffer", "unused-key", path(&ruff)];
+    args.extend_from_slice(targets);
+    args.extend([
+        "--no-cache",
+        "--journal-dir",
+        path(&journal),
+        "--state-file",
+        path(&state),
+    ]);
+    Arc::new(RuffFixer::from_iter(args))
+}
+
+#[tokio::test]
+async fn checks_every_path_given() {
+    let project = Project::new("targets");
+    let tests = project.dir.join("tests");
+    fs::create_dir_all(&tests).unwrap();
+
+    fixer_for(&project, &[path(&project.file), path(&tests)])
+        .run_with(Arc::new(ScriptedProvider::new([FIXED])))
+        .await
+        .unwrap();
+
+    assert_eq!(project.source(), FIXED);
+    let check = format!("check --no-fix {} {} ", path(&project.file), path(&tests));
+    assert!(
+        project
+            .ruff_invocations()
+            .iter()
+            .any(|invocation| invocation.starts_with(&check)),
+        "{:?}",
+        project.ruff_invocations()
+    );
+}
+
+#[tokio::test]
+async fn expands_glob_patterns_in_paths() {
+    let project = Project::new("glob");
+    let other = project.dir.join("src/nested/b.py");
+    fs::create_dir_all(other.parent().unwrap()).unwrap();
+    fs::write(&other, FIXED).unwrap();
+    fs::write(project.dir.join("src/.hidden.py"), FIXED).unwrap();
+    fs::write(project.dir.join("src/notes.txt"), "").unwrap();
+    let pattern = format!("{}/src/**/*.py", path(&project.dir));
+
+    fixer_for(&project, &[&pattern])
+        .run_with(Arc::new(ScriptedProvider::new([FIXED])))
+        .await
+        .unwrap();
+
+    assert_eq!(project.source(), FIXED);
+    let check = format!("check --no-fix {} {} ", path(&project.file), path(&other));
+    assert!(
+        project
+            .ruff_invocations()
+            .iter()
+            .any(|invocation| invocation.starts_with(&check)),
+        "{:?}",
+        project.ruff_invocations()
+    );
+}
+
+#[tokio::test]
+async fn fails_on_a_glob_pattern_matching_nothing() {
+    let project = Project::new("glob-none");
+    let pattern = format!("{}/src/*.pyi", path(&project.dir));
+
+    let err = fixer_for(&project, &[&pattern])
+        .run_with(Arc::new(ScriptedProvider::new([FIXED])))
+        .await
+        .unwrap_err();
+
+    assert!(err.to_string().contains("matches no files"), "{}", err);
+    assert_eq!(project.source(), UNFIXED);
+}
+
+#[tokio::test]
+async fn checks_each_package_in_its_own_directory() {
+    let project = Project::new("packages");
+    fs::write(
+        project.dir.join("src/pyproject.toml"),
+        "[tool.ruff]\nline-length = 100\n\n[tool.ruffer]\nselect = [\"F\"]\nno-llm-rules = [\n    \"F401\",  # imports stay for now\n]\n",
+    )
+    .unwrap();
+    let other = project.dir.join("other");
+    fs::create_dir_all(&other).unwrap();
+    fs::write(other.join("ruff.toml"), "").unwrap();
+
+    fixer_for(&project, &[path(&project.dir)])
+        .run_with(Arc::new(ScriptedProvider::new([FIXED])))
+        .await
+        .unwrap();
+
+    // The package leaves its imports to people.
+    assert_eq!(project.source(), UNFIXED);
+    let src = project.dir.join("src").canonicalize().unwrap();
+    let other = other.canonicalize().unwrap();
+    let invocations = project.ruff_invocations();
+    let checks: Vec<&String> = invocations
+        .iter()
+        .filter(|invocation| invocation.starts_with("check --no-fix"))
+        .collect();
+    assert_eq!(checks.len(), 3, "{:?}", checks);
+    assert!(checks[0].contains(&format!(
+        "--extend-exclude {} --extend-exclude {}",
+        path(&other),
+        path(&src)
+    )));
+    assert!(checks.iter().any(|check| check
+        .starts_with(&format!("check --no-fix {} ", path(&src)))
+        && check.contains("--select F")));
+    let dirs = fs::read_to_string(project.dir.join("ruff.cwd")).unwrap();
+    assert!(dirs.lines().any(|dir| Path::new(dir) == src), "{}", dirs);
+}
+
+/// Starts `ruffer watch` on the project, leaving `ignored.py` alone.
+async fn watch(
+    project: &Project,
+    debounce_ms: &str,
+    provider: Arc<ScriptedProvider>,
+) -> tokio::task::JoinHandle<io::Result<()>> {
+    let ruff = project.dir.join("ruff");
+    let src = project.dir.join("src");
+    let journal = project.journal_dir();
+    let state = project.state_file();
+    let args: Vec<OsString> = [
+        "ruffer",
+        "unused-key",
+        path(&ruff),
+        path(&src),
+        "--no-cache",
+        "--journal-dir",
+        path(&journal),
+        "--state-file",
+        path(&state),
+        "--debounce-ms",
+        debounce_ms,
+        "--watch-ignore",
+        "ignored.py",
+    ]
+    .iter()
+    .map(OsString::from)
+    .collect();
+    let watch = WatchOptions::from_iter(&args);
+    let watching = tokio::spawn(watch.run_with(args, provider));
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    watching
+}
+
+/// Waits up to five seconds for the file to be `content`.
+async fn wait_for(project: &Project, content: &str) {
+    for _ in 0..100 {
+        if project.source() == content {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+}
+
+/// How many runs checked the project's file.
+fn fixing_runs(project: &Project) -> usize {
+    let check = format!("check --no-fix {}", path(&project.file));
+    project
+        .ruff_invocations()
+        .iter()
+        .filter(|invocation| invocation.starts_with(&check))
+        .count()
+}
+
+#[tokio::test]
+async fn fixes_files_as_they_are_saved() {
+    let project = Project::new("watch");
+    let watching = watch(&project, "50", Arc::new(ScriptedProvider::new([FIXED]))).await;
+
+    fs::write(project.dir.join("src/ignored.py"), UNFIXED).unwrap();
+    fs::write(&project.file, UNFIXED).unwrap();
+    wait_for(&project, FIXED).await;
+    // Writing the fixes doesn't start another run.
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    watching.abort();
+
+    assert_eq!(project.source(), FIXED);
+    assert_eq!(fixing_runs(&project), 1, "{:?}", project.ruff_invocations());
+    assert!(!project
+        .ruff_invocations()
+        .iter()
+        .any(|invocation| invocation.contains("ignored.py")));
+}
+
+#[tokio::test]
+async fn fixes_saves_in_quick_succession_in_one_run() {
+    let project = Project::new("watch-debounce");
+    let provider = Arc::new(ScriptedProvider::new([FIXED]));
+    let watching = watch(&project, "300", provider.clone()).await;
+
+    for module in ["sys", "json", "os"] {
+        let content = format!("import {}\n\nprint(\"hi\")\n", module);
+        fs::write(&project.file, content).unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    wait_for(&project, FIXED).await;
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    watching.abort();
+
+    assert_eq!(project.source(), FIXED);
+    assert_eq!(fixing_runs(&project), 1, "{:?}", project.ruff_invocations());
+    let requests = provider.requests();
+    assert_eq!(requests.len(), 1);
+    assert!(requests[0].prompt().contains("`os` imported but unused"));
+}
+
+#[tokio::test]
+async fn ignores_saves_leaving_a_file_as_the_last_run_wrote_it() {
+    let project = Project::new("watch-own-writes");
+    let watching = watch(
+        &project,
+        "50",
+        Arc::new(ScriptedProvider::new([FIXED, FIXED])),
+    )
+    .await;
+    fs::write(&project.file, UNFIXED).unwrap();
+    wait_for(&project, FIXED).await;
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    // Saved again as it was fixed, as an editor reloading it might.
+    fs::write(&project.file, FIXED).unwrap();
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    assert_eq!(fixing_runs(&project), 1, "{:?}", project.ruff_invocations());
+
+    fs::write(&project.file, UNFIXED).unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    wait_for(&project, FIXED).await;
+    watching.abort();
+
+    assert_eq!(project.source(), FIXED);
+    assert_eq!(fixing_runs(&project), 2, "{:?}", project.ruff_invocations());
+}
+
+#[tokio::test]
+async fn serves_fixes_over_http() {
+    let project = Project::new("serve");
+    let port = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let listen = format!("127.0.0.1:{}", port);
+    let args: Vec<OsString> = [
+        "ruffer",
+        "unused-key",
+        path(&project.dir.join("ruff")),
+        "--no-cache",
+        "--journal-dir",
+        path(&project.journal_dir()),
+        "--state-file",
+        path(&project.state_file()),
+        "--listen",
+        &listen,
+    ]
+    .iter()
+    .map(OsString::from)
+    .collect();
+    let serve = ServeOptions::from_iter(&args);
+    let serving = tokio::spawn(serve.run_with(args, Arc::new(ScriptedProvider::new([FIXED]))));
+
+    let client = reqwest::Client::new();
+    let url = format!("http://{}", listen);
+    let mut status = None;
+    for _ in 0..100 {
+        if let Ok(response) = client.get(format!("{}/status", url)).send().await {
+            status = Some(response);
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert_eq!(status.expect("the server is up").status(), 200);
+
+    let filename = path(&project.file);
+    let fixed: serde_json::Value = client
+        .post(format!("{}/fix", url))
+        .json(&serde_json::json!({
+            "filename": filename,
+            "content": UNFIXED,
+            "issues": [{
+                "filename": filename,
+                "code": "F401",
+                "message": "`os` imported but unused",
+                "location": {"row": 1, "column": 8},
+                "end_location": {"row": 1, "column": 10},
+            }],
+        }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let invalid = client
+        .post(format!("{}/fix", url))
+        .body("not json")
+        .send()
+        .await
+        .unwrap();
+    let status: serde_json::Value = client
+        .get(format!("{}/status", url))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let missing = client.get(format!("{}/nope", url)).send().await.unwrap();
+    serving.abort();
+
+    assert_eq!(fixed["content"], FIXED);
+    assert_eq!(fixed["report"]["issues"][0]["code"], "F401");
+    assert_eq!(fixed["report"]["issues"][0]["outcome"], "llm-fixed");
+    assert_eq!(project.source(), UNFIXED);
+    assert_eq!(invalid.status(), 400);
+    assert_eq!(status["served"], 2);
+    assert_eq!(status["failed"], 1);
+    assert_eq!(missing.status(), 404);
+    // The issues were given, so ruff only checked the code around the fix.
+    let checks = project
+        .ruff_invocations()
+        .iter()
+        .filter(|invocation| invocation.starts_with("check --no-fix"))
+        .count();
+    assert_eq!(checks, 2, "{:?}", project.ruff_invocations());
+}
+
+#[tokio::test]
+async fn offers_tools_over_mcp() {
+    let project = Project::new("mcp");
+    let args: Vec<OsString> = [
+        "ruffer",
+        "unused-key",
+        path(&project.dir.join("ruff")),
+        path(&project.dir.join("src")),
+        "--no-cache",
+        "--journal-dir",
+        path(&project.journal_dir()),
+        "--state-file",
+        path(&project.state_file()),
+    ]
+    .iter()
+    .map(OsString::from)
+    .collect();
+    let call = |id: u32, name: &str, arguments: serde_json::Value| {
+        serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "method": "tools/call",
+            "params": { "name": name, "arguments": arguments },
+        })
+        .to_string()
+    };
+    let input = [
+        r#"{"jsonrpc":"2.0","id":1,"method":"initialize","params":{"protocolVersion":"2025-03-26"}}"#
+            .to_string(),
+        r#"{"jsonrpc":"2.0","method":"notifications/initialized"}"#.to_string(),
+        r#"{"jsonrpc":"2.0","id":2,"method":"tools/list"}"#.to_string(),
+        call(3, "check", serde_json::json!({})),
+        call(
+            4,
+            "propose_fix",
+            serde_json::json!({ "filename": path(&project.file) }),
+        ),
+        call(5, "apply_fix", serde_json::json!({ "paths": [path(&project.file)] })),
+        r#"{"jsonrpc":"2.0","id":6,"method":"resources/list"}"#.to_string(),
+    ]
+    .join("\n");
+    let mut output = Vec::new();
+
+    let mcp = McpOptions::from_iter(&args);
+    mcp.run_with(
+        args,
+        Arc::new(ScriptedProvider::new([FIXED, FIXED])),
+        input.as_bytes(),
+        &mut output,
+    )
+    .await
+    .unwrap();
+
+    let responses: Vec<serde_json::Value> = String::from_utf8(output)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    let text = |response: &serde_json::Value| -> serde_json::Value {
+        assert_eq!(response["result"]["isError"], false, "{}", response);
+        serde_json::from_str(response["result"]["content"][0]["text"].as_str().unwrap()).unwrap()
+    };
+    assert_eq!(responses.len(), 6);
+    assert_eq!(responses[0]["result"]["protocolVersion"], "2025-03-26");
+    let tools: Vec<&str> = responses[1]["result"]["tools"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|tool| tool["name"].as_str().unwrap())
+        .collect();
+    assert_eq!(tools, ["check", "propose_fix", "apply_fix"]);
+    let checked = text(&responses[2]);
+    assert_eq!(checked["issues"][0]["code"], "F401");
+    let proposed = text(&responses[3]);
+    assert_eq!(proposed["content"], FIXED);
+    assert!(proposed["patch"].as_str().unwrap().contains("-import os"));
+    let applied = text(&responses[4]);
+    assert_eq!(applied["issues"][0]["outcome"], "llm-fixed");
+    assert_eq!(project.source(), FIXED);
+    assert_eq!(responses[5]["error"]["code"], -32601);
+}
+
+#[tokio::test]
+async fn offers_fixes_as_code_actions_over_lsp() {
+    let project = Project::new("lsp");
+    let args: Vec<OsString> = [
+        "ruffer",
+        "unused-key",
+        path(&project.dir.join("ruff")),
+        "--no-cache",
+        "--journal-dir",
+        path(&project.journal_dir()),
+        "--state-file",
+        path(&project.state_file()),
+    ]
+    .iter()
+    .map(OsString::from)
+    .collect();
+    let uri = format!("file://{}", path(&project.file));
+    let frame = |message: serde_json::Value| {
+        let body = message.to_string();
+        format!("Content-Length: {}\r\n\r\n{}", body.len(), body)
+    };
+    let diagnostic = serde_json::json!({
+        "range": {
+            "start": {"line": 0, "character": 7},
+            "end": {"line": 0, "character": 9},
+        },
+        "code": "F401",
+        "source": "ruffer",
+        "message": "`os` imported but unused",
+    });
+    let input = [
+        serde_json::json!({"jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {}}),
+        serde_json::json!({"jsonrpc": "2.0", "method": "initialized", "params": {}}),
+        serde_json::json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/didOpen",
+            "params": {"textDocument": {"uri": uri, "languageId": "python", "version": 1, "text": UNFIXED}},
+        }),
+        serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": 2,
+            "method": "textDocument/codeAction",
+            "params": {
+                "textDocument": {"uri": uri},
+                "range": diagnostic["range"],
+                "context": {"diagnostics": [diagnostic]},
+            },
+        }),
+        serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": 3,
+            "method": "workspace/executeCommand",
+            "params": {"command": "ruffer.fixAll", "arguments": [uri]},
+        }),
+    ]
+    .into_iter()
+    .map(frame)
+    .collect::<String>()
+        + "Content-Length: 9\r\n\r\n{\"id\": 4,"
+        + &frame(serde_json::json!({"jsonrpc": "2.0", "id": 4, "method": "shutdown"}))
+        + &frame(serde_json::json!({"jsonrpc": "2.0", "method": "exit"}));
+    let mut output = Vec::new();
+
+    let lsp = LspOptions::from_iter(&args);
+    lsp.run_with(
+        args,
+        Arc::new(ScriptedProvider::new([FIXED])),
+        input.as_bytes(),
+        &mut output,
+    )
+    .await
+    .unwrap();
+
+    let output = String::from_utf8(output).unwrap();
+    let messages: Vec<serde_json::Value> = output
+        .split("Content-Length: ")
+        .skip(1)
+        .map(|frame| serde_json::from_str(frame.split_once("\r\n\r\n").unwrap().1).unwrap())
+        .collect();
+    assert_eq!(messages.len(), 7, "{}", output);
+    assert_eq!(
+        messages[0]["result"]["capabilities"]["executeCommandProvider"]["commands"],
+        serde_json::json!(["ruffer.fix", "ruffer.fixAll"])
+    );
+    let published = &messages[1]["params"]["diagnostics"][0];
+    assert_eq!(messages[1]["method"], "textDocument/publishDiagnostics");
+    assert_eq!(published["code"], "F401");
+    assert_eq!(published["range"]["start"], diagnostic["range"]["start"]);
+    let actions = messages[2]["result"].as_array().unwrap();
+    assert_eq!(actions[0]["title"], "Fix F401 with ruffer");
+    assert_eq!(actions[1]["command"]["command"], "ruffer.fixAll");
+    assert_eq!(messages[3]["id"], 3);
+    assert_eq!(messages[4]["method"], "workspace/applyEdit");
+    let edit = &messages[4]["params"]["edit"]["changes"][&uri][0];
+    assert_eq!(
+        edit["range"]["start"],
+        serde_json::json!({"line": 0, "character": 0})
+    );
+    assert_eq!(
+        edit["range"]["end"],
+        serde_json::json!({"line": 2, "character": 0})
+    );
+    assert_eq!(edit["newText"], "");
+    // A malformed message is answered and the server goes on.
+    assert_eq!(messages[5]["error"]["code"], -32700);
+    assert_eq!(messages[5]["id"], serde_json::Value::Null);
+    assert_eq!(messages[6]["id"], 4);
+    // The editor applies the edit; the file is left alone.
+    assert_eq!(project.source(), UNFIXED);
+}
+
+#[tokio::test]
+async fn fixes_code_piped_through_stdin() {
+    let project = Project::new("stdin");
+    let fixed = project.dir.join("a.fixed.py");
+    let fixer = RuffFixer::from_iter([
+        "ruffer",
+        "unused-key",
+        path(&project.dir.join("ruff")),
+        "--no-cache",
+        "--stdin-filename",
+        path(&project.file),
+        "--journal-dir",
+        path(&project.journal_dir()),
+        "--state-file",
+        path(&project.state_file()),
+    ])
+    .piping(UNFIXED.as_bytes(), fs::File::create(&fixed).unwrap());
+
+    Arc::new(fixer)
+        .run_with(Arc::new(ScriptedProvider::new([FIXED])))
+        .await
+        .unwrap();
+
+    assert_eq!(fs::read_to_string(fixed).unwrap(), FIXED);
+    assert_eq!(project.source(), UNFIXED);
+    assert!(!project.state_file().exists());
+    assert!(!project
+        .ruff_invocations()
+        .iter()
+        .any(|invocation| invocation.starts_with("format")));
+}
+
+#[tokio::test]
+async fn passes_clean_code_from_stdin_through() {
+    let project = Project::new("stdin-clean");
+    let out = project.dir.join("out.py");
+    let fixer = RuffFixer::from_iter([
+        "ruffer",
+        "unused-key",
+        path(&project.dir.join("ruff")),
+        "--no-cache",
+        "--stdin-filename",
+        path(&project.file),
+        "--journal-dir",
+        path(&project.journal_dir()),
+        "--state-file",
+        path(&project.state_file()),
+    ])
+    .piping(FIXED.as_bytes(), fs::File::create(&out).unwrap());
+
+    Arc::new(fixer)
+        .run_with(Arc::new(ScriptedProvider::new([FIXED])))
+        .await
+        .unwrap();
+
+    assert_eq!(fs::read_to_string(out).unwrap(), FIXED);
+}
+
+#[tokio::test]
+async fn combines_the_patches_into_one_file() {
+    let project = Project::new("patch-file");
+    let combined = project.dir.join("fixes.patch");
+
+    project
+        .fixer(&["--patch-out", path(&combined)])
+        .run_with(Arc::new(ScriptedProvider::new([FIXED])))
+        .await
+        .unwrap();
+
+    assert_eq!(project.source(), UNFIXED);
+    let patch = fs::read_to_string(combined).unwrap();
+    assert_eq!(patch.matches("+++ b/").count(), 1, "{}", patch);
+    assert!(patch.contains("-import os\n"), "{}", patch);
+}
@@ -0,0 +1,77 @@
+//! Parses `ruff check --output-format json` output of old and new ruff
+//! versions.
+
+use rust_ruffer::issue::{Applicability, Issue};
+
+#[test]
+fn parses_current_schema() {
+    let json = r#"[{
+        "cell": null,
+        "code": "F401",
+        "end_location": {"column": 10, "row": 1},
+        "filename": "/project/a.py",
+        "fix": {
+            "applicability": "safe",
+            "edits": [{"content": "", "end_location": {"column": 1, "row": 2}, "location": {"column": 1, "row": 1}}],
+            "message": "Remove unused import: `os`"
+        },
+        "location": {"column": 8, "row": 1},
+        "message": "`os` imported but unused",
+        "noqa_row": 1,
+        "url": "https://docs.astral.sh/ruff/rules/unused-import"
+    }]"#;
+
+    let issues: Vec<Issue> = serde_json::from_str(json).unwrap();
+    let issue = &issues[0];
+    assert_eq!(issue.code, "F401");
+    assert_eq!(issue.noqa_row, Some(1));
+    assert!(issue.url.as_deref().unwrap().ends_with("unused-import"));
+    assert_eq!(
+        issue.fix.as_ref().unwrap().applicability,
+        Applicability::Safe
+    );
+    let content = "import os\nprint(1)\n";
+    assert_eq!(issue.span(content), Some("os"));
+    assert_eq!(issue.lines(content), "import os");
+}
+
+#[test]
+fn parses_old_schema() {
+    let json = r#"[{
+        "filename": "a.py",
+        "code": "F401",
+        "message": "`os` imported but unused",
+        "location": {"row": 1, "column": 8},
+        "endLocation": {"row": 1, "column": 10},
+        "noqaRow": 1,
+        "fix": {"applicability": "Automatic", "message": null, "edits": []}
+    }]"#;
+
+    let issues: Vec<Issue> = serde_json::from_str(json).unwrap();
+    let issue = &issues[0];
+    assert!(issue.end_location.is_some());
+    assert_eq!(issue.noqa_row, Some(1));
+    assert_eq!(
+        issue.fix.as_ref().unwrap().applicability,
+        Applicability::Safe
+    );
+}
+
+#[test]
+fn gives_syntax_errors_a_code() {
+    let json = r#"[{
+        "filename": "a.py",
+        "code": null,
+        "message": "SyntaxError: Expected an expression",
+        "location": {"row": 2, "column": 5},
+        "end_location": {"row": 3, "column": 1},
+        "fix": null
+    }]"#;
+
+    let mut issues: Vec<Issue> = serde_json::from_str(json).unwrap();
+    assert_eq!(issues[0].code, "E999");
+    assert!(issues[0].fix.is_none());
+    assert_eq!(issues[0].lines("x = 1\ny = (\nz = 2\n"), "y = (");
+    issues[0].end_location.as_mut().unwrap().column = 2;
+    assert_eq!(issues[0].lines("x = 1\ny = (\nz = 2\n"), "y = (\nz = 2");
+}